@@ -22,14 +22,14 @@ use axum::{
         Form, FromRequest, RequestParts, TypedHeader,
     },
     response::IntoResponse,
-    BoxError,
+    BoxError, Json,
 };
 use headers::{authorization::Basic, Authorization};
 use http::StatusCode;
 use mas_config::Encrypter;
 use mas_data_model::{Client, JwksOrJwksUri, StorageBackend};
 use mas_http::HttpServiceExt;
-use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_iana::{jose::JsonWebSignatureAlg, oauth::OAuthClientAuthenticationMethod};
 use mas_jose::{
     DecodedJsonWebToken, DynamicJwksStore, Either, JsonWebKeySet, JsonWebTokenParts, JwtHeader,
     SharedSecret, StaticJwksStore, VerifyingKeystore,
@@ -38,6 +38,7 @@ use mas_storage::{
     oauth2::client::{lookup_client_by_client_id, ClientFetchError},
     PostgresqlBackend,
 };
+use oauth2_types::errors::{INVALID_CLIENT, INVALID_REQUEST, SERVER_ERROR};
 use serde::{de::DeserializeOwned, Deserialize};
 use serde_json::Value;
 use sqlx::PgExecutor;
@@ -98,6 +99,7 @@ impl Credentials {
         &self,
         encrypter: &Encrypter,
         method: OAuthClientAuthenticationMethod,
+        allowed_algs: &[JsonWebSignatureAlg],
         client: &Client<S>,
     ) -> Result<(), CredentialsVerificationError> {
         match (self, method) {
@@ -131,6 +133,10 @@ impl Credentials {
                 Credentials::ClientAssertionJwtBearer { jwt, header, .. },
                 OAuthClientAuthenticationMethod::PrivateKeyJwt,
             ) => {
+                if !allowed_algs.contains(&header.alg()) {
+                    return Err(CredentialsVerificationError::UnsupportedAssertionAlgorithm);
+                }
+
                 // Get the client JWKS
                 let jwks = client
                     .jwks
@@ -147,6 +153,10 @@ impl Credentials {
                 Credentials::ClientAssertionJwtBearer { jwt, header, .. },
                 OAuthClientAuthenticationMethod::ClientSecretJwt,
             ) => {
+                if !allowed_algs.contains(&header.alg()) {
+                    return Err(CredentialsVerificationError::UnsupportedAssertionAlgorithm);
+                }
+
                 // Decrypt the client_secret
                 let encrypted_client_secret = client
                     .encrypted_client_secret
@@ -220,6 +230,9 @@ pub enum CredentialsVerificationError {
 
     #[error("invalid assertion signature")]
     InvalidAssertionSignature,
+
+    #[error("unsupported assertion signing algorithm")]
+    UnsupportedAssertionAlgorithm,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -242,8 +255,39 @@ pub enum ClientAuthorizationError {
 
 impl IntoResponse for ClientAuthorizationError {
     fn into_response(self) -> axum::response::Response {
-        // TODO
-        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        match self {
+            Self::InvalidHeader => (
+                StatusCode::BAD_REQUEST,
+                Json(INVALID_REQUEST.with_description("The Authorization header is invalid.")),
+            ),
+            Self::BadForm(_) => (
+                StatusCode::BAD_REQUEST,
+                Json(INVALID_REQUEST.with_description("The request body could not be parsed.")),
+            ),
+            Self::ClientIdMismatch { .. } => (
+                StatusCode::BAD_REQUEST,
+                Json(INVALID_REQUEST.with_description(
+                    "The client_id in the request body does not match the credentials.",
+                )),
+            ),
+            Self::InvalidRequest => (StatusCode::BAD_REQUEST, Json(INVALID_REQUEST)),
+            Self::MissingCredentials => (
+                StatusCode::UNAUTHORIZED,
+                Json(INVALID_CLIENT.with_description("No client credentials were provided.")),
+            ),
+            Self::UnsupportedClientAssertion { .. } => (
+                StatusCode::UNAUTHORIZED,
+                Json(
+                    INVALID_CLIENT.with_description("The client_assertion_type is not supported."),
+                ),
+            ),
+            Self::InvalidAssertion => (
+                StatusCode::UNAUTHORIZED,
+                Json(INVALID_CLIENT.with_description("The client_assertion is invalid.")),
+            ),
+            Self::InternalError(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(SERVER_ERROR)),
+        }
+        .into_response()
     }
 }
 
@@ -30,6 +30,7 @@ use mas_storage::{
     oauth2::access_token::{lookup_active_access_token, AccessTokenLookupError},
     PostgresqlBackend,
 };
+use oauth2_types::scope::ScopeToken;
 use serde::{de::DeserializeOwned, Deserialize};
 use sqlx::{Acquire, Postgres};
 
@@ -102,6 +103,24 @@ impl<F> UserAuthorization<F> {
 
         Ok(session)
     }
+
+    /// Like [`Self::protected`], but also checks that the session was granted
+    /// the given scope
+    pub async fn protected_with_scope(
+        self,
+        conn: impl Acquire<'_, Database = Postgres> + Send,
+        scope: &ScopeToken,
+    ) -> Result<Session<PostgresqlBackend>, AuthorizationVerificationError> {
+        let session = self.protected(conn).await?;
+
+        if !session.scope.contains(scope) {
+            return Err(AuthorizationVerificationError::InsufficientScope(
+                scope.clone(),
+            ));
+        }
+
+        Ok(session)
+    }
 }
 
 pub enum UserAuthorizationError {
@@ -115,6 +134,7 @@ pub enum AuthorizationVerificationError {
     MissingToken,
     InvalidToken,
     MissingForm,
+    InsufficientScope(ScopeToken),
     InternalError(Box<dyn Error>),
 }
 
@@ -131,10 +151,7 @@ impl From<AccessTokenLookupError> for AuthorizationVerificationError {
 enum BearerError {
     InvalidRequest,
     InvalidToken,
-    #[allow(dead_code)]
-    InsufficientScope {
-        scope: Option<HeaderValue>,
-    },
+    InsufficientScope { scope: Option<HeaderValue> },
 }
 
 impl BearerError {
@@ -258,6 +275,18 @@ impl IntoResponse for AuthorizationVerificationError {
                 });
                 (StatusCode::BAD_REQUEST, headers).into_response()
             }
+            Self::InsufficientScope(scope) => {
+                let mut headers = HeaderMap::new();
+
+                headers.typed_insert(WwwAuthenticate::Bearer {
+                    realm: None,
+                    error: BearerError::InsufficientScope {
+                        scope: HeaderValue::from_str(&scope).ok(),
+                    },
+                    error_description: None,
+                });
+                (StatusCode::FORBIDDEN, headers).into_response()
+            }
             Self::InternalError(e) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
             }
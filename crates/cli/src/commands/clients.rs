@@ -0,0 +1,874 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+use clap::{ArgEnum, Parser};
+use mas_config::{
+    ClientApplicationType, ClientAuthMethodConfig, ClientConfig, ClientsConfig, Encrypter,
+    JwksOrJwksUri, RootConfig,
+};
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_storage::{
+    oauth2::client::{
+        delete_client, insert_client_from_config, list_clients, lookup_client_by_client_id,
+        update_client_from_config,
+    },
+    PostgresqlBackend,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use tracing::{info, warn};
+use url::Url;
+
+use super::output::{print_json, print_table};
+
+type Client = mas_data_model::Client<PostgresqlBackend>;
+
+#[derive(Parser, Debug)]
+pub(super) struct Options {
+    /// Output format of the listings and exports
+    #[clap(long, arg_enum, default_value = "table", global = true)]
+    format: Format,
+
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Format {
+    Table,
+    Json,
+    Yaml,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+#[clap(rename_all = "snake_case")]
+enum AuthMethod {
+    None,
+    ClientSecretBasic,
+    ClientSecretPost,
+    ClientSecretJwt,
+    PrivateKeyJwt,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum ApplicationType {
+    Web,
+    Native,
+}
+
+impl From<ApplicationType> for ClientApplicationType {
+    fn from(application_type: ApplicationType) -> Self {
+        match application_type {
+            ApplicationType::Web => Self::Web,
+            ApplicationType::Native => Self::Native,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// List the clients
+    List,
+
+    /// Show the metadata of a client
+    Show { client_id: String },
+
+    /// Add a client. The generated client secret is printed.
+    Add {
+        client_id: String,
+
+        /// Authentication method of the client on the token endpoint
+        #[clap(long, arg_enum, default_value = "client_secret_basic")]
+        auth_method: AuthMethod,
+
+        /// Use this client secret instead of a random one
+        #[clap(long)]
+        client_secret: Option<String>,
+
+        /// URL of the JWKS of the client, required by `private_key_jwt`
+        #[clap(long)]
+        jwks_uri: Option<Url>,
+
+        /// Kind of application. Native apps can redirect to the loopback
+        /// interface on any port, or to a private-use URI scheme.
+        #[clap(long, arg_enum)]
+        application_type: Option<ApplicationType>,
+
+        /// Allowed redirect URI, can be repeated
+        #[clap(long = "redirect-uri")]
+        redirect_uris: Vec<Url>,
+    },
+
+    /// Update the authentication method, the application type or the redirect
+    /// URIs of a client
+    Update {
+        client_id: String,
+
+        /// Authentication method of the client on the token endpoint
+        #[clap(long, arg_enum)]
+        auth_method: Option<AuthMethod>,
+
+        /// Set the client secret. A random one is generated if the new
+        /// authentication method needs one and the client has none.
+        #[clap(long)]
+        client_secret: Option<String>,
+
+        /// URL of the JWKS of the client, for `private_key_jwt`
+        #[clap(long)]
+        jwks_uri: Option<Url>,
+
+        /// Kind of application
+        #[clap(long, arg_enum)]
+        application_type: Option<ApplicationType>,
+
+        /// Allow a redirect URI, can be repeated
+        #[clap(long = "add-redirect-uri")]
+        add_redirect_uris: Vec<Url>,
+
+        /// Disallow a redirect URI, can be repeated
+        #[clap(long = "remove-redirect-uri")]
+        remove_redirect_uris: Vec<Url>,
+    },
+
+    /// Delete a client, along with its sessions and tokens
+    Delete { client_id: String },
+
+    /// Replace the secret of a client. The new secret is printed.
+    RotateSecret {
+        client_id: String,
+
+        /// Use this client secret instead of a random one
+        #[clap(long)]
+        client_secret: Option<String>,
+    },
+
+    /// Export the definitions of clients, in the format of the `clients`
+    /// configuration section. The output includes the client secrets.
+    Export {
+        /// Only export those clients, defaults to all of them
+        client_ids: Vec<String>,
+    },
+
+    /// Import client definitions from a JSON or YAML file, adding the new
+    /// clients and updating the existing ones
+    Import {
+        /// File with a list of clients, or a configuration file with a
+        /// `clients` section
+        path: PathBuf,
+
+        /// Only show what would change
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Also delete the clients which are not in the file, including the
+        /// dynamically registered ones
+        #[clap(long)]
+        prune: bool,
+    },
+
+    /// Sync the clients of the configuration to the database, adding the new
+    /// clients and updating the existing ones
+    Sync {
+        /// Only show what would change
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Also delete the clients which are not in the configuration,
+        /// including the dynamically registered ones
+        #[clap(long)]
+        prune: bool,
+    },
+}
+
+/// Client definitions, either as a plain list or as a configuration file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Definitions {
+    List(ClientsConfig),
+    Config { clients: ClientsConfig },
+}
+
+fn random_secret() -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn print_value<T: Serialize>(format: Format, value: &T) -> anyhow::Result<()> {
+    match format {
+        Format::Json => print_json(value),
+        Format::Yaml | Format::Table => {
+            print!("{}", serde_yaml::to_string(value)?);
+            Ok(())
+        }
+    }
+}
+
+/// Metadata of a client, with its database ID and without its secret
+fn client_value(client: &Client) -> anyhow::Result<serde_json::Value> {
+    let mut value = serde_json::to_value(client)?;
+    if let Some(object) = value.as_object_mut() {
+        object.remove("encrypted_client_secret");
+        object.insert("id".to_owned(), client.data.into());
+    }
+    Ok(value)
+}
+
+fn auth_method_name(client: &Client) -> String {
+    client
+        .token_endpoint_auth_method
+        .map_or_else(|| "-".to_owned(), |method| method.to_string())
+}
+
+/// Build the authentication method of a client, generating a secret if needed
+/// and none was given
+fn auth_method_config(
+    method: AuthMethod,
+    client_secret: Option<String>,
+    jwks: Option<JwksOrJwksUri>,
+) -> anyhow::Result<ClientAuthMethodConfig> {
+    let client_secret = || client_secret.unwrap_or_else(random_secret);
+    let config = match method {
+        AuthMethod::None => ClientAuthMethodConfig::None,
+        AuthMethod::ClientSecretBasic => ClientAuthMethodConfig::ClientSecretBasic {
+            client_secret: client_secret(),
+        },
+        AuthMethod::ClientSecretPost => ClientAuthMethodConfig::ClientSecretPost {
+            client_secret: client_secret(),
+        },
+        AuthMethod::ClientSecretJwt => ClientAuthMethodConfig::ClientSecretJwt {
+            client_secret: client_secret(),
+        },
+        AuthMethod::PrivateKeyJwt => ClientAuthMethodConfig::PrivateKeyJwt(
+            jwks.context("private_key_jwt needs the JWKS of the client")?,
+        ),
+    };
+    Ok(config)
+}
+
+fn auth_method_of(config: &ClientAuthMethodConfig) -> AuthMethod {
+    match config {
+        ClientAuthMethodConfig::None => AuthMethod::None,
+        ClientAuthMethodConfig::ClientSecretBasic { .. } => AuthMethod::ClientSecretBasic,
+        ClientAuthMethodConfig::ClientSecretPost { .. } => AuthMethod::ClientSecretPost,
+        ClientAuthMethodConfig::ClientSecretJwt { .. } => AuthMethod::ClientSecretJwt,
+        ClientAuthMethodConfig::PrivateKeyJwt(_) => AuthMethod::PrivateKeyJwt,
+    }
+}
+
+/// Turn a client from the database back into a definition, decrypting its
+/// secret
+fn client_to_config(client: &Client, encrypter: &Encrypter) -> anyhow::Result<ClientConfig> {
+    use OAuthClientAuthenticationMethod as M;
+
+    let client_secret = || -> anyhow::Result<String> {
+        let ciphertext = client
+            .encrypted_client_secret
+            .as_deref()
+            .context("client has no secret")?;
+        let secret = encrypter.decrypt_string(ciphertext)?;
+        Ok(String::from_utf8(secret)?)
+    };
+
+    let client_auth_method = match client.token_endpoint_auth_method {
+        Some(M::None) => ClientAuthMethodConfig::None,
+        Some(M::ClientSecretBasic) => ClientAuthMethodConfig::ClientSecretBasic {
+            client_secret: client_secret()?,
+        },
+        Some(M::ClientSecretPost) => ClientAuthMethodConfig::ClientSecretPost {
+            client_secret: client_secret()?,
+        },
+        Some(M::ClientSecretJwt) => ClientAuthMethodConfig::ClientSecretJwt {
+            client_secret: client_secret()?,
+        },
+        Some(M::PrivateKeyJwt) => {
+            let jwks = match &client.jwks {
+                Some(mas_data_model::JwksOrJwksUri::Jwks(jwks)) => {
+                    JwksOrJwksUri::Jwks(jwks.clone())
+                }
+                Some(mas_data_model::JwksOrJwksUri::JwksUri(jwks_uri)) => {
+                    JwksOrJwksUri::JwksUri(jwks_uri.clone())
+                }
+                None => anyhow::bail!("client uses private_key_jwt but has no JWKS"),
+            };
+            ClientAuthMethodConfig::PrivateKeyJwt(jwks)
+        }
+        Some(method) => anyhow::bail!("unsupported authentication method {}", method),
+        None => anyhow::bail!("client has no authentication method"),
+    };
+
+    Ok(ClientConfig {
+        client_id: client.client_id.clone(),
+        client_auth_method,
+        application_type: client.application_type.map(Into::into),
+        redirect_uris: client.redirect_uris.clone(),
+    })
+}
+
+/// List the fields which differ between two definitions of a client
+fn changed_fields(
+    current: &ClientConfig,
+    wanted: &ClientConfig,
+) -> anyhow::Result<Vec<&'static str>> {
+    let mut fields = Vec::new();
+
+    if current.client_auth_method() != wanted.client_auth_method() {
+        fields.push("client_auth_method");
+    }
+
+    if current.client_secret() != wanted.client_secret() {
+        fields.push("client_secret");
+    }
+
+    let current_jwks = current.jwks().map(serde_json::to_value).transpose()?;
+    let wanted_jwks = wanted.jwks().map(serde_json::to_value).transpose()?;
+    if current_jwks != wanted_jwks || current.jwks_uri() != wanted.jwks_uri() {
+        fields.push("jwks");
+    }
+
+    if current.application_type != wanted.application_type {
+        fields.push("application_type");
+    }
+
+    let current_uris: HashSet<&Url> = current.redirect_uris.iter().collect();
+    let wanted_uris: HashSet<&Url> = wanted.redirect_uris.iter().collect();
+    if current_uris != wanted_uris {
+        fields.push("redirect_uris");
+    }
+
+    Ok(fields)
+}
+
+fn encrypt_secret(client: &ClientConfig, encrypter: &Encrypter) -> anyhow::Result<Option<String>> {
+    client
+        .client_secret()
+        .map(|client_secret| encrypter.encryt_to_string(client_secret.as_bytes()))
+        .transpose()
+}
+
+async fn insert(
+    conn: &mut PgConnection,
+    client: &ClientConfig,
+    encrypter: &Encrypter,
+) -> anyhow::Result<()> {
+    let encrypted_client_secret = encrypt_secret(client, encrypter)?;
+    insert_client_from_config(
+        conn,
+        &client.client_id,
+        client.application_type(),
+        client.client_auth_method(),
+        encrypted_client_secret.as_deref(),
+        client.jwks(),
+        client.jwks_uri(),
+        &client.redirect_uris,
+    )
+    .await
+}
+
+async fn update(
+    conn: &mut PgConnection,
+    existing: &Client,
+    client: &ClientConfig,
+    encrypter: &Encrypter,
+) -> anyhow::Result<()> {
+    let encrypted_client_secret = encrypt_secret(client, encrypter)?;
+    update_client_from_config(
+        conn,
+        existing,
+        client.application_type(),
+        client.client_auth_method(),
+        encrypted_client_secret.as_deref(),
+        client.jwks(),
+        client.jwks_uri(),
+        &client.redirect_uris,
+    )
+    .await
+}
+
+/// Add the new clients and update the changed ones, printing a line for each
+/// change. Unless `dry_run` is set, the changes are applied.
+async fn sync(
+    conn: &mut PgConnection,
+    encrypter: &Encrypter,
+    wanted: &[ClientConfig],
+    dry_run: bool,
+    prune: bool,
+) -> anyhow::Result<()> {
+    let mut seen = HashSet::new();
+    for client in wanted {
+        if !seen.insert(client.client_id.as_str()) {
+            anyhow::bail!("client {:?} is defined more than once", client.client_id);
+        }
+    }
+
+    let existing = list_clients(&mut *conn).await?;
+    let (mut added, mut updated, mut removed) = (0, 0, 0);
+
+    for client in wanted {
+        let current = existing.iter().find(|c| c.client_id == client.client_id);
+        let current = if let Some(current) = current {
+            current
+        } else {
+            println!("+ {}", client.client_id);
+            added += 1;
+            if !dry_run {
+                insert(&mut *conn, client, encrypter).await?;
+            }
+            continue;
+        };
+
+        let fields = match client_to_config(current, encrypter) {
+            Ok(current) => changed_fields(&current, client)?,
+            Err(e) => {
+                warn!(client_id = %client.client_id, "Replacing client metadata: {}", e);
+                vec!["client_auth_method"]
+            }
+        };
+
+        if !fields.is_empty() {
+            println!("~ {} ({})", client.client_id, fields.join(", "));
+            updated += 1;
+            if !dry_run {
+                update(&mut *conn, current, client, encrypter).await?;
+            }
+        }
+    }
+
+    let extra: Vec<Client> = existing
+        .into_iter()
+        .filter(|client| !seen.contains(client.client_id.as_str()))
+        .collect();
+    if prune {
+        for client in extra {
+            println!("- {}", client.client_id);
+            removed += 1;
+            if !dry_run {
+                delete_client(&mut *conn, client).await?;
+            }
+        }
+    } else if !extra.is_empty() {
+        info!(
+            count = extra.len(),
+            "Some clients are not in the definitions and are kept, use --prune to delete them"
+        );
+    }
+
+    if dry_run {
+        info!(
+            added,
+            updated, removed, "Nothing changed, this was a dry run"
+        );
+    } else {
+        info!(added, updated, removed, "Clients synced");
+    }
+
+    Ok(())
+}
+
+impl Options {
+    #[allow(clippy::too_many_lines)]
+    pub async fn run(&self, root: &super::Options) -> anyhow::Result<()> {
+        use Subcommand as SC;
+        let config: RootConfig = root.load_config()?;
+        let pool = config.database.connect().await?;
+        let encrypter = config.secrets.encrypter();
+
+        match &self.subcommand {
+            SC::List => {
+                let clients = list_clients(&pool).await?;
+
+                if let Format::Table = self.format {
+                    let rows: Vec<Vec<String>> = clients
+                        .iter()
+                        .map(|client| {
+                            vec![
+                                client.data.to_string(),
+                                client.client_id.clone(),
+                                client.client_name.clone().unwrap_or_else(|| "-".to_owned()),
+                                auth_method_name(client),
+                                client.redirect_uris.len().to_string(),
+                            ]
+                        })
+                        .collect();
+                    print_table(
+                        &["ID", "CLIENT ID", "NAME", "AUTH METHOD", "REDIRECT URIS"],
+                        &rows,
+                    );
+                } else {
+                    let clients: Result<Vec<_>, _> = clients.iter().map(client_value).collect();
+                    print_value(self.format, &clients?)?;
+                }
+
+                Ok(())
+            }
+            SC::Show { client_id } => {
+                let client = lookup_client_by_client_id(&pool, client_id).await?;
+
+                if let Format::Table = self.format {
+                    println!("Client {} (ID {})", client.client_id, client.data);
+                    if let Some(name) = &client.client_name {
+                        println!("Name: {}", name);
+                    }
+                    println!("Authentication method: {}", auth_method_name(&client));
+                    match &client.jwks {
+                        Some(mas_data_model::JwksOrJwksUri::Jwks(_)) => {
+                            println!("JWKS: inline");
+                        }
+                        Some(mas_data_model::JwksOrJwksUri::JwksUri(jwks_uri)) => {
+                            println!("JWKS: {}", jwks_uri);
+                        }
+                        None => {}
+                    }
+                    let grant_types: Vec<String> =
+                        client.grant_types.iter().map(ToString::to_string).collect();
+                    println!("Grant types: {}", grant_types.join(", "));
+
+                    println!("\nRedirect URIs");
+                    for redirect_uri in &client.redirect_uris {
+                        println!("{}", redirect_uri);
+                    }
+                } else {
+                    print_value(self.format, &client_value(&client)?)?;
+                }
+
+                Ok(())
+            }
+            SC::Add {
+                client_id,
+                auth_method,
+                client_secret,
+                jwks_uri,
+                application_type,
+                redirect_uris,
+            } => {
+                let mut conn = pool.acquire().await?;
+
+                let client = ClientConfig {
+                    client_id: client_id.clone(),
+                    client_auth_method: auth_method_config(
+                        *auth_method,
+                        client_secret.clone(),
+                        jwks_uri.clone().map(JwksOrJwksUri::JwksUri),
+                    )?,
+                    application_type: application_type.map(Into::into),
+                    redirect_uris: redirect_uris.clone(),
+                };
+
+                let res = lookup_client_by_client_id(&mut conn, client_id).await;
+                match res {
+                    Ok(_) => anyhow::bail!("client {:?} already exists", client_id),
+                    Err(e) if e.not_found() => {}
+                    Err(e) => anyhow::bail!(e),
+                }
+
+                insert(&mut conn, &client, &encrypter).await?;
+                info!(%client_id, "Client added");
+
+                if let Some(client_secret) = client.client_secret() {
+                    println!("{}", client_secret);
+                }
+
+                Ok(())
+            }
+            SC::Update {
+                client_id,
+                auth_method,
+                client_secret,
+                jwks_uri,
+                application_type,
+                add_redirect_uris,
+                remove_redirect_uris,
+            } => {
+                let mut txn = pool.begin().await?;
+
+                let existing = lookup_client_by_client_id(&mut txn, client_id).await?;
+                let mut client = client_to_config(&existing, &encrypter)
+                    .context("could not read the current client metadata")?;
+
+                let new_secret = client_secret.is_none()
+                    && client.client_secret().is_none()
+                    && !matches!(
+                        auth_method,
+                        Some(AuthMethod::None | AuthMethod::PrivateKeyJwt) | None
+                    );
+
+                let jwks = jwks_uri.clone().map(JwksOrJwksUri::JwksUri).or_else(|| {
+                    match &client.client_auth_method {
+                        ClientAuthMethodConfig::PrivateKeyJwt(jwks) => Some(jwks.clone()),
+                        _ => None,
+                    }
+                });
+                let secret = client_secret
+                    .clone()
+                    .or_else(|| client.client_secret().map(ToOwned::to_owned));
+                let auth_method =
+                    auth_method.unwrap_or_else(|| auth_method_of(&client.client_auth_method));
+                client.client_auth_method = auth_method_config(auth_method, secret, jwks)?;
+
+                if let Some(application_type) = application_type {
+                    client.application_type = Some((*application_type).into());
+                }
+
+                client
+                    .redirect_uris
+                    .retain(|uri| !remove_redirect_uris.contains(uri));
+                for uri in add_redirect_uris {
+                    if !client.redirect_uris.contains(uri) {
+                        client.redirect_uris.push(uri.clone());
+                    }
+                }
+
+                update(&mut txn, &existing, &client, &encrypter).await?;
+                txn.commit().await?;
+                info!(%client_id, "Client updated");
+
+                if new_secret {
+                    if let Some(client_secret) = client.client_secret() {
+                        println!("{}", client_secret);
+                    }
+                }
+
+                Ok(())
+            }
+            SC::Delete { client_id } => {
+                let mut txn = pool.begin().await?;
+
+                let client = lookup_client_by_client_id(&mut txn, client_id).await?;
+                delete_client(&mut txn, client).await?;
+
+                txn.commit().await?;
+                info!(%client_id, "Client deleted");
+
+                Ok(())
+            }
+            SC::RotateSecret {
+                client_id,
+                client_secret,
+            } => {
+                let mut txn = pool.begin().await?;
+
+                let existing = lookup_client_by_client_id(&mut txn, client_id).await?;
+                let mut client = client_to_config(&existing, &encrypter)
+                    .context("could not read the current client metadata")?;
+                if client.client_secret().is_none() {
+                    anyhow::bail!("client {:?} does not authenticate with a secret", client_id);
+                }
+
+                client.client_auth_method = auth_method_config(
+                    auth_method_of(&client.client_auth_method),
+                    client_secret.clone(),
+                    None,
+                )?;
+
+                update(&mut txn, &existing, &client, &encrypter).await?;
+                txn.commit().await?;
+                info!(%client_id, "Client secret replaced");
+
+                if let Some(client_secret) = client.client_secret() {
+                    println!("{}", client_secret);
+                }
+
+                Ok(())
+            }
+            SC::Export { client_ids } => {
+                let mut definitions = Vec::new();
+                for client in list_clients(&pool).await? {
+                    if !client_ids.is_empty() && !client_ids.contains(&client.client_id) {
+                        continue;
+                    }
+
+                    match client_to_config(&client, &encrypter) {
+                        Ok(definition) => definitions.push(definition),
+                        Err(e) => {
+                            warn!(client_id = %client.client_id, "Skipping client: {}", e);
+                        }
+                    }
+                }
+
+                print_value(self.format, &definitions)?;
+
+                Ok(())
+            }
+            SC::Import {
+                path,
+                dry_run,
+                prune,
+            } => {
+                let file = std::fs::File::open(path)
+                    .with_context(|| format!("could not open {}", path.display()))?;
+                let definitions: Definitions = serde_yaml::from_reader(file)
+                    .with_context(|| format!("could not parse {}", path.display()))?;
+                let clients = match definitions {
+                    Definitions::List(clients) | Definitions::Config { clients } => clients,
+                };
+
+                let mut txn = pool.begin().await?;
+                sync(&mut txn, &encrypter, &clients, *dry_run, *prune).await?;
+                txn.commit().await?;
+
+                Ok(())
+            }
+            SC::Sync { dry_run, prune } => {
+                let mut txn = pool.begin().await?;
+                sync(&mut txn, &encrypter, &config.clients, *dry_run, *prune).await?;
+                txn.commit().await?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::MIGRATOR;
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    #[tokio::test]
+    async fn application_type() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+        let encrypter = Encrypter::new(&[0x42; 32]);
+
+        let client_id: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let mut config = ClientConfig {
+            client_id: client_id.clone(),
+            client_auth_method: ClientAuthMethodConfig::None,
+            application_type: Some(ClientApplicationType::Native),
+            redirect_uris: vec!["http://127.0.0.1/callback".parse().unwrap()],
+        };
+        insert(&mut txn, &config, &encrypter).await.unwrap();
+
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.application_type.map(Into::into),
+            Some(ClientApplicationType::Native)
+        );
+        // Native clients can use any port on the loopback interface
+        let redirect_uri = Some("http://127.0.0.1:41234/callback".parse().unwrap());
+        assert!(client.resolve_redirect_uri(&redirect_uri).is_ok());
+
+        let exported = client_to_config(&client, &encrypter).unwrap();
+        assert_eq!(exported.application_type, config.application_type);
+
+        config.application_type = Some(ClientApplicationType::Web);
+        update(&mut txn, &client, &config, &encrypter)
+            .await
+            .unwrap();
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.application_type.map(Into::into),
+            Some(ClientApplicationType::Web)
+        );
+        assert!(client.resolve_redirect_uri(&redirect_uri).is_err());
+
+        txn.rollback().await.unwrap();
+    }
+
+    #[test]
+    fn changed_application_type() {
+        let current = ClientConfig {
+            client_id: "client".to_owned(),
+            client_auth_method: ClientAuthMethodConfig::None,
+            application_type: None,
+            redirect_uris: Vec::new(),
+        };
+        let mut wanted = current.clone();
+        assert!(changed_fields(&current, &wanted).unwrap().is_empty());
+
+        wanted.application_type = Some(ClientApplicationType::Native);
+        assert_eq!(
+            changed_fields(&current, &wanted).unwrap(),
+            vec!["application_type"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sync_application_type() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+        let encrypter = Encrypter::new(&[0x42; 32]);
+
+        let client_id: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let mut config = ClientConfig {
+            client_id: client_id.clone(),
+            client_auth_method: ClientAuthMethodConfig::None,
+            application_type: None,
+            redirect_uris: vec!["https://example.com/callback".parse().unwrap()],
+        };
+        sync(&mut txn, &encrypter, &[config.clone()], false, false)
+            .await
+            .unwrap();
+
+        config.application_type = Some(ClientApplicationType::Native);
+        sync(&mut txn, &encrypter, &[config.clone()], true, false)
+            .await
+            .unwrap();
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+        assert_eq!(client.application_type, None);
+
+        sync(&mut txn, &encrypter, &[config.clone()], false, false)
+            .await
+            .unwrap();
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.application_type.map(Into::into),
+            Some(ClientApplicationType::Native)
+        );
+
+        txn.rollback().await.unwrap();
+    }
+}
@@ -12,15 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use argon2::Argon2;
+use chrono::{Duration, Utc};
 use clap::Parser;
-use mas_config::{DatabaseConfig, RootConfig};
+use mas_config::{DatabaseConfig, PasswordsConfig, RootConfig, UsernamesConfig};
+use mas_handlers::{password_hasher, PasswordPolicy, UsernamePolicy};
 use mas_storage::{
+    login_throttle::{clear_login_throttle, ThrottleScope},
     oauth2::client::{insert_client_from_config, lookup_client_by_client_id, truncate_clients},
+    passwords::PasswordScheme,
+    suspension::{lift_user_suspensions, suspend_user},
     user::{
-        lookup_user_by_username, lookup_user_email, mark_user_email_as_verified, register_user,
+        deactivate_user, erase_user, import_password_hash, lookup_any_user_by_username,
+        lookup_user_by_username, lookup_user_email, mark_user_email_as_verified,
+        register_passwordless_user, register_user,
     },
 };
+use sqlx::{Postgres, Transaction};
 use tracing::{info, warn};
 
 #[derive(Parser, Debug)]
@@ -34,13 +41,59 @@ enum Subcommand {
     /// Register a new user
     Register { username: String, password: String },
 
-    /// List active users
-    Users,
+    /// Import the password hash of a user from another system, creating the
+    /// user if needed, in which case the username has to follow the username
+    /// rules. The hash is upgraded on the next login.
+    ImportPassword {
+        username: String,
+
+        hash: String,
+
+        /// Scheme of the hash, either `bcrypt`, `pbkdf2`, `scrypt` or `argon2`
+        #[clap(long, default_value = "bcrypt")]
+        scheme: PasswordScheme,
+    },
+
+    /// List and administrate users
+    Users(super::users::Options),
 
     /// Mark email address as verified
     VerifyEmail { username: String, email: String },
 
-    /// Import clients from config
+    /// Suspend a user, preventing them from logging in and using their tokens
+    /// until the suspension expires or is lifted
+    Lock {
+        username: String,
+
+        /// Reason of the suspension, shown to the user
+        #[clap(long)]
+        reason: String,
+
+        /// Lift the suspension automatically after this many seconds
+        #[clap(long)]
+        expires_in: Option<u32>,
+    },
+
+    /// Lift the suspension of a user, and the lockout of their account after
+    /// too many failed sign in attempts
+    Unlock { username: String },
+
+    /// Deactivate a user and end all their sessions. The username stays
+    /// reserved.
+    Deactivate {
+        username: String,
+
+        /// Also delete the email addresses, credentials and other personal
+        /// data of the user
+        #[clap(long)]
+        erase: bool,
+    },
+
+    /// List and administrate OAuth 2.0 clients
+    Clients(super::clients::Options),
+
+    /// Import clients from config. Clients which already exist are skipped,
+    /// see `clients sync` to update them.
     ImportClients {
         /// Remove all clients before importing
         #[clap(long)]
@@ -53,10 +106,18 @@ impl Options {
         use Subcommand as SC;
         match &self.subcommand {
             SC::Register { username, password } => {
+                let usernames_config: UsernamesConfig = root.load_config()?;
+                UsernamePolicy::new(&usernames_config)?.check(username)?;
+
+                let passwords_config: PasswordsConfig = root.load_config()?;
+                PasswordPolicy::load(&passwords_config)
+                    .await?
+                    .check(password, &[username])?;
+
                 let config: DatabaseConfig = root.load_config()?;
                 let pool = config.connect().await?;
                 let mut txn = pool.begin().await?;
-                let hasher = Argon2::default();
+                let hasher = password_hasher(&passwords_config.hashing)?;
 
                 let user = register_user(&mut txn, hasher, username, password).await?;
                 txn.commit().await?;
@@ -64,11 +125,13 @@ impl Options {
 
                 Ok(())
             }
-            SC::Users => {
-                warn!("Not implemented yet");
-
-                Ok(())
-            }
+            SC::ImportPassword {
+                username,
+                hash,
+                scheme,
+            } => import_password(root, username, *scheme, hash).await,
+            SC::Clients(c) => Box::pin(c.run(root)).await,
+            SC::Users(c) => Box::pin(c.run(root)).await,
             SC::VerifyEmail { username, email } => {
                 let config: DatabaseConfig = root.load_config()?;
                 let pool = config.connect().await?;
@@ -83,6 +146,22 @@ impl Options {
 
                 Ok(())
             }
+            SC::Lock {
+                username,
+                reason,
+                expires_in,
+            } => lock(root, username, reason, *expires_in).await,
+            SC::Unlock { username } => unlock(root, username).await,
+            SC::Deactivate { username, erase } => {
+                let config: DatabaseConfig = root.load_config()?;
+                let pool = config.connect().await?;
+                let mut txn = pool.begin().await?;
+
+                deactivate(&mut txn, username, *erase).await?;
+                txn.commit().await?;
+
+                Ok(())
+            }
             SC::ImportClients { truncate } => {
                 let config: RootConfig = root.load_config()?;
                 let pool = config.database.connect().await?;
@@ -122,6 +201,7 @@ impl Options {
                     insert_client_from_config(
                         &mut txn,
                         client_id,
+                        client.application_type(),
                         client_auth_method,
                         encrypted_client_secret.as_deref(),
                         jwks,
@@ -138,3 +218,155 @@ impl Options {
         }
     }
 }
+
+/// Import the password hash of a user, creating the user if needed
+async fn import_password(
+    root: &super::Options,
+    username: &str,
+    scheme: PasswordScheme,
+    hash: &str,
+) -> anyhow::Result<()> {
+    let config: DatabaseConfig = root.load_config()?;
+    let pool = config.connect().await?;
+    let mut txn = pool.begin().await?;
+
+    let user = match lookup_user_by_username(&mut txn, username).await {
+        Ok(user) => user,
+        Err(e) if e.not_found() => {
+            let usernames_config: UsernamesConfig = root.load_config()?;
+            UsernamePolicy::new(&usernames_config)?.check(username)?;
+            register_passwordless_user(&mut txn, username).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    import_password_hash(&mut txn, &user, scheme, hash).await?;
+
+    txn.commit().await?;
+    info!(?user, "Password hash imported");
+
+    Ok(())
+}
+
+/// Suspend a user, for `expires_in` seconds if given
+async fn lock(
+    root: &super::Options,
+    username: &str,
+    reason: &str,
+    expires_in: Option<u32>,
+) -> anyhow::Result<()> {
+    let config: DatabaseConfig = root.load_config()?;
+    let pool = config.connect().await?;
+
+    let user = lookup_user_by_username(&pool, username).await?;
+    let expires_at = expires_in.map(|seconds| Utc::now() + Duration::seconds(seconds.into()));
+    let suspension = suspend_user(&pool, &user, reason, expires_at).await?;
+    info!(%username, ?suspension, "User suspended");
+
+    Ok(())
+}
+
+/// Lift the suspensions of a user and clear their login lockout
+async fn unlock(root: &super::Options, username: &str) -> anyhow::Result<()> {
+    let config: DatabaseConfig = root.load_config()?;
+    let pool = config.connect().await?;
+
+    // The lockout is tracked by username, even for unknown users
+    let lifted = match lookup_user_by_username(&pool, username).await {
+        Ok(user) => lift_user_suspensions(&pool, &user).await?,
+        Err(e) if e.not_found() => false,
+        Err(e) => return Err(e.into()),
+    };
+    let cleared = clear_login_throttle(&pool, ThrottleScope::Account, username).await?;
+
+    if lifted {
+        info!(%username, "Suspension lifted");
+    }
+    if cleared {
+        info!(%username, "Account unlocked");
+    }
+    if !lifted && !cleared {
+        info!(%username, "Account was not locked");
+    }
+
+    Ok(())
+}
+
+/// Deactivate a user, and optionally erase their personal data. Users who are
+/// already deactivated can still be erased.
+async fn deactivate(
+    txn: &mut Transaction<'_, Postgres>,
+    username: &str,
+    erase: bool,
+) -> anyhow::Result<()> {
+    let user = lookup_any_user_by_username(&mut *txn, username).await?;
+    if erase {
+        erase_user(txn, &user).await?;
+        info!(%username, "User deactivated and erased");
+    } else {
+        deactivate_user(txn, &user).await?;
+        info!(%username, "User deactivated");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{
+        user::{add_user_email, username_deactivated},
+        MIGRATOR,
+    };
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+    use sqlx::PgPool;
+
+    use super::*;
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    #[tokio::test]
+    async fn erase_deactivated_user() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+
+        let username: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let user = register_passwordless_user(&mut txn, &username)
+            .await
+            .unwrap();
+        add_user_email(&mut txn, &user, format!("{}@example.com", username))
+            .await
+            .unwrap();
+
+        deactivate(&mut txn, &username, false).await.unwrap();
+        assert!(username_deactivated(&mut txn, &username).await.unwrap());
+        assert!(lookup_user_by_username(&mut txn, &username)
+            .await
+            .unwrap_err()
+            .not_found());
+
+        // The personal data of a deactivated user can still be erased
+        deactivate(&mut txn, &username, true).await.unwrap();
+        let emails: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_emails WHERE user_id = $1")
+            .bind(user.data)
+            .fetch_one(&mut txn)
+            .await
+            .unwrap();
+        assert_eq!(emails, 0);
+
+        txn.rollback().await.unwrap();
+    }
+}
@@ -18,12 +18,17 @@ use anyhow::Context;
 use clap::Parser;
 use mas_config::ConfigurationSection;
 
+mod clients;
 mod config;
 mod database;
 mod debug;
 mod manage;
+mod output;
+mod passwords;
+mod registration_tokens;
 mod server;
 mod templates;
+mod users;
 
 #[derive(Parser, Debug)]
 enum Subcommand {
@@ -39,6 +44,12 @@ enum Subcommand {
     /// Manage the instance
     Manage(self::manage::Options),
 
+    /// Password-related commands
+    Passwords(self::passwords::Options),
+
+    /// Manage the tokens required to register when registration is restricted
+    RegistrationTokens(self::registration_tokens::Options),
+
     /// Templates-related commands
     Templates(self::templates::Options),
 
@@ -64,6 +75,8 @@ impl Options {
             Some(S::Database(c)) => c.run(self).await,
             Some(S::Server(c)) => c.run(self).await,
             Some(S::Manage(c)) => c.run(self).await,
+            Some(S::Passwords(c)) => c.run(self).await,
+            Some(S::RegistrationTokens(c)) => c.run(self).await,
             Some(S::Templates(c)) => c.run(self).await,
             Some(S::Debug(c)) => c.run(self).await,
             None => self::server::Options::default().run(self).await,
@@ -0,0 +1,74 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use anyhow::Context;
+use clap::Parser;
+use mas_handlers::BreachedPasswords;
+use tracing::info;
+
+#[derive(Parser, Debug)]
+pub(super) struct Options {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// Build the breached passwords dataset from a downloaded list
+    BuildBreached {
+        /// List of SHA-1 digests, one per line, optionally followed by a colon
+        /// and a count, like the Have I Been Pwned downloads
+        input: PathBuf,
+
+        /// Where the dataset should be saved
+        output: PathBuf,
+
+        /// The list contains plaintext passwords instead of SHA-1 digests
+        #[clap(long)]
+        plaintext: bool,
+
+        /// Skip digests seen less than this number of times
+        #[clap(long, default_value = "0")]
+        min_count: u64,
+    },
+}
+
+impl Options {
+    pub async fn run(&self, _root: &super::Options) -> anyhow::Result<()> {
+        use Subcommand as SC;
+        match &self.subcommand {
+            SC::BuildBreached {
+                input,
+                output,
+                plaintext,
+                min_count,
+            } => {
+                let file = File::open(input)
+                    .with_context(|| format!("could not open {}", input.display()))?;
+                let (plaintext, min_count) = (*plaintext, *min_count);
+                let breached = tokio::task::spawn_blocking(move || {
+                    BreachedPasswords::build(BufReader::new(file), plaintext, min_count)
+                })
+                .await??;
+
+                breached.save(output).await?;
+                info!(count = breached.len(), ?output, "Breached passwords saved");
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{Duration, Utc};
+use clap::Parser;
+use mas_config::DatabaseConfig;
+use mas_storage::registration_token::{
+    add_registration_token, get_registration_tokens, revoke_registration_token,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use tracing::{info, warn};
+
+#[derive(Parser, Debug)]
+pub(super) struct Options {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// Create a new registration token
+    Create {
+        /// Use this token instead of a random one
+        #[clap(long)]
+        token: Option<String>,
+
+        /// How many accounts can be created with the token. Unlimited if not
+        /// set
+        #[clap(long)]
+        usage_limit: Option<u32>,
+
+        /// Number of seconds after which the token expires. Never expires if
+        /// not set
+        #[clap(long)]
+        expires_in: Option<u32>,
+    },
+
+    /// List all registration tokens
+    List,
+
+    /// Revoke a registration token, so it can't be used anymore
+    Revoke { token: String },
+}
+
+impl Options {
+    pub async fn run(&self, root: &super::Options) -> anyhow::Result<()> {
+        use Subcommand as SC;
+        let config: DatabaseConfig = root.load_config()?;
+        let pool = config.connect().await?;
+
+        match &self.subcommand {
+            SC::Create {
+                token,
+                usage_limit,
+                expires_in,
+            } => {
+                let token = token.clone().unwrap_or_else(|| {
+                    thread_rng()
+                        .sample_iter(&Alphanumeric)
+                        .take(24)
+                        .map(char::from)
+                        .collect()
+                });
+                let usage_limit = usage_limit.map(i32::try_from).transpose()?;
+                let expires_at =
+                    expires_in.map(|seconds| Utc::now() + Duration::seconds(seconds.into()));
+
+                let token = add_registration_token(&pool, &token, usage_limit, expires_at).await?;
+                info!(token = %token.token, ?token.usage_limit, ?token.expires_at, "Registration token created");
+
+                Ok(())
+            }
+            SC::List => {
+                let now = Utc::now();
+                for token in get_registration_tokens(&pool).await? {
+                    let status = if token.revoked_at.is_some() {
+                        "revoked"
+                    } else if token.is_valid(now) {
+                        "valid"
+                    } else if token.expires_at.map_or(false, |at| at <= now) {
+                        "expired"
+                    } else {
+                        "used up"
+                    };
+                    let usage_limit = token
+                        .usage_limit
+                        .map_or_else(|| "unlimited".to_owned(), |limit| limit.to_string());
+                    let expires_at = token
+                        .expires_at
+                        .map_or_else(|| "never".to_owned(), |at| at.to_rfc3339());
+                    println!(
+                        "{}\t{}\tuses: {}/{}\texpires: {}",
+                        token.token, status, token.uses, usage_limit, expires_at
+                    );
+                }
+
+                Ok(())
+            }
+            SC::Revoke { token } => {
+                if revoke_registration_token(&pool, token).await? {
+                    info!(%token, "Registration token revoked");
+                } else {
+                    warn!(%token, "No such registration token, or already revoked");
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -24,6 +24,10 @@ use futures::{future::TryFutureExt, stream::TryStreamExt};
 use hyper::Server;
 use mas_config::RootConfig;
 use mas_email::{MailTransport, Mailer};
+use mas_handlers::{
+    AppState, BruteForceProtection, Capabilities, CaptchaVerifier, PasswordBackends,
+    PasswordPolicy, RegistrationPolicy, UpstreamProviders, UsernamePolicy,
+};
 use mas_http::ServerLayer;
 use mas_router::UrlBuilder;
 use mas_storage::MIGRATOR;
@@ -180,6 +184,23 @@ impl Options {
 
         let encrypter = config.secrets.encrypter();
 
+        // Figure out what the server supports from the config and the keys
+        let capabilities = Arc::new(Capabilities::new(&config.oauth2, key_store.as_ref()));
+
+        let upstream_providers = Arc::new(UpstreamProviders::new(&config.upstream_oauth2));
+
+        let password_backends = PasswordBackends::new(&config.ldap, &config.passwords)?;
+
+        let password_policy = PasswordPolicy::load(&config.passwords).await?;
+
+        let brute_force = BruteForceProtection::new(&config.brute_force);
+
+        let registration_policy = RegistrationPolicy::new(&config.registration);
+
+        let username_policy = UsernamePolicy::new(&config.usernames)?;
+
+        let captcha = CaptchaVerifier::new(&config.captcha);
+
         // Load and compile the templates
         let templates = Templates::load_from_config(&config.templates)
             .await
@@ -211,16 +232,26 @@ impl Options {
                 .context("could not watch for templates changes")?;
         }
 
-        let router = mas_handlers::router(
-            &pool,
-            &templates,
-            &key_store,
-            &encrypter,
-            &mailer,
-            &url_builder,
-        )
-        .fallback(static_files)
-        .layer(ServerLayer::default());
+        let state = AppState {
+            pool,
+            templates,
+            key_store,
+            capabilities,
+            upstream_providers,
+            password_backends,
+            password_policy,
+            brute_force,
+            registration_policy,
+            username_policy,
+            captcha,
+            encrypter,
+            mailer,
+            url_builder,
+        };
+
+        let router = mas_handlers::router(&state)
+            .fallback(static_files)
+            .layer(ServerLayer::default());
 
         info!("Listening on http://{}", listener.local_addr().unwrap());
 
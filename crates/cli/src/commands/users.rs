@@ -0,0 +1,343 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::{DateTime, Utc};
+use clap::{ArgEnum, Parser};
+use mas_config::{DatabaseConfig, PasswordsConfig};
+use mas_handlers::{password_hasher, PasswordPolicy};
+use mas_storage::{
+    admin::{list_user_oauth2_sessions, list_user_sessions, list_users, UserFilter},
+    user::{
+        add_user_email, end_user_sessions, get_user_emails, lookup_any_user_by_username,
+        lookup_user_by_username, lookup_user_email, mark_user_email_as_verified, remove_user_email,
+        set_password, set_user_admin, set_user_email_as_primary,
+    },
+};
+use serde_json::json;
+use tracing::info;
+
+use super::output::{print_json, print_table};
+
+#[derive(Parser, Debug)]
+pub(super) struct Options {
+    /// Output format of the listings
+    #[clap(long, arg_enum, default_value = "table", global = true)]
+    format: Format,
+
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Format {
+    Table,
+    Json,
+}
+
+#[derive(Parser, Debug)]
+enum Subcommand {
+    /// List users, optionally filtered
+    List {
+        /// Only users whose username or primary email contains this
+        #[clap(long)]
+        search: Option<String>,
+
+        /// Only users created since this date, in RFC 3339 format
+        #[clap(long)]
+        created_since: Option<DateTime<Utc>>,
+
+        /// Only users with (`true`) or without (`false`) a verified email
+        #[clap(long)]
+        has_verified_email: Option<bool>,
+
+        /// Only suspended (`true`) or not suspended (`false`) users
+        #[clap(long)]
+        locked: Option<bool>,
+
+        /// Only deactivated (`true`) or active (`false`) users
+        #[clap(long)]
+        deactivated: Option<bool>,
+
+        /// Maximum number of users to list
+        #[clap(long, default_value = "50")]
+        limit: u32,
+
+        /// Number of users to skip, to get the next pages
+        #[clap(long, default_value = "0")]
+        offset: u32,
+    },
+
+    /// Show the emails, sessions and OAuth 2.0 sessions of a user
+    Show { username: String },
+
+    /// Set the password of a user
+    SetPassword { username: String, password: String },
+
+    /// Add an email address to a user
+    AddEmail {
+        username: String,
+
+        email: String,
+
+        /// Mark the email address as verified
+        #[clap(long)]
+        verified: bool,
+
+        /// Make it the primary email address of the user
+        #[clap(long)]
+        primary: bool,
+    },
+
+    /// Remove an email address of a user
+    RemoveEmail { username: String, email: String },
+
+    /// End all the sessions of a user, and the OAuth 2.0 sessions started
+    /// from them
+    EndSessions { username: String },
+
+    /// Let a user use the admin API and the admin console
+    SetAdmin {
+        username: String,
+
+        /// Revoke the administration privileges instead
+        #[clap(long)]
+        revoke: bool,
+    },
+}
+
+fn format_date(date: Option<DateTime<Utc>>) -> String {
+    date.map_or_else(|| "-".to_owned(), |date| date.to_rfc3339())
+}
+
+impl Options {
+    #[allow(clippy::too_many_lines)]
+    pub async fn run(&self, root: &super::Options) -> anyhow::Result<()> {
+        use Subcommand as SC;
+        let config: DatabaseConfig = root.load_config()?;
+        let pool = config.connect().await?;
+
+        match &self.subcommand {
+            SC::List {
+                search,
+                created_since,
+                has_verified_email,
+                locked,
+                deactivated,
+                limit,
+                offset,
+            } => {
+                let filter = UserFilter {
+                    id: None,
+                    search: search.clone(),
+                    created_since: *created_since,
+                    has_verified_email: *has_verified_email,
+                    locked: *locked,
+                    deactivated: *deactivated,
+                };
+                let users = list_users(&pool, &filter, (*limit).into(), (*offset).into()).await?;
+
+                match self.format {
+                    Format::Json => print_json(&users)?,
+                    Format::Table => {
+                        let rows: Vec<Vec<String>> = users
+                            .into_iter()
+                            .map(|user| {
+                                let status = if user.deactivated_at.is_some() {
+                                    "deactivated"
+                                } else if user.locked {
+                                    "locked"
+                                } else {
+                                    "active"
+                                };
+                                let email = match (user.primary_email, user.has_verified_email) {
+                                    (Some(email), true) => email,
+                                    (Some(email), false) => format!("{} (unverified)", email),
+                                    (None, _) => "-".to_owned(),
+                                };
+                                vec![
+                                    user.id.to_string(),
+                                    user.username,
+                                    email,
+                                    status.to_owned(),
+                                    user.created_at.to_rfc3339(),
+                                ]
+                            })
+                            .collect();
+                        print_table(&["ID", "USERNAME", "EMAIL", "STATUS", "CREATED AT"], &rows);
+                    }
+                }
+
+                Ok(())
+            }
+            SC::Show { username } => {
+                let user = lookup_any_user_by_username(&pool, username).await?;
+                let emails = get_user_emails(&pool, &user).await?;
+                let sessions = list_user_sessions(&pool, &user).await?;
+                let oauth2_sessions = list_user_oauth2_sessions(&pool, &user).await?;
+                let primary_email_id = user.primary_email.as_ref().map(|email| email.data);
+
+                match self.format {
+                    Format::Json => {
+                        let emails: Vec<_> = emails
+                            .iter()
+                            .map(|email| {
+                                json!({
+                                    "email": email.email,
+                                    "primary": Some(email.data) == primary_email_id,
+                                    "created_at": email.created_at,
+                                    "confirmed_at": email.confirmed_at,
+                                })
+                            })
+                            .collect();
+                        print_json(&json!({
+                            "id": user.data,
+                            "username": user.username,
+                            "emails": emails,
+                            "sessions": sessions,
+                            "oauth2_sessions": oauth2_sessions,
+                        }))?;
+                    }
+                    Format::Table => {
+                        println!("User {} (ID {})", user.username, user.data);
+
+                        println!("\nEmails");
+                        let rows: Vec<Vec<String>> = emails
+                            .into_iter()
+                            .map(|email| {
+                                let primary = if Some(email.data) == primary_email_id {
+                                    "yes"
+                                } else {
+                                    "no"
+                                };
+                                vec![
+                                    email.email,
+                                    primary.to_owned(),
+                                    format_date(email.confirmed_at),
+                                ]
+                            })
+                            .collect();
+                        print_table(&["EMAIL", "PRIMARY", "CONFIRMED AT"], &rows);
+
+                        println!("\nSessions");
+                        let rows: Vec<Vec<String>> = sessions
+                            .into_iter()
+                            .map(|session| {
+                                vec![
+                                    session.id.to_string(),
+                                    if session.active { "yes" } else { "no" }.to_owned(),
+                                    session.created_at.to_rfc3339(),
+                                    format_date(session.last_authenticated_at),
+                                ]
+                            })
+                            .collect();
+                        print_table(
+                            &["ID", "ACTIVE", "CREATED AT", "LAST AUTHENTICATED AT"],
+                            &rows,
+                        );
+
+                        println!("\nOAuth 2.0 sessions");
+                        let rows: Vec<Vec<String>> = oauth2_sessions
+                            .into_iter()
+                            .map(|session| {
+                                vec![
+                                    session.id.to_string(),
+                                    session.user_session_id.to_string(),
+                                    session.client_id,
+                                    session.scope,
+                                    session.created_at.to_rfc3339(),
+                                    format_date(session.ended_at),
+                                ]
+                            })
+                            .collect();
+                        print_table(
+                            &["ID", "SESSION", "CLIENT", "SCOPE", "CREATED AT", "ENDED AT"],
+                            &rows,
+                        );
+                    }
+                }
+
+                Ok(())
+            }
+            SC::SetPassword { username, password } => {
+                let passwords_config: PasswordsConfig = root.load_config()?;
+                PasswordPolicy::load(&passwords_config)
+                    .await?
+                    .check(password, &[username])?;
+                let hasher = password_hasher(&passwords_config.hashing)?;
+
+                let user = lookup_user_by_username(&pool, username).await?;
+                set_password(&pool, hasher, &user, password).await?;
+                info!(%username, "Password changed");
+
+                Ok(())
+            }
+            SC::AddEmail {
+                username,
+                email,
+                verified,
+                primary,
+            } => {
+                let mut txn = pool.begin().await?;
+
+                let user = lookup_user_by_username(&mut txn, username).await?;
+                let mut email = add_user_email(&mut txn, &user, email.clone()).await?;
+                if *verified {
+                    email = mark_user_email_as_verified(&mut txn, email).await?;
+                }
+                if *primary {
+                    set_user_email_as_primary(&mut txn, &email).await?;
+                }
+
+                txn.commit().await?;
+                info!(%username, email = %email.email, "Email added");
+
+                Ok(())
+            }
+            SC::RemoveEmail { username, email } => {
+                let mut txn = pool.begin().await?;
+
+                let user = lookup_user_by_username(&mut txn, username).await?;
+                let email = lookup_user_email(&mut txn, &user, email).await?;
+                remove_user_email(&mut txn, email).await?;
+
+                txn.commit().await?;
+                info!(%username, "Email removed");
+
+                Ok(())
+            }
+            SC::EndSessions { username } => {
+                let mut txn = pool.begin().await?;
+
+                let user = lookup_any_user_by_username(&mut txn, username).await?;
+                end_user_sessions(&mut txn, &user).await?;
+
+                txn.commit().await?;
+                info!(%username, "Sessions ended");
+
+                Ok(())
+            }
+            SC::SetAdmin { username, revoke } => {
+                let user = lookup_any_user_by_username(&pool, username).await?;
+                set_user_admin(&pool, &user, !*revoke).await?;
+                if *revoke {
+                    info!(%username, "Administration privileges revoked");
+                } else {
+                    info!(%username, "Administration privileges granted");
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use chrono::Duration;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+
+use super::ConfigurationSection;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_account_limits() -> AttemptLimitsConfig {
+    AttemptLimitsConfig {
+        free_attempts: 3,
+        lockout_threshold: 10,
+    }
+}
+
+fn default_ip_limits() -> AttemptLimitsConfig {
+    AttemptLimitsConfig {
+        free_attempts: 10,
+        lockout_threshold: 100,
+    }
+}
+
+fn default_password_reset_limits() -> AttemptLimitsConfig {
+    AttemptLimitsConfig {
+        free_attempts: 2,
+        lockout_threshold: 5,
+    }
+}
+
+fn default_backoff_base() -> Duration {
+    Duration::seconds(1)
+}
+
+fn default_backoff_max() -> Duration {
+    Duration::minutes(5)
+}
+
+fn default_lockout_duration() -> Duration {
+    Duration::minutes(15)
+}
+
+fn default_forget_after() -> Duration {
+    Duration::hours(1)
+}
+
+/// How many failed password attempts are tolerated
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AttemptLimitsConfig {
+    /// Failed attempts allowed before having to wait between attempts
+    pub free_attempts: u32,
+
+    /// Failed attempts after which attempts are refused for a while
+    #[schemars(range(min = 1))]
+    pub lockout_threshold: u32,
+}
+
+/// Protections against password guessing on the login and reauth screens,
+/// and against floods of password reset emails
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct BruteForceConfig {
+    /// Whether failed attempts are counted at all
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Limits of failed attempts on a single account
+    #[serde(default = "default_account_limits")]
+    pub account: AttemptLimitsConfig,
+
+    /// Limits of failed attempts from a single IP address
+    #[serde(default = "default_ip_limits")]
+    pub ip: AttemptLimitsConfig,
+
+    /// Limits of password reset requests for a single email address. Requests
+    /// from a single IP address are limited like failed attempts.
+    #[serde(default = "default_password_reset_limits")]
+    pub password_reset: AttemptLimitsConfig,
+
+    /// Wait time in seconds after the first attempt which is not free, doubled
+    /// after each new failure
+    #[schemars(with = "u64", range(min = 1))]
+    #[serde(default = "default_backoff_base")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub backoff_base: Duration,
+
+    /// Maximum wait time between attempts in seconds
+    #[schemars(with = "u64", range(min = 1))]
+    #[serde(default = "default_backoff_max")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub backoff_max: Duration,
+
+    /// How long, in seconds, attempts are refused once the lockout threshold is
+    /// reached
+    #[schemars(with = "u64", range(min = 1))]
+    #[serde(default = "default_lockout_duration")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub lockout_duration: Duration,
+
+    /// Failed attempts older than this number of seconds are forgotten
+    #[schemars(with = "u64", range(min = 60))]
+    #[serde(default = "default_forget_after")]
+    #[serde_as(as = "serde_with::DurationSeconds<i64>")]
+    pub forget_after: Duration,
+
+    /// Take the client IP address from the last entry of the
+    /// `X-Forwarded-For` header, when running behind a reverse proxy
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for BruteForceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            account: default_account_limits(),
+            ip: default_ip_limits(),
+            password_reset: default_password_reset_limits(),
+            backoff_base: default_backoff_base(),
+            backoff_max: default_backoff_max(),
+            lockout_duration: default_lockout_duration(),
+            forget_after: default_forget_after(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for BruteForceConfig {
+    fn path() -> &'static str {
+        "brute_force"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    brute_force:
+                      account:
+                        free_attempts: 5
+                        lockout_threshold: 20
+                      lockout_duration: 3600
+                      trust_forwarded_for: true
+                "#,
+            )?;
+
+            let config = BruteForceConfig::load_from_file("config.yaml")?;
+
+            assert!(config.enabled);
+            assert_eq!(config.account.free_attempts, 5);
+            assert_eq!(config.account.lockout_threshold, 20);
+            assert_eq!(config.ip.lockout_threshold, 100);
+            assert_eq!(config.password_reset.lockout_threshold, 5);
+            assert_eq!(config.lockout_duration, Duration::hours(1));
+            assert_eq!(config.backoff_max, Duration::minutes(5));
+            assert!(config.trust_forwarded_for);
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ConfigurationSection;
+
+fn default_difficulty() -> u8 {
+    16
+}
+
+/// Challenge users have to solve on registration, to keep bots away
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "service", rename_all = "snake_case")]
+pub enum CaptchaConfig {
+    /// No challenge
+    Disabled,
+
+    /// A proof-of-work computed by the browser, which does not involve any
+    /// third party
+    ProofOfWork {
+        /// Number of leading zero bits the SHA-256 hash of the solution must
+        /// have. Each additional bit doubles the average solving time
+        #[schemars(range(min = 1, max = 32))]
+        #[serde(default = "default_difficulty")]
+        difficulty: u8,
+    },
+
+    /// hCaptcha
+    #[serde(rename = "hcaptcha")]
+    HCaptcha {
+        /// Site key, given to the browser
+        site_key: String,
+
+        /// Secret key, used to verify the responses
+        secret_key: String,
+
+        /// Override the verification endpoint, mostly useful for testing
+        #[serde(default)]
+        verify_url: Option<Url>,
+    },
+
+    /// Google reCAPTCHA v2
+    #[serde(rename = "recaptcha")]
+    ReCaptcha {
+        /// Site key, given to the browser
+        site_key: String,
+
+        /// Secret key, used to verify the responses
+        secret_key: String,
+
+        /// Override the verification endpoint, mostly useful for testing
+        #[serde(default)]
+        verify_url: Option<Url>,
+    },
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self::Disabled
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for CaptchaConfig {
+    fn path() -> &'static str {
+        "captcha"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    captcha:
+                      service: hcaptcha
+                      site_key: 10000000-ffff-ffff-ffff-000000000001
+                      secret_key: "0x0000000000000000000000000000000000000000"
+                "#,
+            )?;
+
+            let config = CaptchaConfig::load_from_file("config.yaml")?;
+
+            assert!(matches!(
+                config,
+                CaptchaConfig::HCaptcha {
+                    verify_url: None,
+                    ..
+                }
+            ));
+
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    captcha:
+                      service: proof_of_work
+                "#,
+            )?;
+
+            let config = CaptchaConfig::load_from_file("config.yaml")?;
+
+            assert!(matches!(
+                config,
+                CaptchaConfig::ProofOfWork { difficulty: 16 }
+            ));
+
+            Ok(())
+        });
+    }
+}
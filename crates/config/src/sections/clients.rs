@@ -17,6 +17,7 @@ use std::ops::{Deref, DerefMut};
 use async_trait::async_trait;
 use mas_iana::oauth::OAuthClientAuthenticationMethod;
 use mas_jose::JsonWebKeySet;
+use oauth2_types::oidc::ApplicationType;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
@@ -25,10 +26,14 @@ use url::Url;
 
 use super::ConfigurationSection;
 
+/// Keys used by a client to sign its `private_key_jwt` assertions
 #[derive(JsonSchema, Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum JwksOrJwksUri {
+    /// The JSON Web Key Set, inline
     Jwks(JsonWebKeySet),
+
+    /// URL of the JSON Web Key Set
     JwksUri(Url),
 }
 
@@ -71,6 +76,28 @@ pub enum ClientAuthMethodConfig {
     PrivateKeyJwt(JwksOrJwksUri),
 }
 
+/// Kind of application of a client, which restricts the redirect URIs it can
+/// use
+#[derive(JsonSchema, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientApplicationType {
+    /// `web`: a web application, with `https` redirect URIs
+    Web,
+
+    /// `native`: a native application, which can also redirect to the
+    /// loopback interface on any port, or to a private-use URI scheme
+    Native,
+}
+
+impl From<ApplicationType> for ClientApplicationType {
+    fn from(application_type: ApplicationType) -> Self {
+        match application_type {
+            ApplicationType::Web => Self::Web,
+            ApplicationType::Native => Self::Native,
+        }
+    }
+}
+
 /// An OAuth 2.0 client configuration
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
@@ -82,6 +109,11 @@ pub struct ClientConfig {
     #[serde(flatten)]
     pub client_auth_method: ClientAuthMethodConfig,
 
+    /// Kind of application. The redirect URIs are matched exactly if it is not
+    /// set.
+    #[serde(default)]
+    pub application_type: Option<ClientApplicationType>,
+
     /// List of allowed redirect URIs
     #[serde(default)]
     pub redirect_uris: Vec<Url>,
@@ -123,6 +155,16 @@ impl ClientConfig {
         }
     }
 
+    #[doc(hidden)]
+    #[must_use]
+    pub fn application_type(&self) -> Option<ApplicationType> {
+        self.application_type
+            .map(|application_type| match application_type {
+                ClientApplicationType::Web => ApplicationType::Web,
+                ClientApplicationType::Native => ApplicationType::Native,
+            })
+    }
+
     #[doc(hidden)]
     #[must_use]
     pub fn jwks(&self) -> Option<&JsonWebKeySet> {
@@ -194,6 +236,7 @@ mod tests {
                   clients:
                     - client_id: public
                       client_auth_method: none
+                      application_type: native
                       redirect_uris:
                         - https://exemple.fr/callback
 
@@ -239,8 +282,14 @@ mod tests {
                 vec!["https://exemple.fr/callback".parse().unwrap()]
             );
 
+            assert_eq!(
+                config.0[0].application_type(),
+                Some(ApplicationType::Native)
+            );
+
             assert_eq!(config.0[1].client_id, "secret-basic");
             assert_eq!(config.0[1].redirect_uris, Vec::new());
+            assert_eq!(config.0[1].application_type(), None);
 
             Ok(())
         });
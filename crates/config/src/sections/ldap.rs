@@ -0,0 +1,247 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ConfigurationSection;
+
+fn default_url() -> Url {
+    "ldap://localhost:389/".parse().unwrap()
+}
+
+fn default_filter() -> String {
+    "(uid={username})".to_owned()
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_email_attribute() -> Option<String> {
+    Some("mail".to_owned())
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn default_display_name_attribute() -> Option<String> {
+    Some("displayName".to_owned())
+}
+
+fn default_order() -> Vec<PasswordBackend> {
+    vec![PasswordBackend::Ldap, PasswordBackend::Local]
+}
+
+/// How users are bound to the directory to check their password
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum LdapBindConfig {
+    /// Bind directly with a DN built from the username
+    Direct {
+        /// Template of the user DN, `{username}` is replaced with the escaped
+        /// username
+        dn_template: String,
+    },
+
+    /// Search the user entry first, then bind with its DN
+    Search {
+        /// DN used to search the directory. Searches anonymously if not set
+        #[serde(default)]
+        bind_dn: Option<String>,
+
+        /// Password of the search DN
+        #[serde(default)]
+        bind_password: Option<String>,
+
+        /// Base DN of the search
+        base_dn: String,
+
+        /// Filter of the search, `{username}` is replaced with the escaped
+        /// username
+        #[serde(default = "default_filter")]
+        filter: String,
+    },
+}
+
+impl Default for LdapBindConfig {
+    fn default() -> Self {
+        Self::Direct {
+            dn_template: "uid={username},ou=people,dc=example,dc=com".to_owned(),
+        }
+    }
+}
+
+/// Mapping of directory attributes to local user attributes
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LdapAttributesConfig {
+    /// Attribute holding the email address of the user, imported as verified
+    #[serde(default = "default_email_attribute")]
+    pub email: Option<String>,
+
+    /// Attribute holding the display name of the user
+    #[serde(default = "default_display_name_attribute")]
+    pub display_name: Option<String>,
+}
+
+impl Default for LdapAttributesConfig {
+    fn default() -> Self {
+        Self {
+            email: default_email_attribute(),
+            display_name: default_display_name_attribute(),
+        }
+    }
+}
+
+/// Where user passwords can be checked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordBackend {
+    /// The LDAP directory
+    Ldap,
+
+    /// The passwords stored in the database
+    Local,
+}
+
+/// Configuration of the LDAP authentication backend
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LdapConfig {
+    /// Whether to check passwords against the LDAP directory
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// URL of the directory, either `ldap://` or `ldaps://`
+    #[serde(default = "default_url")]
+    pub url: Url,
+
+    /// Upgrade `ldap://` connections with `StartTLS`
+    #[serde(default)]
+    pub starttls: bool,
+
+    /// How users are bound to the directory
+    #[serde(default)]
+    pub bind: LdapBindConfig,
+
+    /// Mapping of directory attributes to local user attributes
+    #[serde(default)]
+    pub attributes: LdapAttributesConfig,
+
+    /// Order in which password backends are tried on login. Only the local
+    /// backend is used if LDAP is not enabled
+    #[serde(default = "default_order")]
+    pub order: Vec<PasswordBackend>,
+}
+
+impl Default for LdapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: default_url(),
+            starttls: false,
+            bind: LdapBindConfig::default(),
+            attributes: LdapAttributesConfig::default(),
+            order: default_order(),
+        }
+    }
+}
+
+impl LdapConfig {
+    /// Password backends to try on login, in order
+    #[must_use]
+    pub fn backends(&self) -> Vec<PasswordBackend> {
+        if self.enabled {
+            self.order.clone()
+        } else {
+            vec![PasswordBackend::Local]
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for LdapConfig {
+    fn path() -> &'static str {
+        "ldap"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    ldap:
+                      enabled: true
+                      url: ldap://ldap.example.com/
+                      starttls: true
+                      bind:
+                        mode: search
+                        bind_dn: cn=mas,dc=example,dc=com
+                        bind_password: hunter2
+                        base_dn: ou=people,dc=example,dc=com
+                      attributes:
+                        display_name: cn
+                      order:
+                        - local
+                        - ldap
+                "#,
+            )?;
+
+            let config = LdapConfig::load_from_file("config.yaml")?;
+
+            assert!(config.enabled);
+            assert!(config.starttls);
+            assert_eq!(config.url.as_str(), "ldap://ldap.example.com/");
+            match config.bind {
+                LdapBindConfig::Search {
+                    ref bind_dn,
+                    ref base_dn,
+                    ref filter,
+                    ..
+                } => {
+                    assert_eq!(bind_dn.as_deref(), Some("cn=mas,dc=example,dc=com"));
+                    assert_eq!(base_dn, "ou=people,dc=example,dc=com");
+                    assert_eq!(filter, "(uid={username})");
+                }
+                LdapBindConfig::Direct { .. } => panic!("expected search bind"),
+            }
+            assert_eq!(config.attributes.email.as_deref(), Some("mail"));
+            assert_eq!(config.attributes.display_name.as_deref(), Some("cn"));
+            assert_eq!(
+                config.backends(),
+                vec![PasswordBackend::Local, PasswordBackend::Ldap]
+            );
+
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn disabled_uses_local_passwords() {
+        let config = LdapConfig::default();
+        assert_eq!(config.backends(), vec![PasswordBackend::Local]);
+    }
+}
@@ -16,27 +16,48 @@ use async_trait::async_trait;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+mod brute_force;
+mod captcha;
 mod clients;
 mod csrf;
 mod database;
 mod email;
 mod http;
+mod ldap;
+mod oauth2;
+mod passwords;
+mod registration;
 mod secrets;
 mod telemetry;
 mod templates;
+mod upstream_oauth2;
+mod usernames;
 
 pub use self::{
-    clients::{ClientAuthMethodConfig, ClientConfig, ClientsConfig},
+    brute_force::{AttemptLimitsConfig, BruteForceConfig},
+    captcha::CaptchaConfig,
+    clients::{
+        ClientApplicationType, ClientAuthMethodConfig, ClientConfig, ClientsConfig, JwksOrJwksUri,
+    },
     csrf::CsrfConfig,
     database::DatabaseConfig,
     email::{EmailConfig, EmailSmtpMode, EmailTransportConfig},
     http::HttpConfig,
+    ldap::{LdapAttributesConfig, LdapBindConfig, LdapConfig, PasswordBackend},
+    oauth2::{OAuth2Config, PkceConfig},
+    passwords::{PasswordHashingConfig, PasswordsConfig},
+    registration::{RegistrationConfig, RegistrationMode},
     secrets::{Encrypter, SecretsConfig},
     telemetry::{
         MetricsConfig, MetricsExporterConfig, Propagator, TelemetryConfig, TracingConfig,
         TracingExporterConfig,
     },
     templates::TemplatesConfig,
+    upstream_oauth2::{
+        UpstreamClaimsConfig, UpstreamClientAuthConfig, UpstreamOAuth2Config,
+        UpstreamProviderConfig,
+    },
+    usernames::UsernamesConfig,
 };
 use crate::util::ConfigurationSection;
 
@@ -71,6 +92,38 @@ pub struct RootConfig {
     #[serde(default)]
     pub email: EmailConfig,
 
+    /// Configuration of the OAuth 2.0 and OIDC features
+    #[serde(default)]
+    pub oauth2: OAuth2Config,
+
+    /// LDAP authentication backend
+    #[serde(default)]
+    pub ldap: LdapConfig,
+
+    /// Rules new passwords have to follow
+    #[serde(default)]
+    pub passwords: PasswordsConfig,
+
+    /// Protections against password guessing
+    #[serde(default)]
+    pub brute_force: BruteForceConfig,
+
+    /// Who is allowed to create an account
+    #[serde(default)]
+    pub registration: RegistrationConfig,
+
+    /// Rules usernames of new users have to follow
+    #[serde(default)]
+    pub usernames: UsernamesConfig,
+
+    /// Challenge to solve on registration
+    #[serde(default)]
+    pub captcha: CaptchaConfig,
+
+    /// Upstream OIDC providers users can log in with
+    #[serde(default)]
+    pub upstream_oauth2: UpstreamOAuth2Config,
+
     /// Application secrets
     pub secrets: SecretsConfig,
 }
@@ -90,6 +143,14 @@ impl ConfigurationSection<'_> for RootConfig {
             templates: TemplatesConfig::generate().await?,
             csrf: CsrfConfig::generate().await?,
             email: EmailConfig::generate().await?,
+            oauth2: OAuth2Config::generate().await?,
+            ldap: LdapConfig::generate().await?,
+            passwords: PasswordsConfig::generate().await?,
+            brute_force: BruteForceConfig::generate().await?,
+            registration: RegistrationConfig::generate().await?,
+            usernames: UsernamesConfig::generate().await?,
+            captcha: CaptchaConfig::generate().await?,
+            upstream_oauth2: UpstreamOAuth2Config::generate().await?,
             secrets: SecretsConfig::generate().await?,
         })
     }
@@ -103,6 +164,14 @@ impl ConfigurationSection<'_> for RootConfig {
             templates: TemplatesConfig::test(),
             csrf: CsrfConfig::test(),
             email: EmailConfig::test(),
+            oauth2: OAuth2Config::test(),
+            ldap: LdapConfig::test(),
+            passwords: PasswordsConfig::test(),
+            brute_force: BruteForceConfig::test(),
+            registration: RegistrationConfig::test(),
+            usernames: UsernamesConfig::test(),
+            captcha: CaptchaConfig::test(),
+            upstream_oauth2: UpstreamOAuth2Config::test(),
             secrets: SecretsConfig::test(),
         }
     }
@@ -0,0 +1,161 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::ConfigurationSection;
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_client_auth_methods() -> Vec<OAuthClientAuthenticationMethod> {
+    vec![
+        OAuthClientAuthenticationMethod::ClientSecretBasic,
+        OAuthClientAuthenticationMethod::ClientSecretPost,
+        OAuthClientAuthenticationMethod::ClientSecretJwt,
+        OAuthClientAuthenticationMethod::PrivateKeyJwt,
+        OAuthClientAuthenticationMethod::None,
+    ]
+}
+
+/// Policy on the use of PKCE in the authorization code flow
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PkceConfig {
+    /// Require PKCE for public clients, which use the `none` authentication
+    /// method at the token endpoint
+    #[serde(default = "default_true")]
+    pub required_for_public_clients: bool,
+
+    /// Require PKCE for all clients, including confidential ones
+    #[serde(default)]
+    pub required_for_all_clients: bool,
+
+    /// Allow the `plain` code challenge method. If not set, only `S256` is
+    /// accepted
+    #[serde(default)]
+    pub allow_plain: bool,
+}
+
+impl Default for PkceConfig {
+    fn default() -> Self {
+        Self {
+            required_for_public_clients: default_true(),
+            required_for_all_clients: false,
+            allow_plain: false,
+        }
+    }
+}
+
+impl PkceConfig {
+    /// Whether a client using the given authentication method must use PKCE
+    #[must_use]
+    pub fn is_required(&self, auth_method: Option<OAuthClientAuthenticationMethod>) -> bool {
+        self.required_for_all_clients
+            || (self.required_for_public_clients
+                && matches!(
+                    auth_method,
+                    None | Some(OAuthClientAuthenticationMethod::None)
+                ))
+    }
+}
+
+/// Configuration of the OAuth 2.0 and OIDC features exposed by the server
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OAuth2Config {
+    /// Whether the implicit flow (the `token` response type) is allowed
+    #[serde(default = "default_true")]
+    pub implicit_flow: bool,
+
+    /// Client authentication methods accepted by the token and introspection
+    /// endpoints
+    #[serde(default = "default_client_auth_methods")]
+    pub client_auth_methods: Vec<OAuthClientAuthenticationMethod>,
+
+    /// Policy on the use of PKCE
+    #[serde(default)]
+    pub pkce: PkceConfig,
+}
+
+impl Default for OAuth2Config {
+    fn default() -> Self {
+        Self {
+            implicit_flow: default_true(),
+            client_auth_methods: default_client_auth_methods(),
+            pkce: PkceConfig::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for OAuth2Config {
+    fn path() -> &'static str {
+        "oauth2"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    oauth2:
+                      implicit_flow: false
+                      client_auth_methods:
+                        - client_secret_basic
+                        - none
+                      pkce:
+                        required_for_all_clients: true
+                "#,
+            )?;
+
+            let config = OAuth2Config::load_from_file("config.yaml")?;
+
+            assert!(!config.implicit_flow);
+            assert_eq!(
+                config.client_auth_methods,
+                vec![
+                    OAuthClientAuthenticationMethod::ClientSecretBasic,
+                    OAuthClientAuthenticationMethod::None,
+                ]
+            );
+            assert!(config.pkce.required_for_public_clients);
+            assert!(config.pkce.required_for_all_clients);
+            assert!(!config.pkce.allow_plain);
+            assert!(config
+                .pkce
+                .is_required(Some(OAuthClientAuthenticationMethod::ClientSecretBasic)));
+
+            Ok(())
+        });
+    }
+}
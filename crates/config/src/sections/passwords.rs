@@ -0,0 +1,193 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::ConfigurationSection;
+
+fn default_min_length() -> usize {
+    8
+}
+
+fn default_min_score() -> u8 {
+    2
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_memory_cost() -> u32 {
+    4096
+}
+
+fn default_time_cost() -> u32 {
+    3
+}
+
+fn default_parallelism() -> u32 {
+    1
+}
+
+/// Cost parameters of the Argon2id hashes of new passwords. Existing hashes
+/// made with other parameters are upgraded when their user logs in.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PasswordHashingConfig {
+    /// Memory size in KiB
+    #[schemars(range(min = 8))]
+    #[serde(default = "default_memory_cost")]
+    pub memory_cost: u32,
+
+    /// Number of iterations
+    #[schemars(range(min = 1))]
+    #[serde(default = "default_time_cost")]
+    pub time_cost: u32,
+
+    /// Degree of parallelism
+    #[schemars(range(min = 1))]
+    #[serde(default = "default_parallelism")]
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashingConfig {
+    fn default() -> Self {
+        Self {
+            memory_cost: default_memory_cost(),
+            time_cost: default_time_cost(),
+            parallelism: default_parallelism(),
+        }
+    }
+}
+
+/// Rules new passwords have to follow, on registration and password changes
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PasswordsConfig {
+    /// Minimum number of characters
+    #[schemars(range(min = 1))]
+    #[serde(default = "default_min_length")]
+    pub min_length: usize,
+
+    /// Require at least one lowercase letter
+    #[serde(default)]
+    pub require_lowercase: bool,
+
+    /// Require at least one uppercase letter
+    #[serde(default)]
+    pub require_uppercase: bool,
+
+    /// Require at least one digit
+    #[serde(default)]
+    pub require_digit: bool,
+
+    /// Require at least one character which is neither a letter nor a digit
+    #[serde(default)]
+    pub require_symbol: bool,
+
+    /// Minimum strength score, from 0 (anything goes) to 4 (very hard to
+    /// guess)
+    #[schemars(range(min = 0, max = 4))]
+    #[serde(default = "default_min_score")]
+    pub min_score: u8,
+
+    /// Refuse passwords containing the username or an email address of the
+    /// user
+    #[serde(default = "default_true")]
+    pub reject_user_identifiers: bool,
+
+    /// Path to a dataset of breached passwords to refuse, built with the
+    /// `passwords build-breached` command
+    #[serde(default)]
+    pub breached_passwords: Option<String>,
+
+    /// How new passwords are hashed
+    #[serde(default)]
+    pub hashing: PasswordHashingConfig,
+}
+
+impl Default for PasswordsConfig {
+    fn default() -> Self {
+        Self {
+            min_length: default_min_length(),
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            min_score: default_min_score(),
+            reject_user_identifiers: true,
+            breached_passwords: None,
+            hashing: PasswordHashingConfig::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for PasswordsConfig {
+    fn path() -> &'static str {
+        "passwords"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    passwords:
+                      min_length: 12
+                      require_digit: true
+                      min_score: 3
+                      breached_passwords: /var/lib/mas/breached.bin
+                      hashing:
+                        memory_cost: 19456
+                        time_cost: 2
+                "#,
+            )?;
+
+            let config = PasswordsConfig::load_from_file("config.yaml")?;
+
+            assert_eq!(config.min_length, 12);
+            assert!(config.require_digit);
+            assert!(!config.require_symbol);
+            assert_eq!(config.min_score, 3);
+            assert!(config.reject_user_identifiers);
+            assert_eq!(
+                config.breached_passwords.as_deref(),
+                Some("/var/lib/mas/breached.bin")
+            );
+            assert_eq!(config.hashing.memory_cost, 19456);
+            assert_eq!(config.hashing.time_cost, 2);
+            assert_eq!(config.hashing.parallelism, 1);
+
+            Ok(())
+        });
+    }
+}
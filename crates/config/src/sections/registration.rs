@@ -0,0 +1,112 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::ConfigurationSection;
+
+/// Who is allowed to create an account
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// Nobody, accounts are only created by admins
+    Disabled,
+
+    /// Anyone
+    Open,
+
+    /// Anyone with a registration token issued by an admin
+    Token,
+
+    /// Anyone with an email address in one of the allowed domains
+    EmailDomains,
+}
+
+impl Default for RegistrationMode {
+    fn default() -> Self {
+        Self::Open
+    }
+}
+
+/// Configuration of the user registration
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct RegistrationConfig {
+    /// Who is allowed to create an account
+    #[serde(default)]
+    pub mode: RegistrationMode,
+
+    /// Domains of the email addresses allowed to register in the
+    /// `email_domains` mode
+    #[serde(default)]
+    pub allowed_email_domains: Vec<String>,
+
+    /// Make the email address mandatory in the registration form. It is
+    /// always mandatory in the `email_domains` mode
+    #[serde(default)]
+    pub require_email: bool,
+
+    /// Refuse to continue authorization grants until the user has verified
+    /// an email address. Always enabled in the `email_domains` mode
+    #[serde(default)]
+    pub require_email_verification: bool,
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for RegistrationConfig {
+    fn path() -> &'static str {
+        "registration"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    registration:
+                      mode: email_domains
+                      allowed_email_domains:
+                        - example.com
+                      require_email_verification: true
+                "#,
+            )?;
+
+            let config = RegistrationConfig::load_from_file("config.yaml")?;
+
+            assert_eq!(config.mode, RegistrationMode::EmailDomains);
+            assert_eq!(config.allowed_email_domains, vec!["example.com".to_owned()]);
+            assert!(!config.require_email);
+            assert!(config.require_email_verification);
+
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Deref;
+
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::ConfigurationSection;
+
+fn default_scope() -> String {
+    "openid profile email".to_owned()
+}
+
+fn default_username_claim() -> String {
+    "preferred_username".to_owned()
+}
+
+/// How the server authenticates to the token endpoint of an upstream provider
+#[derive(JsonSchema, Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "token_endpoint_auth_method", rename_all = "snake_case")]
+pub enum UpstreamClientAuthConfig {
+    /// `none`: No authentication
+    None,
+
+    /// `client_secret_basic`: `client_id` and `client_secret` used as basic
+    /// authorization credentials
+    ClientSecretBasic {
+        /// The client secret
+        client_secret: String,
+    },
+
+    /// `client_secret_post`: `client_id` and `client_secret` sent in the
+    /// request body
+    ClientSecretPost {
+        /// The client secret
+        client_secret: String,
+    },
+}
+
+/// Mapping of the claims of an upstream ID token to local user attributes
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UpstreamClaimsConfig {
+    /// Claim used as the username of users provisioned on their first login
+    #[serde(default = "default_username_claim")]
+    pub username: String,
+}
+
+impl Default for UpstreamClaimsConfig {
+    fn default() -> Self {
+        Self {
+            username: default_username_claim(),
+        }
+    }
+}
+
+/// An upstream OIDC provider users can log in with
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct UpstreamProviderConfig {
+    /// Stable identifier of the provider, used in URLs and to link upstream
+    /// accounts to local users. Changing it unlinks all existing accounts.
+    pub id: String,
+
+    /// Name of the provider displayed on the login page
+    pub name: String,
+
+    /// Issuer of the provider, used for discovery and to validate ID tokens
+    pub issuer: Url,
+
+    /// Client ID registered on the provider
+    pub client_id: String,
+
+    /// Client authentication used at the provider token endpoint
+    #[serde(flatten)]
+    pub client_auth: UpstreamClientAuthConfig,
+
+    /// Scope requested from the provider
+    #[serde(default = "default_scope")]
+    pub scope: String,
+
+    /// Mapping of the upstream claims to local user attributes
+    #[serde(default)]
+    pub claims: UpstreamClaimsConfig,
+}
+
+/// Configuration of the upstream OIDC providers
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct UpstreamOAuth2Config(pub Vec<UpstreamProviderConfig>);
+
+impl UpstreamOAuth2Config {
+    /// Find a provider by its identifier
+    #[must_use]
+    pub fn find(&self, id: &str) -> Option<&UpstreamProviderConfig> {
+        self.0.iter().find(|provider| provider.id == id)
+    }
+}
+
+impl Deref for UpstreamOAuth2Config {
+    type Target = Vec<UpstreamProviderConfig>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl ConfigurationSection<'_> for UpstreamOAuth2Config {
+    fn path() -> &'static str {
+        "upstream_oauth2"
+    }
+
+    async fn generate() -> anyhow::Result<Self> {
+        Ok(Self::default())
+    }
+
+    fn test() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use figment::Jail;
+
+    use super::*;
+
+    #[test]
+    fn load_config() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "config.yaml",
+                r#"
+                    upstream_oauth2:
+                      - id: corp
+                        name: Corporate SSO
+                        issuer: https://sso.example.com/
+                        client_id: mas
+                        token_endpoint_auth_method: client_secret_basic
+                        client_secret: hunter2
+                      - id: public
+                        name: Public
+                        issuer: https://accounts.example.org/
+                        client_id: mas
+                        token_endpoint_auth_method: none
+                        scope: openid
+                        claims:
+                          username: nickname
+                "#,
+            )?;
+
+            let config = UpstreamOAuth2Config::load_from_file("config.yaml")?;
+
+            assert_eq!(config.len(), 2);
+
+            let corp = config.find("corp").unwrap();
+            assert_eq!(corp.name, "Corporate SSO");
+            assert_eq!(corp.issuer.as_str(), "https://sso.example.com/");
+            assert!(matches!(
+                &corp.client_auth,
+                UpstreamClientAuthConfig::ClientSecretBasic { client_secret } if client_secret == "hunter2"
+            ));
+            assert_eq!(corp.scope, "openid profile email");
+            assert_eq!(corp.claims.username, "preferred_username");
+
+            let public = config.find("public").unwrap();
+            assert!(matches!(public.client_auth, UpstreamClientAuthConfig::None));
+            assert_eq!(public.scope, "openid");
+            assert_eq!(public.claims.username, "nickname");
+
+            assert!(config.find("unknown").is_none());
+
+            Ok(())
+        });
+    }
+}
@@ -36,7 +36,7 @@ pub use self::{
     tokens::{AccessToken, RefreshToken, TokenFormatError, TokenType},
     traits::{StorageBackend, StorageBackendMarker},
     users::{
-        Authentication, BrowserSession, User, UserEmail, UserEmailVerification,
-        UserEmailVerificationState,
+        Authentication, AuthenticationMethod, BrowserSession, UnknownAuthenticationMethod, User,
+        UserEmail, UserEmailVerification, UserEmailVerificationState,
     },
 };
@@ -165,6 +165,9 @@ pub struct AuthorizationGrant<T: StorageBackend> {
     pub code: Option<AuthorizationCode>,
     pub client: Client<T>,
     pub redirect_uri: Url,
+    /// Whether the `redirect_uri` was explicitly given in the authorization
+    /// request, in which case it must be repeated in the token request
+    pub redirect_uri_provided: bool,
     pub scope: oauth2_types::scope::Scope,
     pub state: Option<String>,
     pub nonce: Option<String>,
@@ -185,6 +188,7 @@ impl<S: StorageBackendMarker> From<AuthorizationGrant<S>> for AuthorizationGrant
             code: g.code,
             client: g.client.into(),
             redirect_uri: g.redirect_uri,
+            redirect_uri_provided: g.redirect_uri_provided,
             scope: g.scope,
             state: g.state,
             nonce: g.nonce,
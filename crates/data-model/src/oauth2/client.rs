@@ -17,7 +17,10 @@ use mas_iana::{
     oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod},
 };
 use mas_jose::JsonWebKeySet;
-use oauth2_types::requests::GrantType;
+use oauth2_types::{
+    oidc::{is_loopback_redirect_uri, ApplicationType},
+    requests::GrantType,
+};
 use serde::Serialize;
 use thiserror::Error;
 use url::Url;
@@ -56,6 +59,9 @@ pub struct Client<T: StorageBackend> {
     /// declaring that it will restrict itself to using.
     pub grant_types: Vec<GrantType>,
 
+    /// Kind of application, which affects what redirect URIs are accepted
+    pub application_type: Option<ApplicationType>,
+
     /// Array of e-mail addresses of people responsible for this Client
     pub contacts: Vec<String>,
 
@@ -107,6 +113,7 @@ impl<S: StorageBackendMarker> From<Client<S>> for Client<()> {
             redirect_uris: c.redirect_uris,
             response_types: c.response_types,
             grant_types: c.grant_types,
+            application_type: c.application_type,
             contacts: c.contacts,
             client_name: c.client_name,
             logo_uri: c.logo_uri,
@@ -145,7 +152,102 @@ impl<S: StorageBackend> Client<S> {
             ([one], None) => Ok(one),
             (_, None) => Err(InvalidRedirectUriError::MultipleRegistered),
             (uris, Some(uri)) if uris.contains(uri) => Ok(uri),
+            // Native apps listening on the loopback interface get a port from the OS
+            // at request time, so the port is ignored when matching, as per RFC 8252
+            // section 7.3
+            (uris, Some(uri))
+                if self.application_type == Some(ApplicationType::Native)
+                    && is_loopback_redirect_uri(uri)
+                    && uris.iter().any(|registered| {
+                        is_loopback_redirect_uri(registered)
+                            && without_port(registered) == without_port(uri)
+                    }) =>
+            {
+                Ok(uri)
+            }
             _ => Err(InvalidRedirectUriError::NotAllowed),
         }
     }
 }
+
+fn without_port(uri: &Url) -> Url {
+    let mut uri = uri.clone();
+    // This can't fail on loopback http URIs
+    let _ = uri.set_port(None);
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(application_type: Option<ApplicationType>, redirect_uris: &[&str]) -> Client<()> {
+        Client {
+            data: (),
+            client_id: "client".to_owned(),
+            encrypted_client_secret: None,
+            redirect_uris: redirect_uris.iter().map(|u| u.parse().unwrap()).collect(),
+            response_types: Vec::new(),
+            grant_types: Vec::new(),
+            application_type,
+            contacts: Vec::new(),
+            client_name: None,
+            logo_uri: None,
+            client_uri: None,
+            policy_uri: None,
+            tos_uri: None,
+            jwks: None,
+            id_token_signed_response_alg: None,
+            userinfo_signed_response_alg: None,
+            token_endpoint_auth_method: None,
+            token_endpoint_auth_signing_alg: None,
+            initiate_login_uri: None,
+        }
+    }
+
+    #[test]
+    fn native_loopback_ignores_port() {
+        let native = client(
+            Some(ApplicationType::Native),
+            &["http://127.0.0.1/callback", "http://[::1]:8080/callback"],
+        );
+
+        for uri in [
+            "http://127.0.0.1/callback",
+            "http://127.0.0.1:51004/callback",
+            "http://[::1]:1234/callback",
+        ] {
+            let uri = Some(uri.parse().unwrap());
+            assert_eq!(
+                native.resolve_redirect_uri(&uri).unwrap(),
+                uri.as_ref().unwrap()
+            );
+        }
+
+        for uri in [
+            "http://127.0.0.1:51004/other",
+            "http://127.0.0.2:51004/callback",
+            "https://127.0.0.1:51004/callback",
+        ] {
+            let uri = Some(uri.parse().unwrap());
+            assert!(matches!(
+                native.resolve_redirect_uri(&uri),
+                Err(InvalidRedirectUriError::NotAllowed)
+            ));
+        }
+    }
+
+    #[test]
+    fn web_loopback_is_exact() {
+        let web = client(Some(ApplicationType::Web), &["http://127.0.0.1/callback"]);
+
+        let uri = Some("http://127.0.0.1:51004/callback".parse().unwrap());
+        assert!(matches!(
+            web.resolve_redirect_uri(&uri),
+            Err(InvalidRedirectUriError::NotAllowed)
+        ));
+
+        let uri = Some("http://127.0.0.1/callback".parse().unwrap());
+        assert!(web.resolve_redirect_uri(&uri).is_ok());
+    }
+}
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::str::FromStr;
+
 use chrono::{DateTime, Duration, Utc};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::traits::{StorageBackend, StorageBackendMarker};
 
@@ -24,6 +27,9 @@ pub struct User<T: StorageBackend> {
     pub username: String,
     pub sub: String,
     pub primary_email: Option<UserEmail<T>>,
+
+    /// Whether the user can administrate the service
+    pub is_admin: bool,
 }
 
 impl<T: StorageBackend> User<T>
@@ -37,6 +43,7 @@ where
             username: "john".to_string(),
             sub: "123-456".to_string(),
             primary_email: None,
+            is_admin: false,
         }]
     }
 }
@@ -48,6 +55,63 @@ impl<S: StorageBackendMarker> From<User<S>> for User<()> {
             username: u.username,
             sub: u.sub,
             primary_email: u.primary_email.map(Into::into),
+            is_admin: u.is_admin,
+        }
+    }
+}
+
+/// Authentication method reference values, as per RFC 8176
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuthenticationMethod {
+    /// Password-based authentication
+    #[serde(rename = "pwd")]
+    Password,
+
+    /// One-time password, e.g. TOTP
+    #[serde(rename = "otp")]
+    Otp,
+
+    /// Federated authentication, through an upstream provider
+    #[serde(rename = "fed")]
+    Federated,
+
+    /// Proof-of-possession of a key, through `WebAuthn`
+    #[serde(rename = "hwk")]
+    Webauthn,
+}
+
+impl AuthenticationMethod {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Password => "pwd",
+            Self::Otp => "otp",
+            Self::Federated => "fed",
+            Self::Webauthn => "hwk",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthenticationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown authentication method {0:?}")]
+pub struct UnknownAuthenticationMethod(String);
+
+impl FromStr for AuthenticationMethod {
+    type Err = UnknownAuthenticationMethod;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pwd" => Ok(Self::Password),
+            "otp" => Ok(Self::Otp),
+            "fed" => Ok(Self::Federated),
+            "hwk" => Ok(Self::Webauthn),
+            _ => Err(UnknownAuthenticationMethod(s.to_owned())),
         }
     }
 }
@@ -58,6 +122,7 @@ pub struct Authentication<T: StorageBackend> {
     #[serde(skip_serializing)]
     pub data: T::AuthenticationData,
     pub created_at: DateTime<Utc>,
+    pub methods: Vec<AuthenticationMethod>,
 }
 
 impl<S: StorageBackendMarker> From<Authentication<S>> for Authentication<()> {
@@ -65,7 +130,32 @@ impl<S: StorageBackendMarker> From<Authentication<S>> for Authentication<()> {
         Authentication {
             data: (),
             created_at: a.created_at,
+            methods: a.methods,
+        }
+    }
+}
+
+impl<T: StorageBackend> Authentication<T> {
+    /// Whether the user proved their identity with more than one factor
+    #[must_use]
+    pub fn is_multi_factor(&self) -> bool {
+        let second_factor = self.methods.iter().any(|method| {
+            matches!(
+                method,
+                AuthenticationMethod::Otp | AuthenticationMethod::Webauthn
+            )
+        });
+        second_factor && self.methods.len() > 1
+    }
+
+    /// Values of the `amr` claim describing this authentication
+    #[must_use]
+    pub fn amr(&self) -> Vec<String> {
+        let mut amr: Vec<String> = self.methods.iter().map(ToString::to_string).collect();
+        if self.is_multi_factor() {
+            amr.push("mfa".to_owned());
         }
+        amr
     }
 }
 
@@ -18,7 +18,9 @@ use lettre::{
     message::{Mailbox, MessageBuilder, MultiPart},
     AsyncTransport, Message,
 };
-use mas_templates::{EmailVerificationContext, Templates};
+use mas_templates::{
+    AccountLockedEmailContext, EmailVerificationContext, PasswordResetEmailContext, Templates,
+};
 
 use crate::MailTransport;
 
@@ -95,4 +97,77 @@ impl Mailer {
         self.transport.send(message).await?;
         Ok(())
     }
+
+    async fn prepare_password_reset_email(
+        &self,
+        to: Mailbox,
+        context: &PasswordResetEmailContext,
+    ) -> anyhow::Result<Message> {
+        let plain = self.templates.render_password_reset_txt(context).await?;
+
+        let html = self.templates.render_password_reset_html(context).await?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let message = self
+            .base_message()
+            // TODO: template/localize this
+            .subject("Reset your password")
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Send a password reset link to a user
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    pub async fn send_password_reset_email(
+        &self,
+        to: Mailbox,
+        context: &PasswordResetEmailContext,
+    ) -> anyhow::Result<()> {
+        let message = self.prepare_password_reset_email(to, context).await?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
+
+    async fn prepare_account_locked_email(
+        &self,
+        to: Mailbox,
+        context: &AccountLockedEmailContext,
+    ) -> anyhow::Result<Message> {
+        let plain = self.templates.render_account_locked_txt(context).await?;
+
+        let html = self.templates.render_account_locked_html(context).await?;
+
+        let multipart = MultiPart::alternative_plain_html(plain, html);
+
+        let message = self
+            .base_message()
+            // TODO: template/localize this
+            .subject("Too many failed sign-in attempts")
+            .to(to)
+            .multipart(multipart)?;
+
+        Ok(message)
+    }
+
+    /// Warn a user that their account got locked after too many failed login
+    /// attempts
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if the email failed rendering or failed sending
+    pub async fn send_account_locked_email(
+        &self,
+        to: Mailbox,
+        context: &AccountLockedEmailContext,
+    ) -> anyhow::Result<()> {
+        let message = self.prepare_account_locked_email(to, context).await?;
+        self.transport.send(message).await?;
+        Ok(())
+    }
 }
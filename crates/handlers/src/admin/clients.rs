@@ -0,0 +1,174 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    body::HttpBody,
+    extract::{Extension, Path, Query},
+    Json,
+};
+use hyper::StatusCode;
+use mas_axum_utils::user_authorization::UserAuthorization;
+use mas_data_model::JwksOrJwksUri;
+use mas_iana::oauth::OAuthClientAuthenticationMethod;
+use mas_storage::{
+    oauth2::client::{delete_client, list_clients, lookup_client_by_client_id},
+    PostgresqlBackend,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use url::Url;
+
+use super::{authorize, openapi::Operation, ApiMethod, ApiRouter, Page, Pagination, RouteError};
+
+/// An OAuth 2.0 client. Its secret is never exposed.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Client {
+    client_id: String,
+    client_name: Option<String>,
+    redirect_uris: Vec<Url>,
+    token_endpoint_auth_method: Option<OAuthClientAuthenticationMethod>,
+
+    /// Whether the client authenticates with a secret
+    has_client_secret: bool,
+
+    /// Whether the client has an inline JSON Web Key Set
+    has_jwks: bool,
+
+    jwks_uri: Option<Url>,
+}
+
+impl From<mas_data_model::Client<PostgresqlBackend>> for Client {
+    fn from(client: mas_data_model::Client<PostgresqlBackend>) -> Self {
+        let (has_jwks, jwks_uri) = match client.jwks {
+            Some(JwksOrJwksUri::Jwks(_)) => (true, None),
+            Some(JwksOrJwksUri::JwksUri(uri)) => (false, Some(uri)),
+            None => (false, None),
+        };
+
+        Self {
+            client_id: client.client_id,
+            client_name: client.client_name,
+            redirect_uris: client.redirect_uris,
+            token_endpoint_auth_method: client.token_endpoint_auth_method,
+            has_client_secret: client.encrypted_client_secret.is_some(),
+            has_jwks,
+            jwks_uri,
+        }
+    }
+}
+
+/// Parameters of routes on a client
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct ClientPath {
+    /// ID of the client
+    client_id: String,
+}
+
+pub(super) fn routes<B>(router: ApiRouter<B>) -> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    router
+        .route(
+            "/clients",
+            ApiMethod::Get,
+            list,
+            Operation::new("clients", "listClients", "List OAuth 2.0 clients")
+                .query::<Pagination>()
+                .response::<Page<Client>>(),
+        )
+        .route(
+            "/clients/:client_id",
+            ApiMethod::Get,
+            get,
+            Operation::new("clients", "getClient", "Get an OAuth 2.0 client")
+                .path::<ClientPath>()
+                .response::<Client>(),
+        )
+        .route(
+            "/clients/:client_id",
+            ApiMethod::Delete,
+            delete,
+            Operation::new(
+                "clients",
+                "deleteClient",
+                "Delete an OAuth 2.0 client, with its sessions and the consents given to it",
+            )
+            .path::<ClientPath>(),
+        )
+}
+
+/// Load the client targeted by a request
+pub(super) async fn load_client(
+    conn: &mut PgConnection,
+    client_id: &str,
+) -> Result<mas_data_model::Client<PostgresqlBackend>, RouteError> {
+    lookup_client_by_client_id(conn, client_id)
+        .await
+        .map_err(|e| {
+            if e.not_found() {
+                RouteError::NotFound("Client")
+            } else {
+                RouteError::Internal(e.into())
+            }
+        })
+}
+
+async fn list(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<Client>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let clients = list_clients(&mut conn)
+        .await
+        .map_err(|e| RouteError::Internal(e.into()))?
+        .into_iter()
+        .map(Client::from)
+        .collect();
+
+    Ok(Json(pagination.slice(clients)))
+}
+
+async fn get(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<ClientPath>,
+) -> Result<Json<Client>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let client = load_client(&mut conn, &path.client_id).await?;
+
+    Ok(Json(client.into()))
+}
+
+async fn delete(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<ClientPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let client = load_client(&mut conn, &path.client_id).await?;
+    delete_client(&mut conn, client).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,134 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    body::HttpBody,
+    extract::{Extension, Path, Query},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::user_authorization::UserAuthorization;
+use mas_storage::{
+    admin::{list_user_consents, ConsentSummary},
+    oauth2::consent::delete_client_consent,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{
+    authorize, clients::load_client, load_user, openapi::Operation, ApiMethod, ApiRouter, Page,
+    Pagination, RouteError, UserPath,
+};
+
+/// The scope a user consented to give to a client
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Consent {
+    client_id: String,
+    scope: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<ConsentSummary> for Consent {
+    fn from(consent: ConsentSummary) -> Self {
+        Self {
+            client_id: consent.client_id,
+            scope: consent.scope,
+            created_at: consent.created_at,
+            updated_at: consent.updated_at,
+        }
+    }
+}
+
+/// Parameters of routes on the consent of a user to a client
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct ConsentPath {
+    /// ID of the user
+    user_id: i64,
+
+    /// ID of the client
+    client_id: String,
+}
+
+pub(super) fn routes<B>(router: ApiRouter<B>) -> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    router
+        .route(
+            "/users/:user_id/consents",
+            ApiMethod::Get,
+            list,
+            Operation::new(
+                "consents",
+                "listUserConsents",
+                "List the clients a user consented to, and the scope they granted",
+            )
+            .path::<UserPath>()
+            .query::<Pagination>()
+            .response::<Page<Consent>>(),
+        )
+        .route(
+            "/users/:user_id/consents/:client_id",
+            ApiMethod::Delete,
+            revoke,
+            Operation::new(
+                "consents",
+                "revokeUserConsent",
+                "Forget the consent a user gave to a client, so that they are asked again",
+            )
+            .path::<ConsentPath>(),
+        )
+}
+
+async fn list(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<Consent>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let consents = list_user_consents(&mut conn, &user)
+        .await?
+        .into_iter()
+        .map(Consent::from)
+        .collect();
+
+    Ok(Json(pagination.slice(consents)))
+}
+
+async fn revoke(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<ConsentPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let client = load_client(&mut conn, &path.client_id).await?;
+
+    if !delete_client_consent(&mut conn, &user, &client).await? {
+        return Err(RouteError::NotFound("Consent"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,192 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    body::HttpBody,
+    extract::{Extension, Path, Query},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use lettre::Address;
+use mas_axum_utils::user_authorization::UserAuthorization;
+use mas_data_model::UserEmail;
+use mas_storage::{
+    user::{
+        add_user_email, get_user_email, get_user_emails, mark_user_email_as_verified,
+        remove_user_email, set_user_email_as_primary,
+    },
+    PostgresqlBackend,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{
+    authorize, load_user, openapi::Operation, ApiMethod, ApiRouter, Page, Pagination, RouteError,
+    UserItemPath, UserPath,
+};
+
+/// An email address of a user
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Email {
+    id: i64,
+    email: String,
+
+    /// Whether this is the primary email address of the user
+    primary: bool,
+
+    created_at: DateTime<Utc>,
+    confirmed_at: Option<DateTime<Utc>>,
+}
+
+impl Email {
+    fn new(email: UserEmail<PostgresqlBackend>, primary_id: Option<i64>) -> Self {
+        Self {
+            id: email.data,
+            primary: primary_id == Some(email.data),
+            email: email.email,
+            created_at: email.created_at,
+            confirmed_at: email.confirmed_at,
+        }
+    }
+}
+
+/// An email address to add to a user
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct AddEmailRequest {
+    email: String,
+
+    /// Mark the email address as verified
+    #[serde(default)]
+    verified: bool,
+
+    /// Make it the primary email address of the user
+    #[serde(default)]
+    primary: bool,
+}
+
+pub(super) fn routes<B>(router: ApiRouter<B>) -> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    router
+        .route(
+            "/users/:user_id/emails",
+            ApiMethod::Get,
+            list,
+            Operation::new(
+                "emails",
+                "listUserEmails",
+                "List the email addresses of a user",
+            )
+            .path::<UserPath>()
+            .query::<Pagination>()
+            .response::<Page<Email>>(),
+        )
+        .route(
+            "/users/:user_id/emails",
+            ApiMethod::Post,
+            add,
+            Operation::new("emails", "addUserEmail", "Add an email address to a user")
+                .path::<UserPath>()
+                .body::<AddEmailRequest>()
+                .response::<Email>(),
+        )
+        .route(
+            "/users/:user_id/emails/:id",
+            ApiMethod::Delete,
+            remove,
+            Operation::new(
+                "emails",
+                "removeUserEmail",
+                "Remove an email address of a user",
+            )
+            .path::<UserItemPath>(),
+        )
+}
+
+async fn list(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<Email>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let primary_id = user.primary_email.as_ref().map(|email| email.data);
+    let emails = get_user_emails(&mut conn, &user)
+        .await?
+        .into_iter()
+        .map(|email| Email::new(email, primary_id))
+        .collect();
+
+    Ok(Json(pagination.slice(emails)))
+}
+
+async fn add(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Json(request): Json<AddEmailRequest>,
+) -> Result<Json<Email>, RouteError> {
+    if request.email.parse::<Address>().is_err() {
+        return Err(RouteError::BadRequest(format!(
+            "{:?} is not a valid email address",
+            request.email
+        )));
+    }
+
+    let mut txn = pool.begin().await?;
+    authorize(&mut txn, user_authorization).await?;
+
+    let user = load_user(&mut txn, path.user_id).await?;
+    let mut email = add_user_email(&mut txn, &user, request.email).await?;
+    if request.verified {
+        email = mark_user_email_as_verified(&mut txn, email).await?;
+    }
+
+    let mut primary_id = user.primary_email.as_ref().map(|email| email.data);
+    if request.primary {
+        set_user_email_as_primary(&mut txn, &email).await?;
+        primary_id = Some(email.data);
+    }
+
+    txn.commit().await?;
+
+    Ok(Json(Email::new(email, primary_id)))
+}
+
+async fn remove(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserItemPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut txn = pool.begin().await?;
+    authorize(&mut txn, user_authorization).await?;
+
+    let user = load_user(&mut txn, path.user_id).await?;
+    let email = get_user_email(&mut txn, &user, path.id)
+        .await
+        .map_err(|e| RouteError::not_found_or(e, "Email"))?;
+    remove_user_email(&mut txn, email).await?;
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,344 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Administrative REST API, protected by the [`scope::ADMIN`] scope and only
+//! usable by administrators.
+//!
+//! Routes are registered through an [`ApiRouter`], which keeps a description
+//! of each operation, so that the API description served at `/openapi.json`
+//! always matches the actual routes.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use axum::{
+    body::HttpBody,
+    extract::Extension,
+    handler::Handler,
+    response::{IntoResponse, Response},
+    routing::{get, MethodFilter, MethodRouter},
+    Json, Router,
+};
+use hyper::StatusCode;
+use mas_axum_utils::user_authorization::{AuthorizationVerificationError, UserAuthorization};
+use mas_data_model::User;
+use mas_router::UrlBuilder;
+use mas_storage::PostgresqlBackend;
+use oauth2_types::scope;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use self::openapi::{Document, Operation};
+
+mod clients;
+mod consents;
+mod emails;
+mod openapi;
+mod sessions;
+mod users;
+
+/// Default number of items per page
+const DEFAULT_LIMIT: i64 = 50;
+
+/// Maximum number of items per page
+const MAX_LIMIT: i64 = 500;
+
+/// An error, as returned by the API
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ApiError {
+    /// A short code identifying the kind of error
+    error: &'static str,
+
+    /// A human readable description of the error
+    error_description: String,
+}
+
+pub(crate) enum RouteError {
+    /// The request is not authorized, or the access token lacks the admin
+    /// scope
+    Authorization(Response),
+
+    /// The user behind the access token is not an administrator
+    NotAdmin,
+
+    NotFound(&'static str),
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl From<AuthorizationVerificationError> for RouteError {
+    fn from(e: AuthorizationVerificationError) -> Self {
+        // The error is not `Send`, so it is turned into a response right away
+        Self::Authorization(e.into_response())
+    }
+}
+
+impl From<sqlx::Error> for RouteError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+impl From<anyhow::Error> for RouteError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+impl RouteError {
+    /// Turn an error from the storage into a [`RouteError::NotFound`] if it
+    /// comes from a missing row
+    fn not_found_or(e: anyhow::Error, what: &'static str) -> Self {
+        match e.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::RowNotFound) => Self::NotFound(what),
+            _ => Self::Internal(e),
+        }
+    }
+}
+
+impl IntoResponse for RouteError {
+    fn into_response(self) -> Response {
+        let (status, error, error_description) = match self {
+            Self::Authorization(res) => return res,
+            Self::NotAdmin => (
+                StatusCode::FORBIDDEN,
+                "not_admin",
+                "This API can only be used by administrators".to_owned(),
+            ),
+            Self::NotFound(what) => (
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("{} not found", what),
+            ),
+            Self::BadRequest(description) => (StatusCode::BAD_REQUEST, "bad_request", description),
+            Self::Internal(e) => {
+                tracing::error!(error = ?e, "Admin API request failed");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "server_error",
+                    "The server encountered an internal error".to_owned(),
+                )
+            }
+        };
+
+        let body = ApiError {
+            error,
+            error_description,
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Check that the access token has the admin scope and belongs to an
+/// administrator. Returns that administrator.
+async fn authorize(
+    conn: &mut PgConnection,
+    user_authorization: UserAuthorization,
+) -> Result<User<PostgresqlBackend>, RouteError> {
+    let session = user_authorization
+        .protected_with_scope(conn, &scope::ADMIN)
+        .await?;
+
+    let user = session.browser_session.user;
+    if !user.is_admin {
+        return Err(RouteError::NotAdmin);
+    }
+
+    Ok(user)
+}
+
+/// Pagination parameters of listings
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct Pagination {
+    /// Maximum number of items to return, 50 by default, and at most 500
+    limit: Option<i64>,
+
+    /// Number of items to skip
+    offset: Option<i64>,
+}
+
+impl Pagination {
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+
+    fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    /// Paginate a listing which was fetched with one more item than the limit
+    fn page<T>(&self, mut data: Vec<T>) -> Page<T> {
+        let limit = self.limit();
+        let offset = self.offset();
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let next_offset = if data.len() > limit as usize {
+            data.truncate(limit as usize);
+            Some(offset + limit)
+        } else {
+            None
+        };
+
+        Page { data, next_offset }
+    }
+
+    /// Paginate a listing which was fully fetched
+    fn slice<T>(&self, data: Vec<T>) -> Page<T> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let data = data
+            .into_iter()
+            .skip(self.offset() as usize)
+            .take(self.limit() as usize + 1)
+            .collect();
+
+        self.page(data)
+    }
+}
+
+/// A page of a listing
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Page<T> {
+    data: Vec<T>,
+
+    /// Offset of the next page, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<i64>,
+}
+
+/// HTTP methods used by the routes of the API
+#[derive(Clone, Copy)]
+pub(crate) enum ApiMethod {
+    Get,
+    Post,
+    Delete,
+}
+
+impl ApiMethod {
+    fn filter(self) -> MethodFilter {
+        match self {
+            Self::Get => MethodFilter::GET,
+            Self::Post => MethodFilter::POST,
+            Self::Delete => MethodFilter::DELETE,
+        }
+    }
+
+    /// Name of the method, as used in the `OpenAPI` paths
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Get => "get",
+            Self::Post => "post",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// Registers the routes of the API along with a description of them
+pub(crate) struct ApiRouter<B> {
+    routes: BTreeMap<&'static str, MethodRouter<B>>,
+    document: Document,
+}
+
+impl<B> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+{
+    fn new() -> Self {
+        Self {
+            routes: BTreeMap::new(),
+            document: Document::new(),
+        }
+    }
+
+    fn route<H, T>(
+        mut self,
+        path: &'static str,
+        method: ApiMethod,
+        handler: H,
+        operation: Operation,
+    ) -> Self
+    where
+        H: Handler<T, B>,
+        T: 'static,
+    {
+        self.document.add(path, method, operation);
+        let route = self.routes.remove(path).unwrap_or_default();
+        self.routes.insert(path, route.on(method.filter(), handler));
+        self
+    }
+
+    fn into_router(self) -> Router<B> {
+        let document = Arc::new(self.document.build());
+        let openapi = move |Extension(url_builder): Extension<UrlBuilder>| async move {
+            let mut document = (*document).clone();
+            document["servers"] = serde_json::json!([{ "url": url_builder.admin_api() }]);
+            Json(document)
+        };
+
+        self.routes
+            .into_iter()
+            .fold(Router::new(), |router, (path, route)| {
+                router.route(path, route)
+            })
+            .route("/openapi.json", get(openapi))
+    }
+}
+
+pub fn router<B>() -> Router<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    let router = ApiRouter::new();
+    let router = users::routes(router);
+    let router = emails::routes(router);
+    let router = sessions::routes(router);
+    let router = consents::routes(router);
+    let router = clients::routes(router);
+    router.into_router()
+}
+
+/// Parameters of routes on a user
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct UserPath {
+    /// ID of the user
+    user_id: i64,
+}
+
+/// Parameters of routes on an item belonging to a user
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct UserItemPath {
+    /// ID of the user
+    user_id: i64,
+
+    /// ID of the item
+    id: i64,
+}
+
+/// Load the user targeted by a request
+async fn load_user(
+    conn: &mut PgConnection,
+    user_id: i64,
+) -> Result<User<PostgresqlBackend>, RouteError> {
+    mas_storage::user::lookup_user(conn, user_id)
+        .await
+        .map_err(|e| {
+            if e.not_found() {
+                RouteError::NotFound("User")
+            } else {
+                RouteError::Internal(e.into())
+            }
+        })
+}
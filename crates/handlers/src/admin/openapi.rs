@@ -0,0 +1,194 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Generation of the description of the admin API, in the `OpenAPI` format
+
+use schemars::{
+    gen::{SchemaGenerator, SchemaSettings},
+    schema::Schema,
+    JsonSchema,
+};
+use serde_json::{json, Map, Value};
+
+use super::{ApiError, ApiMethod};
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+/// Description of an operation of the API
+pub(crate) struct Operation {
+    tag: &'static str,
+    id: &'static str,
+    summary: &'static str,
+    parameters: Vec<(&'static str, SchemaFn)>,
+    body: Option<SchemaFn>,
+    response: Option<SchemaFn>,
+}
+
+impl Operation {
+    /// Describe an operation, which responds with a `204 No Content` unless
+    /// a response is set with [`Operation::response`]
+    pub fn new(tag: &'static str, id: &'static str, summary: &'static str) -> Self {
+        Self {
+            tag,
+            id,
+            summary,
+            parameters: Vec::new(),
+            body: None,
+            response: None,
+        }
+    }
+
+    /// Parameters extracted from the path, with the fields of `T`
+    pub fn path<T: JsonSchema>(mut self) -> Self {
+        self.parameters.push(("path", T::json_schema));
+        self
+    }
+
+    /// Parameters extracted from the query string, with the fields of `T`
+    pub fn query<T: JsonSchema>(mut self) -> Self {
+        self.parameters.push(("query", T::json_schema));
+        self
+    }
+
+    /// JSON request body
+    pub fn body<T: JsonSchema>(mut self) -> Self {
+        self.body = Some(SchemaGenerator::subschema_for::<T>);
+        self
+    }
+
+    /// JSON response body
+    pub fn response<T: JsonSchema>(mut self) -> Self {
+        self.response = Some(SchemaGenerator::subschema_for::<T>);
+        self
+    }
+
+    fn build(self, gen: &mut SchemaGenerator) -> Value {
+        let mut parameters = Vec::new();
+        for (location, schema_fn) in self.parameters {
+            let schema = schema_fn(gen).into_object();
+            let object = match schema.object {
+                Some(object) => object,
+                None => continue,
+            };
+
+            for (name, property) in object.properties {
+                let mut property = property.into_object();
+                let description = property
+                    .metadata
+                    .as_mut()
+                    .and_then(|metadata| metadata.description.take());
+                let required = location == "path" || object.required.contains(&name);
+
+                parameters.push(json!({
+                    "name": name,
+                    "in": location,
+                    "required": required,
+                    "description": description,
+                    "schema": property,
+                }));
+            }
+        }
+
+        let success = match self.response {
+            Some(schema_fn) => json!({
+                "200": {
+                    "description": "OK",
+                    "content": { "application/json": { "schema": schema_fn(gen) } },
+                },
+            }),
+            None => json!({ "204": { "description": "No Content" } }),
+        };
+
+        let mut responses = success;
+        responses["default"] = json!({
+            "description": "Error",
+            "content": {
+                "application/json": { "schema": gen.subschema_for::<ApiError>() },
+            },
+        });
+
+        let mut operation = json!({
+            "tags": [self.tag],
+            "operationId": self.id,
+            "summary": self.summary,
+            "parameters": parameters,
+            "responses": responses,
+        });
+
+        if let Some(schema_fn) = self.body {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": schema_fn(gen) } },
+            });
+        }
+
+        operation
+    }
+}
+
+/// API description being built
+pub(crate) struct Document {
+    gen: SchemaGenerator,
+    paths: Map<String, Value>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self {
+            gen: SchemaSettings::openapi3().into_generator(),
+            paths: Map::new(),
+        }
+    }
+
+    pub fn add(&mut self, path: &str, method: ApiMethod, operation: Operation) {
+        // Path parameters are `:name` in axum, and `{name}` in OpenAPI
+        let path = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(name) => format!("{{{}}}", name),
+                None => segment.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let operation = operation.build(&mut self.gen);
+        let item = self
+            .paths
+            .entry(path)
+            .or_insert_with(|| Value::Object(Map::new()));
+        item[method.as_str()] = operation;
+    }
+
+    /// Build the document. The `servers` are set when serving it.
+    pub fn build(mut self) -> Value {
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Matrix Authentication Service admin API",
+                "description": "Administrate the users of the service and their sessions. \
+                    Requests must be authenticated with an access token of an administrator, \
+                    which was granted the `urn:mas:admin` scope.",
+                "version": "1",
+            },
+            "security": [{ "bearer": [] }],
+            "paths": self.paths,
+            "components": {
+                "securitySchemes": {
+                    "bearer": { "type": "http", "scheme": "bearer" },
+                },
+                "schemas": self.gen.take_definitions(),
+            },
+        })
+    }
+}
@@ -0,0 +1,211 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    body::HttpBody,
+    extract::{Extension, Path, Query},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::user_authorization::UserAuthorization;
+use mas_storage::admin::{
+    end_user_oauth2_session, end_user_session, list_user_oauth2_sessions, list_user_sessions,
+    OAuth2SessionSummary, UserSessionSummary,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use super::{
+    authorize, load_user, openapi::Operation, ApiMethod, ApiRouter, Page, Pagination, RouteError,
+    UserItemPath, UserPath,
+};
+
+/// A browser session of a user
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct BrowserSession {
+    id: i64,
+    active: bool,
+    created_at: DateTime<Utc>,
+    last_authenticated_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserSessionSummary> for BrowserSession {
+    fn from(session: UserSessionSummary) -> Self {
+        Self {
+            id: session.id,
+            active: session.active,
+            created_at: session.created_at,
+            last_authenticated_at: session.last_authenticated_at,
+        }
+    }
+}
+
+/// A session of an OAuth 2.0 client, started from a browser session
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct OAuth2Session {
+    id: i64,
+
+    /// ID of the browser session it was started from
+    browser_session_id: i64,
+
+    client_id: String,
+    scope: String,
+    created_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+}
+
+impl From<OAuth2SessionSummary> for OAuth2Session {
+    fn from(session: OAuth2SessionSummary) -> Self {
+        Self {
+            id: session.id,
+            browser_session_id: session.user_session_id,
+            client_id: session.client_id,
+            scope: session.scope,
+            created_at: session.created_at,
+            ended_at: session.ended_at,
+        }
+    }
+}
+
+pub(super) fn routes<B>(router: ApiRouter<B>) -> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    router
+        .route(
+            "/users/:user_id/browser-sessions",
+            ApiMethod::Get,
+            list_browser_sessions,
+            Operation::new(
+                "sessions",
+                "listUserBrowserSessions",
+                "List the browser sessions of a user",
+            )
+            .path::<UserPath>()
+            .query::<Pagination>()
+            .response::<Page<BrowserSession>>(),
+        )
+        .route(
+            "/users/:user_id/browser-sessions/:id",
+            ApiMethod::Delete,
+            end_browser_session,
+            Operation::new(
+                "sessions",
+                "endUserBrowserSession",
+                "End an active browser session of a user, and the OAuth 2.0 sessions started \
+                 from it",
+            )
+            .path::<UserItemPath>(),
+        )
+        .route(
+            "/users/:user_id/oauth2-sessions",
+            ApiMethod::Get,
+            list_oauth2_sessions,
+            Operation::new(
+                "sessions",
+                "listUserOAuth2Sessions",
+                "List the OAuth 2.0 sessions of a user",
+            )
+            .path::<UserPath>()
+            .query::<Pagination>()
+            .response::<Page<OAuth2Session>>(),
+        )
+        .route(
+            "/users/:user_id/oauth2-sessions/:id",
+            ApiMethod::Delete,
+            end_oauth2_session,
+            Operation::new(
+                "sessions",
+                "endUserOAuth2Session",
+                "End an active OAuth 2.0 session of a user",
+            )
+            .path::<UserItemPath>(),
+        )
+}
+
+async fn list_browser_sessions(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<BrowserSession>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let sessions = list_user_sessions(&mut conn, &user)
+        .await?
+        .into_iter()
+        .map(BrowserSession::from)
+        .collect();
+
+    Ok(Json(pagination.slice(sessions)))
+}
+
+async fn end_browser_session(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserItemPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut txn = pool.begin().await?;
+    authorize(&mut txn, user_authorization).await?;
+
+    let user = load_user(&mut txn, path.user_id).await?;
+    if !end_user_session(&mut txn, &user, path.id).await? {
+        return Err(RouteError::NotFound("Active browser session"));
+    }
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_oauth2_sessions(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<OAuth2Session>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let sessions = list_user_oauth2_sessions(&mut conn, &user)
+        .await?
+        .into_iter()
+        .map(OAuth2Session::from)
+        .collect();
+
+    Ok(Json(pagination.slice(sessions)))
+}
+
+async fn end_oauth2_session(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserItemPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    if !end_user_oauth2_session(&mut conn, &user, path.id).await? {
+        return Err(RouteError::NotFound("Active OAuth 2.0 session"));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,273 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    body::HttpBody,
+    extract::{Extension, Path, Query},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use hyper::StatusCode;
+use mas_axum_utils::user_authorization::UserAuthorization;
+use mas_storage::{
+    admin::{list_users, UserFilter, UserSummary},
+    login_throttle::{clear_login_throttle, ThrottleScope},
+    suspension::{lift_user_suspensions, suspend_user},
+    user::{deactivate_user, erase_user},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use super::{
+    authorize, load_user, openapi::Operation, ApiMethod, ApiRouter, Page, Pagination, RouteError,
+    UserPath,
+};
+
+/// A user
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct User {
+    id: i64,
+    username: String,
+    primary_email: Option<String>,
+    has_verified_email: bool,
+
+    /// Whether the user is currently suspended
+    locked: bool,
+
+    is_admin: bool,
+    created_at: DateTime<Utc>,
+    deactivated_at: Option<DateTime<Utc>>,
+}
+
+impl From<UserSummary> for User {
+    fn from(user: UserSummary) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            primary_email: user.primary_email,
+            has_verified_email: user.has_verified_email,
+            locked: user.locked,
+            is_admin: user.is_admin,
+            created_at: user.created_at,
+            deactivated_at: user.deactivated_at,
+        }
+    }
+}
+
+/// Filters of the user listing
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct Filter {
+    /// Only users whose username or primary email contains this, regardless of
+    /// the case
+    search: Option<String>,
+
+    /// Only users created since this date
+    created_since: Option<DateTime<Utc>>,
+
+    /// Only users with, or without, a verified email
+    has_verified_email: Option<bool>,
+
+    /// Only users who are, or are not, suspended
+    locked: Option<bool>,
+
+    /// Only users who are, or are not, deactivated
+    deactivated: Option<bool>,
+}
+
+/// Suspension of a user
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct LockRequest {
+    /// Reason of the suspension, shown to the user
+    reason: String,
+
+    /// Lift the suspension automatically after this many seconds
+    expires_in: Option<u32>,
+}
+
+/// Deactivation of a user
+#[derive(Deserialize, JsonSchema)]
+pub(crate) struct DeactivateRequest {
+    /// Also erase the personal data of the user
+    #[serde(default)]
+    erase: bool,
+}
+
+pub(super) fn routes<B>(router: ApiRouter<B>) -> ApiRouter<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    router
+        .route(
+            "/users",
+            ApiMethod::Get,
+            list,
+            Operation::new("users", "listUsers", "List users")
+                .query::<Filter>()
+                .query::<Pagination>()
+                .response::<Page<User>>(),
+        )
+        .route(
+            "/users/:user_id",
+            ApiMethod::Get,
+            get,
+            Operation::new("users", "getUser", "Get a user")
+                .path::<UserPath>()
+                .response::<User>(),
+        )
+        .route(
+            "/users/:user_id/lock",
+            ApiMethod::Post,
+            lock,
+            Operation::new(
+                "users",
+                "lockUser",
+                "Suspend a user, preventing them from logging in and using their tokens",
+            )
+            .path::<UserPath>()
+            .body::<LockRequest>(),
+        )
+        .route(
+            "/users/:user_id/unlock",
+            ApiMethod::Post,
+            unlock,
+            Operation::new(
+                "users",
+                "unlockUser",
+                "Lift the suspension of a user, and the lockout of their account after too many \
+                 failed sign in attempts",
+            )
+            .path::<UserPath>(),
+        )
+        .route(
+            "/users/:user_id/deactivate",
+            ApiMethod::Post,
+            deactivate,
+            Operation::new(
+                "users",
+                "deactivateUser",
+                "Deactivate a user and end all their sessions",
+            )
+            .path::<UserPath>()
+            .body::<DeactivateRequest>(),
+        )
+}
+
+async fn list(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Query(filter): Query<Filter>,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Page<User>>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let filter = UserFilter {
+        id: None,
+        search: filter.search,
+        created_since: filter.created_since,
+        has_verified_email: filter.has_verified_email,
+        locked: filter.locked,
+        deactivated: filter.deactivated,
+    };
+
+    // Fetch one more user to know if there is a next page
+    let users = list_users(
+        &mut conn,
+        &filter,
+        pagination.limit() + 1,
+        pagination.offset(),
+    )
+    .await?;
+    let users = users.into_iter().map(User::from).collect();
+
+    Ok(Json(pagination.page(users)))
+}
+
+async fn get(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+) -> Result<Json<User>, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let filter = UserFilter {
+        id: Some(path.user_id),
+        ..UserFilter::default()
+    };
+
+    let user = list_users(&mut conn, &filter, 1, 0)
+        .await?
+        .pop()
+        .ok_or(RouteError::NotFound("User"))?;
+
+    Ok(Json(user.into()))
+}
+
+async fn lock(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Json(request): Json<LockRequest>,
+) -> Result<StatusCode, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    let expires_at = request
+        .expires_in
+        .map(|seconds| Utc::now() + Duration::seconds(seconds.into()));
+    suspend_user(&mut conn, &user, &request.reason, expires_at).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unlock(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+) -> Result<StatusCode, RouteError> {
+    let mut conn = pool.acquire().await?;
+    authorize(&mut conn, user_authorization).await?;
+
+    let user = load_user(&mut conn, path.user_id).await?;
+    lift_user_suspensions(&mut conn, &user).await?;
+    clear_login_throttle(&mut conn, ThrottleScope::Account, &user.username).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn deactivate(
+    Extension(pool): Extension<PgPool>,
+    user_authorization: UserAuthorization,
+    Path(path): Path<UserPath>,
+    Json(request): Json<DeactivateRequest>,
+) -> Result<StatusCode, RouteError> {
+    let mut txn = pool.begin().await?;
+    authorize(&mut txn, user_authorization).await?;
+
+    let user = load_user(&mut txn, path.user_id).await?;
+    if request.erase {
+        erase_user(&mut txn, &user).await?;
+    } else {
+        deactivate_user(&mut txn, &user).await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,224 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline check of passwords against known data breaches
+//!
+//! The dataset is a file starting with a magic header, followed by the sorted
+//! first 8 bytes of the SHA-1 digest of each breached password, as big-endian
+//! integers. Truncating the digests keeps the file small, with a negligible
+//! chance of false positives.
+
+use std::{io::BufRead, path::Path, sync::Arc};
+
+use anyhow::Context;
+use data_encoding::HEXLOWER_PERMISSIVE;
+use sha1::{Digest, Sha1};
+
+const MAGIC: &[u8; 8] = b"MASBPW01";
+
+/// A set of passwords known to be part of data breaches
+#[derive(Debug, Clone, Default)]
+pub struct BreachedPasswords {
+    prefixes: Arc<Vec<u64>>,
+}
+
+fn prefix(digest: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+impl BreachedPasswords {
+    /// Build a dataset from a list of breached passwords, one per line.
+    ///
+    /// Unless `plaintext` is set, lines are hex-encoded SHA-1 digests,
+    /// optionally followed by a colon and the number of times it was seen, as
+    /// distributed by Have I Been Pwned. Digests seen less than `min_count`
+    /// times are skipped. Empty lines are ignored in both modes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list can't be read or has invalid lines
+    pub fn build(input: impl BufRead, plaintext: bool, min_count: u64) -> anyhow::Result<Self> {
+        let mut prefixes = Vec::new();
+
+        for (number, line) in input.lines().enumerate() {
+            let line = line.context("could not read the password list")?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+
+            if plaintext {
+                prefixes.push(prefix(&Sha1::digest(line.as_bytes())));
+                continue;
+            }
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (digest, count) = match line.split_once(':') {
+                Some((digest, count)) => {
+                    let count: u64 = count
+                        .parse()
+                        .with_context(|| format!("invalid count on line {}", number + 1))?;
+                    (digest, count)
+                }
+                None => (line, u64::MAX),
+            };
+
+            if count < min_count {
+                continue;
+            }
+
+            let digest = HEXLOWER_PERMISSIVE
+                .decode(digest.as_bytes())
+                .ok()
+                .filter(|d| d.len() == 20)
+                .with_context(|| format!("invalid SHA-1 digest on line {}", number + 1))?;
+            prefixes.push(prefix(&digest));
+        }
+
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        Ok(Self {
+            prefixes: Arc::new(prefixes),
+        })
+    }
+
+    /// Load a dataset previously saved with [`BreachedPasswords::save`]
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be read or is not a valid dataset
+    pub async fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path).await.with_context(|| {
+            format!("could not read breached passwords from {}", path.display())
+        })?;
+        Self::from_bytes(&bytes)
+            .with_context(|| format!("invalid breached passwords dataset {}", path.display()))
+    }
+
+    /// Save the dataset to a file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can't be written
+    pub async fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        tokio::fs::write(path, self.to_bytes())
+            .await
+            .with_context(|| format!("could not write breached passwords to {}", path.display()))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let records = bytes
+            .strip_prefix(MAGIC)
+            .context("missing dataset header")?;
+
+        if records.len() % 8 != 0 {
+            anyhow::bail!("truncated dataset");
+        }
+
+        let prefixes: Vec<u64> = records.chunks_exact(8).map(prefix).collect();
+
+        // Lookups rely on the records being sorted
+        if prefixes.windows(2).any(|w| w[0] >= w[1]) {
+            anyhow::bail!("dataset records are not sorted");
+        }
+
+        Ok(Self {
+            prefixes: Arc::new(prefixes),
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(MAGIC.len() + self.prefixes.len() * 8);
+        bytes.extend_from_slice(MAGIC);
+        for prefix in self.prefixes.iter() {
+            bytes.extend_from_slice(&prefix.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Number of passwords in the dataset
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    /// Whether the dataset is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    /// Check if a password is part of the dataset
+    #[must_use]
+    pub fn contains(&self, password: &str) -> bool {
+        let prefix = prefix(&Sha1::digest(password.as_bytes()));
+        self.prefixes.binary_search(&prefix).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_from_plaintext() {
+        let list = "password\nhunter2\n\n123456\n\r\n";
+        let breached = BreachedPasswords::build(list.as_bytes(), true, 0).unwrap();
+
+        // Empty lines are not hashed as the empty password
+        assert_eq!(breached.len(), 3);
+        assert!(!breached.contains(""));
+        assert!(breached.contains("hunter2"));
+        assert!(!breached.contains("Hunter2"));
+    }
+
+    #[test]
+    fn build_from_digests() {
+        // SHA-1 of "password" and "hunter2"
+        let list = "5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+                    F3BBBD66A63D4BF1747940578EC3D0103530E21D:17\r\n";
+
+        let breached = BreachedPasswords::build(list.as_bytes(), false, 0).unwrap();
+        assert!(breached.contains("password"));
+        assert!(breached.contains("hunter2"));
+
+        let breached = BreachedPasswords::build(list.as_bytes(), false, 100).unwrap();
+        assert!(breached.contains("password"));
+        assert!(!breached.contains("hunter2"));
+
+        assert!(BreachedPasswords::build("not a digest".as_bytes(), false, 0).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let list = "password\nhunter2\n";
+        let breached = BreachedPasswords::build(list.as_bytes(), true, 0).unwrap();
+
+        let bytes = breached.to_bytes();
+        let loaded = BreachedPasswords::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.prefixes, breached.prefixes);
+
+        assert!(BreachedPasswords::from_bytes(&bytes[..12]).is_err());
+        assert!(BreachedPasswords::from_bytes(&bytes[8..]).is_err());
+    }
+}
@@ -0,0 +1,423 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Slows down password and second factor code guessing on the login and
+//! reauth screens, by counting failed attempts per account and per IP address.
+//! Password reset requests are counted the same way, per email address and
+//! per IP address.
+
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequest, RequestParts},
+};
+use chrono::{DateTime, Utc};
+use lettre::{message::Mailbox, Address};
+use mas_config::{AttemptLimitsConfig, BruteForceConfig};
+use mas_data_model::errors::HtmlError;
+use mas_email::Mailer;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    login_throttle::{
+        clear_login_throttle, lock_login_throttle, lookup_login_throttle, record_login_failure,
+        LoginThrottle, ThrottleScope,
+    },
+    user::lookup_user_by_username,
+};
+use mas_templates::AccountLockedEmailContext;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use thiserror::Error;
+
+/// Why an attempt was refused before even checking the password
+#[derive(Debug, Error)]
+pub(crate) enum Throttled {
+    #[error("too many failed attempts, locked until {0}")]
+    Locked(DateTime<Utc>),
+
+    #[error("too many failed attempts, retry in {0} seconds")]
+    Backoff(i64),
+}
+
+impl HtmlError for Throttled {
+    fn html_display(&self) -> String {
+        match self {
+            Self::Locked(until) => format!(
+                "Too many failed attempts. Signing in is blocked until {}",
+                until.format("%Y-%m-%d %H:%M UTC")
+            ),
+            Self::Backoff(seconds) => {
+                format!("Too many failed attempts. Try again in {} seconds", seconds)
+            }
+        }
+    }
+}
+
+/// Counts failed password attempts and refuses new ones when there were too
+/// many
+#[derive(Debug, Clone)]
+pub struct BruteForceProtection {
+    config: BruteForceConfig,
+}
+
+impl BruteForceProtection {
+    #[must_use]
+    pub fn new(config: &BruteForceConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// What the attempts of a user are counted against
+    fn scopes(
+        &self,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> Vec<(ThrottleScope, String, &AttemptLimitsConfig)> {
+        let mut scopes = vec![(
+            ThrottleScope::Account,
+            username.to_string(),
+            &self.config.account,
+        )];
+        if let Some(ip) = ip {
+            scopes.push((ThrottleScope::Ip, ip.to_string(), &self.config.ip));
+        }
+        scopes
+    }
+
+    /// What the password reset requests for an address are counted against
+    fn password_reset_scopes(
+        &self,
+        address: &str,
+        ip: Option<IpAddr>,
+    ) -> Vec<(ThrottleScope, String, &AttemptLimitsConfig)> {
+        let mut scopes = vec![(
+            ThrottleScope::ResetAddress,
+            address.to_lowercase(),
+            &self.config.password_reset,
+        )];
+        if let Some(ip) = ip {
+            scopes.push((ThrottleScope::ResetIp, ip.to_string(), &self.config.ip));
+        }
+        scopes
+    }
+
+    /// When the next attempt is allowed, given the previous failures
+    fn next_attempt(
+        &self,
+        throttle: &LoginThrottle,
+        limits: &AttemptLimitsConfig,
+    ) -> Option<DateTime<Utc>> {
+        if let Some(locked_until) = throttle.locked_until {
+            if locked_until > Utc::now() {
+                return Some(locked_until);
+            }
+        }
+
+        if throttle.last_failure_at + self.config.forget_after < Utc::now() {
+            return None;
+        }
+
+        let over = i64::from(throttle.failures) - i64::from(limits.free_attempts);
+        if over < 0 {
+            return None;
+        }
+
+        // The wait time doubles after each failure, up to the configured maximum
+        let delay = self.config.backoff_base * 2_i32.pow(u32::try_from(over.min(16)).unwrap_or(16));
+        let delay = delay.min(self.config.backoff_max);
+        Some(throttle.last_failure_at + delay)
+    }
+
+    /// Check if a new attempt is allowed right now
+    pub(crate) async fn check(
+        &self,
+        conn: &mut PgConnection,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<Option<Throttled>> {
+        self.check_scopes(conn, self.scopes(username, ip)).await
+    }
+
+    /// Check if a new password reset link can be sent to the address right now
+    pub(crate) async fn check_password_reset(
+        &self,
+        conn: &mut PgConnection,
+        address: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<Option<Throttled>> {
+        self.check_scopes(conn, self.password_reset_scopes(address, ip))
+            .await
+    }
+
+    async fn check_scopes(
+        &self,
+        conn: &mut PgConnection,
+        scopes: Vec<(ThrottleScope, String, &AttemptLimitsConfig)>,
+    ) -> anyhow::Result<Option<Throttled>> {
+        if !self.config.enabled {
+            return Ok(None);
+        }
+
+        let now = Utc::now();
+        for (scope, key, limits) in scopes {
+            let throttle = match lookup_login_throttle(&mut *conn, scope, &key).await? {
+                Some(throttle) => throttle,
+                None => continue,
+            };
+
+            if let Some(next_attempt) = self.next_attempt(&throttle, limits) {
+                if next_attempt > now {
+                    let throttled = if throttle.locked_until == Some(next_attempt) {
+                        Throttled::Locked(next_attempt)
+                    } else {
+                        // Round up, so that retrying right after the delay works
+                        let seconds = (next_attempt - now).num_milliseconds() / 1000 + 1;
+                        Throttled::Backoff(seconds)
+                    };
+                    return Ok(Some(throttled));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Count a failed attempt, locking the account or IP address out once
+    /// there were too many. The lockout is written to the security log, and
+    /// the user is warned by email.
+    ///
+    /// This does not use the transaction of the login attempt, which is rolled
+    /// back on failure.
+    pub(crate) async fn record_failure(
+        &self,
+        pool: &PgPool,
+        mailer: &Mailer,
+        url_builder: &UrlBuilder,
+        username: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let mut conn = pool.acquire().await?;
+        for (scope, key, limits) in self.scopes(username, ip) {
+            let until = match self.count(&mut conn, scope, &key, limits).await? {
+                Some(until) => until,
+                None => continue,
+            };
+
+            if scope == ThrottleScope::Account {
+                if let Err(e) = notify(&mut conn, mailer, url_builder, username, until).await {
+                    tracing::error!(
+                        error = &*e as &dyn std::error::Error,
+                        "Could not send the lockout notification"
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count a password reset request, refusing new ones for a while once
+    /// there were too many
+    pub(crate) async fn record_password_reset(
+        &self,
+        conn: &mut PgConnection,
+        address: &str,
+        ip: Option<IpAddr>,
+    ) -> anyhow::Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        for (scope, key, limits) in self.password_reset_scopes(address, ip) {
+            self.count(&mut *conn, scope, &key, limits).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Count an attempt in the given scope, and lock it once the lockout
+    /// threshold is reached. Returns until when it got locked.
+    async fn count(
+        &self,
+        conn: &mut PgConnection,
+        scope: ThrottleScope,
+        key: &str,
+        limits: &AttemptLimitsConfig,
+    ) -> anyhow::Result<Option<DateTime<Utc>>> {
+        let throttle =
+            record_login_failure(&mut *conn, scope, key, self.config.forget_after).await?;
+
+        if throttle.failures < i32::try_from(limits.lockout_threshold).unwrap_or(i32::MAX) {
+            return Ok(None);
+        }
+
+        let until = Utc::now() + self.config.lockout_duration;
+        lock_login_throttle(&mut *conn, scope, key, until).await?;
+
+        tracing::warn!(
+            target: "mas::security",
+            scope = scope.as_str(),
+            key = %key,
+            until = %until,
+            "Locked out after too many attempts"
+        );
+
+        Ok(Some(until))
+    }
+
+    /// Forget the failed attempts on an account after a successful login
+    pub(crate) async fn record_success(
+        &self,
+        executor: impl PgExecutor<'_>,
+        username: &str,
+    ) -> anyhow::Result<()> {
+        if self.config.enabled {
+            clear_login_throttle(executor, ThrottleScope::Account, username).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Warn the owner of an account, if it exists and has an email address, that
+/// it got locked
+async fn notify(
+    conn: &mut PgConnection,
+    mailer: &Mailer,
+    url_builder: &UrlBuilder,
+    username: &str,
+    until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let user = match lookup_user_by_username(&mut *conn, username).await {
+        Ok(user) => user,
+        Err(e) if e.not_found() => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let email = match &user.primary_email {
+        Some(email) => email,
+        None => return Ok(()),
+    };
+
+    let address: Address = email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+    let context =
+        AccountLockedEmailContext::new(user.clone().into(), until, url_builder.password_forgot());
+
+    mailer.send_account_locked_email(mailbox, &context).await?;
+
+    Ok(())
+}
+
+/// IP address of the client, used to count failed attempts
+pub(crate) struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait]
+impl<B> FromRequest<B> for ClientIp
+where
+    B: Send,
+{
+    type Rejection = Infallible;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let trust_forwarded_for = req
+            .extensions()
+            .get::<BruteForceProtection>()
+            .map_or(false, |protection| protection.config.trust_forwarded_for);
+
+        if trust_forwarded_for {
+            // The last entry is the one added by the reverse proxy, the others
+            // are under the control of the client
+            let forwarded = req
+                .headers()
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .last()
+                .and_then(|ip| ip.trim().parse().ok());
+
+            if forwarded.is_some() {
+                return Ok(Self(forwarded));
+            }
+        }
+
+        let ip = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        Ok(Self(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let protection = BruteForceProtection::new(&BruteForceConfig::default());
+        let limits = AttemptLimitsConfig {
+            free_attempts: 3,
+            lockout_threshold: 10,
+        };
+        let now = Utc::now();
+        let throttle = |failures| LoginThrottle {
+            failures,
+            last_failure_at: now,
+            locked_until: None,
+        };
+
+        assert_eq!(protection.next_attempt(&throttle(2), &limits), None);
+        assert_eq!(
+            protection.next_attempt(&throttle(3), &limits),
+            Some(now + Duration::seconds(1))
+        );
+        assert_eq!(
+            protection.next_attempt(&throttle(5), &limits),
+            Some(now + Duration::seconds(4))
+        );
+        assert_eq!(
+            protection.next_attempt(&throttle(30), &limits),
+            Some(now + Duration::minutes(5))
+        );
+
+        // Old failures are forgotten
+        let old = LoginThrottle {
+            last_failure_at: now - Duration::hours(2),
+            ..throttle(9)
+        };
+        assert_eq!(protection.next_attempt(&old, &limits), None);
+
+        // Lockouts take precedence
+        let locked = LoginThrottle {
+            locked_until: Some(now + Duration::minutes(10)),
+            ..throttle(0)
+        };
+        assert_eq!(
+            protection.next_attempt(&locked, &limits),
+            Some(now + Duration::minutes(10))
+        );
+    }
+}
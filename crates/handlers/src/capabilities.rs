@@ -0,0 +1,244 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registry of what the server supports, built once at startup.
+//!
+//! The discovery document is generated from it, and the request handlers
+//! check incoming requests against it, so that what is advertised and what is
+//! enforced come from the same place.
+
+use mas_config::{OAuth2Config, PkceConfig};
+use mas_iana::{
+    jose::JsonWebSignatureAlg,
+    oauth::{
+        OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
+        PkceCodeChallengeMethod,
+    },
+};
+use mas_jose::SigningKeystore;
+use oauth2_types::{
+    requests::{GrantType, Prompt, ResponseMode},
+    scope::{self, ScopeToken},
+};
+
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    scopes: Vec<ScopeToken>,
+    response_types: Vec<OAuthAuthorizationEndpointResponseType>,
+    response_modes: Vec<ResponseMode>,
+    grant_types: Vec<GrantType>,
+    client_auth_methods: Vec<OAuthClientAuthenticationMethod>,
+    client_auth_signing_algs: Vec<JsonWebSignatureAlg>,
+    code_challenge_methods: Vec<PkceCodeChallengeMethod>,
+    pkce: PkceConfig,
+    signing_algs: Vec<JsonWebSignatureAlg>,
+    prompt_values: Vec<Prompt>,
+    claims: Vec<&'static str>,
+}
+
+impl Capabilities {
+    /// Figure out the server capabilities from the configuration and the
+    /// available signing keys
+    #[must_use]
+    pub fn new(config: &OAuth2Config, key_store: &impl SigningKeystore) -> Self {
+        let mut response_types = vec![OAuthAuthorizationEndpointResponseType::Code];
+        let mut grant_types = vec![GrantType::AuthorizationCode, GrantType::RefreshToken];
+        if config.implicit_flow {
+            // ID tokens are not issued from the authorization endpoint yet, so
+            // only the `token` response types are available
+            response_types.push(OAuthAuthorizationEndpointResponseType::Token);
+            response_types.push(OAuthAuthorizationEndpointResponseType::CodeToken);
+            grant_types.push(GrantType::Implicit);
+        }
+
+        let mut client_auth_methods = config.client_auth_methods.clone();
+        client_auth_methods.sort();
+        client_auth_methods.dedup();
+
+        let mut code_challenge_methods = vec![PkceCodeChallengeMethod::S256];
+        if config.pkce.allow_plain {
+            code_challenge_methods.insert(0, PkceCodeChallengeMethod::Plain);
+        }
+
+        let mut signing_algs = Vec::from_iter(key_store.supported_algorithms());
+        signing_algs.sort();
+
+        Self {
+            scopes: vec![scope::OPENID, scope::EMAIL, scope::ADMIN],
+            response_types,
+            response_modes: vec![
+                ResponseMode::FormPost,
+                ResponseMode::Query,
+                ResponseMode::Fragment,
+            ],
+            grant_types,
+            client_auth_methods,
+            client_auth_signing_algs: vec![
+                JsonWebSignatureAlg::Hs256,
+                JsonWebSignatureAlg::Hs384,
+                JsonWebSignatureAlg::Hs512,
+                JsonWebSignatureAlg::Rs256,
+                JsonWebSignatureAlg::Rs384,
+                JsonWebSignatureAlg::Rs512,
+            ],
+            code_challenge_methods,
+            pkce: config.pkce.clone(),
+            signing_algs,
+            prompt_values: vec![
+                Prompt::None,
+                Prompt::Login,
+                Prompt::Consent,
+                Prompt::SelectAccount,
+                Prompt::Create,
+            ],
+            claims: vec![
+                // Claims in the ID token
+                "iss",
+                "sub",
+                "aud",
+                "iat",
+                "exp",
+                "nonce",
+                "auth_time",
+                "amr",
+                "at_hash",
+                "c_hash",
+                // Claims in the userinfo response
+                "username",
+                "email",
+                "email_verified",
+            ],
+        }
+    }
+
+    #[must_use]
+    pub fn scopes(&self) -> &[ScopeToken] {
+        &self.scopes
+    }
+
+    #[must_use]
+    pub fn response_types(&self) -> &[OAuthAuthorizationEndpointResponseType] {
+        &self.response_types
+    }
+
+    #[must_use]
+    pub fn supports_response_type(
+        &self,
+        response_type: OAuthAuthorizationEndpointResponseType,
+    ) -> bool {
+        self.response_types.contains(&response_type)
+    }
+
+    #[must_use]
+    pub fn response_modes(&self) -> &[ResponseMode] {
+        &self.response_modes
+    }
+
+    #[must_use]
+    pub fn supports_response_mode(&self, response_mode: ResponseMode) -> bool {
+        self.response_modes.contains(&response_mode)
+    }
+
+    #[must_use]
+    pub fn grant_types(&self) -> &[GrantType] {
+        &self.grant_types
+    }
+
+    #[must_use]
+    pub fn supports_grant_type(&self, grant_type: GrantType) -> bool {
+        self.grant_types.contains(&grant_type)
+    }
+
+    #[must_use]
+    pub fn client_auth_methods(&self) -> &[OAuthClientAuthenticationMethod] {
+        &self.client_auth_methods
+    }
+
+    #[must_use]
+    pub fn supports_client_auth_method(&self, method: OAuthClientAuthenticationMethod) -> bool {
+        self.client_auth_methods.contains(&method)
+    }
+
+    /// Client authentication methods accepted by the introspection endpoint,
+    /// which does not allow unauthenticated clients
+    #[must_use]
+    pub fn introspection_auth_methods(&self) -> Vec<OAuthClientAuthenticationMethod> {
+        self.client_auth_methods
+            .iter()
+            .copied()
+            .filter(|m| *m != OAuthClientAuthenticationMethod::None)
+            .collect()
+    }
+
+    #[must_use]
+    pub fn supports_introspection_auth_method(
+        &self,
+        method: OAuthClientAuthenticationMethod,
+    ) -> bool {
+        method != OAuthClientAuthenticationMethod::None && self.supports_client_auth_method(method)
+    }
+
+    #[must_use]
+    pub fn client_auth_signing_algs(&self) -> &[JsonWebSignatureAlg] {
+        &self.client_auth_signing_algs
+    }
+
+    #[must_use]
+    pub fn supports_client_auth_signing_alg(&self, alg: JsonWebSignatureAlg) -> bool {
+        self.client_auth_signing_algs.contains(&alg)
+    }
+
+    #[must_use]
+    pub fn code_challenge_methods(&self) -> &[PkceCodeChallengeMethod] {
+        &self.code_challenge_methods
+    }
+
+    #[must_use]
+    pub fn supports_code_challenge_method(&self, method: PkceCodeChallengeMethod) -> bool {
+        self.code_challenge_methods.contains(&method)
+    }
+
+    /// Whether a client using the given authentication method at the token
+    /// endpoint must use PKCE
+    #[must_use]
+    pub fn requires_pkce(&self, auth_method: Option<OAuthClientAuthenticationMethod>) -> bool {
+        self.pkce.is_required(auth_method)
+    }
+
+    /// Algorithms the server can use to sign ID tokens and userinfo responses
+    #[must_use]
+    pub fn signing_algs(&self) -> &[JsonWebSignatureAlg] {
+        &self.signing_algs
+    }
+
+    #[must_use]
+    pub fn supports_signing_alg(&self, alg: JsonWebSignatureAlg) -> bool {
+        self.signing_algs.contains(&alg)
+    }
+
+    #[must_use]
+    pub fn prompt_values(&self) -> &[Prompt] {
+        &self.prompt_values
+    }
+
+    #[must_use]
+    pub fn supports_prompt(&self, prompt: Prompt) -> bool {
+        self.prompt_values.contains(&prompt)
+    }
+
+    #[must_use]
+    pub fn claims(&self) -> &[&'static str] {
+        &self.claims
+    }
+}
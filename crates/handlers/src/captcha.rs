@@ -0,0 +1,471 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Challenges keeping bots away from the registration form
+
+use std::net::IpAddr;
+
+use anyhow::Context;
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use headers::{ContentType, HeaderMapExt};
+use hyper::Body;
+use mas_axum_utils::CookieExt;
+use mas_config::{CaptchaConfig, Encrypter};
+use mas_data_model::errors::HtmlError;
+use mas_http::HttpServiceExt;
+use mas_storage::captcha::consume_captcha_challenge;
+use mas_templates::CaptchaContext;
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use thiserror::Error;
+use tower::ServiceExt;
+use url::Url;
+
+const COOKIE_NAME: &str = "captcha-challenge";
+const HCAPTCHA_VERIFY_URL: &str = "https://hcaptcha.com/siteverify";
+const RECAPTCHA_VERIFY_URL: &str = "https://www.google.com/recaptcha/api/siteverify";
+
+#[derive(Debug, Error)]
+pub(crate) enum CaptchaError {
+    #[error("challenge response is missing")]
+    Missing,
+
+    #[error("challenge response is invalid")]
+    Invalid,
+}
+
+impl HtmlError for CaptchaError {
+    fn html_display(&self) -> String {
+        match self {
+            Self::Missing => "Please complete the challenge".to_string(),
+            Self::Invalid => "The challenge was not completed, please try again".to_string(),
+        }
+    }
+}
+
+/// Fields added to a form by the challenge widgets
+#[derive(Deserialize, Default)]
+pub(crate) struct CaptchaForm {
+    #[serde(default)]
+    pow_solution: String,
+
+    #[serde(default, rename = "h-captcha-response")]
+    hcaptcha_response: String,
+
+    #[serde(default, rename = "g-recaptcha-response")]
+    recaptcha_response: String,
+}
+
+/// Proof-of-work challenge in progress, kept in an encrypted cookie
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    challenge: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct VerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remoteip: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VerifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+/// Check whether the SHA-256 hash of the challenge and the solution starts
+/// with enough zero bits
+fn check_proof_of_work(challenge: &str, solution: &str, difficulty: u8) -> bool {
+    // The solution is a counter, don't bother hashing anything else
+    if solution.is_empty() || solution.len() > 20 || !solution.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+
+    let hash = Sha256::new()
+        .chain_update(challenge)
+        .chain_update(":")
+        .chain_update(solution)
+        .finalize();
+
+    let mut zeros = 0;
+    for byte in hash {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zeros >= u32::from(difficulty)
+}
+
+/// Record a solved proof-of-work challenge, returning `false` if it was already
+/// used
+async fn consume(pool: &PgPool, pending: &PendingChallenge) -> anyhow::Result<bool> {
+    let mut conn = pool.acquire().await?;
+    consume_captcha_challenge(&mut conn, &pending.challenge, pending.expires_at).await
+}
+
+/// Ask a hCaptcha or reCAPTCHA compatible server whether the response is
+/// valid
+async fn verify_remote(
+    verify_url: &Url,
+    secret: &str,
+    response: &str,
+    remote_ip: Option<IpAddr>,
+) -> anyhow::Result<bool> {
+    let body = serde_urlencoded::to_string(&VerifyRequest {
+        secret,
+        response,
+        remoteip: remote_ip.map(|ip| ip.to_string()),
+    })?;
+
+    let mut request = hyper::Request::builder()
+        .method("POST")
+        .uri(verify_url.as_str())
+        .body(Body::from(body))?;
+    request
+        .headers_mut()
+        .typed_insert(ContentType::form_url_encoded());
+
+    let response = mas_http::client("captcha-verify")
+        .json::<VerifyResponse>()
+        .oneshot(request)
+        .await
+        .context("could not verify the challenge response")?
+        .into_body();
+
+    if !response.success {
+        tracing::info!(error_codes = ?response.error_codes, "Challenge response rejected");
+    }
+
+    Ok(response.success)
+}
+
+/// The configured registration challenge
+#[derive(Debug, Clone)]
+pub struct CaptchaVerifier {
+    config: CaptchaConfig,
+}
+
+impl CaptchaVerifier {
+    #[must_use]
+    pub fn new(config: &CaptchaConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Prepare the challenge to show in the form, starting a new proof-of-work
+    /// if needed
+    pub(crate) fn challenge(
+        &self,
+        cookie_jar: PrivateCookieJar<Encrypter>,
+    ) -> (Option<CaptchaContext>, PrivateCookieJar<Encrypter>) {
+        match &self.config {
+            CaptchaConfig::Disabled => (None, cookie_jar),
+            CaptchaConfig::ProofOfWork { difficulty } => {
+                let mut bytes = [0; 16];
+                thread_rng().fill_bytes(&mut bytes);
+                let pending = PendingChallenge {
+                    challenge: BASE64URL_NOPAD.encode(&bytes),
+                    expires_at: Utc::now() + Duration::minutes(15),
+                };
+
+                let mut cookie = Cookie::new(COOKIE_NAME, "");
+                cookie.set_path("/");
+                cookie.set_http_only(true);
+                let cookie_jar = cookie_jar.add(cookie.encode(&pending));
+
+                let ctx = CaptchaContext::ProofOfWork {
+                    challenge: pending.challenge,
+                    difficulty: *difficulty,
+                };
+                (Some(ctx), cookie_jar)
+            }
+            CaptchaConfig::HCaptcha { site_key, .. } => (
+                Some(CaptchaContext::HCaptcha {
+                    site_key: site_key.clone(),
+                }),
+                cookie_jar,
+            ),
+            CaptchaConfig::ReCaptcha { site_key, .. } => (
+                Some(CaptchaContext::ReCaptcha {
+                    site_key: site_key.clone(),
+                }),
+                cookie_jar,
+            ),
+        }
+    }
+
+    /// Check the response to the challenge. A proof-of-work challenge can only
+    /// be used once: solved challenges are recorded until they expire, as the
+    /// cookie holding them could be sent again.
+    ///
+    /// This does not use the transaction of the form, so that a challenge is
+    /// consumed even if the rest of the form is rejected.
+    pub(crate) async fn verify(
+        &self,
+        pool: &PgPool,
+        cookie_jar: PrivateCookieJar<Encrypter>,
+        form: &CaptchaForm,
+        remote_ip: Option<IpAddr>,
+    ) -> (
+        anyhow::Result<Result<(), CaptchaError>>,
+        PrivateCookieJar<Encrypter>,
+    ) {
+        let (secret, response, verify_url, default_url) = match &self.config {
+            CaptchaConfig::Disabled => return (Ok(Ok(())), cookie_jar),
+            CaptchaConfig::ProofOfWork { difficulty } => {
+                let pending: Option<PendingChallenge> = cookie_jar
+                    .get(COOKIE_NAME)
+                    .and_then(|cookie| cookie.decode().ok())
+                    .filter(|pending: &PendingChallenge| pending.expires_at > Utc::now());
+                let cookie_jar =
+                    cookie_jar.remove(Cookie::build(COOKIE_NAME, "").path("/").finish());
+
+                let res = match pending {
+                    _ if form.pow_solution.is_empty() => Ok(Err(CaptchaError::Missing)),
+                    Some(pending)
+                        if check_proof_of_work(
+                            &pending.challenge,
+                            &form.pow_solution,
+                            *difficulty,
+                        ) =>
+                    {
+                        consume(pool, &pending).await.map(|consumed| {
+                            if consumed {
+                                Ok(())
+                            } else {
+                                Err(CaptchaError::Invalid)
+                            }
+                        })
+                    }
+                    _ => Ok(Err(CaptchaError::Invalid)),
+                };
+                return (res, cookie_jar);
+            }
+            CaptchaConfig::HCaptcha {
+                secret_key,
+                verify_url,
+                ..
+            } => (
+                secret_key,
+                &form.hcaptcha_response,
+                verify_url,
+                HCAPTCHA_VERIFY_URL,
+            ),
+            CaptchaConfig::ReCaptcha {
+                secret_key,
+                verify_url,
+                ..
+            } => (
+                secret_key,
+                &form.recaptcha_response,
+                verify_url,
+                RECAPTCHA_VERIFY_URL,
+            ),
+        };
+
+        if response.is_empty() {
+            return (Ok(Err(CaptchaError::Missing)), cookie_jar);
+        }
+
+        let verify_url = match verify_url {
+            Some(url) => url.clone(),
+            None => match Url::parse(default_url) {
+                Ok(url) => url,
+                Err(e) => return (Err(e.into()), cookie_jar),
+            },
+        };
+
+        let res = verify_remote(&verify_url, secret, response, remote_ip)
+            .await
+            .map(|valid| {
+                if valid {
+                    Ok(())
+                } else {
+                    Err(CaptchaError::Invalid)
+                }
+            });
+        (res, cookie_jar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use axum::{
+        extract::{FromRequest, RequestParts},
+        response::IntoResponse,
+    };
+    use hyper::{
+        header::{COOKIE, SET_COOKIE},
+        service::{make_service_fn, service_fn},
+        Response, Server,
+    };
+    use mas_storage::MIGRATOR;
+
+    use super::*;
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    /// The cookie jar of a request sending the given cookie
+    async fn request_cookie_jar(
+        encrypter: &Encrypter,
+        cookie: &str,
+    ) -> PrivateCookieJar<Encrypter> {
+        let request = hyper::Request::builder()
+            .extension(encrypter.clone())
+            .header(COOKIE, cookie)
+            .body(())
+            .unwrap();
+        PrivateCookieJar::from_request(&mut RequestParts::new(request))
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn proof_of_work() {
+        let challenge = "c2FsdA";
+        let difficulty = 8;
+        let solution = (0_u64..1_000_000)
+            .map(|n| n.to_string())
+            .find(|n| check_proof_of_work(challenge, n, difficulty))
+            .unwrap();
+
+        let hash = Sha256::digest(format!("{}:{}", challenge, solution).as_bytes());
+        assert_eq!(hash[0], 0);
+
+        assert!(!check_proof_of_work("other", &solution, 32));
+        assert!(!check_proof_of_work(challenge, "", difficulty));
+        assert!(!check_proof_of_work(challenge, "-1", difficulty));
+        assert!(!check_proof_of_work(challenge, "1e9", difficulty));
+    }
+
+    #[tokio::test]
+    async fn proof_of_work_replay() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+
+        let encrypter = Encrypter::new(&[0x42; 32]);
+        let verifier = CaptchaVerifier::new(&CaptchaConfig::ProofOfWork { difficulty: 4 });
+
+        let (ctx, cookie_jar) = verifier.challenge(request_cookie_jar(&encrypter, "").await);
+        let challenge = match ctx {
+            Some(CaptchaContext::ProofOfWork { challenge, .. }) => challenge,
+            _ => panic!("expected a proof-of-work challenge"),
+        };
+        let response = cookie_jar.into_response();
+        let cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+        let cookie = cookie.split(';').next().unwrap();
+
+        let form = CaptchaForm {
+            pow_solution: (0_u64..1_000_000)
+                .map(|n| n.to_string())
+                .find(|n| check_proof_of_work(&challenge, n, 4))
+                .unwrap(),
+            ..CaptchaForm::default()
+        };
+
+        let (res, _) = verifier
+            .verify(
+                &pool,
+                request_cookie_jar(&encrypter, cookie).await,
+                &form,
+                None,
+            )
+            .await;
+        assert!(res.unwrap().is_ok());
+
+        // Sending the same cookie and solution again does not work
+        let (res, _) = verifier
+            .verify(
+                &pool,
+                request_cookie_jar(&encrypter, cookie).await,
+                &form,
+                None,
+            )
+            .await;
+        assert!(matches!(res.unwrap(), Err(CaptchaError::Invalid)));
+    }
+
+    /// Start a stand-in verification server accepting only the "valid"
+    /// response for the "s3cret" secret
+    async fn verify_server() -> Url {
+        let make_service = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: hyper::Request<Body>| async {
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                let params: Vec<(String, String)> = serde_urlencoded::from_bytes(&body).unwrap();
+                let has =
+                    |key: &str, value: &str| params.iter().any(|(k, v)| k == key && v == value);
+                let body = if has("secret", "s3cret") && has("response", "valid") {
+                    r#"{"success": true}"#
+                } else {
+                    r#"{"success": false, "error-codes": ["invalid-input-response"]}"#
+                };
+
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+            }))
+        });
+
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        Url::parse(&format!("http://{}/siteverify", addr)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn remote_verification() {
+        let verify_url = verify_server().await;
+
+        assert!(verify_remote(&verify_url, "s3cret", "valid", None)
+            .await
+            .unwrap());
+        assert!(!verify_remote(&verify_url, "s3cret", "forged", None)
+            .await
+            .unwrap());
+        assert!(!verify_remote(&verify_url, "wrong", "valid", None)
+            .await
+            .unwrap());
+
+        let closed = Url::parse("http://127.0.0.1:1/siteverify").unwrap();
+        assert!(verify_remote(&closed, "s3cret", "valid", None)
+            .await
+            .is_err());
+    }
+}
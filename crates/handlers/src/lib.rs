@@ -37,30 +37,150 @@ use mas_templates::Templates;
 use sqlx::PgPool;
 use tower_http::cors::{Any, CorsLayer};
 
+mod admin;
+mod breached_passwords;
+mod brute_force;
+mod capabilities;
+mod captcha;
 mod health;
 mod oauth2;
+mod password_policy;
+mod passwords;
+mod recovery_codes;
+mod registration;
+mod totp;
+mod upstream_oauth2;
+mod username_policy;
 mod views;
+mod webauthn;
+
+pub use self::{
+    breached_passwords::BreachedPasswords,
+    brute_force::BruteForceProtection,
+    capabilities::Capabilities,
+    captcha::CaptchaVerifier,
+    password_policy::{PasswordPolicy, PasswordPolicyError},
+    passwords::{password_hasher, PasswordBackends},
+    registration::RegistrationPolicy,
+    upstream_oauth2::UpstreamProviders,
+    username_policy::{UsernamePolicy, UsernamePolicyError},
+};
+
+/// The services and configuration shared by all the handlers
+#[derive(Clone)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub templates: Templates,
+    pub key_store: Arc<StaticKeystore>,
+    pub capabilities: Arc<Capabilities>,
+    pub upstream_providers: Arc<UpstreamProviders>,
+    pub password_backends: PasswordBackends,
+    pub password_policy: PasswordPolicy,
+    pub brute_force: BruteForceProtection,
+    pub registration_policy: RegistrationPolicy,
+    pub username_policy: UsernamePolicy,
+    pub captcha: CaptchaVerifier,
+    pub encrypter: Encrypter,
+    pub mailer: Mailer,
+    pub url_builder: UrlBuilder,
+}
 
 #[must_use]
-pub fn router<B>(
-    pool: &PgPool,
-    templates: &Templates,
-    key_store: &Arc<StaticKeystore>,
-    encrypter: &Encrypter,
-    mailer: &Mailer,
-    url_builder: &UrlBuilder,
-) -> Router<B>
+pub fn router<B>(state: &AppState) -> Router<B>
 where
     B: HttpBody + Send + 'static,
     <B as HttpBody>::Data: Send,
     <B as HttpBody>::Error: std::error::Error + Send + Sync,
 {
-    // All those routes are API-like, with a common CORS layer
-    let api_router = Router::new()
+    Router::new()
+        .route(mas_router::Index::route(), get(self::views::index::get))
+        .route(mas_router::Healthcheck::route(), get(self::health::get))
+        .route(
+            mas_router::Login::route(),
+            get(self::views::login::get).post(self::views::login::post),
+        )
+        .route(mas_router::Logout::route(), post(self::views::logout::post))
+        .route(
+            mas_router::Reauth::route(),
+            get(self::views::reauth::get).post(self::views::reauth::post),
+        )
+        .route(
+            mas_router::PasskeyLogin::route(),
+            post(self::views::passkey_login::post),
+        )
+        .route(
+            mas_router::SecondFactor::route(),
+            get(self::views::second_factor::get).post(self::views::second_factor::post),
+        )
+        .route(
+            mas_router::PasswordForgot::route(),
+            get(self::views::password_forgot::get).post(self::views::password_forgot::post),
+        )
+        .route(
+            mas_router::PasswordReset::route(),
+            get(self::views::password_reset::get).post(self::views::password_reset::post),
+        )
+        .route(
+            mas_router::Register::route(),
+            get(self::views::register::get).post(self::views::register::post),
+        )
+        .route(
+            mas_router::VerifyEmail::route(),
+            get(self::views::verify::get),
+        )
+        .route(
+            mas_router::EmailVerificationRequired::route(),
+            get(self::views::email_verification_required::get)
+                .post(self::views::email_verification_required::post),
+        )
+        .route(
+            mas_router::OAuth2AuthorizationEndpoint::route(),
+            get(self::oauth2::authorization::get),
+        )
+        .route(
+            mas_router::ContinueAuthorizationGrant::route(),
+            get(self::oauth2::authorization::complete::get),
+        )
+        .route(
+            mas_router::Consent::route(),
+            get(self::oauth2::consent::get).post(self::oauth2::consent::post),
+        )
+        .merge(account_router())
+        .merge(admin_console_router())
+        .merge(upstream_router())
+        .merge(api_router())
+        .layer(Extension(state.pool.clone()))
+        .layer(Extension(state.templates.clone()))
+        .layer(Extension(state.key_store.clone()))
+        .layer(Extension(state.capabilities.clone()))
+        .layer(Extension(state.upstream_providers.clone()))
+        .layer(Extension(state.password_backends.clone()))
+        .layer(Extension(state.password_policy.clone()))
+        .layer(Extension(state.brute_force.clone()))
+        .layer(Extension(state.registration_policy.clone()))
+        .layer(Extension(state.username_policy.clone()))
+        .layer(Extension(state.captcha.clone()))
+        .layer(Extension(state.encrypter.clone()))
+        .layer(Extension(state.url_builder.clone()))
+        .layer(Extension(state.mailer.clone()))
+}
+
+/// Routes of the API-like endpoints, with a common CORS layer
+fn api_router<B>() -> Router<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    Router::new()
         .route(
             mas_router::OidcConfiguration::route(),
             get(self::oauth2::discovery::get),
         )
+        .route(
+            mas_router::OAuth2AuthorizationServerMetadata::route(),
+            get(self::oauth2::discovery::get),
+        )
         .route(
             mas_router::Webfinger::route(),
             get(self::oauth2::webfinger::get),
@@ -88,6 +208,7 @@ where
             mas_router::OAuth2RegistrationEndpoint::route(),
             post(self::oauth2::registration::post),
         )
+        .nest(mas_router::AdminApi::route(), self::admin::router())
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -100,54 +221,74 @@ where
                     CONTENT_TYPE,
                 ])
                 .max_age(Duration::from_secs(60 * 60)),
-        );
+        )
+}
 
+/// Routes of the pages where users manage their account
+fn account_router<B>() -> Router<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
     Router::new()
-        .route(mas_router::Index::route(), get(self::views::index::get))
-        .route(mas_router::Healthcheck::route(), get(self::health::get))
+        .route(mas_router::Account::route(), get(self::views::account::get))
         .route(
-            mas_router::Login::route(),
-            get(self::views::login::get).post(self::views::login::post),
+            mas_router::AccountPassword::route(),
+            get(self::views::account::password::get).post(self::views::account::password::post),
         )
-        .route(mas_router::Logout::route(), post(self::views::logout::post))
         .route(
-            mas_router::Reauth::route(),
-            get(self::views::reauth::get).post(self::views::reauth::post),
+            mas_router::AccountTotp::route(),
+            get(self::views::account::totp::get).post(self::views::account::totp::post),
         )
         .route(
-            mas_router::Register::route(),
-            get(self::views::register::get).post(self::views::register::post),
+            mas_router::AccountPasskeys::route(),
+            get(self::views::account::passkeys::get).post(self::views::account::passkeys::post),
         )
         .route(
-            mas_router::VerifyEmail::route(),
-            get(self::views::verify::get),
+            mas_router::AccountRecoveryCodes::route(),
+            get(self::views::account::recovery_codes::get)
+                .post(self::views::account::recovery_codes::post),
         )
-        .route(mas_router::Account::route(), get(self::views::account::get))
         .route(
-            mas_router::AccountPassword::route(),
-            get(self::views::account::password::get).post(self::views::account::password::post),
+            mas_router::AccountDelete::route(),
+            get(self::views::account::delete::get).post(self::views::account::delete::post),
         )
         .route(
             mas_router::AccountEmails::route(),
             get(self::views::account::emails::get).post(self::views::account::emails::post),
         )
+}
+
+/// Routes of the admin console
+fn admin_console_router<B>() -> Router<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    Router::new()
+        .route(mas_router::Admin::route(), get(self::views::admin::get))
         .route(
-            mas_router::OAuth2AuthorizationEndpoint::route(),
-            get(self::oauth2::authorization::get),
+            mas_router::AdminUser::route(),
+            get(self::views::admin::user::get).post(self::views::admin::user::post),
         )
+}
+
+/// Routes used to log in through an upstream OAuth 2.0 provider
+fn upstream_router<B>() -> Router<B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    Router::new()
         .route(
-            mas_router::ContinueAuthorizationGrant::route(),
-            get(self::oauth2::authorization::complete::get),
+            mas_router::UpstreamOAuth2Authorize::route(),
+            get(self::upstream_oauth2::authorize::get),
         )
         .route(
-            mas_router::Consent::route(),
-            get(self::oauth2::consent::get).post(self::oauth2::consent::post),
+            mas_router::UpstreamOAuth2Callback::route(),
+            get(self::upstream_oauth2::callback::get),
         )
-        .merge(api_router)
-        .layer(Extension(pool.clone()))
-        .layer(Extension(templates.clone()))
-        .layer(Extension(key_store.clone()))
-        .layer(Extension(encrypter.clone()))
-        .layer(Extension(url_builder.clone()))
-        .layer(Extension(mailer.clone()))
 }
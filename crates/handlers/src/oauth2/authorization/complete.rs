@@ -36,12 +36,17 @@ use mas_storage::{
     PostgresqlBackend,
 };
 use mas_templates::Templates;
-use oauth2_types::requests::{AccessTokenResponse, AuthorizationResponse};
+use oauth2_types::{
+    errors::ACCESS_DENIED,
+    requests::{AccessTokenResponse, AuthorizationResponse},
+    scope,
+};
 use rand::thread_rng;
 use sqlx::{PgPool, Postgres, Transaction};
 use thiserror::Error;
 
 use super::callback::{CallbackDestination, CallbackDestinationError, InvalidRedirectUriError};
+use crate::registration::RegistrationPolicy;
 
 #[derive(Debug, Error)]
 pub enum RouteError {
@@ -107,6 +112,7 @@ impl From<CallbackDestinationError> for RouteError {
 pub(crate) async fn get(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Path(grant_id): Path<i64>,
 ) -> Result<Response, RouteError> {
@@ -129,7 +135,7 @@ pub(crate) async fn get(
         return Ok((cookie_jar, mas_router::Login::and_then(continue_grant).go()).into_response());
     };
 
-    match complete(grant, session, txn).await {
+    match complete(grant, session, &registration_policy, txn).await {
         Ok(params) => {
             let res = callback_destination.go(&templates, params).await?;
             Ok((cookie_jar, res).into_response())
@@ -139,10 +145,19 @@ pub(crate) async fn get(
             mas_router::Reauth::and_then(continue_grant).go(),
         )
             .into_response()),
+        Err(GrantCompletionError::RequiresEmailVerification) => Ok((
+            cookie_jar,
+            mas_router::EmailVerificationRequired::and_then(continue_grant).go(),
+        )
+            .into_response()),
         Err(GrantCompletionError::RequiresConsent) => {
             let next = mas_router::Consent(grant_id);
             Ok((cookie_jar, next.go()).into_response())
         }
+        Err(GrantCompletionError::ScopeNotAllowed) => {
+            let res = callback_destination.go(&templates, ACCESS_DENIED).await?;
+            Ok((cookie_jar, res).into_response())
+        }
         Err(GrantCompletionError::NotPending) => Err(RouteError::NotPending),
         Err(GrantCompletionError::Internal(e)) => Err(RouteError::Internal(e)),
         Err(GrantCompletionError::Anyhow(e)) => Err(RouteError::Anyhow(e)),
@@ -163,8 +178,14 @@ pub enum GrantCompletionError {
     #[error("user needs to reauthenticate")]
     RequiresReauth,
 
+    #[error("user needs to verify an email address")]
+    RequiresEmailVerification,
+
     #[error("client lacks consent")]
     RequiresConsent,
+
+    #[error("user is not allowed to get the requested scope")]
+    ScopeNotAllowed,
 }
 
 impl From<sqlx::Error> for GrantCompletionError {
@@ -182,6 +203,7 @@ impl From<InvalidRedirectUriError> for GrantCompletionError {
 pub(crate) async fn complete(
     grant: AuthorizationGrant<PostgresqlBackend>,
     browser_session: BrowserSession<PostgresqlBackend>,
+    registration_policy: &RegistrationPolicy,
     mut txn: Transaction<'_, Postgres>,
 ) -> Result<AuthorizationResponse<Option<AccessTokenResponse>>, GrantCompletionError> {
     // Verify that the grant is in a pending stage
@@ -189,12 +211,25 @@ pub(crate) async fn complete(
         return Err(GrantCompletionError::NotPending);
     }
 
+    // Only administrators can give access to the admin API
+    if grant.scope.contains(&scope::ADMIN) && !browser_session.user.is_admin {
+        return Err(GrantCompletionError::ScopeNotAllowed);
+    }
+
     // Check if the authentication is fresh enough
     if !browser_session.was_authenticated_after(grant.max_auth_time()) {
         txn.commit().await?;
         return Err(GrantCompletionError::RequiresReauth);
     }
 
+    if registration_policy
+        .needs_email_verification(&mut txn, &browser_session.user)
+        .await?
+    {
+        txn.commit().await?;
+        return Err(GrantCompletionError::RequiresEmailVerification);
+    }
+
     let current_consent =
         fetch_client_consent(&mut txn, &browser_session.user, &grant.client).await?;
 
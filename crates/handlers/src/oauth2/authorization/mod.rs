@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use anyhow::{anyhow, Context};
 use axum::{
     extract::{Extension, Form},
@@ -31,9 +33,10 @@ use mas_storage::oauth2::{
 use mas_templates::Templates;
 use oauth2_types::{
     errors::{
-        CONSENT_REQUIRED, INTERACTION_REQUIRED, INVALID_REQUEST, LOGIN_REQUIRED,
-        REGISTRATION_NOT_SUPPORTED, REQUEST_NOT_SUPPORTED, REQUEST_URI_NOT_SUPPORTED, SERVER_ERROR,
-        UNAUTHORIZED_CLIENT,
+        ACCESS_DENIED, CODE_CHALLENGE_REQUIRED, CONSENT_REQUIRED, INTERACTION_REQUIRED,
+        INVALID_REQUEST, LOGIN_REQUIRED, REGISTRATION_NOT_SUPPORTED, REQUEST_NOT_SUPPORTED,
+        REQUEST_URI_NOT_SUPPORTED, SERVER_ERROR, TRANSFORM_ALGORITHM_NOT_SUPPORTED,
+        UNAUTHORIZED_CLIENT, UNSUPPORTED_RESPONSE_TYPE,
     },
     pkce,
     prelude::*,
@@ -46,6 +49,7 @@ use sqlx::PgPool;
 use thiserror::Error;
 
 use self::{callback::CallbackDestination, complete::GrantCompletionError};
+use crate::{registration::RegistrationPolicy, Capabilities};
 
 mod callback;
 pub mod complete;
@@ -160,6 +164,8 @@ fn resolve_response_mode(
 pub(crate) async fn get(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(capabilities): Extension<Arc<Capabilities>>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Form(params): Form<Params>,
 ) -> Result<Response, RouteError> {
@@ -215,6 +221,23 @@ pub(crate) async fn get(
                     .await?);
             }
 
+            // Check that the server supports what the client asked for
+            if !capabilities.supports_response_type(response_type) {
+                return Ok(callback_destination
+                    .go(&templates, UNSUPPORTED_RESPONSE_TYPE)
+                    .await?);
+            }
+
+            if !capabilities.supports_response_mode(response_mode) {
+                return Ok(callback_destination.go(&templates, INVALID_REQUEST).await?);
+            }
+
+            if let Some(prompt) = params.auth.prompt {
+                if !capabilities.supports_prompt(prompt) {
+                    return Ok(callback_destination.go(&templates, INVALID_REQUEST).await?);
+                }
+            }
+
             // Check if it is allowed to use this grant type
             if !client.grant_types.contains(&GrantType::AuthorizationCode) {
                 return Ok(callback_destination
@@ -228,6 +251,24 @@ pub(crate) async fn get(
             }
 
             let code: Option<AuthorizationCode> = if response_type.has_code() {
+                // Check the PKCE parameters against the policy
+                match &params.pkce {
+                    Some(pkce)
+                        if !capabilities
+                            .supports_code_challenge_method(pkce.code_challenge_method) =>
+                    {
+                        return Ok(callback_destination
+                            .go(&templates, TRANSFORM_ALGORITHM_NOT_SUPPORTED)
+                            .await?);
+                    }
+                    None if capabilities.requires_pkce(client.token_endpoint_auth_method) => {
+                        return Ok(callback_destination
+                            .go(&templates, CODE_CHALLENGE_REQUIRED)
+                            .await?);
+                    }
+                    _ => {}
+                }
+
                 // 32 random alphanumeric characters, about 190bit of entropy
                 let code: String = thread_rng()
                     .sample_iter(&Alphanumeric)
@@ -274,6 +315,7 @@ pub(crate) async fn get(
                 &mut txn,
                 client,
                 redirect_uri.clone(),
+                params.auth.redirect_uri.is_some(),
                 scope,
                 code,
                 params.auth.state.clone(),
@@ -324,18 +366,26 @@ pub(crate) async fn get(
                 // Else, we immediately try to complete the authorization grant
                 (Some(user_session), Some(Prompt::None)) => {
                     // With prompt=none, we should get back to the client immediately
-                    match self::complete::complete(grant, user_session, txn).await {
+                    match self::complete::complete(grant, user_session, &registration_policy, txn)
+                        .await
+                    {
                         Ok(params) => callback_destination.go(&templates, params).await?,
                         Err(GrantCompletionError::RequiresConsent) => {
                             callback_destination
                                 .go(&templates, CONSENT_REQUIRED)
                                 .await?
                         }
-                        Err(GrantCompletionError::RequiresReauth) => {
+                        Err(
+                            GrantCompletionError::RequiresReauth
+                            | GrantCompletionError::RequiresEmailVerification,
+                        ) => {
                             callback_destination
                                 .go(&templates, INTERACTION_REQUIRED)
                                 .await?
                         }
+                        Err(GrantCompletionError::ScopeNotAllowed) => {
+                            callback_destination.go(&templates, ACCESS_DENIED).await?
+                        }
                         Err(GrantCompletionError::Anyhow(a)) => return Err(RouteError::Anyhow(a)),
                         Err(GrantCompletionError::Internal(e)) => {
                             return Err(RouteError::Internal(e))
@@ -349,7 +399,9 @@ pub(crate) async fn get(
                 (Some(user_session), _) => {
                     let grant_id = grant.data;
                     // Else, we show the relevant reauth/consent page if necessary
-                    match self::complete::complete(grant, user_session, txn).await {
+                    match self::complete::complete(grant, user_session, &registration_policy, txn)
+                        .await
+                    {
                         Ok(params) => callback_destination.go(&templates, params).await?,
                         Err(GrantCompletionError::RequiresConsent) => {
                             mas_router::Consent(grant_id).go().into_response()
@@ -359,6 +411,14 @@ pub(crate) async fn get(
                                 .go()
                                 .into_response()
                         }
+                        Err(GrantCompletionError::RequiresEmailVerification) => {
+                            mas_router::EmailVerificationRequired::and_then(continue_grant)
+                                .go()
+                                .into_response()
+                        }
+                        Err(GrantCompletionError::ScopeNotAllowed) => {
+                            callback_destination.go(&templates, ACCESS_DENIED).await?
+                        }
                         Err(GrantCompletionError::Anyhow(a)) => return Err(RouteError::Anyhow(a)),
                         Err(GrantCompletionError::Internal(e)) => {
                             return Err(RouteError::Internal(e))
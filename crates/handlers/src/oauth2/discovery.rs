@@ -15,51 +15,65 @@
 use std::sync::Arc;
 
 use axum::{extract::Extension, response::IntoResponse, Json};
-use mas_iana::{
-    jose::JsonWebSignatureAlg,
-    oauth::{
-        OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
-        PkceCodeChallengeMethod,
-    },
-};
-use mas_jose::{SigningKeystore, StaticKeystore};
+use hyper::StatusCode;
+use mas_iana::jose::JsonWebSignatureAlg;
+use mas_jose::{DecodedJsonWebToken, SigningKeystore, StaticKeystore};
 use mas_router::UrlBuilder;
 use oauth2_types::{
     oidc::{ClaimType, Metadata, SubjectType},
-    requests::{Display, GrantType, Prompt, ResponseMode},
-    scope,
+    requests::Display,
 };
+use serde::Serialize;
 
-#[allow(clippy::too_many_lines)]
-pub(crate) async fn get(
-    Extension(key_store): Extension<Arc<StaticKeystore>>,
-    Extension(url_builder): Extension<UrlBuilder>,
-) -> impl IntoResponse {
+use crate::Capabilities;
+
+/// Claims of the `signed_metadata` JWT, as described in RFC 8414 section 2.1
+#[derive(Serialize)]
+struct SignedMetadata<'a> {
+    iss: String,
+    #[serde(flatten)]
+    metadata: &'a Metadata,
+}
+
+/// Sign the metadata document with the key store, preferring RS256 which all
+/// clients must support
+async fn sign_metadata(
+    metadata: &Metadata,
+    key_store: &StaticKeystore,
+    url_builder: &UrlBuilder,
+) -> anyhow::Result<String> {
+    let supported = key_store.supported_algorithms();
+    let alg = if supported.contains(&JsonWebSignatureAlg::Rs256) {
+        JsonWebSignatureAlg::Rs256
+    } else {
+        supported
+            .into_iter()
+            .min()
+            .ok_or_else(|| anyhow::anyhow!("no signing key available"))?
+    };
+
+    let header = key_store.prepare_header(alg).await?;
+    let claims = SignedMetadata {
+        iss: url_builder.oidc_issuer().to_string(),
+        metadata,
+    };
+
+    let jwt = DecodedJsonWebToken::new(header, claims);
+    let jwt = jwt.sign(key_store).await?;
+    Ok(jwt.serialize())
+}
+
+fn metadata(capabilities: &Capabilities, url_builder: &UrlBuilder) -> Metadata {
     // This is how clients can authenticate
-    let client_auth_methods_supported = Some(vec![
-        OAuthClientAuthenticationMethod::ClientSecretBasic,
-        OAuthClientAuthenticationMethod::ClientSecretPost,
-        OAuthClientAuthenticationMethod::ClientSecretJwt,
-        OAuthClientAuthenticationMethod::PrivateKeyJwt,
-        OAuthClientAuthenticationMethod::None,
-    ]);
-
-    let client_auth_signing_alg_values_supported = Some(vec![
-        JsonWebSignatureAlg::Hs256,
-        JsonWebSignatureAlg::Hs384,
-        JsonWebSignatureAlg::Hs512,
-        JsonWebSignatureAlg::Rs256,
-        JsonWebSignatureAlg::Rs384,
-        JsonWebSignatureAlg::Rs512,
-    ]);
+    let token_endpoint_auth_methods_supported = Some(capabilities.client_auth_methods().to_vec());
+    let introspection_endpoint_auth_methods_supported =
+        Some(capabilities.introspection_auth_methods());
+
+    let client_auth_signing_alg_values_supported =
+        Some(capabilities.client_auth_signing_algs().to_vec());
 
     // This is how we can sign stuff
-    let jwt_signing_alg_values_supported = Some({
-        let algs = key_store.supported_algorithms();
-        let mut algs = Vec::from_iter(algs);
-        algs.sort();
-        algs
-    });
+    let jwt_signing_alg_values_supported = Some(capabilities.signing_algs().to_vec());
 
     // Prepare all the endpoints
     let issuer = Some(url_builder.oidc_issuer());
@@ -70,42 +84,26 @@ pub(crate) async fn get(
     let userinfo_endpoint = Some(url_builder.oidc_userinfo_endpoint());
     let registration_endpoint = Some(url_builder.oauth_registration_endpoint());
 
-    let scopes_supported = Some(vec![scope::OPENID.to_string(), scope::EMAIL.to_string()]);
-
-    let response_types_supported = Some(vec![
-        OAuthAuthorizationEndpointResponseType::Code,
-        OAuthAuthorizationEndpointResponseType::Token,
-        OAuthAuthorizationEndpointResponseType::IdToken,
-        OAuthAuthorizationEndpointResponseType::CodeToken,
-        OAuthAuthorizationEndpointResponseType::CodeIdToken,
-        OAuthAuthorizationEndpointResponseType::IdTokenToken,
-        OAuthAuthorizationEndpointResponseType::CodeIdToken,
-    ]);
-
-    let response_modes_supported = Some(vec![
-        ResponseMode::FormPost,
-        ResponseMode::Query,
-        ResponseMode::Fragment,
-    ]);
-
-    let grant_types_supported = Some(vec![
-        GrantType::AuthorizationCode,
-        GrantType::Implicit,
-        GrantType::RefreshToken,
-    ]);
-
-    let token_endpoint_auth_methods_supported = client_auth_methods_supported.clone();
+    let scopes_supported = Some(
+        capabilities
+            .scopes()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    );
+
+    let response_types_supported = Some(capabilities.response_types().to_vec());
+
+    let response_modes_supported = Some(capabilities.response_modes().to_vec());
+
+    let grant_types_supported = Some(capabilities.grant_types().to_vec());
+
     let token_endpoint_auth_signing_alg_values_supported =
         client_auth_signing_alg_values_supported.clone();
-
-    let introspection_endpoint_auth_methods_supported = client_auth_methods_supported;
     let introspection_endpoint_auth_signing_alg_values_supported =
         client_auth_signing_alg_values_supported;
 
-    let code_challenge_methods_supported = Some(vec![
-        PkceCodeChallengeMethod::Plain,
-        PkceCodeChallengeMethod::S256,
-    ]);
+    let code_challenge_methods_supported = Some(capabilities.code_challenge_methods().to_vec());
 
     let subject_types_supported = Some(vec![SubjectType::Public]);
 
@@ -116,25 +114,21 @@ pub(crate) async fn get(
 
     let claim_types_supported = Some(vec![ClaimType::Normal]);
 
-    let claims_supported = Some(vec![
-        "iss".to_string(),
-        "sub".to_string(),
-        "aud".to_string(),
-        "iat".to_string(),
-        "exp".to_string(),
-        "nonce".to_string(),
-        "auth_time".to_string(),
-        "at_hash".to_string(),
-        "c_hash".to_string(),
-    ]);
+    let claims_supported = Some(
+        capabilities
+            .claims()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    );
 
     let claims_parameter_supported = Some(false);
     let request_parameter_supported = Some(false);
     let request_uri_parameter_supported = Some(false);
 
-    let prompt_values_supported = Some(vec![Prompt::None, Prompt::Login, Prompt::Create]);
+    let prompt_values_supported = Some(capabilities.prompt_values().to_vec());
 
-    let metadata = Metadata {
+    Metadata {
         issuer,
         authorization_endpoint,
         token_endpoint,
@@ -162,7 +156,149 @@ pub(crate) async fn get(
         request_uri_parameter_supported,
         prompt_values_supported,
         ..Metadata::default()
+    }
+}
+
+pub(crate) async fn get(
+    Extension(key_store): Extension<Arc<StaticKeystore>>,
+    Extension(capabilities): Extension<Arc<Capabilities>>,
+    Extension(url_builder): Extension<UrlBuilder>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let mut metadata = metadata(&capabilities, &url_builder);
+
+    let signed_metadata = sign_metadata(&metadata, &key_store, &url_builder)
+        .await
+        .map_err(|e| {
+            tracing::error!(error = %e, "Failed to sign the server metadata");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    metadata.signed_metadata = Some(signed_metadata);
+
+    Ok(Json(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fmt::Debug, hash::Hash};
+
+    use mas_config::{OAuth2Config, PkceConfig};
+    use mas_iana::oauth::{
+        OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod,
+        PkceCodeChallengeMethod,
     };
+    use mas_jose::JsonWebTokenParts;
+    use oauth2_types::requests::GrantType;
+
+    use super::*;
+
+    fn key_store() -> StaticKeystore {
+        let mut key_store = StaticKeystore::new();
+        key_store.add_test_rsa_key().unwrap();
+        key_store.add_test_ecdsa_key().unwrap();
+        key_store
+    }
+
+    fn url_builder() -> UrlBuilder {
+        UrlBuilder::new("https://example.com/".parse().unwrap())
+    }
+
+    fn assert_no_duplicates<T: Eq + Hash + Debug>(values: &[T]) {
+        let unique: HashSet<&T> = values.iter().collect();
+        assert_eq!(unique.len(), values.len(), "duplicates in {:?}", values);
+    }
+
+    /// The metadata lists are built from configuration which may repeat
+    /// values, but should advertise each of them only once
+    fn assert_lists_unique(metadata: &Metadata) {
+        assert_no_duplicates(metadata.response_types_supported.as_ref().unwrap());
+        assert_no_duplicates(metadata.grant_types_supported.as_ref().unwrap());
+        assert_no_duplicates(
+            metadata
+                .token_endpoint_auth_methods_supported
+                .as_ref()
+                .unwrap(),
+        );
+        assert_no_duplicates(
+            metadata
+                .introspection_endpoint_auth_methods_supported
+                .as_ref()
+                .unwrap(),
+        );
+        assert_no_duplicates(metadata.scopes_supported.as_ref().unwrap());
+        assert_no_duplicates(metadata.claims_supported.as_ref().unwrap());
+    }
+
+    #[test]
+    fn default_metadata() {
+        let capabilities = Capabilities::new(&OAuth2Config::default(), &key_store());
+        let metadata = metadata(&capabilities, &url_builder());
+        assert_lists_unique(&metadata);
+
+        let response_types = metadata.response_types_supported.unwrap();
+        assert!(response_types.contains(&OAuthAuthorizationEndpointResponseType::Token));
+        // ID tokens are not issued by the authorization endpoint
+        assert!(!response_types.contains(&OAuthAuthorizationEndpointResponseType::IdToken));
+        // The plain PKCE method is disallowed by default
+        assert_eq!(
+            metadata.code_challenge_methods_supported.unwrap(),
+            vec![PkceCodeChallengeMethod::S256]
+        );
+    }
+
+    #[test]
+    fn restricted_metadata() {
+        let config = OAuth2Config {
+            implicit_flow: false,
+            client_auth_methods: vec![
+                OAuthClientAuthenticationMethod::ClientSecretBasic,
+                OAuthClientAuthenticationMethod::None,
+                OAuthClientAuthenticationMethod::ClientSecretBasic,
+            ],
+            pkce: PkceConfig {
+                allow_plain: true,
+                ..PkceConfig::default()
+            },
+        };
+        let capabilities = Capabilities::new(&config, &key_store());
+        let metadata = metadata(&capabilities, &url_builder());
+        assert_lists_unique(&metadata);
+
+        assert_eq!(
+            metadata.response_types_supported.unwrap(),
+            vec![OAuthAuthorizationEndpointResponseType::Code]
+        );
+        assert!(!metadata
+            .grant_types_supported
+            .unwrap()
+            .contains(&GrantType::Implicit));
+        assert_eq!(
+            metadata
+                .introspection_endpoint_auth_methods_supported
+                .unwrap(),
+            vec![OAuthClientAuthenticationMethod::ClientSecretBasic]
+        );
+    }
+
+    #[tokio::test]
+    async fn signed_metadata_verifies() {
+        let key_store = key_store();
+        let url_builder = url_builder();
+        let capabilities = Capabilities::new(&OAuth2Config::default(), &key_store);
+
+        let metadata = metadata(&capabilities, &url_builder);
+        let signed = sign_metadata(&metadata, &key_store, &url_builder)
+            .await
+            .unwrap();
+
+        let jwt: JsonWebTokenParts = signed.parse().unwrap();
+        let decoded = jwt.decode::<serde_json::Value>().unwrap();
+        jwt.verify(decoded.header(), &key_store).await.unwrap();
 
-    Json(metadata)
+        assert_eq!(decoded.header().alg(), JsonWebSignatureAlg::Rs256);
+        let claims = decoded.claims();
+        assert_eq!(claims["iss"], "https://example.com/");
+        assert_eq!(claims["issuer"], "https://example.com/");
+        assert_eq!(claims["token_endpoint"], "https://example.com/oauth2/token");
+        assert!(claims.get("signed_metadata").is_none());
+    }
 }
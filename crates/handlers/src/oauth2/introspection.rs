@@ -12,12 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use axum::{extract::Extension, response::IntoResponse, Json};
 use hyper::StatusCode;
 use mas_axum_utils::client_authorization::{ClientAuthorization, CredentialsVerificationError};
 use mas_config::Encrypter;
 use mas_data_model::{TokenFormatError, TokenType};
-use mas_iana::oauth::{OAuthClientAuthenticationMethod, OAuthTokenTypeHint};
+use mas_iana::oauth::OAuthTokenTypeHint;
 use mas_storage::oauth2::{
     access_token::{lookup_active_access_token, AccessTokenLookupError},
     client::ClientFetchError,
@@ -26,6 +28,8 @@ use mas_storage::oauth2::{
 use oauth2_types::requests::{IntrospectionRequest, IntrospectionResponse};
 use sqlx::PgPool;
 
+use crate::Capabilities;
+
 pub enum RouteError {
     Internal(Box<dyn std::error::Error + Send + Sync + 'static>),
     ClientNotFound,
@@ -122,22 +126,26 @@ const INACTIVE: IntrospectionResponse = IntrospectionResponse {
 pub(crate) async fn post(
     Extension(pool): Extension<PgPool>,
     Extension(encrypter): Extension<Encrypter>,
+    Extension(capabilities): Extension<Arc<Capabilities>>,
     client_authorization: ClientAuthorization<IntrospectionRequest>,
 ) -> Result<impl IntoResponse, RouteError> {
     let mut conn = pool.acquire().await?;
 
     let client = client_authorization.credentials.fetch(&mut conn).await?;
 
-    let method = match client.token_endpoint_auth_method {
-        None | Some(OAuthClientAuthenticationMethod::None) => {
-            return Err(RouteError::NotAllowed);
-        }
-        Some(c) => c,
-    };
+    let method = client
+        .token_endpoint_auth_method
+        .filter(|method| capabilities.supports_introspection_auth_method(*method))
+        .ok_or(RouteError::NotAllowed)?;
 
     client_authorization
         .credentials
-        .verify(&encrypter, method, &client)
+        .verify(
+            &encrypter,
+            method,
+            capabilities.client_auth_signing_algs(),
+            &client,
+        )
         .await?;
 
     let form = if let Some(form) = client_authorization.form {
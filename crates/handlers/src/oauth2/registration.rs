@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use axum::{response::IntoResponse, Extension, Json};
 use hyper::StatusCode;
 use mas_iana::oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod};
@@ -26,6 +28,8 @@ use sqlx::PgPool;
 use thiserror::Error;
 use tracing::info;
 
+use crate::Capabilities;
+
 #[derive(Debug, Error)]
 pub(crate) enum RouteError {
     #[error(transparent)]
@@ -58,13 +62,40 @@ impl IntoResponse for RouteError {
 #[tracing::instrument(skip_all, err)]
 pub(crate) async fn post(
     Extension(pool): Extension<PgPool>,
+    Extension(capabilities): Extension<Arc<Capabilities>>,
     Json(body): Json<ClientMetadata>,
 ) -> Result<impl IntoResponse, RouteError> {
     info!(?body, "Client registration");
 
+    // Check that the client only asks for things the server supports
+    let supported = body
+        .response_types
+        .iter()
+        .all(|rt| capabilities.supports_response_type(*rt))
+        && body
+            .grant_types
+            .iter()
+            .all(|gt| capabilities.supports_grant_type(*gt))
+        && body
+            .token_endpoint_auth_method
+            .map_or(true, |m| capabilities.supports_client_auth_method(m))
+        && body
+            .token_endpoint_auth_signing_alg
+            .map_or(true, |a| capabilities.supports_client_auth_signing_alg(a))
+        && body
+            .id_token_signed_response_alg
+            .map_or(true, |a| capabilities.supports_signing_alg(a))
+        && body
+            .userinfo_signed_response_alg
+            .map_or(true, |a| capabilities.supports_signing_alg(a));
+
+    if !supported {
+        return Err(RouteError::InvalidClientMetadata);
+    }
+
     // Let's validate a bunch of things on the client body first
     for uri in &body.redirect_uris {
-        if uri.fragment().is_some() {
+        if uri.fragment().is_some() || !body.application_type.is_valid_redirect_uri(uri) {
             return Err(RouteError::InvalidRedirectUri);
         }
     }
@@ -122,6 +153,7 @@ pub(crate) async fn post(
         None,
         &body.response_types,
         &body.grant_types,
+        body.application_type,
         &body.contacts,
         body.client_name.as_deref(),
         body.logo_uri.as_ref(),
@@ -32,7 +32,7 @@ use mas_router::UrlBuilder;
 use mas_storage::{
     oauth2::{
         access_token::{add_access_token, revoke_access_token},
-        authorization_grant::{exchange_grant, lookup_grant_by_code},
+        authorization_grant::{exchange_grant, lookup_grant_by_code, GrantLookupError},
         client::ClientFetchError,
         end_oauth_session,
         refresh_token::{
@@ -43,9 +43,13 @@ use mas_storage::{
     DatabaseInconsistencyError, PostgresqlBackend,
 };
 use oauth2_types::{
-    errors::{INVALID_CLIENT, INVALID_GRANT, INVALID_REQUEST, SERVER_ERROR, UNAUTHORIZED_CLIENT},
+    errors::{
+        INVALID_CLIENT, INVALID_GRANT, INVALID_REQUEST, SERVER_ERROR, UNAUTHORIZED_CLIENT,
+        UNSUPPORTED_GRANT_TYPE,
+    },
     requests::{
-        AccessTokenRequest, AccessTokenResponse, AuthorizationCodeGrant, RefreshTokenGrant,
+        AccessTokenRequest, AccessTokenResponse, AuthorizationCodeGrant, GrantType,
+        RefreshTokenGrant,
     },
     scope,
 };
@@ -58,6 +62,8 @@ use thiserror::Error;
 use tracing::debug;
 use url::Url;
 
+use crate::Capabilities;
+
 #[serde_as]
 #[skip_serializing_none]
 #[derive(Serialize, Debug)]
@@ -86,6 +92,9 @@ pub(crate) enum RouteError {
     #[error("bad request")]
     BadRequest,
 
+    #[error("invalid request: {0}")]
+    InvalidRequest(&'static str),
+
     #[error("client not found")]
     ClientNotFound,
 
@@ -95,11 +104,14 @@ pub(crate) enum RouteError {
     #[error("could not verify client credentials")]
     ClientCredentialsVerification(#[from] CredentialsVerificationError),
 
-    #[error("invalid grant")]
-    InvalidGrant,
+    #[error("invalid grant: {0}")]
+    InvalidGrant(&'static str),
 
     #[error("unauthorized client")]
     UnauthorizedClient,
+
+    #[error("unsupported grant type")]
+    UnsupportedGrantType,
 }
 
 impl From<ClientFetchError> for RouteError {
@@ -112,10 +124,20 @@ impl From<ClientFetchError> for RouteError {
     }
 }
 
+impl From<GrantLookupError> for RouteError {
+    fn from(e: GrantLookupError) -> Self {
+        if e.not_found() {
+            Self::InvalidGrant("The authorization code is unknown.")
+        } else {
+            Self::Internal(Box::new(e))
+        }
+    }
+}
+
 impl From<RefreshTokenLookupError> for RouteError {
     fn from(e: RefreshTokenLookupError) -> Self {
         if e.not_found() {
-            Self::InvalidGrant
+            Self::InvalidGrant("The refresh token is unknown or was already used.")
         } else {
             Self::Internal(Box::new(e))
         }
@@ -124,18 +146,36 @@ impl From<RefreshTokenLookupError> for RouteError {
 
 impl IntoResponse for RouteError {
     fn into_response(self) -> axum::response::Response {
+        // Status codes and error codes as per RFC 6749 section 5.2
         match self {
             Self::Internal(_) | Self::Anyhow(_) => {
                 (StatusCode::INTERNAL_SERVER_ERROR, Json(SERVER_ERROR))
             }
             Self::BadRequest => (StatusCode::BAD_REQUEST, Json(INVALID_REQUEST)),
-            Self::ClientNotFound | Self::ClientCredentialsVerification(_) => {
+            Self::InvalidRequest(description) => (
+                StatusCode::BAD_REQUEST,
+                Json(INVALID_REQUEST.with_description(description)),
+            ),
+            Self::ClientNotFound => (
+                StatusCode::UNAUTHORIZED,
+                Json(INVALID_CLIENT.with_description("The client is unknown.")),
+            ),
+            Self::ClientNotAllowed => (
+                StatusCode::UNAUTHORIZED,
+                Json(
+                    INVALID_CLIENT
+                        .with_description("The client authentication method is not allowed."),
+                ),
+            ),
+            Self::ClientCredentialsVerification(_) => {
                 (StatusCode::UNAUTHORIZED, Json(INVALID_CLIENT))
             }
-            Self::ClientNotAllowed | Self::UnauthorizedClient => {
-                (StatusCode::UNAUTHORIZED, Json(UNAUTHORIZED_CLIENT))
-            }
-            Self::InvalidGrant => (StatusCode::BAD_REQUEST, Json(INVALID_GRANT)),
+            Self::InvalidGrant(description) => (
+                StatusCode::BAD_REQUEST,
+                Json(INVALID_GRANT.with_description(description)),
+            ),
+            Self::UnauthorizedClient => (StatusCode::BAD_REQUEST, Json(UNAUTHORIZED_CLIENT)),
+            Self::UnsupportedGrantType => (StatusCode::BAD_REQUEST, Json(UNSUPPORTED_GRANT_TYPE)),
         }
         .into_response()
     }
@@ -157,6 +197,7 @@ impl From<ClaimError> for RouteError {
 pub(crate) async fn post(
     client_authorization: ClientAuthorization<AccessTokenRequest>,
     Extension(key_store): Extension<Arc<StaticKeystore>>,
+    Extension(capabilities): Extension<Arc<Capabilities>>,
     Extension(url_builder): Extension<UrlBuilder>,
     Extension(pool): Extension<PgPool>,
     Extension(encrypter): Extension<Encrypter>,
@@ -167,24 +208,50 @@ pub(crate) async fn post(
 
     let method = client
         .token_endpoint_auth_method
+        .filter(|method| capabilities.supports_client_auth_method(*method))
         .ok_or(RouteError::ClientNotAllowed)?;
 
     client_authorization
         .credentials
-        .verify(&encrypter, method, &client)
+        .verify(
+            &encrypter,
+            method,
+            capabilities.client_auth_signing_algs(),
+            &client,
+        )
         .await?;
 
     let form = client_authorization.form.ok_or(RouteError::BadRequest)?;
 
     let reply = match form {
-        AccessTokenRequest::AuthorizationCode(grant) => {
-            authorization_code_grant(&grant, &client, &key_store, &url_builder, txn).await?
+        AccessTokenRequest::AuthorizationCode(grant)
+            if capabilities.supports_grant_type(GrantType::AuthorizationCode) =>
+        {
+            if !client.grant_types.contains(&GrantType::AuthorizationCode) {
+                return Err(RouteError::UnauthorizedClient);
+            }
+
+            authorization_code_grant(
+                &grant,
+                &client,
+                &key_store,
+                &capabilities,
+                &url_builder,
+                txn,
+            )
+            .await?
         }
-        AccessTokenRequest::RefreshToken(grant) => {
+        AccessTokenRequest::RefreshToken(grant)
+            if capabilities.supports_grant_type(GrantType::RefreshToken) =>
+        {
+            if !client.grant_types.contains(&GrantType::RefreshToken) {
+                return Err(RouteError::UnauthorizedClient);
+            }
+
             refresh_token_grant(&grant, &client, txn).await?
         }
         _ => {
-            return Err(RouteError::InvalidGrant);
+            return Err(RouteError::UnsupportedGrantType);
         }
     };
 
@@ -210,11 +277,11 @@ async fn authorization_code_grant(
     grant: &AuthorizationCodeGrant,
     client: &Client<PostgresqlBackend>,
     key_store: &StaticKeystore,
+    capabilities: &Capabilities,
     url_builder: &UrlBuilder,
     mut txn: Transaction<'_, Postgres>,
 ) -> Result<AccessTokenResponse, RouteError> {
     // TODO: there is a bunch of unnecessary cloning here
-    // TODO: handle "not found" cases
     let authz_grant = lookup_grant_by_code(&mut txn, &grant.code).await?;
 
     // TODO: that's not a timestamp from the DB. Let's assume they are in sync
@@ -223,7 +290,9 @@ async fn authorization_code_grant(
     let session = match authz_grant.stage {
         AuthorizationGrantStage::Cancelled { cancelled_at } => {
             debug!(%cancelled_at, "Authorization grant was cancelled");
-            return Err(RouteError::InvalidGrant);
+            return Err(RouteError::InvalidGrant(
+                "The authorization grant was cancelled.",
+            ));
         }
         AuthorizationGrantStage::Exchanged {
             exchanged_at,
@@ -239,11 +308,15 @@ async fn authorization_code_grant(
                 txn.commit().await?;
             }
 
-            return Err(RouteError::InvalidGrant);
+            return Err(RouteError::InvalidGrant(
+                "The authorization code was already used.",
+            ));
         }
         AuthorizationGrantStage::Pending => {
             debug!("Authorization grant has not been fulfilled yet");
-            return Err(RouteError::InvalidGrant);
+            return Err(RouteError::InvalidGrant(
+                "The authorization grant has not been fulfilled yet.",
+            ));
         }
         AuthorizationGrantStage::Fulfilled {
             ref session,
@@ -251,7 +324,9 @@ async fn authorization_code_grant(
         } => {
             if now - fulfilled_at > Duration::minutes(10) {
                 debug!("Code exchange took more than 10 minutes");
-                return Err(RouteError::InvalidGrant);
+                return Err(RouteError::InvalidGrant(
+                    "The authorization code has expired.",
+                ));
             }
 
             session
@@ -264,21 +339,66 @@ async fn authorization_code_grant(
         .as_ref()
         .ok_or_else(|| anyhow::anyhow!(DatabaseInconsistencyError))?;
 
+    // The code must have been issued to the authenticated client, as per RFC 6749
+    // section 4.1.3
     if client.client_id != session.client.client_id {
-        return Err(RouteError::UnauthorizedClient);
+        return Err(RouteError::InvalidGrant(
+            "The authorization code was issued to another client.",
+        ));
+    }
+
+    // If the redirect_uri was included in the authorization request, it must be
+    // repeated here with the same value
+    match &grant.redirect_uri {
+        Some(redirect_uri) if *redirect_uri != authz_grant.redirect_uri => {
+            return Err(RouteError::InvalidGrant(
+                "The redirect_uri does not match the one used in the authorization request.",
+            ));
+        }
+        None if authz_grant.redirect_uri_provided => {
+            return Err(RouteError::InvalidRequest(
+                "The redirect_uri parameter is required.",
+            ));
+        }
+        _ => {}
     }
 
     match (code.pkce.as_ref(), grant.code_verifier.as_ref()) {
+        // The policy might have changed since the grant was created
+        (None, _) if capabilities.requires_pkce(client.token_endpoint_auth_method) => {
+            debug!("PKCE is required for this client but the grant has no code challenge");
+            return Err(RouteError::InvalidGrant(
+                "The authorization request had no code challenge.",
+            ));
+        }
+        (Some(pkce), _) if !capabilities.supports_code_challenge_method(pkce.challenge_method) => {
+            debug!(method = %pkce.challenge_method, "Code challenge method is not allowed");
+            return Err(RouteError::InvalidGrant(
+                "The code challenge method is not allowed.",
+            ));
+        }
         (None, None) => {}
         // We have a challenge but no verifier (or vice-versa)? Bad request.
-        (Some(_), None) | (None, Some(_)) => return Err(RouteError::BadRequest),
+        (Some(_), None) => {
+            return Err(RouteError::InvalidRequest(
+                "The code_verifier parameter is required.",
+            ))
+        }
+        (None, Some(_)) => {
+            return Err(RouteError::InvalidRequest(
+                "The code_verifier parameter was not expected.",
+            ))
+        }
         // If we have both, we need to check the code validity
         (Some(pkce), Some(verifier)) => {
             if !pkce.verify(verifier) {
-                return Err(RouteError::BadRequest);
+                // As per RFC 7636 section 4.6
+                return Err(RouteError::InvalidGrant(
+                    "The code_verifier does not match the code challenge.",
+                ));
             }
         }
-    };
+    }
 
     let browser_session = &session.browser_session;
 
@@ -310,6 +430,7 @@ async fn authorization_code_grant(
         }
         if let Some(ref last_authentication) = browser_session.last_authentication {
             claims::AUTH_TIME.insert(&mut claims, last_authentication.created_at)?;
+            claims::AMR.insert(&mut claims, last_authentication.amr())?;
         }
 
         claims::AT_HASH.insert(&mut claims, hash(Sha256::new(), &access_token_str)?)?;
@@ -356,7 +477,9 @@ async fn refresh_token_grant(
 
     if client.client_id != session.client.client_id {
         // As per https://datatracker.ietf.org/doc/html/rfc6749#section-5.2
-        return Err(RouteError::InvalidGrant);
+        return Err(RouteError::InvalidGrant(
+            "The refresh token was issued to another client.",
+        ));
     }
 
     let ttl = Duration::minutes(5);
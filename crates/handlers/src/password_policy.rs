@@ -0,0 +1,419 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks new passwords against the configured strength rules
+
+use mas_config::PasswordsConfig;
+use mas_data_model::{errors::HtmlError, User};
+use mas_storage::{user::get_user_emails, PostgresqlBackend};
+use sqlx::PgExecutor;
+use thiserror::Error;
+
+use crate::breached_passwords::BreachedPasswords;
+
+/// Passwords users tend to pick first, matched anywhere in the password
+const COMMON_WORDS: &[&str] = &[
+    "password",
+    "passw0rd",
+    "qwerty",
+    "azerty",
+    "letmein",
+    "welcome",
+    "admin",
+    "dragon",
+    "monkey",
+    "football",
+    "baseball",
+    "iloveyou",
+    "sunshine",
+    "princess",
+    "master",
+    "shadow",
+    "superman",
+    "batman",
+    "trustno1",
+    "hunter",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "secret",
+    "login",
+    "abc123",
+    "123456",
+    "654321",
+    "111111",
+    "000000",
+    "changeme",
+    "starwars",
+    "whatever",
+    "freedom",
+    "matrix",
+    "computer",
+    "internet",
+    "hello",
+    "charlie",
+    "michael",
+    "jordan",
+    "liverpool",
+    "pokemon",
+    "cheese",
+    "killer",
+    "soccer",
+    "access",
+];
+
+/// Runs of characters which are easy to type one after another
+const SEQUENCES: &[&str] = &[
+    "abcdefghijklmnopqrstuvwxyz",
+    "0123456789",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    "azertyuiop",
+    "qsdfghjklm",
+    "wxcvbn",
+];
+
+/// Why a new password was refused
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordPolicyError {
+    #[error("password must be at least {0} characters long")]
+    TooShort(usize),
+
+    #[error("password must contain a lowercase letter")]
+    MissingLowercase,
+
+    #[error("password must contain an uppercase letter")]
+    MissingUppercase,
+
+    #[error("password must contain a digit")]
+    MissingDigit,
+
+    #[error("password must contain a symbol")]
+    MissingSymbol,
+
+    #[error("password is too easy to guess")]
+    TooWeak,
+
+    #[error("password must not contain the username or email address")]
+    ContainsIdentifier,
+
+    #[error("password is part of a known data breach")]
+    Breached,
+}
+
+impl HtmlError for PasswordPolicyError {
+    fn html_display(&self) -> String {
+        match self {
+            Self::TooShort(min) => format!("Password must be at least {} characters long", min),
+            Self::MissingLowercase => "Password must contain a lowercase letter".to_string(),
+            Self::MissingUppercase => "Password must contain an uppercase letter".to_string(),
+            Self::MissingDigit => "Password must contain a digit".to_string(),
+            Self::MissingSymbol => {
+                "Password must contain a character which is neither a letter nor a digit"
+                    .to_string()
+            }
+            Self::TooWeak => {
+                "This password is too easy to guess, try a longer one or a few unrelated words"
+                    .to_string()
+            }
+            Self::ContainsIdentifier => {
+                "Password must not contain your username or email address".to_string()
+            }
+            Self::Breached => {
+                "This password appeared in a data breach, please choose another one".to_string()
+            }
+        }
+    }
+}
+
+/// The new password and its confirmation differ
+#[derive(Debug, Error)]
+#[error("passwords don't match")]
+pub(crate) struct PasswordMismatch;
+
+impl HtmlError for PasswordMismatch {
+    fn html_display(&self) -> String {
+        "Passwords don't match".to_string()
+    }
+}
+
+/// Strength rules new passwords have to follow
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    config: PasswordsConfig,
+    breached: Option<BreachedPasswords>,
+}
+
+impl PasswordPolicy {
+    /// Build the policy, without the breached passwords check
+    #[must_use]
+    pub fn new(config: &PasswordsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            breached: None,
+        }
+    }
+
+    /// Build the policy, loading the breached passwords dataset if one is
+    /// configured
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dataset can't be loaded
+    pub async fn load(config: &PasswordsConfig) -> anyhow::Result<Self> {
+        let policy = Self::new(config);
+        if let Some(path) = &config.breached_passwords {
+            let breached = BreachedPasswords::load(path).await?;
+            tracing::info!(count = breached.len(), "Loaded breached passwords");
+            Ok(policy.with_breached_passwords(breached))
+        } else {
+            Ok(policy)
+        }
+    }
+
+    /// Refuse passwords which are part of the given dataset
+    #[must_use]
+    pub fn with_breached_passwords(self, breached: BreachedPasswords) -> Self {
+        Self {
+            breached: Some(breached),
+            ..self
+        }
+    }
+
+    /// Check a new password, `identifiers` being the username and email
+    /// addresses of the user it is meant for
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule the password breaks
+    pub fn check<S: AsRef<str>>(
+        &self,
+        password: &str,
+        identifiers: &[S],
+    ) -> Result<(), PasswordPolicyError> {
+        let config = &self.config;
+
+        if password.chars().count() < config.min_length {
+            return Err(PasswordPolicyError::TooShort(config.min_length));
+        }
+
+        if config.require_lowercase && !password.chars().any(char::is_lowercase) {
+            return Err(PasswordPolicyError::MissingLowercase);
+        }
+
+        if config.require_uppercase && !password.chars().any(char::is_uppercase) {
+            return Err(PasswordPolicyError::MissingUppercase);
+        }
+
+        if config.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(PasswordPolicyError::MissingDigit);
+        }
+
+        if config.require_symbol && password.chars().all(char::is_alphanumeric) {
+            return Err(PasswordPolicyError::MissingSymbol);
+        }
+
+        if config.reject_user_identifiers && contains_identifier(password, identifiers) {
+            return Err(PasswordPolicyError::ContainsIdentifier);
+        }
+
+        if let Some(breached) = &self.breached {
+            if breached.contains(password) {
+                return Err(PasswordPolicyError::Breached);
+            }
+        }
+
+        if score(password) < config.min_score {
+            return Err(PasswordPolicyError::TooWeak);
+        }
+
+        Ok(())
+    }
+}
+
+/// Username and email addresses of a user, which should not be part of their
+/// password
+pub(crate) async fn user_identifiers(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<String>> {
+    let emails = get_user_emails(executor, user).await?;
+    let mut identifiers = vec![user.username.clone()];
+    identifiers.extend(emails.into_iter().map(|e| e.email));
+    Ok(identifiers)
+}
+
+fn contains_identifier<S: AsRef<str>>(password: &str, identifiers: &[S]) -> bool {
+    let password = password.to_lowercase();
+    identifiers
+        .iter()
+        .map(AsRef::as_ref)
+        .flat_map(|identifier| {
+            // Also check the local part of email addresses on its own
+            let local_part = identifier.split_once('@').map(|(local, _)| local);
+            std::iter::once(identifier).chain(local_part)
+        })
+        .map(str::to_lowercase)
+        .any(|identifier| {
+            if identifier.chars().count() < 3 {
+                password == identifier
+            } else {
+                password.contains(&identifier)
+            }
+        })
+}
+
+/// Whether `b` can be typed right after `a` by following a common sequence,
+/// in either direction
+fn is_sequence(a: char, b: char) -> bool {
+    SEQUENCES.iter().any(|seq| {
+        seq.find(a).map_or(false, |i| {
+            let next = seq[i + 1..].chars().next();
+            let previous = seq[..i].chars().last();
+            next == Some(b) || previous == Some(b)
+        })
+    })
+}
+
+/// Estimate how hard a password is to guess, from 0 (trivial) to 4 (very
+/// hard).
+///
+/// This follows the scale of zxcvbn: the estimation is the base-10 logarithm
+/// of the number of guesses, with characters following a common word, a
+/// repetition or a keyboard sequence barely counting.
+#[allow(clippy::cast_precision_loss)]
+fn score(password: &str) -> u8 {
+    let lowercase = password.to_lowercase();
+    let chars: Vec<char> = lowercase.chars().collect();
+
+    let pool = [
+        password.chars().any(char::is_lowercase).then(|| 26),
+        password.chars().any(char::is_uppercase).then(|| 26),
+        password.chars().any(|c| c.is_ascii_digit()).then(|| 10),
+        password
+            .chars()
+            .any(|c| c.is_ascii() && !c.is_ascii_alphanumeric())
+            .then(|| 33),
+        password
+            .chars()
+            .any(|c| !c.is_ascii() && !c.is_alphabetic())
+            .then(|| 100),
+    ]
+    .iter()
+    .flatten()
+    .sum::<u32>()
+    .max(10);
+    let char_guesses = f64::from(pool).log10();
+    let predictable_guesses = 2_f64.log10() / 3.;
+    let word_guesses = (COMMON_WORDS.len() as f64).log10();
+
+    // Characters covered by a common word
+    let mut in_word = vec![false; chars.len()];
+    for word in COMMON_WORDS {
+        for (start, _) in lowercase.match_indices(word) {
+            let start = lowercase[..start].chars().count();
+            let len = word.chars().count();
+            in_word[start..start + len]
+                .iter_mut()
+                .for_each(|c| *c = true);
+        }
+    }
+
+    let mut guesses = 0.;
+    let mut previous: Option<char> = None;
+    let mut previous_in_word = false;
+    for (c, in_word) in chars.iter().copied().zip(in_word) {
+        guesses += if in_word {
+            if previous_in_word {
+                0.
+            } else {
+                word_guesses
+            }
+        } else if previous.map_or(false, |p| p == c || is_sequence(p, c)) {
+            predictable_guesses
+        } else {
+            char_guesses
+        };
+        previous = Some(c);
+        previous_in_word = in_word;
+    }
+
+    match guesses {
+        g if g < 3. => 0,
+        g if g < 6. => 1,
+        g if g < 8. => 2,
+        g if g < 10. => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn score_test() {
+        assert_eq!(score("password"), 0);
+        assert_eq!(score("aaaaaaaaaa"), 0);
+        assert_eq!(score("qwertyuiop"), 0);
+        assert_eq!(score("abcdefgh1234"), 1);
+        assert!(score("password1") < 2);
+        assert_eq!(score("correct horse battery staple"), 4);
+        assert_eq!(score("kfjrmzqp"), 4);
+    }
+
+    #[test]
+    fn check_test() {
+        let policy = PasswordPolicy::new(&PasswordsConfig {
+            require_uppercase: true,
+            require_symbol: true,
+            ..PasswordsConfig::default()
+        });
+
+        assert_eq!(
+            policy.check("Ab!", &["bob"]),
+            Err(PasswordPolicyError::TooShort(8))
+        );
+        assert_eq!(
+            policy.check("kfjrmzqp!", &["bob"]),
+            Err(PasswordPolicyError::MissingUppercase)
+        );
+        assert_eq!(
+            policy.check("kfjrMzqpx", &["bob"]),
+            Err(PasswordPolicyError::MissingSymbol)
+        );
+        assert_eq!(
+            policy.check("Password!", &["bob"]),
+            Err(PasswordPolicyError::TooWeak)
+        );
+        assert_eq!(
+            policy.check("xx-Alice-xx", &["alice"]),
+            Err(PasswordPolicyError::ContainsIdentifier)
+        );
+        assert_eq!(
+            policy.check("Jdoe-works-here!", &["bob", "jdoe@example.com"]),
+            Err(PasswordPolicyError::ContainsIdentifier)
+        );
+        assert_eq!(policy.check("Fresh-Lemon-Tiger", &["bob"]), Ok(()));
+
+        let breached = BreachedPasswords::build("Fresh-Lemon-Tiger".as_bytes(), true, 0).unwrap();
+        let policy = policy.with_breached_passwords(breached);
+        assert_eq!(
+            policy.check("Fresh-Lemon-Tiger", &["bob"]),
+            Err(PasswordPolicyError::Breached)
+        );
+    }
+}
@@ -0,0 +1,511 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks user passwords against the configured backends, in order
+
+use std::sync::Arc;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use mas_config::{LdapConfig, PasswordBackend, PasswordHashingConfig, PasswordsConfig};
+use mas_data_model::{errors::HtmlError, BrowserSession, User};
+use mas_ldap::{Directory, DirectoryEntry, LdapDirectory};
+use mas_storage::{
+    user::{
+        add_user_email, check_password, get_user_emails, lookup_user_by_username,
+        mark_user_email_as_verified, register_passwordless_user, set_display_name,
+        set_user_email_as_primary, username_deactivated, verify_user_password, AuthenticationError,
+        LoginError,
+    },
+    PostgresqlBackend,
+};
+use sqlx::{Postgres, Transaction};
+use thiserror::Error;
+
+use crate::{
+    username_policy::{UsernamePolicy, UsernamePolicyError},
+    views::suspended::AccountDeactivated,
+};
+
+#[derive(Debug, Error)]
+pub(crate) enum PasswordLoginError {
+    /// The local password check failed
+    #[error(transparent)]
+    Local(#[from] LoginError),
+
+    /// The directory rejected the credentials
+    #[error("invalid username or password")]
+    Directory,
+
+    /// The directory accepted the credentials of a deactivated user
+    #[error(transparent)]
+    Deactivated(#[from] AccountDeactivated),
+
+    /// The directory accepted the credentials of a user whose username can't
+    /// be used locally
+    #[error(transparent)]
+    Username(#[from] UsernamePolicyError),
+
+    #[error("failed to login")]
+    Other(#[from] anyhow::Error),
+}
+
+impl HtmlError for PasswordLoginError {
+    fn html_display(&self) -> String {
+        match self {
+            Self::Local(e) => e.html_display(),
+            Self::Directory => "Invalid username or password".to_string(),
+            Self::Deactivated(e) => e.html_display(),
+            Self::Username(e) => e.html_display(),
+            Self::Other(e) => format!("Internal error: <pre>{}</pre>", e),
+        }
+    }
+}
+
+/// Build the Argon2id hasher of new passwords
+///
+/// # Errors
+///
+/// Returns an error if the cost parameters are invalid
+pub fn password_hasher(config: &PasswordHashingConfig) -> anyhow::Result<Argon2<'static>> {
+    let params = Params::new(
+        config.memory_cost,
+        config.time_cost,
+        config.parallelism,
+        None,
+    )
+    .map_err(|e| anyhow::anyhow!("invalid password hashing parameters: {}", e))?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+/// Password backends tried on login and reauth
+#[derive(Clone)]
+pub struct PasswordBackends {
+    order: Vec<PasswordBackend>,
+    directory: Option<Arc<dyn Directory>>,
+    hasher: Argon2<'static>,
+}
+
+impl std::fmt::Debug for PasswordBackends {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PasswordBackends")
+            .field("order", &self.order)
+            .field("directory", &self.directory)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PasswordBackends {
+    /// # Errors
+    ///
+    /// Returns an error if the password hashing parameters are invalid
+    pub fn new(config: &LdapConfig, passwords: &PasswordsConfig) -> anyhow::Result<Self> {
+        let directory = config
+            .enabled
+            .then(|| Arc::new(LdapDirectory::new(config)) as Arc<dyn Directory>);
+
+        Ok(Self {
+            order: config.backends(),
+            directory,
+            hasher: password_hasher(&passwords.hashing)?,
+        })
+    }
+
+    /// Use the given directory instead of the LDAP server
+    #[must_use]
+    pub fn with_directory(order: Vec<PasswordBackend>, directory: Arc<dyn Directory>) -> Self {
+        Self {
+            order,
+            directory: Some(directory),
+            hasher: Argon2::default(),
+        }
+    }
+
+    /// Hasher of new local passwords, also used to upgrade existing hashes on
+    /// login
+    #[must_use]
+    pub fn hasher(&self) -> Argon2<'static> {
+        self.hasher.clone()
+    }
+
+    /// Ask the directory, if any, to check the password of a user.
+    /// Unreachable directories are logged and treated as a rejection, so that
+    /// the next backends can still be tried.
+    async fn check_directory(&self, username: &str, password: &str) -> Option<DirectoryEntry> {
+        let directory = self.directory.as_ref()?;
+        match directory.authenticate(username, password).await {
+            Ok(entry) => entry,
+            Err(e) => {
+                tracing::warn!(
+                    error = &e as &dyn std::error::Error,
+                    "LDAP authentication failed"
+                );
+                None
+            }
+        }
+    }
+
+    /// Check the password of a user, without starting a session. Users
+    /// provisioned from the directory have to follow the username policy.
+    pub(crate) async fn verify(
+        &self,
+        txn: &mut Transaction<'_, Postgres>,
+        username_policy: &UsernamePolicy,
+        username: &str,
+        password: String,
+    ) -> Result<User<PostgresqlBackend>, PasswordLoginError> {
+        let mut error = PasswordLoginError::Directory;
+
+        for backend in &self.order {
+            match backend {
+                PasswordBackend::Local => {
+                    match verify_user_password(&mut *txn, &self.hasher, username, password.clone())
+                        .await
+                    {
+                        Ok(user) => return Ok(user),
+                        Err(
+                            e @ (LoginError::NotFound { .. } | LoginError::Authentication { .. }),
+                        ) => {
+                            error = e.into();
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+
+                PasswordBackend::Ldap => {
+                    if let Some(entry) = self.check_directory(username, &password).await {
+                        // The directory doesn't know about local deactivations
+                        if username_deactivated(&mut *txn, username).await? {
+                            return Err(AccountDeactivated.into());
+                        }
+                        let user =
+                            sync_directory_user(txn, username_policy, username, entry).await?;
+                        return Ok(user);
+                    }
+                    error = PasswordLoginError::Directory;
+                }
+            }
+        }
+
+        Err(error)
+    }
+
+    /// Check the password of the user of an existing session, without
+    /// recording the authentication. Returns `false` if the password is wrong
+    pub(crate) async fn verify_session(
+        &self,
+        txn: &mut Transaction<'_, Postgres>,
+        session: &BrowserSession<PostgresqlBackend>,
+        password: String,
+    ) -> anyhow::Result<bool> {
+        for backend in &self.order {
+            match backend {
+                PasswordBackend::Local => {
+                    match check_password(&mut *txn, &self.hasher, &session.user, password.clone())
+                        .await
+                    {
+                        Ok(()) => return Ok(true),
+                        // Wrong password, or no local password at all
+                        Err(
+                            AuthenticationError::Password(_)
+                            | AuthenticationError::Fetch(sqlx::Error::RowNotFound),
+                        ) => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+
+                PasswordBackend::Ldap => {
+                    if self
+                        .check_directory(&session.user.username, &password)
+                        .await
+                        .is_some()
+                    {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Find or create the local user matching a directory entry, and update its
+/// attributes from the directory
+async fn sync_directory_user(
+    txn: &mut Transaction<'_, Postgres>,
+    username_policy: &UsernamePolicy,
+    username: &str,
+    entry: DirectoryEntry,
+) -> Result<User<PostgresqlBackend>, PasswordLoginError> {
+    let mut user = match lookup_user_by_username(&mut *txn, username).await {
+        Ok(user) => user,
+        Err(e) if e.not_found() => {
+            username_policy.check(username)?;
+            register_passwordless_user(&mut *txn, username).await?
+        }
+        Err(e) => return Err(anyhow::Error::from(e).into()),
+    };
+
+    if let Some(display_name) = entry.display_name {
+        set_display_name(&mut *txn, &user, &display_name).await?;
+    }
+
+    if let Some(email) = entry.email {
+        let emails = get_user_emails(&mut *txn, &user).await?;
+        let existing = emails.into_iter().find(|e| e.email == email);
+        let email = match existing {
+            Some(e) if e.confirmed_at.is_some() => e,
+            Some(e) => mark_user_email_as_verified(&mut *txn, e).await?,
+            None => {
+                let e = add_user_email(&mut *txn, &user, email).await?;
+                mark_user_email_as_verified(&mut *txn, e).await?
+            }
+        };
+
+        if user.primary_email.is_none() {
+            set_user_email_as_primary(&mut *txn, &email).await?;
+            user.primary_email = Some(email);
+        }
+    }
+
+    Ok(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_config::UsernamesConfig;
+    use mas_ldap::MemoryDirectory;
+    use mas_storage::{
+        passwords::PasswordScheme,
+        user::{deactivate_user, import_password_hash, register_user, start_session},
+        MIGRATOR,
+    };
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+    use sqlx::{Acquire, PgPool};
+
+    use super::*;
+
+    fn random_username() -> String {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect()
+    }
+
+    fn policy() -> UsernamePolicy {
+        UsernamePolicy::new(&UsernamesConfig::default()).unwrap()
+    }
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    #[tokio::test]
+    async fn fallback_order() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut conn = pool.acquire().await.unwrap();
+
+        let ldap_user = random_username();
+        let local_user = random_username();
+
+        let mut txn = conn.begin().await.unwrap();
+        register_user(&mut txn, Argon2::default(), &local_user, "local-password")
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        let email = format!("{}@example.com", ldap_user);
+        let directory = MemoryDirectory::new().with_user(
+            &ldap_user,
+            "ldap-password",
+            DirectoryEntry {
+                dn: format!("uid={},dc=example,dc=com", ldap_user),
+                email: Some(email.clone()),
+                display_name: Some("Directory User".to_owned()),
+            },
+        );
+        let backends = PasswordBackends::with_directory(
+            vec![PasswordBackend::Ldap, PasswordBackend::Local],
+            Arc::new(directory),
+        );
+
+        // First login through the directory provisions the user
+        let mut txn = conn.begin().await.unwrap();
+        let user = backends
+            .verify(&mut txn, &policy(), &ldap_user, "ldap-password".to_owned())
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+        let primary_email = user.primary_email.as_ref().unwrap();
+        assert_eq!(primary_email.email, email);
+        assert!(primary_email.confirmed_at.is_some());
+
+        let mut txn = conn.begin().await.unwrap();
+
+        // Next logins reuse it
+        let again = backends
+            .verify(&mut txn, &policy(), &ldap_user, "ldap-password".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(again.data, user.data);
+
+        // Users unknown to the directory fall back to local passwords
+        backends
+            .verify(
+                &mut txn,
+                &policy(),
+                &local_user,
+                "local-password".to_owned(),
+            )
+            .await
+            .unwrap();
+
+        let err = backends
+            .verify(&mut txn, &policy(), &ldap_user, "wrong".to_owned())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PasswordLoginError::Local(LoginError::Authentication { .. })
+        ));
+
+        // Directory users can reauthenticate with their directory password
+        let session = start_session(&mut txn, user).await.unwrap();
+        assert!(backends
+            .verify_session(&mut txn, &session, "ldap-password".to_owned())
+            .await
+            .unwrap());
+        assert!(!backends
+            .verify_session(&mut txn, &session, "wrong".to_owned())
+            .await
+            .unwrap());
+
+        // Only the local backend is used when LDAP is disabled
+        let local_only =
+            PasswordBackends::new(&LdapConfig::default(), &PasswordsConfig::default()).unwrap();
+        assert!(local_only
+            .verify(&mut txn, &policy(), &ldap_user, "ldap-password".to_owned())
+            .await
+            .is_err());
+
+        // Deactivated directory users are told so, instead of being provisioned
+        // again
+        deactivate_user(&mut txn, &session.user).await.unwrap();
+        let err = backends
+            .verify(&mut txn, &policy(), &ldap_user, "ldap-password".to_owned())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PasswordLoginError::Deactivated(_)));
+
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn directory_username_policy() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+
+        // Matrix localparts can't have uppercase letters
+        let username = random_username().to_ascii_uppercase();
+        let directory = MemoryDirectory::new().with_user(
+            &username,
+            "ldap-password",
+            DirectoryEntry {
+                dn: format!("uid={},dc=example,dc=com", username),
+                email: None,
+                display_name: None,
+            },
+        );
+        let backends =
+            PasswordBackends::with_directory(vec![PasswordBackend::Ldap], Arc::new(directory));
+
+        let err = backends
+            .verify(&mut txn, &policy(), &username, "ldap-password".to_owned())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            PasswordLoginError::Username(UsernamePolicyError::Uppercase)
+        ));
+        assert!(lookup_user_by_username(&mut txn, &username)
+            .await
+            .unwrap_err()
+            .not_found());
+
+        txn.rollback().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn legacy_hash_upgrade() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+
+        let username = random_username();
+        let user = register_passwordless_user(&mut txn, &username)
+            .await
+            .unwrap();
+        let hash = bcrypt::hash("hunter2", 4).unwrap();
+        import_password_hash(&mut txn, &user, PasswordScheme::Bcrypt, &hash)
+            .await
+            .unwrap();
+
+        let backends =
+            PasswordBackends::new(&LdapConfig::default(), &PasswordsConfig::default()).unwrap();
+        assert!(backends
+            .verify(&mut txn, &policy(), &username, "hunter3".to_owned())
+            .await
+            .is_err());
+        backends
+            .verify(&mut txn, &policy(), &username, "hunter2".to_owned())
+            .await
+            .unwrap();
+
+        // The bcrypt hash got replaced by an Argon2 one
+        let scheme: String = sqlx::query_scalar(
+            "SELECT scheme FROM user_passwords WHERE user_id = $1 ORDER BY id DESC LIMIT 1",
+        )
+        .bind(user.data)
+        .fetch_one(&mut txn)
+        .await
+        .unwrap();
+        assert_eq!(scheme, "argon2");
+
+        backends
+            .verify(&mut txn, &policy(), &username, "hunter2".to_owned())
+            .await
+            .unwrap();
+
+        txn.rollback().await.unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! One-time recovery codes, generated when a second factor is enrolled
+
+use argon2::Argon2;
+use mas_data_model::User;
+use mas_storage::{
+    recovery_codes::{has_recovery_codes, set_recovery_codes},
+    PostgresqlBackend,
+};
+use rand::{seq::SliceRandom, thread_rng};
+use sqlx::{Postgres, Transaction};
+
+/// Number of codes generated at once
+const COUNT: usize = 10;
+
+/// Characters used in codes, without the ones easily mistaken for each other
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generate a new set of codes, formatted as two groups of five characters
+pub(crate) fn generate() -> Vec<String> {
+    let mut rng = thread_rng();
+    (0..COUNT)
+        .map(|_| {
+            let chars: Vec<char> = (0..10)
+                .map(|_| char::from(*ALPHABET.choose(&mut rng).unwrap()))
+                .collect();
+            let (first, second) = chars.split_at(5);
+            format!(
+                "{}-{}",
+                first.iter().collect::<String>(),
+                second.iter().collect::<String>()
+            )
+        })
+        .collect()
+}
+
+/// Normalize a code typed by the user before checking it, so that the
+/// grouping and the case don't matter
+pub(crate) fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Replace the recovery codes of a user with new ones, returning them so they
+/// can be shown once
+pub(crate) async fn regenerate(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<String>> {
+    let codes = generate();
+    let normalized: Vec<String> = codes.iter().map(|code| normalize(code)).collect();
+    set_recovery_codes(txn, Argon2::default(), user, &normalized).await?;
+    Ok(codes)
+}
+
+/// Generate recovery codes for a user who just enrolled a second factor,
+/// unless they already got some for a previous one
+pub(crate) async fn generate_on_enrolment(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Option<Vec<String>>> {
+    if has_recovery_codes(&mut *txn, user).await? {
+        return Ok(None);
+    }
+
+    regenerate(txn, user).await.map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_codes_survive_normalization() {
+        let codes = generate();
+        assert_eq!(codes.len(), COUNT);
+
+        for code in &codes {
+            assert_eq!(code.len(), 11);
+            let normalized = normalize(code);
+            assert_eq!(normalized.len(), 10);
+            assert_eq!(normalize(&code.to_uppercase()), normalized);
+            assert_eq!(
+                normalize(&format!(" {} ", code.replace('-', " "))),
+                normalized
+            );
+        }
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decides who is allowed to create an account
+
+use lettre::Address;
+use mas_config::{RegistrationConfig, RegistrationMode};
+use mas_data_model::{errors::HtmlError, User};
+use mas_storage::{
+    registration_token::consume_registration_token, user::get_user_emails, PostgresqlBackend,
+};
+use sqlx::PgExecutor;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub(crate) enum RegistrationError {
+    #[error("registration is disabled")]
+    Disabled,
+
+    #[error("missing registration token")]
+    MissingToken,
+
+    #[error("invalid registration token")]
+    InvalidToken,
+
+    #[error("missing email address")]
+    MissingEmail,
+
+    #[error("invalid email address")]
+    InvalidEmail,
+
+    #[error("email domain not allowed")]
+    EmailDomainNotAllowed,
+}
+
+impl HtmlError for RegistrationError {
+    fn html_display(&self) -> String {
+        match self {
+            Self::Disabled => "Registration is disabled".to_string(),
+            Self::MissingToken => "A registration token is required".to_string(),
+            Self::InvalidToken => {
+                "This registration token is invalid, expired or was already used".to_string()
+            }
+            Self::MissingEmail => "An email address is required".to_string(),
+            Self::InvalidEmail => "This email address is invalid".to_string(),
+            Self::EmailDomainNotAllowed => {
+                "Email addresses of this domain can't be used to register".to_string()
+            }
+        }
+    }
+}
+
+/// The configured registration rules
+#[derive(Debug, Clone)]
+pub struct RegistrationPolicy {
+    config: RegistrationConfig,
+}
+
+impl RegistrationPolicy {
+    #[must_use]
+    pub fn new(config: &RegistrationConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Whether anyone can create an account at all
+    pub(crate) fn enabled(&self) -> bool {
+        self.config.mode != RegistrationMode::Disabled
+    }
+
+    /// Whether a registration token has to be given
+    pub(crate) fn token_required(&self) -> bool {
+        self.config.mode == RegistrationMode::Token
+    }
+
+    /// Whether an email address has to be given
+    pub(crate) fn email_required(&self) -> bool {
+        self.config.require_email || self.config.mode == RegistrationMode::EmailDomains
+    }
+
+    /// Whether users need a verified email address to continue to clients
+    pub(crate) fn email_verification_required(&self) -> bool {
+        self.config.require_email_verification || self.config.mode == RegistrationMode::EmailDomains
+    }
+
+    /// Whether the domain of an email address is allowed to register, in the
+    /// [`RegistrationMode::EmailDomains`] mode
+    fn domain_allowed(&self, address: &Address) -> bool {
+        self.config.mode != RegistrationMode::EmailDomains
+            || self
+                .config
+                .allowed_email_domains
+                .iter()
+                .any(|domain| domain.eq_ignore_ascii_case(address.domain()))
+    }
+
+    /// Whether the user still has to verify an email address before
+    /// continuing to clients. When registration is restricted to some email
+    /// domains, the verified address has to be in one of them.
+    pub(crate) async fn needs_email_verification(
+        &self,
+        executor: impl PgExecutor<'_>,
+        user: &User<PostgresqlBackend>,
+    ) -> anyhow::Result<bool> {
+        if !self.email_verification_required() {
+            return Ok(false);
+        }
+
+        let emails = get_user_emails(executor, user).await?;
+        Ok(!emails.iter().any(|email| {
+            email.confirmed_at.is_some()
+                && email
+                    .email
+                    .parse()
+                    .map_or(false, |address| self.domain_allowed(&address))
+        }))
+    }
+
+    /// Check that the email address given on registration is acceptable
+    pub(crate) fn check_email(&self, email: &str) -> Result<(), RegistrationError> {
+        if email.is_empty() {
+            return if self.email_required() {
+                Err(RegistrationError::MissingEmail)
+            } else {
+                Ok(())
+            };
+        }
+
+        let address: Address = email.parse().map_err(|_| RegistrationError::InvalidEmail)?;
+
+        if !self.domain_allowed(&address) {
+            return Err(RegistrationError::EmailDomainNotAllowed);
+        }
+
+        Ok(())
+    }
+
+    /// Check and consume the registration token, if one is required
+    pub(crate) async fn use_token(
+        &self,
+        executor: impl PgExecutor<'_>,
+        token: &str,
+    ) -> anyhow::Result<Result<(), RegistrationError>> {
+        if !self.token_required() {
+            return Ok(Ok(()));
+        }
+
+        if token.is_empty() {
+            return Ok(Err(RegistrationError::MissingToken));
+        }
+
+        if consume_registration_token(executor, token).await? {
+            Ok(Ok(()))
+        } else {
+            Ok(Err(RegistrationError::InvalidToken))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mas_storage::{
+        user::{add_user_email, mark_user_email_as_verified, register_passwordless_user},
+        MIGRATOR,
+    };
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+    use sqlx::PgPool;
+
+    use super::*;
+
+    fn random_string() -> String {
+        thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(|c| char::from(c).to_ascii_lowercase())
+            .collect()
+    }
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    #[test]
+    fn check_email() {
+        let policy = RegistrationPolicy::new(&RegistrationConfig {
+            mode: RegistrationMode::EmailDomains,
+            allowed_email_domains: vec!["example.com".to_owned()],
+            ..RegistrationConfig::default()
+        });
+
+        assert!(policy.check_email("alice@example.com").is_ok());
+        assert!(policy.check_email("alice@EXAMPLE.com").is_ok());
+        assert!(matches!(
+            policy.check_email("alice@example.org"),
+            Err(RegistrationError::EmailDomainNotAllowed)
+        ));
+        assert!(matches!(
+            policy.check_email("alice@sub.example.com"),
+            Err(RegistrationError::EmailDomainNotAllowed)
+        ));
+        assert!(matches!(
+            policy.check_email("not an email"),
+            Err(RegistrationError::InvalidEmail)
+        ));
+        assert!(matches!(
+            policy.check_email(""),
+            Err(RegistrationError::MissingEmail)
+        ));
+
+        let open = RegistrationPolicy::new(&RegistrationConfig::default());
+        assert!(open.check_email("").is_ok());
+        assert!(open.check_email("alice@example.org").is_ok());
+    }
+
+    #[tokio::test]
+    async fn email_verification_in_allowed_domain() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+
+        let policy = RegistrationPolicy::new(&RegistrationConfig {
+            mode: RegistrationMode::EmailDomains,
+            allowed_email_domains: vec!["example.com".to_owned()],
+            ..RegistrationConfig::default()
+        });
+
+        let user = register_passwordless_user(&mut txn, &random_string())
+            .await
+            .unwrap();
+        assert!(policy
+            .needs_email_verification(&mut txn, &user)
+            .await
+            .unwrap());
+
+        // A confirmed address in another domain is not enough
+        let email = format!("{}@example.org", random_string());
+        let email = add_user_email(&mut txn, &user, email).await.unwrap();
+        mark_user_email_as_verified(&mut txn, email).await.unwrap();
+        assert!(policy
+            .needs_email_verification(&mut txn, &user)
+            .await
+            .unwrap());
+
+        // Neither is an unconfirmed one in an allowed domain
+        let email = format!("{}@example.com", random_string());
+        let email = add_user_email(&mut txn, &user, email).await.unwrap();
+        assert!(policy
+            .needs_email_verification(&mut txn, &user)
+            .await
+            .unwrap());
+
+        mark_user_email_as_verified(&mut txn, email).await.unwrap();
+        assert!(!policy
+            .needs_email_verification(&mut txn, &user)
+            .await
+            .unwrap());
+
+        txn.rollback().await.unwrap();
+    }
+}
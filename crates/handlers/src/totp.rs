@@ -0,0 +1,181 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Time-based one-time passwords, as per RFC 6238
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use mas_config::Encrypter;
+use mas_data_model::User;
+use mas_storage::{
+    totp::{consume_totp_step, lookup_totp_secret},
+    PostgresqlBackend,
+};
+use qrcode::{render::svg, QrCode};
+use rand::{thread_rng, RngCore};
+use sha1::Sha1;
+use sqlx::PgConnection;
+use url::Url;
+
+/// Length of a time step, in seconds
+const PERIOD: i64 = 30;
+
+/// Number of digits in a code
+const DIGITS: u32 = 6;
+
+/// Number of steps before and after the current one for which codes are
+/// still accepted, to account for clock drift
+const SKEW: i64 = 1;
+
+/// Generate a new random secret
+pub(crate) fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; 20];
+    thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Encode a secret the way authenticator apps expect it to be typed in
+pub(crate) fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// Build the `otpauth://` URI used to enrol an authenticator app
+pub(crate) fn provisioning_uri(issuer: &str, account: &str, secret: &[u8]) -> Url {
+    let mut uri = Url::parse("otpauth://totp/").unwrap();
+    uri.set_path(&format!("{}:{}", issuer, account));
+    uri.query_pairs_mut()
+        .append_pair("secret", &encode_secret(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &PERIOD.to_string());
+    uri
+}
+
+/// Render a provisioning URI as an SVG QR code
+pub(crate) fn qr_code_svg(uri: &Url) -> anyhow::Result<String> {
+    let code = QrCode::new(uri.as_str())?;
+    let image = code.render::<svg::Color>().min_dimensions(200, 200).build();
+    Ok(image)
+}
+
+/// Compute the code for a given time step
+fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, see RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10_u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Check a code against a secret.
+///
+/// Returns the time step the code matched, which should be recorded to
+/// prevent the same code from being used twice.
+pub(crate) fn verify(secret: &[u8], code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    let current = now.timestamp() / PERIOD;
+
+    (current - SKEW..=current + SKEW).find(|step| code_at(secret, *step) == code)
+}
+
+/// Check a code against the TOTP secret enrolled by a user, and make sure it
+/// can't be used again
+pub(crate) async fn check_user_code(
+    conn: &mut PgConnection,
+    encrypter: &Encrypter,
+    user: &User<PostgresqlBackend>,
+    code: &str,
+) -> anyhow::Result<bool> {
+    let secret = lookup_totp_secret(&mut *conn, user)
+        .await?
+        .context("user has no TOTP secret")?;
+    let secret = encrypter
+        .decrypt_string(&secret.encrypted_secret)
+        .context("could not decrypt TOTP secret")?;
+
+    match verify(&secret, code, Utc::now()) {
+        Some(step) => consume_totp_step(&mut *conn, user, step).await,
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    // Test vectors from RFC 6238 appendix B, truncated to 6 digits
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn rfc6238_vectors() {
+        let vectors = [
+            (59, "287082"),
+            (1_111_111_109, "081804"),
+            (1_111_111_111, "050471"),
+            (1_234_567_890, "005924"),
+            (2_000_000_000, "279037"),
+            (20_000_000_000, "353130"),
+        ];
+
+        for (time, expected) in vectors {
+            assert_eq!(code_at(SECRET, time / PERIOD), expected);
+        }
+    }
+
+    #[test]
+    fn verify_window() {
+        let now = Utc.timestamp(1_111_111_111, 0);
+        let step = now.timestamp() / PERIOD;
+
+        assert_eq!(verify(SECRET, "050471", now), Some(step));
+        assert_eq!(verify(SECRET, "050 471", now), Some(step));
+
+        let previous = code_at(SECRET, step - 1);
+        assert_eq!(verify(SECRET, &previous, now), Some(step - 1));
+
+        let too_old = code_at(SECRET, step - 2);
+        assert_eq!(verify(SECRET, &too_old, now), None);
+
+        assert_eq!(verify(SECRET, "000000", now), None);
+    }
+
+    #[test]
+    fn provisioning_uri_format() {
+        let uri = provisioning_uri("auth.example.com", "alice", SECRET);
+        assert_eq!(uri.scheme(), "otpauth");
+        assert_eq!(uri.host_str(), Some("totp"));
+        assert_eq!(uri.path(), "/auth.example.com:alice");
+        assert!(uri
+            .query_pairs()
+            .any(|(k, v)| k == "secret" && v == "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"));
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{
+    extract::{Extension, Path, Query},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use mas_axum_utils::{fancy_error, CookieExt, FancyError};
+use mas_config::Encrypter;
+use mas_iana::oauth::PkceCodeChallengeMethod;
+use mas_router::UrlBuilder;
+use mas_templates::Templates;
+use oauth2_types::pkce::CodeChallengeMethodExt;
+
+use super::{random_string, PendingAuthorization, UpstreamProviders, COOKIE_NAME};
+use crate::views::shared::OptionalPostAuthAction;
+
+#[tracing::instrument(skip(templates, providers, url_builder, cookie_jar))]
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(providers): Extension<Arc<UpstreamProviders>>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Path(provider_id): Path<String>,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let provider = providers
+        .find(&provider_id)
+        .context("unknown upstream provider")
+        .map_err(fancy_error(templates.clone()))?;
+
+    let discovered = provider
+        .discover()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let state = random_string(32);
+    let nonce = random_string(32);
+    let code_verifier = random_string(64);
+    let code_challenge = PkceCodeChallengeMethod::S256.compute_challenge(&code_verifier);
+    let redirect_uri = url_builder.upstream_oauth_callback(provider.config.id.clone());
+
+    let mut uri = discovered.metadata.authorization_endpoint.clone();
+    uri.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.config.client_id)
+        .append_pair("redirect_uri", redirect_uri.as_str())
+        .append_pair("scope", &provider.config.scope)
+        .append_pair("state", &state)
+        .append_pair("nonce", &nonce)
+        .append_pair("code_challenge", &code_challenge)
+        .append_pair("code_challenge_method", "S256");
+
+    let pending = PendingAuthorization {
+        provider: provider.config.id.clone(),
+        state,
+        nonce,
+        code_verifier,
+        post_auth_action: serde_urlencoded::to_string(&query)
+            .map_err(fancy_error(templates.clone()))?,
+    };
+
+    let mut cookie = Cookie::new(COOKIE_NAME, "");
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    let cookie_jar = cookie_jar.add(cookie.encode(&pending));
+
+    Ok((cookie_jar, Redirect::to(uri.as_str())).into_response())
+}
@@ -0,0 +1,318 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use axum::{
+    extract::{Extension, Path, Query},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use headers::{Authorization, ContentType, HeaderMapExt};
+use hyper::Body;
+use mas_axum_utils::{fancy_error, CookieExt, FancyError, SessionInfoExt};
+use mas_config::{Encrypter, UpstreamClientAuthConfig};
+use mas_data_model::{errors::HtmlError, AuthenticationMethod, User};
+use mas_http::HttpServiceExt;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    upstream_oauth2::{add_upstream_link, lookup_user_by_upstream_subject},
+    user::{
+        record_session_authentication, register_passwordless_user, start_session,
+        username_deactivated, username_exists,
+    },
+    PostgresqlBackend,
+};
+use mas_templates::{ErrorContext, Templates};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+use super::{
+    validate_id_token, PendingAuthorization, UpstreamIdentity, UpstreamProvider, UpstreamProviders,
+    COOKIE_NAME,
+};
+use crate::{
+    username_policy::{UsernamePolicy, UsernamePolicyError},
+    views::{shared::OptionalPostAuthAction, suspended},
+};
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct CallbackParams {
+    state: Option<String>,
+    code: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CodeExchangeRequest<'a> {
+    grant_type: &'static str,
+    code: &'a str,
+    redirect_uri: &'a str,
+    code_verifier: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_secret: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenResponse {
+    Success {
+        id_token: String,
+    },
+    Error {
+        error: String,
+        #[serde(default)]
+        error_description: Option<String>,
+    },
+}
+
+/// Exchange the authorization code at the provider token endpoint, returning
+/// the ID token
+async fn exchange_code(
+    provider: &UpstreamProvider,
+    token_endpoint: &str,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> anyhow::Result<String> {
+    let config = &provider.config;
+    let (basic, client_id, client_secret) = match &config.client_auth {
+        UpstreamClientAuthConfig::None => (None, Some(config.client_id.as_str()), None),
+        UpstreamClientAuthConfig::ClientSecretBasic { client_secret } => (
+            Some(Authorization::basic(&config.client_id, client_secret)),
+            None,
+            None,
+        ),
+        UpstreamClientAuthConfig::ClientSecretPost { client_secret } => (
+            None,
+            Some(config.client_id.as_str()),
+            Some(client_secret.as_str()),
+        ),
+    };
+
+    let body = serde_urlencoded::to_string(&CodeExchangeRequest {
+        grant_type: "authorization_code",
+        code,
+        redirect_uri,
+        code_verifier,
+        client_id,
+        client_secret,
+    })?;
+
+    let mut request = hyper::Request::builder()
+        .method("POST")
+        .uri(token_endpoint)
+        .body(Body::from(body))?;
+    request
+        .headers_mut()
+        .typed_insert(ContentType::form_url_encoded());
+    if let Some(basic) = basic {
+        request.headers_mut().typed_insert(basic);
+    }
+
+    let response = mas_http::client("upstream-token")
+        .json::<TokenResponse>()
+        .oneshot(request)
+        .await
+        .context("could not exchange the authorization code")?
+        .into_body();
+
+    match response {
+        TokenResponse::Success { id_token } => Ok(id_token),
+        TokenResponse::Error {
+            error,
+            error_description,
+        } => Err(anyhow!(
+            "upstream provider refused the authorization code: {} {}",
+            error,
+            error_description.unwrap_or_default()
+        )),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip(templates, pool, providers, username_policy, url_builder, cookie_jar))]
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(providers): Extension<Arc<UpstreamProviders>>,
+    Extension(username_policy): Extension<UsernamePolicy>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Path(provider_id): Path<String>,
+    Query(params): Query<CallbackParams>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let pending: PendingAuthorization = cookie_jar
+        .get(COOKIE_NAME)
+        .context("no upstream login in progress")
+        .and_then(|cookie| Ok(cookie.decode()?))
+        .map_err(fancy_error(templates.clone()))?;
+
+    // The pending authorization is single-use
+    let cookie_jar = cookie_jar.remove(Cookie::build(COOKIE_NAME, "").path("/").finish());
+
+    let provider = providers
+        .find(&provider_id)
+        .context("unknown upstream provider")
+        .map_err(fancy_error(templates.clone()))?;
+
+    let identity = complete_authorization(provider, &url_builder, params, &pending)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let user = lookup_user_by_upstream_subject(&mut txn, &provider_id, &identity.subject)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let user = if let Some(user) = user {
+        if username_deactivated(&mut txn, &user.username)
+            .await
+            .map_err(fancy_error(templates.clone()))?
+        {
+            return suspended::deactivated(&templates, cookie_jar).await;
+        }
+        user
+    } else {
+        let provisioned = provision_user(&mut txn, provider, &username_policy, identity)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+
+        match provisioned {
+            Ok(user) => user,
+            Err(e) => {
+                let ctx = ErrorContext::new()
+                    .with_code("invalid_username")
+                    .with_description(format!(
+                        "The username given by the provider can't be used: {}",
+                        e.html_display()
+                    ));
+                let content = templates
+                    .render_error(&ctx)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+                return Ok((StatusCode::FORBIDDEN, cookie_jar, Html(content)).into_response());
+            }
+        }
+    };
+
+    let cookie_jar = match suspended::check(&templates, &mut txn, &user, cookie_jar).await? {
+        Ok(cookie_jar) => cookie_jar,
+        Err(reply) => return Ok(reply),
+    };
+
+    let mut session = start_session(&mut txn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    record_session_authentication(&mut txn, &mut session, &[AuthenticationMethod::Federated])
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    let next: OptionalPostAuthAction = serde_urlencoded::from_str(&pending.post_auth_action)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let cookie_jar = cookie_jar.set_session(&session);
+    Ok((cookie_jar, next.go_next()).into_response())
+}
+
+/// Check the callback parameters against the pending authorization, and get
+/// the identity of the user from the provider
+async fn complete_authorization(
+    provider: &UpstreamProvider,
+    url_builder: &UrlBuilder,
+    params: CallbackParams,
+    pending: &PendingAuthorization,
+) -> anyhow::Result<UpstreamIdentity> {
+    if pending.provider != provider.config.id || params.state.as_deref() != Some(&pending.state) {
+        bail!("state mismatch in upstream callback");
+    }
+
+    if let Some(error) = params.error {
+        bail!(
+            "upstream provider returned an error: {} {}",
+            error,
+            params.error_description.unwrap_or_default()
+        );
+    }
+
+    let code = params.code.context("missing code in upstream callback")?;
+
+    let discovered = provider.discover().await?;
+
+    let redirect_uri = url_builder.upstream_oauth_callback(provider.config.id.clone());
+    let id_token = exchange_code(
+        provider,
+        discovered.metadata.token_endpoint.as_str(),
+        redirect_uri.as_str(),
+        &code,
+        &pending.code_verifier,
+    )
+    .await?;
+
+    let jwks = discovered
+        .jwks
+        .lock()
+        .map_err(|_| anyhow!("JWKS cache poisoned"))?
+        .clone();
+
+    validate_id_token(
+        &id_token,
+        jwks,
+        &discovered.metadata.issuer,
+        &provider.config.client_id,
+        &pending.nonce,
+        &provider.config.claims.username,
+    )
+    .await
+}
+
+/// Create a local user for an upstream identity seen for the first time.
+/// Returns why it can't be created if the username is not acceptable.
+async fn provision_user(
+    txn: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    provider: &UpstreamProvider,
+    username_policy: &UsernamePolicy,
+    identity: UpstreamIdentity,
+) -> anyhow::Result<Result<User<PostgresqlBackend>, UsernamePolicyError>> {
+    let username = identity.username.with_context(|| {
+        format!(
+            "upstream provider did not return the {:?} claim",
+            provider.config.claims.username
+        )
+    })?;
+
+    if let Err(e) = username_policy.check(&username) {
+        return Ok(Err(e));
+    }
+
+    // Never link an upstream account to an existing local user implicitly, and
+    // never reuse the username of a deactivated one
+    if username_exists(&mut *txn, &username).await? {
+        return Ok(Err(UsernamePolicyError::Taken));
+    }
+
+    let user = register_passwordless_user(&mut *txn, &username).await?;
+    add_upstream_link(&mut *txn, &provider.config.id, &identity.subject, &user).await?;
+
+    Ok(Ok(user))
+}
@@ -0,0 +1,356 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Login through upstream OIDC providers
+
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{bail, Context};
+use mas_config::{UpstreamOAuth2Config, UpstreamProviderConfig};
+use mas_http::HttpServiceExt;
+use mas_jose::{
+    claims::{self, TimeOptions},
+    DynamicJwksStore, JsonWebKeySet, JsonWebTokenParts, VerifyingKeystore,
+};
+use mas_router::{PostAuthAction, Route};
+use mas_templates::UpstreamProviderLink;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+use tower::{BoxError, ServiceExt};
+use url::Url;
+
+pub mod authorize;
+pub mod callback;
+
+/// Name of the cookie holding the state of an in-progress upstream login
+const COOKIE_NAME: &str = "upstream-oauth2";
+
+/// Subset of the provider discovery document needed to log in
+#[derive(Deserialize, Debug, Clone)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: Url,
+    token_endpoint: Url,
+    jwks_uri: Url,
+}
+
+struct DiscoveredProvider {
+    metadata: ProviderMetadata,
+    // The store is not `Sync`, but clones share their cache
+    jwks: Mutex<DynamicJwksStore>,
+}
+
+pub struct UpstreamProvider {
+    config: UpstreamProviderConfig,
+    discovered: OnceCell<DiscoveredProvider>,
+}
+
+impl UpstreamProvider {
+    /// Fetch the provider metadata, only once it succeeded
+    async fn discover(&self) -> anyhow::Result<&DiscoveredProvider> {
+        self.discovered
+            .get_or_try_init(|| async {
+                let issuer = self.config.issuer.as_str().trim_end_matches('/');
+                let uri = format!("{}/.well-known/openid-configuration", issuer);
+
+                let request = hyper::Request::builder()
+                    .method("GET")
+                    .uri(&uri)
+                    .body(hyper::Body::empty())?;
+
+                let metadata = mas_http::client("upstream-discovery")
+                    .json::<ProviderMetadata>()
+                    .oneshot(request)
+                    .await
+                    .with_context(|| format!("could not fetch provider metadata from {}", uri))?
+                    .into_body();
+
+                // OIDC Discovery 1.0 section 4.3
+                if metadata.issuer.trim_end_matches('/') != issuer {
+                    bail!(
+                        "provider metadata issuer {:?} does not match the configured issuer",
+                        metadata.issuer
+                    );
+                }
+
+                let jwks_uri = metadata.jwks_uri.to_string();
+                let exporter = mas_http::client("upstream-fetch-jwks")
+                    .json::<JsonWebKeySet>()
+                    .map_request(move |()| {
+                        hyper::Request::builder()
+                            .method("GET")
+                            .uri(&jwks_uri)
+                            .body(hyper::Body::empty())
+                            .unwrap()
+                    })
+                    .map_response(hyper::Response::into_body)
+                    .map_err(BoxError::from)
+                    .boxed_clone();
+
+                Ok(DiscoveredProvider {
+                    metadata,
+                    jwks: Mutex::new(DynamicJwksStore::new(exporter)),
+                })
+            })
+            .await
+    }
+}
+
+/// Upstream OIDC providers configured on the server, with their metadata
+/// lazily discovered on first use
+pub struct UpstreamProviders {
+    providers: Vec<UpstreamProvider>,
+}
+
+impl UpstreamProviders {
+    #[must_use]
+    pub fn new(config: &UpstreamOAuth2Config) -> Self {
+        let providers = config
+            .iter()
+            .map(|config| UpstreamProvider {
+                config: config.clone(),
+                discovered: OnceCell::new(),
+            })
+            .collect();
+
+        Self { providers }
+    }
+
+    fn find(&self, id: &str) -> Option<&UpstreamProvider> {
+        self.providers.iter().find(|p| p.config.id == id)
+    }
+
+    /// Links to display on the login page
+    pub(crate) fn links(
+        &self,
+        post_auth_action: Option<&PostAuthAction>,
+    ) -> Vec<UpstreamProviderLink> {
+        self.providers
+            .iter()
+            .map(|p| {
+                let route = mas_router::UpstreamOAuth2Authorize::new(
+                    p.config.id.clone(),
+                    post_auth_action.cloned(),
+                );
+                UpstreamProviderLink::new(p.config.name.clone(), route.relative_url().into_owned())
+            })
+            .collect()
+    }
+}
+
+/// State of an in-progress upstream login, kept in an encrypted cookie
+#[derive(Serialize, Deserialize, Debug)]
+struct PendingAuthorization {
+    provider: String,
+    state: String,
+    nonce: String,
+    code_verifier: String,
+    /// The post auth action, URL-encoded as it can't go through the cookie
+    /// encoding directly
+    post_auth_action: String,
+}
+
+fn random_string(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// Identity asserted by an upstream provider
+#[derive(Debug, PartialEq)]
+struct UpstreamIdentity {
+    subject: String,
+    username: Option<String>,
+}
+
+/// Verify the signature and claims of an ID token as per OIDC Core 1.0
+/// section 3.1.3.7, and extract the identity it asserts
+async fn validate_id_token<S>(
+    id_token: &str,
+    store: S,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    username_claim: &str,
+) -> anyhow::Result<UpstreamIdentity>
+where
+    S: VerifyingKeystore,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let token: JsonWebTokenParts = id_token.parse().context("invalid ID token")?;
+    let (header, mut claims) = token
+        .decode::<HashMap<String, serde_json::Value>>()
+        .context("invalid ID token")?
+        .split();
+
+    // The store is taken by value, as it might not be `Sync`
+    token
+        .verify(&header, &store)
+        .await
+        .context("could not verify the ID token signature")?;
+
+    let username = claims
+        .get(username_claim)
+        .and_then(serde_json::Value::as_str)
+        .map(ToOwned::to_owned);
+
+    let iss = claims::ISS.extract_required(&mut claims)?;
+    if iss != issuer {
+        bail!("ID token was issued by {:?}, expected {:?}", iss, issuer);
+    }
+
+    let aud = claims::AUD.extract_required(&mut claims)?;
+    if !aud.contains(&client_id.to_owned()) {
+        bail!("ID token was not issued for this client");
+    }
+
+    let options = TimeOptions::default().freeze();
+    claims::EXP.extract_required_with_options(&mut claims, &options)?;
+    claims::IAT.extract_required_with_options(&mut claims, &options)?;
+
+    let token_nonce = claims::NONCE.extract_required(&mut claims)?;
+    if token_nonce != nonce {
+        bail!("ID token nonce mismatch");
+    }
+
+    let subject = claims::SUB.extract_required(&mut claims)?;
+
+    Ok(UpstreamIdentity { subject, username })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use mas_iana::jose::JsonWebSignatureAlg;
+    use mas_jose::{DecodedJsonWebToken, SharedSecret, SigningKeystore};
+
+    use super::*;
+
+    const SECRET: &[u8] = b"a very secret shared secret value";
+
+    async fn id_token(edit: impl FnOnce(&mut HashMap<String, serde_json::Value>)) -> String {
+        let store = SharedSecret::new(&SECRET);
+        let now = Utc::now();
+        let mut claims = HashMap::new();
+        claims::ISS
+            .insert(&mut claims, "https://sso.example.com/")
+            .unwrap();
+        claims::SUB.insert(&mut claims, "abcd").unwrap();
+        claims::AUD.insert(&mut claims, "mas".to_owned()).unwrap();
+        claims::IAT.insert(&mut claims, now).unwrap();
+        claims::EXP
+            .insert(&mut claims, now + Duration::hours(1))
+            .unwrap();
+        claims::NONCE.insert(&mut claims, "n0nce").unwrap();
+        claims::PREFERRED_USERNAME
+            .insert(&mut claims, "alice")
+            .unwrap();
+        edit(&mut claims);
+
+        let header = store
+            .prepare_header(JsonWebSignatureAlg::Hs256)
+            .await
+            .unwrap();
+        DecodedJsonWebToken::new(header, claims)
+            .sign(&store)
+            .await
+            .unwrap()
+            .serialize()
+    }
+
+    async fn validate(id_token: &str, username_claim: &str) -> anyhow::Result<UpstreamIdentity> {
+        let store = SharedSecret::new(&SECRET);
+        validate_id_token(
+            id_token,
+            store,
+            "https://sso.example.com/",
+            "mas",
+            "n0nce",
+            username_claim,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn valid_id_token() {
+        let token = id_token(|_| {}).await;
+
+        let identity = validate(&token, "preferred_username").await.unwrap();
+        assert_eq!(
+            identity,
+            UpstreamIdentity {
+                subject: "abcd".to_owned(),
+                username: Some("alice".to_owned()),
+            }
+        );
+
+        let identity = validate(&token, "sub").await.unwrap();
+        assert_eq!(identity.username.as_deref(), Some("abcd"));
+
+        let identity = validate(&token, "nickname").await.unwrap();
+        assert_eq!(identity.username, None);
+    }
+
+    #[tokio::test]
+    async fn invalid_id_token() {
+        let token = id_token(|c| {
+            claims::ISS.insert(c, "https://evil.example.com/").unwrap();
+        })
+        .await;
+        assert!(validate(&token, "sub").await.is_err());
+
+        let token = id_token(|c| {
+            claims::AUD.insert(c, "someone-else".to_owned()).unwrap();
+        })
+        .await;
+        assert!(validate(&token, "sub").await.is_err());
+
+        let token = id_token(|c| {
+            claims::NONCE.insert(c, "replayed").unwrap();
+        })
+        .await;
+        assert!(validate(&token, "sub").await.is_err());
+
+        let token = id_token(|c| {
+            claims::EXP
+                .insert(c, Utc::now() - Duration::hours(1))
+                .unwrap();
+        })
+        .await;
+        assert!(validate(&token, "sub").await.is_err());
+
+        let token = id_token(|c| {
+            c.remove("sub");
+        })
+        .await;
+        assert!(validate(&token, "sub").await.is_err());
+
+        // Signed with another key
+        let token = id_token(|_| {}).await;
+        let other_secret = b"another secret, unknown to the provider";
+        let res = validate_id_token(
+            &token,
+            SharedSecret::new(&other_secret),
+            "https://sso.example.com/",
+            "mas",
+            "n0nce",
+            "sub",
+        )
+        .await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks usernames of new users against the Matrix localpart grammar and the
+//! configured rules
+
+use anyhow::Context;
+use mas_config::UsernamesConfig;
+use mas_data_model::errors::HtmlError;
+use regex::RegexSet;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UsernamePolicyError {
+    #[error("username too short")]
+    TooShort(usize),
+
+    #[error("username too long")]
+    TooLong(usize),
+
+    #[error("username has uppercase characters")]
+    Uppercase,
+
+    #[error("username has invalid character {0:?}")]
+    InvalidCharacter(char),
+
+    #[error("username is reserved")]
+    Reserved,
+
+    #[error("username is denied")]
+    Denied,
+
+    #[error("username is already taken")]
+    Taken,
+}
+
+impl HtmlError for UsernamePolicyError {
+    fn html_display(&self) -> String {
+        match self {
+            Self::TooShort(min) => format!("Username must be at least {} characters long", min),
+            Self::TooLong(max) => format!("Username must be at most {} characters long", max),
+            Self::Uppercase => "Username must be lowercase".to_string(),
+            Self::InvalidCharacter(c) => format!(
+                "Username can't contain {:?}, only letters, digits and the characters . _ = - /",
+                c
+            ),
+            Self::Reserved | Self::Denied => "This username is not available".to_string(),
+            Self::Taken => "This username is already taken".to_string(),
+        }
+    }
+}
+
+/// Characters allowed in Matrix user ID localparts, besides lowercase ASCII
+/// letters and digits
+const LOCALPART_SYMBOLS: &[char] = &['.', '_', '=', '-', '/'];
+
+/// Rules usernames of new users have to follow
+#[derive(Debug, Clone)]
+pub struct UsernamePolicy {
+    config: UsernamesConfig,
+    denylist: RegexSet,
+}
+
+impl UsernamePolicy {
+    /// Build the policy, compiling the denylist
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if one of the denylist patterns is not a valid regular
+    /// expression
+    pub fn new(config: &UsernamesConfig) -> anyhow::Result<Self> {
+        let denylist =
+            RegexSet::new(&config.denylist).context("invalid username denylist pattern")?;
+
+        Ok(Self {
+            config: config.clone(),
+            denylist,
+        })
+    }
+
+    /// Check a username against the rules
+    ///
+    /// # Errors
+    ///
+    /// Returns the first rule the username does not follow
+    pub fn check(&self, username: &str) -> Result<(), UsernamePolicyError> {
+        let length = username.chars().count();
+        if length < self.config.min_length {
+            return Err(UsernamePolicyError::TooShort(self.config.min_length));
+        }
+
+        if length > self.config.max_length {
+            return Err(UsernamePolicyError::TooLong(self.config.max_length));
+        }
+
+        if username.chars().any(|c| c.is_ascii_uppercase()) {
+            return Err(UsernamePolicyError::Uppercase);
+        }
+
+        if let Some(c) = username.chars().find(|c| {
+            !(c.is_ascii_lowercase() || c.is_ascii_digit() || LOCALPART_SYMBOLS.contains(c))
+        }) {
+            return Err(UsernamePolicyError::InvalidCharacter(c));
+        }
+
+        if self
+            .config
+            .reserved
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(username))
+        {
+            return Err(UsernamePolicyError::Reserved);
+        }
+
+        if self.denylist.is_match(username) {
+            return Err(UsernamePolicyError::Denied);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_test() {
+        let policy = UsernamePolicy::new(&UsernamesConfig {
+            max_length: 16,
+            denylist: vec!["^bot-".to_owned(), "nazi".to_owned()],
+            ..UsernamesConfig::default()
+        })
+        .unwrap();
+
+        assert!(policy.check("john").is_ok());
+        assert!(policy.check("john.doe_42=/-").is_ok());
+        assert!(policy.check("robot-john").is_ok());
+
+        assert!(matches!(
+            policy.check(""),
+            Err(UsernamePolicyError::TooShort(1))
+        ));
+        assert!(matches!(
+            policy.check("averyveryverylongname"),
+            Err(UsernamePolicyError::TooLong(16))
+        ));
+        assert!(matches!(
+            policy.check("John"),
+            Err(UsernamePolicyError::Uppercase)
+        ));
+        assert!(matches!(
+            policy.check("john doe"),
+            Err(UsernamePolicyError::InvalidCharacter(' '))
+        ));
+        assert!(matches!(
+            policy.check("jöhn"),
+            Err(UsernamePolicyError::InvalidCharacter('ö'))
+        ));
+        assert!(matches!(
+            policy.check("admin"),
+            Err(UsernamePolicyError::Reserved)
+        ));
+        assert!(matches!(
+            policy.check("bot-john"),
+            Err(UsernamePolicyError::Denied)
+        ));
+    }
+
+    #[test]
+    fn invalid_denylist() {
+        assert!(UsernamePolicy::new(&UsernamesConfig {
+            denylist: vec!["(".to_owned()],
+            ..UsernamesConfig::default()
+        })
+        .is_err());
+    }
+}
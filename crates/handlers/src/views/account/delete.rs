@@ -0,0 +1,169 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::{Duration, Utc};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{ErroredForm, HtmlError, WrapFormError},
+    BrowserSession,
+};
+use mas_router::{PostAuthAction, Route};
+use mas_storage::{
+    user::{deactivate_user, erase_user},
+    PostgresqlBackend,
+};
+use mas_templates::{
+    AccountDeleteContext, AccountDeleteFormField, EmptyContext, TemplateContext, Templates,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use thiserror::Error;
+
+#[derive(Deserialize)]
+pub(crate) struct DeleteForm {
+    username: String,
+    erase: Option<String>,
+}
+
+#[derive(Debug, Error)]
+#[error("username does not match")]
+struct UsernameMismatch;
+
+impl HtmlError for UsernameMismatch {
+    fn html_display(&self) -> String {
+        "This is not your username".to_string()
+    }
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if let Some(session) = maybe_session {
+        render(templates, session, None, cookie_jar).await
+    } else {
+        let login = mas_router::Login::default();
+        Ok((cookie_jar, login.go()).into_response())
+    }
+}
+
+async fn render(
+    templates: Templates,
+    session: BrowserSession<PostgresqlBackend>,
+    form_error: Option<ErroredForm<AccountDeleteFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let ctx = AccountDeleteContext::default();
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_delete(&ctx)
+        .await
+        .map_err(fancy_error(templates))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<DeleteForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    // Ask for the credentials again unless they were checked in the last few
+    // minutes
+    if !session.was_authenticated_after(Utc::now() - Duration::minutes(5)) {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::DeleteAccount);
+        return Ok((cookie_jar, reauth.go()).into_response());
+    }
+
+    if form.username != session.user.username {
+        let form_error = UsernameMismatch.on_field(AccountDeleteFormField::Username);
+        return render(templates, session, Some(form_error), cookie_jar).await;
+    }
+
+    if form.erase.is_some() {
+        erase_user(&mut txn, &session.user)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+    } else {
+        deactivate_user(&mut txn, &session.user)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+    }
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    let cookie_jar = cookie_jar.update_session_info(&session_info.mark_session_ended());
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let ctx = EmptyContext
+        .maybe_with_session::<PostgresqlBackend>(None)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_deleted(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
@@ -97,14 +97,23 @@ async fn render(
     Ok((cookie_jar, Html(content)).into_response())
 }
 
-async fn start_email_verification(
+pub(crate) async fn start_email_verification(
     mailer: &Mailer,
     url_builder: &UrlBuilder,
     executor: impl PgExecutor<'_>,
     user: &User<PostgresqlBackend>,
     user_email: &UserEmail<PostgresqlBackend>,
 ) -> anyhow::Result<()> {
-    // First, generate a code
+    let code = add_verification_code(executor, user_email).await?;
+    send_verification_email(mailer, url_builder, user, user_email, code).await
+}
+
+/// Generate and save a verification code for an email address, to be sent with
+/// [`send_verification_email`] once it is committed
+pub(crate) async fn add_verification_code(
+    executor: impl PgExecutor<'_>,
+    user_email: &UserEmail<PostgresqlBackend>,
+) -> anyhow::Result<String> {
     let code: String = thread_rng()
         .sample_iter(&Alphanumeric)
         .take(32)
@@ -113,7 +122,17 @@ async fn start_email_verification(
 
     add_user_email_verification_code(executor, user_email, &code).await?;
 
-    // And send the verification email
+    Ok(code)
+}
+
+/// Send the verification link of an email address
+pub(crate) async fn send_verification_email(
+    mailer: &Mailer,
+    url_builder: &UrlBuilder,
+    user: &User<PostgresqlBackend>,
+    user_email: &UserEmail<PostgresqlBackend>,
+    code: String,
+) -> anyhow::Result<()> {
     let address: Address = user_email.email.parse()?;
 
     let mailbox = Mailbox::new(Some(user.username.clone()), address);
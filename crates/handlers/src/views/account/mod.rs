@@ -12,8 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod delete;
 pub mod emails;
+pub mod passkeys;
 pub mod password;
+pub mod recovery_codes;
+pub mod totp;
 
 use axum::{
     extract::Extension,
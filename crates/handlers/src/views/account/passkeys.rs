@@ -0,0 +1,246 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{ErroredForm, HtmlError, WrapFormError},
+    BrowserSession,
+};
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{
+    webauthn::{
+        add_webauthn_credential, get_user_webauthn_credentials, remove_webauthn_credential,
+    },
+    PostgresqlBackend,
+};
+use mas_templates::{
+    AccountPasskeysContext, Passkey, PasskeyFormField, TemplateContext, Templates,
+};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+
+use crate::{
+    recovery_codes,
+    webauthn::{self, PublicKeyCredential, RelyingParty, WebauthnError},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ManagementForm {
+    Register { name: String, credential: String },
+    Remove { data: String },
+}
+
+#[derive(Debug, Error)]
+#[error("missing passkey name")]
+struct MissingName;
+
+impl HtmlError for MissingName {
+    fn html_display(&self) -> String {
+        "Give a name to this passkey".to_string()
+    }
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if let Some(session) = maybe_session {
+        render(
+            &templates,
+            &url_builder,
+            session,
+            None,
+            cookie_jar,
+            &mut conn,
+        )
+        .await
+    } else {
+        let login = mas_router::Login::default();
+        Ok((cookie_jar, login.go()).into_response())
+    }
+}
+
+async fn render(
+    templates: &Templates,
+    url_builder: &UrlBuilder,
+    session: BrowserSession<PostgresqlBackend>,
+    form_error: Option<ErroredForm<PasskeyFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    conn: &mut PgConnection,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let credentials = get_user_webauthn_credentials(&mut *conn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (challenge, cookie_jar) = webauthn::start_challenge(cookie_jar);
+    let options =
+        RelyingParty::new(url_builder).creation_options(&challenge, &session.user, &credentials);
+
+    let passkeys = credentials
+        .into_iter()
+        .map(|c| Passkey::new(c.id, c.name, c.created_at, c.last_used_at))
+        .collect();
+
+    let ctx = AccountPasskeysContext::new(passkeys, options);
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_passkeys(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<ManagementForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let cookie_jar = match form {
+        ManagementForm::Register { name, credential } => {
+            let (challenge, cookie_jar) = webauthn::take_challenge(cookie_jar);
+
+            let name = name.trim();
+            if name.is_empty() {
+                let form_error = MissingName.on_field(PasskeyFormField::Name);
+                return render(
+                    &templates,
+                    &url_builder,
+                    session,
+                    Some(form_error),
+                    cookie_jar,
+                    &mut txn,
+                )
+                .await;
+            }
+
+            let result = challenge.and_then(|challenge| {
+                let credential: PublicKeyCredential =
+                    serde_json::from_str(&credential).map_err(|_| WebauthnError::Malformed)?;
+                RelyingParty::new(&url_builder).verify_registration(&challenge, &credential)
+            });
+
+            let new_credential = match result {
+                Ok(new_credential) => new_credential,
+                Err(e) => {
+                    return render(
+                        &templates,
+                        &url_builder,
+                        session,
+                        Some(e.on_form()),
+                        cookie_jar,
+                        &mut txn,
+                    )
+                    .await;
+                }
+            };
+
+            add_webauthn_credential(
+                &mut txn,
+                &session.user,
+                &new_credential.id,
+                &new_credential.public_key,
+                new_credential.sign_count.into(),
+                name,
+            )
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+
+            let codes = recovery_codes::generate_on_enrolment(&mut txn, &session.user)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            if let Some(codes) = codes {
+                txn.commit().await.map_err(fancy_error(templates.clone()))?;
+                return super::recovery_codes::show(
+                    &templates,
+                    session,
+                    codes,
+                    mas_router::AccountPasskeys::route(),
+                    cookie_jar,
+                )
+                .await;
+            }
+
+            cookie_jar
+        }
+
+        ManagementForm::Remove { data } => {
+            let id = data.parse().map_err(fancy_error(templates.clone()))?;
+            remove_webauthn_credential(&mut txn, &session.user, id)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            cookie_jar
+        }
+    };
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, mas_router::AccountPasskeys.go()).into_response())
+}
@@ -12,7 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use argon2::Argon2;
 use axum::{
     extract::{Extension, Form},
     response::{Html, IntoResponse, Response},
@@ -23,16 +22,24 @@ use mas_axum_utils::{
     fancy_error, FancyError, SessionInfoExt,
 };
 use mas_config::Encrypter;
-use mas_data_model::BrowserSession;
+use mas_data_model::{
+    errors::{ErroredForm, WrapFormError},
+    BrowserSession,
+};
 use mas_router::Route;
 use mas_storage::{
     user::{authenticate_session, set_password},
     PostgresqlBackend,
 };
-use mas_templates::{EmptyContext, TemplateContext, Templates};
+use mas_templates::{AccountPasswordContext, AccountPasswordFormField, TemplateContext, Templates};
 use serde::Deserialize;
 use sqlx::PgPool;
 
+use crate::{
+    password_policy::{user_identifiers, PasswordMismatch, PasswordPolicy},
+    passwords::PasswordBackends,
+};
+
 #[derive(Deserialize)]
 pub struct ChangeForm {
     current_password: String,
@@ -58,7 +65,7 @@ pub(crate) async fn get(
         .map_err(fancy_error(templates.clone()))?;
 
     if let Some(session) = maybe_session {
-        render(templates, session, cookie_jar).await
+        render(templates, session, None, cookie_jar).await
     } else {
         let login = mas_router::Login::default();
         Ok((cookie_jar, login.go()).into_response())
@@ -68,13 +75,18 @@ pub(crate) async fn get(
 async fn render(
     templates: Templates,
     session: BrowserSession<PostgresqlBackend>,
+    form_error: Option<ErroredForm<AccountPasswordFormField>>,
     cookie_jar: PrivateCookieJar<Encrypter>,
 ) -> Result<Response, FancyError> {
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
 
-    let ctx = EmptyContext
-        .with_session(session)
-        .with_csrf(csrf_token.form_value());
+    let ctx = AccountPasswordContext::default();
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
 
     let content = templates
         .render_account_password(&ctx)
@@ -87,6 +99,8 @@ async fn render(
 pub(crate) async fn post(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(password_policy): Extension<PasswordPolicy>,
+    Extension(password_backends): Extension<PasswordBackends>,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Form(form): Form<ProtectedForm<ChangeForm>>,
 ) -> Result<Response, FancyError> {
@@ -110,21 +124,29 @@ pub(crate) async fn post(
         return Ok((cookie_jar, login.go()).into_response());
     };
 
-    authenticate_session(&mut txn, &mut session, form.current_password)
+    let phf = password_backends.hasher();
+    authenticate_session(&mut txn, &phf, &mut session, form.current_password)
         .await
         .map_err(fancy_error(templates.clone()))?;
 
-    // TODO: display nice form errors
     if form.new_password != form.new_password_confirm {
-        return Err(anyhow::anyhow!("password mismatch")).map_err(fancy_error(templates.clone()));
+        let form_error = PasswordMismatch.on_field(AccountPasswordFormField::NewPasswordConfirm);
+        return render(templates, session, Some(form_error), cookie_jar).await;
+    }
+
+    let identifiers = user_identifiers(&mut txn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    if let Err(e) = password_policy.check(&form.new_password, &identifiers) {
+        let form_error = e.on_field(AccountPasswordFormField::NewPassword);
+        return render(templates, session, Some(form_error), cookie_jar).await;
     }
 
-    let phf = Argon2::default();
     set_password(&mut txn, phf, &session.user, &form.new_password)
         .await
         .map_err(fancy_error(templates.clone()))?;
 
-    let reply = render(templates.clone(), session, cookie_jar).await?;
+    let reply = render(templates.clone(), session, None, cookie_jar).await?;
 
     txn.commit().await.map_err(fancy_error(templates.clone()))?;
 
@@ -0,0 +1,154 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::{Duration, Utc};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::BrowserSession;
+use mas_router::{PostAuthAction, Route};
+use mas_storage::{recovery_codes::count_unused_recovery_codes, PostgresqlBackend};
+use mas_templates::{
+    AccountRecoveryCodesContext, RecoveryCodesContext, TemplateContext, Templates,
+};
+use sqlx::PgPool;
+
+use crate::{recovery_codes, views::second_factor};
+
+/// Show newly generated codes, once, before continuing to the given page
+pub(crate) async fn show(
+    templates: &Templates,
+    session: BrowserSession<PostgresqlBackend>,
+    codes: Vec<String>,
+    next: &str,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let ctx = RecoveryCodesContext::new(codes, next.to_owned())
+        .with_session(session)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_recovery_codes(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    let remaining = count_unused_recovery_codes(&mut conn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let ctx = AccountRecoveryCodesContext::new(remaining)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_recovery_codes(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    // Recovery codes are only useful with a second factor to recover from
+    let has_second_factor = second_factor::required(&mut txn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    if !has_second_factor {
+        return Ok((cookie_jar, mas_router::Account.go()).into_response());
+    }
+
+    // Generating new codes gives access to the account, so ask for the
+    // credentials again unless they were checked in the last few minutes
+    if !session.was_authenticated_after(Utc::now() - Duration::minutes(5)) {
+        let reauth = mas_router::Reauth::and_then(PostAuthAction::ManageRecoveryCodes);
+        return Ok((cookie_jar, reauth.go()).into_response());
+    }
+
+    let codes = recovery_codes::regenerate(&mut txn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    show(
+        &templates,
+        session,
+        codes,
+        mas_router::AccountRecoveryCodes::route(),
+        cookie_jar,
+    )
+    .await
+}
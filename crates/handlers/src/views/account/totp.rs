@@ -0,0 +1,268 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, CookieExt, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{HtmlError, WrapFormError},
+    BrowserSession,
+};
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{
+    totp::{consume_totp_step, lookup_totp_secret, remove_totp_secret, set_totp_secret},
+    PostgresqlBackend,
+};
+use mas_templates::{AccountTotpContext, TemplateContext, Templates, TotpEnrolment, TotpFormField};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+
+use crate::{
+    recovery_codes,
+    totp::{self, check_user_code},
+};
+
+/// Holds the secret being enrolled until the user confirms it with a code
+const COOKIE_NAME: &str = "totp-enrolment";
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ManagementForm {
+    Enable { code: String },
+    Disable { code: String },
+}
+
+#[derive(Debug, Error)]
+#[error("invalid code")]
+struct InvalidCode;
+
+impl HtmlError for InvalidCode {
+    fn html_display(&self) -> String {
+        "Invalid code".to_string()
+    }
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if let Some(session) = maybe_session {
+        render(
+            &templates,
+            &url_builder,
+            session,
+            None,
+            cookie_jar,
+            &mut conn,
+        )
+        .await
+    } else {
+        let login = mas_router::Login::default();
+        Ok((cookie_jar, login.go()).into_response())
+    }
+}
+
+async fn render(
+    templates: &Templates,
+    url_builder: &UrlBuilder,
+    session: BrowserSession<PostgresqlBackend>,
+    form_error: Option<InvalidCode>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    conn: &mut PgConnection,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let enrolled = lookup_totp_secret(&mut *conn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .is_some();
+
+    let (ctx, cookie_jar) = if enrolled {
+        (AccountTotpContext::enabled(), cookie_jar)
+    } else {
+        // Reuse the secret being enrolled, if any, so that reloading the page
+        // does not invalidate an already scanned QR code
+        let pending: Option<Vec<u8>> = cookie_jar
+            .get(COOKIE_NAME)
+            .and_then(|cookie| cookie.decode().ok());
+        let (secret, cookie_jar) = if let Some(secret) = pending {
+            (secret, cookie_jar)
+        } else {
+            let secret = totp::generate_secret();
+            let mut cookie = Cookie::new(COOKIE_NAME, "");
+            cookie.set_path(mas_router::AccountTotp::route());
+            cookie.set_http_only(true);
+            let cookie_jar = cookie_jar.add(cookie.encode(&secret));
+            (secret, cookie_jar)
+        };
+
+        let issuer = url_builder.oidc_issuer();
+        let issuer = issuer.host_str().unwrap_or("matrix-authentication-service");
+        let uri = totp::provisioning_uri(issuer, &session.user.username, &secret);
+        let qr_code = totp::qr_code_svg(&uri).map_err(fancy_error(templates.clone()))?;
+        let enrolment = TotpEnrolment::new(totp::encode_secret(&secret), qr_code);
+        (AccountTotpContext::enrolling(enrolment), cookie_jar)
+    };
+
+    let ctx = if let Some(e) = form_error {
+        ctx.with_form_error(e.on_field(TotpFormField::Code))
+    } else {
+        ctx
+    };
+
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_totp(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(encrypter): Extension<Encrypter>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<ManagementForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::default();
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let cookie_jar = match form {
+        ManagementForm::Enable { code } => {
+            let secret: Vec<u8> = cookie_jar
+                .get(COOKIE_NAME)
+                .ok_or_else(|| anyhow::anyhow!("no TOTP enrolment in progress"))
+                .and_then(|cookie| Ok(cookie.decode()?))
+                .map_err(fancy_error(templates.clone()))?;
+
+            let step = if let Some(step) = totp::verify(&secret, &code, chrono::Utc::now()) {
+                step
+            } else {
+                return render(
+                    &templates,
+                    &url_builder,
+                    session,
+                    Some(InvalidCode),
+                    cookie_jar,
+                    &mut txn,
+                )
+                .await;
+            };
+
+            let encrypted_secret = encrypter
+                .encryt_to_string(&secret)
+                .map_err(fancy_error(templates.clone()))?;
+            set_totp_secret(&mut txn, &session.user, &encrypted_secret)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            // The confirmation code can't be used to log in afterwards
+            consume_totp_step(&mut txn, &session.user, step)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+
+            let cookie_jar = cookie_jar.remove(
+                Cookie::build(COOKIE_NAME, "")
+                    .path(mas_router::AccountTotp::route())
+                    .finish(),
+            );
+
+            let codes = recovery_codes::generate_on_enrolment(&mut txn, &session.user)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            if let Some(codes) = codes {
+                txn.commit().await.map_err(fancy_error(templates.clone()))?;
+                return super::recovery_codes::show(
+                    &templates,
+                    session,
+                    codes,
+                    mas_router::AccountTotp::route(),
+                    cookie_jar,
+                )
+                .await;
+            }
+
+            cookie_jar
+        }
+
+        ManagementForm::Disable { code } => {
+            let valid = check_user_code(&mut txn, &encrypter, &session.user, &code)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+
+            if !valid {
+                return render(
+                    &templates,
+                    &url_builder,
+                    session,
+                    Some(InvalidCode),
+                    cookie_jar,
+                    &mut txn,
+                )
+                .await;
+            }
+
+            remove_totp_secret(&mut txn, &session.user)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            cookie_jar
+        }
+    };
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, mas_router::AccountTotp.go()).into_response())
+}
@@ -0,0 +1,177 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Admin console, where administrators search users and act on their account
+
+pub mod user;
+
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::{Duration, Utc};
+use mas_axum_utils::{csrf::CsrfExt, fancy_error, FancyError, SessionInfoExt};
+use mas_config::Encrypter;
+use mas_data_model::BrowserSession;
+use mas_router::{AdminSearch, PostAuthAction, Route};
+use mas_storage::{
+    admin::{list_users, UserFilter, UserSummary},
+    PostgresqlBackend,
+};
+use mas_templates::{AdminIndexContext, AdminUser, ErrorContext, TemplateContext, Templates};
+use sqlx::PgPool;
+
+/// Number of users shown per page
+const PAGE_SIZE: i64 = 50;
+
+/// Administrators have to confirm their credentials at most this many minutes
+/// before using the console
+const REAUTH_AFTER_MINUTES: i64 = 15;
+
+/// Check that the session belongs to an administrator who authenticated
+/// recently. Otherwise, returns the response to send instead: a redirect to
+/// the login or reauth page, or an error page.
+async fn check_admin(
+    templates: &Templates,
+    maybe_session: Option<BrowserSession<PostgresqlBackend>>,
+    next: PostAuthAction,
+) -> Result<Result<BrowserSession<PostgresqlBackend>, Response>, FancyError> {
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::and_then(next);
+        return Ok(Err(login.go().into_response()));
+    };
+
+    if !session.user.is_admin {
+        let response = error_page(
+            templates,
+            StatusCode::FORBIDDEN,
+            "forbidden",
+            "Only administrators can access this page",
+        )
+        .await?;
+        return Ok(Err(response));
+    }
+
+    if !session.was_authenticated_after(Utc::now() - Duration::minutes(REAUTH_AFTER_MINUTES)) {
+        let reauth = mas_router::Reauth::and_then(next);
+        return Ok(Err(reauth.go().into_response()));
+    }
+
+    Ok(Ok(session))
+}
+
+async fn error_page(
+    templates: &Templates,
+    status: StatusCode,
+    code: &'static str,
+    description: &str,
+) -> Result<Response, FancyError> {
+    let ctx = ErrorContext::new()
+        .with_code(code)
+        .with_description(description.to_string());
+
+    let content = templates
+        .render_error(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((status, Html(content)).into_response())
+}
+
+fn admin_user(user: UserSummary) -> AdminUser {
+    AdminUser::new(
+        user.id,
+        user.username,
+        user.primary_email,
+        user.has_verified_email,
+        user.locked,
+        user.is_admin,
+        user.created_at,
+        user.deactivated_at,
+    )
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Query(query): Query<AdminSearch>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = match check_admin(&templates, maybe_session, PostAuthAction::Administrate).await?
+    {
+        Ok(session) => session,
+        Err(response) => return Ok((cookie_jar, response).into_response()),
+    };
+
+    let search = query
+        .search
+        .map(|search| search.trim().to_string())
+        .filter(|search| !search.is_empty());
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let filter = UserFilter {
+        search: search.clone(),
+        ..UserFilter::default()
+    };
+
+    // Fetch one more user to know if there is a next page
+    let mut users = list_users(&mut conn, &filter, PAGE_SIZE + 1, offset)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let has_next_page = users.len() > PAGE_SIZE as usize;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    users.truncate(PAGE_SIZE as usize);
+
+    let page = |offset: i64| {
+        let search = AdminSearch {
+            search: search.clone(),
+            offset: (offset > 0).then(|| offset),
+        };
+        mas_router::Admin::new(search).relative_url().into_owned()
+    };
+    let previous_page = (offset > 0).then(|| page((offset - PAGE_SIZE).max(0)));
+    let next_page = has_next_page.then(|| page(offset + PAGE_SIZE));
+
+    let users = users.into_iter().map(admin_user).collect();
+    let ctx = AdminIndexContext::new(search, users)
+        .with_pages(previous_page, next_page)
+        .with_session(session)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_admin_index(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
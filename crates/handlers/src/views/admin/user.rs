@@ -0,0 +1,392 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form, Path},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::{Duration, Utc};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{ErroredForm, HtmlError, WrapFormError},
+    BrowserSession, User,
+};
+use mas_email::Mailer;
+use mas_router::{PostAuthAction, UrlBuilder};
+use mas_storage::{
+    admin::{
+        end_user_oauth2_session, end_user_session, list_user_consents, list_user_oauth2_sessions,
+        list_user_sessions, list_users, UserFilter,
+    },
+    login_throttle::{clear_login_throttle, ThrottleScope},
+    oauth2::{client::lookup_client_by_client_id, consent::delete_client_consent},
+    suspension::{get_active_suspension, lift_user_suspensions, suspend_user},
+    user::{get_user_email, get_user_emails, lookup_user},
+    PostgresqlBackend,
+};
+use mas_templates::{
+    AccountSuspendedContext, AdminBrowserSession, AdminConsent, AdminLockFormField,
+    AdminOAuth2Session, AdminUserContext, TemplateContext, Templates,
+};
+use serde::Deserialize;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::info;
+
+use super::{admin_user, check_admin, error_page};
+use crate::views::account::emails::{add_verification_code, send_verification_email};
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub(crate) enum ActionForm {
+    Lock {
+        reason: String,
+        expires_in: Option<String>,
+    },
+    Unlock,
+    ResendVerification {
+        data: String,
+    },
+    EndSession {
+        data: String,
+    },
+    #[serde(rename = "end_oauth2_session")]
+    EndOAuth2Session {
+        data: String,
+    },
+    RevokeConsent {
+        data: String,
+    },
+}
+
+#[derive(Debug, Error)]
+#[error("missing suspension reason")]
+struct MissingReason;
+
+impl HtmlError for MissingReason {
+    fn html_display(&self) -> String {
+        "The reason is shown to the user, and can't be empty".to_string()
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("administrator tried to suspend themselves")]
+struct SelfSuspension;
+
+impl HtmlError for SelfSuspension {
+    fn html_display(&self) -> String {
+        "You can't suspend your own account".to_string()
+    }
+}
+
+/// Load the user shown on the page, including deactivated ones
+async fn load_user(
+    templates: &Templates,
+    conn: &mut PgConnection,
+    id: i64,
+) -> Result<Result<User<PostgresqlBackend>, Response>, FancyError> {
+    match lookup_user(conn, id).await {
+        Ok(user) => Ok(Ok(user)),
+        Err(e) if e.not_found() => {
+            let response = error_page(
+                templates,
+                StatusCode::NOT_FOUND,
+                "not_found",
+                "User not found",
+            )
+            .await?;
+            Ok(Err(response))
+        }
+        Err(e) => Err(fancy_error(templates.clone())(e)),
+    }
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Path(user_id): Path<i64>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let next = PostAuthAction::AdministrateUser { data: user_id };
+    let session = match check_admin(&templates, maybe_session, next).await? {
+        Ok(session) => session,
+        Err(response) => return Ok((cookie_jar, response).into_response()),
+    };
+
+    let user = match load_user(&templates, &mut conn, user_id).await? {
+        Ok(user) => user,
+        Err(response) => return Ok((cookie_jar, response).into_response()),
+    };
+
+    let ctx = AdminUserContext::new(admin_summary(&templates, &mut conn, &user).await?);
+    render(templates, session, &user, ctx, cookie_jar, &mut conn).await
+}
+
+async fn admin_summary(
+    templates: &Templates,
+    conn: &mut PgConnection,
+    user: &User<PostgresqlBackend>,
+) -> Result<mas_templates::AdminUser, FancyError> {
+    let filter = UserFilter {
+        id: Some(user.data),
+        ..UserFilter::default()
+    };
+
+    let summary = list_users(conn, &filter, 1, 0)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .pop()
+        .ok_or_else(|| fancy_error(templates.clone())("user disappeared"))?;
+
+    Ok(admin_user(summary))
+}
+
+async fn render(
+    templates: Templates,
+    session: BrowserSession<PostgresqlBackend>,
+    user: &User<PostgresqlBackend>,
+    ctx: AdminUserContext,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    conn: &mut PgConnection,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let suspension = get_active_suspension(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    let emails = get_user_emails(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    let browser_sessions = list_user_sessions(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .into_iter()
+        .map(|s| AdminBrowserSession::new(s.id, s.active, s.created_at, s.last_authenticated_at))
+        .collect();
+    let oauth2_sessions = list_user_oauth2_sessions(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .into_iter()
+        .map(|s| {
+            AdminOAuth2Session::new(
+                s.id,
+                s.user_session_id,
+                s.client_id,
+                s.scope,
+                s.created_at,
+                s.ended_at,
+            )
+        })
+        .collect();
+    let consents = list_user_consents(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .into_iter()
+        .map(|c| AdminConsent::new(c.client_id, c.scope, c.updated_at))
+        .collect();
+
+    let ctx = ctx
+        .with_emails(emails)
+        .with_sessions(browser_sessions, oauth2_sessions)
+        .with_consents(consents);
+    let ctx = if let Some(suspension) = suspension {
+        ctx.with_suspension(AccountSuspendedContext::new(
+            suspension.reason,
+            suspension.expires_at,
+        ))
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_admin_user(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+#[allow(clippy::too_many_lines)]
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Extension(mailer): Extension<Mailer>,
+    Path(user_id): Path<i64>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<ActionForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let next = PostAuthAction::AdministrateUser { data: user_id };
+    let session = match check_admin(&templates, maybe_session, next).await? {
+        Ok(session) => session,
+        Err(response) => return Ok((cookie_jar, response).into_response()),
+    };
+
+    let user = match load_user(&templates, &mut txn, user_id).await? {
+        Ok(user) => user,
+        Err(response) => return Ok((cookie_jar, response).into_response()),
+    };
+
+    let admin = session.user.username.clone();
+    let mut form_error: Option<ErroredForm<AdminLockFormField>> = None;
+    let mut verification = None;
+    let notice = match form {
+        ActionForm::Lock { reason, expires_in } => {
+            let reason = reason.trim();
+            if user.data == session.user.data {
+                form_error = Some(SelfSuspension.on_form());
+                None
+            } else if reason.is_empty() {
+                form_error = Some(MissingReason.on_field(AdminLockFormField::Reason));
+                None
+            } else {
+                let expires_in: Option<u32> = expires_in
+                    .filter(|expires_in| !expires_in.is_empty())
+                    .map(|expires_in| expires_in.parse())
+                    .transpose()
+                    .map_err(fancy_error(templates.clone()))?;
+                let expires_at =
+                    expires_in.map(|seconds| Utc::now() + Duration::seconds(seconds.into()));
+
+                suspend_user(&mut txn, &user, reason, expires_at)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+                info!(%admin, user.id = user.data, "User suspended from the admin console");
+                Some("User suspended".to_string())
+            }
+        }
+        ActionForm::Unlock => {
+            lift_user_suspensions(&mut txn, &user)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            clear_login_throttle(&mut txn, ThrottleScope::Account, &user.username)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            info!(%admin, user.id = user.data, "User unlocked from the admin console");
+            Some("Suspension lifted".to_string())
+        }
+        ActionForm::ResendVerification { data } => {
+            let id = data.parse().map_err(fancy_error(templates.clone()))?;
+            let email = get_user_email(&mut txn, &user, id)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+
+            if email.confirmed_at.is_some() {
+                Some(format!("{} is already verified", email.email))
+            } else {
+                let code = add_verification_code(&mut txn, &email)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+                let notice = format!("Verification email sent to {}", email.email);
+                verification = Some((email, code));
+                Some(notice)
+            }
+        }
+        ActionForm::EndSession { data } => {
+            let id = data.parse().map_err(fancy_error(templates.clone()))?;
+            let ended = end_user_session(&mut txn, &user, id)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            info!(%admin, user.id = user.data, session.id = id, "Browser session ended from the admin console");
+            Some(if ended {
+                "Browser session ended".to_string()
+            } else {
+                "The browser session already ended".to_string()
+            })
+        }
+        ActionForm::EndOAuth2Session { data } => {
+            let id = data.parse().map_err(fancy_error(templates.clone()))?;
+            let ended = end_user_oauth2_session(&mut txn, &user, id)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            info!(%admin, user.id = user.data, session.id = id, "OAuth 2.0 session ended from the admin console");
+            Some(if ended {
+                "OAuth 2.0 session ended".to_string()
+            } else {
+                "The OAuth 2.0 session already ended".to_string()
+            })
+        }
+        ActionForm::RevokeConsent { data } => {
+            let client = lookup_client_by_client_id(&mut txn, &data)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            delete_client_consent(&mut txn, &user, &client)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            info!(%admin, user.id = user.data, client.id = %data, "Consent revoked from the admin console");
+            Some(format!(
+                "The user will be asked again for their consent to {}",
+                data
+            ))
+        }
+    };
+
+    let ctx = AdminUserContext::new(admin_summary(&templates, &mut txn, &user).await?);
+    let ctx = match notice {
+        Some(notice) => ctx.with_notice(notice),
+        None => ctx,
+    };
+    let ctx = match form_error {
+        Some(form_error) => ctx.with_form_error(form_error),
+        None => ctx,
+    };
+
+    let reply = render(templates.clone(), session, &user, ctx, cookie_jar, &mut txn).await?;
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    // The email is only sent once the code is stored, so that the link works
+    if let Some((email, code)) = verification {
+        if let Err(e) = send_verification_email(&mailer, &url_builder, &user, &email, code).await {
+            tracing::error!(
+                error = &*e as &dyn std::error::Error,
+                "Could not send the verification email"
+            );
+        }
+    }
+
+    Ok(reply)
+}
@@ -0,0 +1,176 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{
+    extract::{Extension, Form, Query},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{BrowserSession, UserEmail};
+use mas_email::Mailer;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{user::get_user_emails, PostgresqlBackend};
+use mas_templates::{EmailVerificationRequiredContext, TemplateContext, Templates};
+use sqlx::{PgConnection, PgPool};
+
+use super::{account::emails::start_email_verification, shared::OptionalPostAuthAction};
+use crate::registration::RegistrationPolicy;
+
+/// The address the user is asked to verify: the primary one if it is not
+/// verified yet, else any unverified one
+async fn pending_email(
+    conn: &mut PgConnection,
+    session: &BrowserSession<PostgresqlBackend>,
+) -> anyhow::Result<Option<UserEmail<PostgresqlBackend>>> {
+    let primary = session.user.primary_email.as_ref().map(|email| email.data);
+    let mut emails = get_user_emails(conn, &session.user).await?;
+    emails.sort_by_key(|email| Some(email.data) != primary);
+    Ok(emails
+        .into_iter()
+        .find(|email| email.confirmed_at.is_none()))
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::from(query.post_auth_action);
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    // Once the address is verified, the "Continue" button leads back here and
+    // is sent to the next step
+    let needs_verification = registration_policy
+        .needs_email_verification(&mut conn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    if !needs_verification {
+        let reply = query.go_next();
+        return Ok((cookie_jar, reply).into_response());
+    }
+
+    render(&templates, &mut conn, session, query, false, cookie_jar).await
+}
+
+async fn render(
+    templates: &Templates,
+    conn: &mut PgConnection,
+    session: BrowserSession<PostgresqlBackend>,
+    query: OptionalPostAuthAction,
+    sent: bool,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let email = pending_email(conn, &session)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let next = query
+        .load_context(conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let continue_link =
+        mas_router::EmailVerificationRequired::from(query.post_auth_action).relative_url();
+    let ctx = EmailVerificationRequiredContext::new(
+        email.map(|email| email.email),
+        continue_link.to_string(),
+    );
+    let ctx = if sent { ctx.sent() } else { ctx };
+    let ctx = if let Some(next) = next {
+        ctx.with_post_action(next)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_email_verification_required(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+/// Send the verification link again
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<()>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    let maybe_session = session_info
+        .load_session(&mut txn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let session = if let Some(session) = maybe_session {
+        session
+    } else {
+        let login = mas_router::Login::from(query.post_auth_action);
+        return Ok((cookie_jar, login.go()).into_response());
+    };
+
+    let email = pending_email(&mut txn, &session)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let sent = if let Some(email) = email {
+        start_email_verification(&mailer, &url_builder, &mut txn, &session.user, &email)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+        true
+    } else {
+        false
+    };
+
+    let reply = render(&templates, &mut txn, session, query, sent, cookie_jar).await?;
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+    Ok(reply)
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use axum::{
     extract::{Extension, Form, Query},
     response::{Html, IntoResponse, Response},
@@ -22,14 +24,23 @@ use mas_axum_utils::{
     fancy_error, FancyError, SessionInfoExt,
 };
 use mas_config::Encrypter;
-use mas_data_model::errors::WrapFormError;
-use mas_router::Route;
-use mas_storage::user::login;
+use mas_data_model::{errors::WrapFormError, AuthenticationMethod};
+use mas_email::Mailer;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::user::{record_session_authentication, start_session};
 use mas_templates::{LoginContext, LoginFormField, TemplateContext, Templates};
 use serde::Deserialize;
 use sqlx::PgPool;
 
-use super::shared::OptionalPostAuthAction;
+use super::{second_factor, shared::OptionalPostAuthAction, suspended};
+use crate::{
+    brute_force::{BruteForceProtection, ClientIp},
+    passwords::{PasswordBackends, PasswordLoginError},
+    registration::RegistrationPolicy,
+    upstream_oauth2::UpstreamProviders,
+    username_policy::UsernamePolicy,
+    webauthn::{self, RelyingParty},
+};
 
 #[derive(Deserialize)]
 pub(crate) struct LoginForm {
@@ -37,10 +48,20 @@ pub(crate) struct LoginForm {
     password: String,
 }
 
-#[tracing::instrument(skip(templates, pool, cookie_jar))]
+#[tracing::instrument(skip(
+    templates,
+    pool,
+    registration_policy,
+    upstream_providers,
+    url_builder,
+    cookie_jar
+))]
 pub(crate) async fn get(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
+    Extension(upstream_providers): Extension<Arc<UpstreamProviders>>,
+    Extension(url_builder): Extension<UrlBuilder>,
     Query(query): Query<OptionalPostAuthAction>,
     cookie_jar: PrivateCookieJar<Encrypter>,
 ) -> Result<Response, FancyError> {
@@ -71,9 +92,23 @@ pub(crate) async fn get(
         } else {
             ctx
         };
+        let upstream_links = upstream_providers.links(query.post_auth_action.as_ref());
+        let passkey_action =
+            mas_router::PasskeyLogin::from(query.post_auth_action.clone()).relative_url();
         let register_link = mas_router::Register::from(query.post_auth_action).relative_url();
+
+        // Any discoverable credential can be used to sign in without a username
+        let (challenge, cookie_jar) = webauthn::start_challenge(cookie_jar);
+        let passkey_options = RelyingParty::new(&url_builder).request_options(&challenge, &[]);
+
+        let ctx = if registration_policy.enabled() {
+            ctx.with_register_link(register_link.to_string())
+        } else {
+            ctx
+        };
         let ctx = ctx
-            .with_register_link(register_link.to_string())
+            .with_webauthn(passkey_options, passkey_action.to_string())
+            .with_upstream_providers(upstream_links)
             .with_csrf(csrf_token.form_value());
 
         let content = templates
@@ -85,18 +120,22 @@ pub(crate) async fn get(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn post(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(password_backends): Extension<PasswordBackends>,
+    Extension(username_policy): Extension<UsernamePolicy>,
+    Extension(brute_force): Extension<BruteForceProtection>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<OptionalPostAuthAction>,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Form(form): Form<ProtectedForm<LoginForm>>,
 ) -> Result<Response, FancyError> {
     use mas_storage::user::LoginError;
-    let mut conn = pool
-        .acquire()
-        .await
-        .map_err(fancy_error(templates.clone()))?;
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
 
     let form = cookie_jar
         .verify_form(form)
@@ -104,29 +143,93 @@ pub(crate) async fn post(
 
     let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
 
-    // TODO: recover
-    match login(&mut conn, &form.username, form.password).await {
-        Ok(session_info) => {
-            let cookie_jar = cookie_jar.set_session(&session_info);
-            let reply = query.go_next();
-            Ok((cookie_jar, reply).into_response())
-        }
-        Err(e) => {
-            let errored_form = match e {
-                LoginError::NotFound { .. } => e.on_field(LoginFormField::Username),
-                LoginError::Authentication { .. } => e.on_field(LoginFormField::Password),
-                LoginError::Other(_) => e.on_form(),
-            };
-            let ctx = LoginContext::default()
-                .with_form_error(errored_form)
-                .with_csrf(csrf_token.form_value());
-
-            let content = templates
-                .render_login(&ctx)
+    let throttled = brute_force
+        .check(&mut txn, &form.username, ip)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let errored_form = if let Some(throttled) = throttled {
+        throttled.on_form()
+    } else {
+        // TODO: recover
+        match password_backends
+            .verify(&mut txn, &username_policy, &form.username, form.password)
+            .await
+        {
+            Ok(user) => {
+                let cookie_jar =
+                    match suspended::check(&templates, &mut txn, &user, cookie_jar).await? {
+                        Ok(cookie_jar) => cookie_jar,
+                        Err(reply) => return Ok(reply),
+                    };
+
+                let has_second_factor = second_factor::required(&mut txn, &user)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+
+                if has_second_factor {
+                    // Directory users might have been provisioned by the password check
+                    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+                    let reply = second_factor::challenge(cookie_jar, &user, None, &query)
+                        .map_err(fancy_error(templates.clone()))?;
+                    return Ok(reply.into_response());
+                }
+
+                brute_force
+                    .record_success(&mut txn, &form.username)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+
+                let mut session = start_session(&mut txn, user)
+                    .await
+                    .map_err(fancy_error(templates.clone()))?;
+                record_session_authentication(
+                    &mut txn,
+                    &mut session,
+                    &[AuthenticationMethod::Password],
+                )
                 .await
                 .map_err(fancy_error(templates.clone()))?;
+                txn.commit().await.map_err(fancy_error(templates.clone()))?;
 
-            Ok((cookie_jar, Html(content)).into_response())
+                let cookie_jar = cookie_jar.set_session(&session);
+                let reply = query.go_next();
+                return Ok((cookie_jar, reply).into_response());
+            }
+            Err(e) => {
+                if !matches!(
+                    e,
+                    PasswordLoginError::Other(_)
+                        | PasswordLoginError::Deactivated(_)
+                        | PasswordLoginError::Username(_)
+                        | PasswordLoginError::Local(LoginError::Other(_))
+                ) {
+                    brute_force
+                        .record_failure(&pool, &mailer, &url_builder, &form.username, ip)
+                        .await
+                        .map_err(fancy_error(templates.clone()))?;
+                }
+
+                match e {
+                    PasswordLoginError::Local(LoginError::NotFound { .. })
+                    | PasswordLoginError::Username(_) => e.on_field(LoginFormField::Username),
+                    PasswordLoginError::Local(LoginError::Authentication { .. }) => {
+                        e.on_field(LoginFormField::Password)
+                    }
+                    _ => e.on_form(),
+                }
+            }
         }
-    }
+    };
+
+    let ctx = LoginContext::default()
+        .with_form_error(errored_form)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_login(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
 }
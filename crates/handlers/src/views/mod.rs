@@ -13,10 +13,17 @@
 // limitations under the License.
 
 pub mod account;
+pub mod admin;
+pub mod email_verification_required;
 pub mod index;
 pub mod login;
 pub mod logout;
+pub mod passkey_login;
+pub mod password_forgot;
+pub mod password_reset;
 pub mod reauth;
 pub mod register;
+pub mod second_factor;
 pub mod shared;
+pub mod suspended;
 pub mod verify;
@@ -0,0 +1,157 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Passwordless sign-in with a discoverable passkey, started from the login
+//! page
+
+use axum::{
+    extract::{Extension, Form, Query},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{ErroredForm, WrapFormError},
+    AuthenticationMethod, User,
+};
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{
+    user::{record_session_authentication, start_session, username_deactivated},
+    webauthn::{lookup_webauthn_credential, update_webauthn_sign_count},
+    PostgresqlBackend,
+};
+use mas_templates::{LoginContext, LoginFormField, TemplateContext, Templates};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use super::{
+    shared::OptionalPostAuthAction,
+    suspended::{self, AccountDeactivated},
+};
+use crate::webauthn::{self, PublicKeyCredential, RelyingParty, WebauthnError};
+
+#[derive(Deserialize)]
+pub(crate) struct PasskeyForm {
+    credential: String,
+}
+
+/// Find the owner of the credential and check the assertion, updating the
+/// signature counter of the credential
+async fn authenticate(
+    txn: &mut Transaction<'_, Postgres>,
+    relying_party: &RelyingParty,
+    challenge: Result<String, WebauthnError>,
+    credential: &str,
+) -> anyhow::Result<Result<User<PostgresqlBackend>, ErroredForm<LoginFormField>>> {
+    let challenge = match challenge {
+        Ok(challenge) => challenge,
+        Err(e) => return Ok(Err(e.on_form())),
+    };
+
+    let credential: PublicKeyCredential = match serde_json::from_str(credential) {
+        Ok(credential) => credential,
+        Err(_) => return Ok(Err(WebauthnError::Malformed.on_form())),
+    };
+
+    let (stored, user) = match lookup_webauthn_credential(&mut *txn, credential.id()).await? {
+        Some(found) => found,
+        None => return Ok(Err(WebauthnError::UnknownCredential.on_form())),
+    };
+
+    // Without a password, the authenticator must have verified the user
+    let sign_count =
+        match relying_party.verify_assertion(&challenge, &credential, &stored, &user, true) {
+            Ok(sign_count) => sign_count,
+            Err(e) => return Ok(Err(e.on_form())),
+        };
+    update_webauthn_sign_count(&mut *txn, &stored, sign_count.into()).await?;
+
+    if username_deactivated(&mut *txn, &user.username).await? {
+        return Ok(Err(AccountDeactivated.on_form()));
+    }
+
+    Ok(Ok(user))
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Query(query): Query<OptionalPostAuthAction>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<PasskeyForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let relying_party = RelyingParty::new(&url_builder);
+    let (challenge, cookie_jar) = webauthn::take_challenge(cookie_jar);
+
+    let result = authenticate(&mut txn, &relying_party, challenge, &form.credential)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    match result {
+        Ok(user) => {
+            let cookie_jar = match suspended::check(&templates, &mut txn, &user, cookie_jar).await?
+            {
+                Ok(cookie_jar) => cookie_jar,
+                Err(reply) => return Ok(reply),
+            };
+
+            let mut session = start_session(&mut txn, user)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            record_session_authentication(
+                &mut txn,
+                &mut session,
+                &[AuthenticationMethod::Webauthn],
+            )
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+            txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+            let cookie_jar = cookie_jar.set_session(&session);
+            Ok((cookie_jar, query.go_next()).into_response())
+        }
+        Err(e) => {
+            // Offer to try again with a fresh challenge
+            let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+            let (challenge, cookie_jar) = webauthn::start_challenge(cookie_jar);
+            let passkey_action =
+                mas_router::PasskeyLogin::from(query.post_auth_action).relative_url();
+            let ctx = LoginContext::default()
+                .with_form_error(e)
+                .with_webauthn(
+                    relying_party.request_options(&challenge, &[]),
+                    passkey_action.to_string(),
+                )
+                .with_csrf(csrf_token.form_value());
+
+            let content = templates
+                .render_login(&ctx)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+
+            Ok((cookie_jar, Html(content)).into_response())
+        }
+    }
+}
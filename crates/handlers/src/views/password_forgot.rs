@@ -0,0 +1,170 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Ask for an email address to send a password reset link to
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use lettre::{message::Mailbox, Address};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{HtmlError, WrapFormError},
+    User, UserEmail,
+};
+use mas_email::Mailer;
+use mas_router::UrlBuilder;
+use mas_storage::{
+    password_reset::{add_password_reset, lookup_user_by_verified_email},
+    PostgresqlBackend,
+};
+use mas_templates::{PasswordForgotContext, PasswordResetEmailContext, TemplateContext, Templates};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::Deserialize;
+use sqlx::PgPool;
+use thiserror::Error;
+
+use crate::brute_force::{BruteForceProtection, ClientIp};
+
+#[derive(Deserialize)]
+pub(crate) struct ForgotForm {
+    email: String,
+}
+
+#[derive(Debug, Error)]
+#[error("too many password reset requests")]
+struct TooManyRequests;
+
+impl HtmlError for TooManyRequests {
+    fn html_display(&self) -> String {
+        "Too many reset links were asked for. Try again later".to_string()
+    }
+}
+
+async fn render(
+    templates: &Templates,
+    ctx: PasswordForgotContext,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let ctx = ctx.with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_password_forgot(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    render(&templates, PasswordForgotContext::default(), cookie_jar).await
+}
+
+async fn send_reset_link(
+    mailer: &Mailer,
+    url_builder: &UrlBuilder,
+    user: &User<PostgresqlBackend>,
+    user_email: &UserEmail<PostgresqlBackend>,
+    code: String,
+) -> anyhow::Result<()> {
+    let address: Address = user_email.email.parse()?;
+    let mailbox = Mailbox::new(Some(user.username.clone()), address);
+
+    let link = url_builder.password_reset(code);
+    let context = PasswordResetEmailContext::new(user.clone().into(), link);
+
+    mailer.send_password_reset_email(mailbox, &context).await?;
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    Extension(brute_force): Extension<BruteForceProtection>,
+    ClientIp(ip): ClientIp,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<ForgotForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+    let email = form.email.trim();
+
+    // Requests are counted whether the address is known or not
+    let throttled = brute_force
+        .check_password_reset(&mut txn, email, ip)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    if throttled.is_some() {
+        let ctx = PasswordForgotContext::default().with_form_error(TooManyRequests.on_form());
+        return render(&templates, ctx, cookie_jar).await;
+    }
+
+    brute_force
+        .record_password_reset(&mut txn, email, ip)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let found = lookup_user_by_verified_email(&mut txn, email)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let reset = if let Some((user, user_email)) = found {
+        let code: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        add_password_reset(&mut txn, &user_email, &code)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+
+        Some((user, user_email, code))
+    } else {
+        None
+    };
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    // The answer is the same whether the address is known or not, and whether
+    // the email could be sent or not, so that it can't be used to find out who
+    // has an account
+    if let Some((user, user_email, code)) = reset {
+        if let Err(e) = send_reset_link(&mailer, &url_builder, &user, &user_email, code).await {
+            tracing::error!(
+                error = &*e as &dyn std::error::Error,
+                "Could not send the password reset email"
+            );
+        }
+    }
+
+    render(&templates, PasswordForgotContext::sent(), cookie_jar).await
+}
@@ -0,0 +1,148 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Choose a new password from a reset link
+
+use axum::{
+    extract::{Extension, Form, Path},
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use chrono::Duration;
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, FancyError,
+};
+use mas_config::Encrypter;
+use mas_data_model::errors::{ErroredForm, WrapFormError};
+use mas_router::Route;
+use mas_storage::{
+    password_reset::{consume_password_reset, lookup_password_reset, PasswordReset},
+    user::{end_user_sessions, set_password},
+};
+use mas_templates::{PasswordResetContext, PasswordResetFormField, TemplateContext, Templates};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::{
+    password_policy::{user_identifiers, PasswordMismatch, PasswordPolicy},
+    passwords::PasswordBackends,
+};
+
+#[derive(Deserialize)]
+pub(crate) struct ResetForm {
+    password: String,
+    password_confirm: String,
+}
+
+async fn load(
+    templates: &Templates,
+    txn: &mut Transaction<'_, Postgres>,
+    code: &str,
+) -> Result<PasswordReset, FancyError> {
+    // TODO: make this configurable
+    lookup_password_reset(txn, code, Duration::hours(1))
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .ok_or_else(|| anyhow::anyhow!("This password reset link is invalid or has expired"))
+        .map_err(fancy_error(templates.clone()))
+}
+
+async fn render(
+    templates: &Templates,
+    reset: &PasswordReset,
+    form_error: Option<ErroredForm<PasswordResetFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let ctx = PasswordResetContext::new(reset.user.username.clone());
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_password_reset(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Path(code): Path<String>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+    let reset = load(&templates, &mut txn, &code).await?;
+    render(&templates, &reset, None, cookie_jar).await
+}
+
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(password_policy): Extension<PasswordPolicy>,
+    Extension(password_backends): Extension<PasswordBackends>,
+    Path(code): Path<String>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<ResetForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let reset = load(&templates, &mut txn, &code).await?;
+
+    if form.password != form.password_confirm {
+        let form_error = PasswordMismatch.on_field(PasswordResetFormField::PasswordConfirm);
+        return render(&templates, &reset, Some(form_error), cookie_jar).await;
+    }
+
+    let identifiers = user_identifiers(&mut txn, &reset.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    if let Err(e) = password_policy.check(&form.password, &identifiers) {
+        let form_error = e.on_field(PasswordResetFormField::Password);
+        return render(&templates, &reset, Some(form_error), cookie_jar).await;
+    }
+
+    set_password(
+        &mut txn,
+        password_backends.hasher(),
+        &reset.user,
+        &form.password,
+    )
+    .await
+    .map_err(fancy_error(templates.clone()))?;
+
+    consume_password_reset(&mut txn, &reset)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    // Whoever knew the old password must not stay logged in
+    end_user_sessions(&mut txn, &reset.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, mas_router::Login::default().go()).into_response())
+}
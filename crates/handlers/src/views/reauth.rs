@@ -22,19 +22,39 @@ use mas_axum_utils::{
     fancy_error, FancyError, SessionInfoExt,
 };
 use mas_config::Encrypter;
-use mas_router::Route;
-use mas_storage::user::authenticate_session;
-use mas_templates::{ReauthContext, TemplateContext, Templates};
+use mas_data_model::{
+    errors::{ErroredForm, HtmlError, WrapFormError},
+    AuthenticationMethod, BrowserSession,
+};
+use mas_email::Mailer;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{user::record_session_authentication, PostgresqlBackend};
+use mas_templates::{ReauthContext, ReauthFormField, TemplateContext, Templates};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
 
-use super::shared::OptionalPostAuthAction;
+use super::{second_factor, shared::OptionalPostAuthAction};
+use crate::{
+    brute_force::{BruteForceProtection, ClientIp},
+    passwords::PasswordBackends,
+};
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct ReauthForm {
     password: String,
 }
 
+#[derive(Debug, Error)]
+#[error("invalid password")]
+struct WrongPassword;
+
+impl HtmlError for WrongPassword {
+    fn html_display(&self) -> String {
+        "Invalid password".to_string()
+    }
+}
+
 pub(crate) async fn get(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
@@ -46,7 +66,6 @@ pub(crate) async fn get(
         .await
         .map_err(fancy_error(templates.clone()))?;
 
-    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
     let maybe_session = session_info
@@ -63,9 +82,22 @@ pub(crate) async fn get(
         return Ok((cookie_jar, login.go()).into_response());
     };
 
+    render(&templates, &mut conn, session, query, None, cookie_jar).await
+}
+
+async fn render(
+    templates: &Templates,
+    conn: &mut PgConnection,
+    session: BrowserSession<PostgresqlBackend>,
+    query: OptionalPostAuthAction,
+    form_error: Option<ErroredForm<ReauthFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
     let ctx = ReauthContext::default();
     let next = query
-        .load_context(&mut conn)
+        .load_context(conn)
         .await
         .map_err(fancy_error(templates.clone()))?;
     let ctx = if let Some(next) = next {
@@ -73,6 +105,11 @@ pub(crate) async fn get(
     } else {
         ctx
     };
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
     let ctx = ctx.with_session(session).with_csrf(csrf_token.form_value());
 
     let content = templates
@@ -83,9 +120,15 @@ pub(crate) async fn get(
     Ok((cookie_jar, Html(content)).into_response())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn post(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(password_backends): Extension<PasswordBackends>,
+    Extension(brute_force): Extension<BruteForceProtection>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    ClientIp(ip): ClientIp,
     Query(query): Query<OptionalPostAuthAction>,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Form(form): Form<ProtectedForm<ReauthForm>>,
@@ -112,8 +155,67 @@ pub(crate) async fn post(
         return Ok((cookie_jar, login.go()).into_response());
     };
 
-    // TODO: recover from errors here
-    authenticate_session(&mut txn, &mut session, form.password)
+    let username = session.user.username.clone();
+
+    let throttled = brute_force
+        .check(&mut txn, &username, ip)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if let Some(throttled) = throttled {
+        let form_error = throttled.on_form();
+        return render(
+            &templates,
+            &mut txn,
+            session,
+            query,
+            Some(form_error),
+            cookie_jar,
+        )
+        .await;
+    }
+
+    let valid = password_backends
+        .verify_session(&mut txn, &session, form.password)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if !valid {
+        brute_force
+            .record_failure(&pool, &mailer, &url_builder, &username, ip)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+
+        let form_error = WrongPassword.on_field(ReauthFormField::Password);
+        return render(
+            &templates,
+            &mut txn,
+            session,
+            query,
+            Some(form_error),
+            cookie_jar,
+        )
+        .await;
+    }
+
+    let has_second_factor = second_factor::required(&mut txn, &session.user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    // The reauthentication is only recorded once the second factor is checked
+    if has_second_factor {
+        txn.commit().await.map_err(fancy_error(templates.clone()))?;
+        let reply = second_factor::challenge(cookie_jar, &session.user, Some(&session), &query)
+            .map_err(fancy_error(templates.clone()))?;
+        return Ok(reply.into_response());
+    }
+
+    brute_force
+        .record_success(&mut txn, &username)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    record_session_authentication(&mut txn, &mut session, &[AuthenticationMethod::Password])
         .await
         .map_err(fancy_error(templates.clone()))?;
     let cookie_jar = cookie_jar.set_session(&session);
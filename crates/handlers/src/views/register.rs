@@ -14,7 +14,6 @@
 
 #![allow(clippy::trait_duplication_in_bounds)]
 
-use argon2::Argon2;
 use axum::{
     extract::{Extension, Form, Query},
     response::{Html, IntoResponse, Response},
@@ -25,24 +24,47 @@ use mas_axum_utils::{
     fancy_error, FancyError, SessionInfoExt,
 };
 use mas_config::Encrypter;
-use mas_router::Route;
-use mas_storage::user::{register_user, start_session};
-use mas_templates::{RegisterContext, TemplateContext, Templates};
+use mas_data_model::errors::{ErroredForm, WrapFormError};
+use mas_email::Mailer;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::user::{
+    add_user_email, register_user, set_user_email_as_primary, start_session, username_exists,
+};
+use mas_templates::{RegisterContext, RegisterFormField, TemplateContext, Templates};
 use serde::Deserialize;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 
-use super::shared::OptionalPostAuthAction;
+use super::{
+    account::emails::{add_verification_code, send_verification_email},
+    shared::OptionalPostAuthAction,
+};
+use crate::{
+    brute_force::ClientIp,
+    captcha::{CaptchaError, CaptchaForm, CaptchaVerifier},
+    password_policy::{PasswordMismatch, PasswordPolicy},
+    passwords::PasswordBackends,
+    registration::{RegistrationError, RegistrationPolicy},
+    username_policy::{UsernamePolicy, UsernamePolicyError},
+};
 
 #[derive(Deserialize)]
 pub(crate) struct RegisterForm {
     username: String,
     password: String,
     password_confirm: String,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    token: String,
+    #[serde(flatten)]
+    captcha: CaptchaForm,
 }
 
 pub(crate) async fn get(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
+    Extension(captcha): Extension<CaptchaVerifier>,
     Query(query): Query<OptionalPostAuthAction>,
     cookie_jar: PrivateCookieJar<Encrypter>,
 ) -> Result<Response, FancyError> {
@@ -51,7 +73,6 @@ pub(crate) async fn get(
         .await
         .map_err(fancy_error(templates.clone()))?;
 
-    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
     let (session_info, cookie_jar) = cookie_jar.session_info();
 
     let maybe_session = session_info
@@ -63,58 +84,227 @@ pub(crate) async fn get(
         let reply = query.go_next();
         Ok((cookie_jar, reply).into_response())
     } else {
-        let ctx = RegisterContext::default();
-        let next = query
-            .load_context(&mut conn)
-            .await
-            .map_err(fancy_error(templates.clone()))?;
-        let ctx = if let Some(next) = next {
-            ctx.with_post_action(next)
-        } else {
-            ctx
-        };
-        let login_link = mas_router::Login::from(query.post_auth_action).relative_url();
-        let ctx = ctx.with_login_link(login_link.to_string());
-        let ctx = ctx.with_csrf(csrf_token.form_value());
-
-        let content = templates
-            .render_register(&ctx)
-            .await
-            .map_err(fancy_error(templates.clone()))?;
+        render(
+            &templates,
+            &registration_policy,
+            &captcha,
+            &mut conn,
+            query,
+            None,
+            cookie_jar,
+        )
+        .await
+    }
+}
+
+async fn render(
+    templates: &Templates,
+    registration_policy: &RegistrationPolicy,
+    captcha: &CaptchaVerifier,
+    conn: &mut PgConnection,
+    query: OptionalPostAuthAction,
+    form_error: Option<ErroredForm<RegisterFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let (captcha, cookie_jar) = captcha.challenge(cookie_jar);
 
-        Ok((cookie_jar, Html(content)).into_response())
+    let ctx = RegisterContext::default();
+    let ctx = if registration_policy.enabled() {
+        ctx
+    } else {
+        ctx.disabled()
+    };
+    let ctx = if registration_policy.email_required() {
+        ctx.with_email_required()
+    } else {
+        ctx
+    };
+    let ctx = if registration_policy.token_required() {
+        ctx.with_token_required()
+    } else {
+        ctx
+    };
+    let ctx = if let Some(captcha) = captcha {
+        ctx.with_captcha(captcha)
+    } else {
+        ctx
+    };
+    let next = query
+        .load_context(conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    let ctx = if let Some(next) = next {
+        ctx.with_post_action(next)
+    } else {
+        ctx
+    };
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+    let login_link = mas_router::Login::from(query.post_auth_action).relative_url();
+    let ctx = ctx.with_login_link(login_link.to_string());
+    let ctx = ctx.with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_register(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+/// Check the registration form, returning the error to display if it is not
+/// acceptable.
+///
+/// This consumes the registration token, so the transaction must be rolled
+/// back if the registration does not go through.
+async fn check_form(
+    conn: &mut PgConnection,
+    registration_policy: &RegistrationPolicy,
+    username_policy: &UsernamePolicy,
+    password_policy: &PasswordPolicy,
+    captcha_check: Result<(), CaptchaError>,
+    form: &RegisterForm,
+) -> anyhow::Result<Result<(), ErroredForm<RegisterFormField>>> {
+    if !registration_policy.enabled() {
+        return Ok(Err(RegistrationError::Disabled.on_form()));
     }
+
+    if let Err(e) = captcha_check {
+        return Ok(Err(e.on_form()));
+    }
+
+    if let Err(e) = username_policy.check(&form.username) {
+        return Ok(Err(e.on_field(RegisterFormField::Username)));
+    }
+
+    if username_exists(&mut *conn, &form.username).await? {
+        return Ok(Err(
+            UsernamePolicyError::Taken.on_field(RegisterFormField::Username)
+        ));
+    }
+
+    if let Err(e) = registration_policy.check_email(&form.email) {
+        return Ok(Err(e.on_field(RegisterFormField::Email)));
+    }
+
+    if form.password != form.password_confirm {
+        return Ok(Err(
+            PasswordMismatch.on_field(RegisterFormField::PasswordConfirm)
+        ));
+    }
+
+    let mut identifiers = vec![form.username.as_str()];
+    if !form.email.is_empty() {
+        identifiers.push(&form.email);
+    }
+    if let Err(e) = password_policy.check(&form.password, &identifiers) {
+        return Ok(Err(e.on_field(RegisterFormField::Password)));
+    }
+
+    // The token is checked last, so that it is only consumed if the rest of the
+    // form is valid
+    if let Err(e) = registration_policy
+        .use_token(&mut *conn, &form.token)
+        .await?
+    {
+        return Ok(Err(e.on_field(RegisterFormField::Token)));
+    }
+
+    Ok(Ok(()))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn post(
     Extension(templates): Extension<Templates>,
     Extension(pool): Extension<PgPool>,
+    Extension(password_policy): Extension<PasswordPolicy>,
+    Extension(password_backends): Extension<PasswordBackends>,
+    Extension(registration_policy): Extension<RegistrationPolicy>,
+    Extension(username_policy): Extension<UsernamePolicy>,
+    Extension(captcha): Extension<CaptchaVerifier>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
     Query(query): Query<OptionalPostAuthAction>,
+    ClientIp(ip): ClientIp,
     cookie_jar: PrivateCookieJar<Encrypter>,
     Form(form): Form<ProtectedForm<RegisterForm>>,
 ) -> Result<Response, FancyError> {
-    // TODO: display nice form errors
     let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
 
     let form = cookie_jar
         .verify_form(form)
         .map_err(fancy_error(templates.clone()))?;
 
-    if form.password != form.password_confirm {
-        return Err(anyhow::anyhow!("password mismatch")).map_err(fancy_error(templates.clone()));
+    let (captcha_check, cookie_jar) = captcha.verify(&pool, cookie_jar, &form.captcha, ip).await;
+    let captcha_check = captcha_check.map_err(fancy_error(templates.clone()))?;
+
+    let form_check = check_form(
+        &mut txn,
+        &registration_policy,
+        &username_policy,
+        &password_policy,
+        captcha_check,
+        &form,
+    )
+    .await
+    .map_err(fancy_error(templates.clone()))?;
+
+    if let Err(form_error) = form_check {
+        return render(
+            &templates,
+            &registration_policy,
+            &captcha,
+            &mut txn,
+            query,
+            Some(form_error),
+            cookie_jar,
+        )
+        .await;
     }
 
-    let pfh = Argon2::default();
+    let pfh = password_backends.hasher();
     let user = register_user(&mut txn, pfh, &form.username, &form.password)
         .await
         .map_err(fancy_error(templates.clone()))?;
 
+    let verification = if form.email.is_empty() {
+        None
+    } else {
+        let user_email = add_user_email(&mut txn, &user, form.email)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+        set_user_email_as_primary(&mut txn, &user_email)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+        let code = add_verification_code(&mut txn, &user_email)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+        Some((user_email, code))
+    };
+
     let session = start_session(&mut txn, user)
         .await
         .map_err(fancy_error(templates.clone()))?;
 
     txn.commit().await.map_err(fancy_error(templates.clone()))?;
 
+    // The email is only sent once the account exists, so that the link works.
+    // If it fails, the user can still ask for a new one.
+    if let Some((user_email, code)) = verification {
+        if let Err(e) =
+            send_verification_email(&mailer, &url_builder, &session.user, &user_email, code).await
+        {
+            tracing::error!(
+                error = &*e as &dyn std::error::Error,
+                "Could not send the verification email"
+            );
+        }
+    }
+
     let cookie_jar = cookie_jar.set_session(&session);
     let reply = query.go_next();
     Ok((cookie_jar, reply).into_response())
@@ -0,0 +1,422 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Second factor prompt, shown after a successful password check for users
+//! who enrolled an authenticator app or a passkey
+
+use axum::{
+    extract::{Extension, Form},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use chrono::{DateTime, Duration, Utc};
+use mas_axum_utils::{
+    csrf::{CsrfExt, ProtectedForm},
+    fancy_error, CookieExt, FancyError, SessionInfoExt,
+};
+use mas_config::Encrypter;
+use mas_data_model::{
+    errors::{ErroredForm, HtmlError, WrapFormError},
+    AuthenticationMethod, BrowserSession, User,
+};
+use mas_email::Mailer;
+use mas_router::{Route, UrlBuilder};
+use mas_storage::{
+    recovery_codes::{consume_recovery_code, count_unused_recovery_codes},
+    totp::lookup_totp_secret,
+    user::{lookup_user_by_username, record_session_authentication, start_session},
+    webauthn::{get_user_webauthn_credentials, update_webauthn_sign_count},
+    PostgresqlBackend,
+};
+use mas_templates::{SecondFactorContext, TemplateContext, Templates, TotpFormField};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+
+use super::shared::OptionalPostAuthAction;
+use crate::{
+    brute_force::{BruteForceProtection, ClientIp},
+    recovery_codes,
+    totp::check_user_code,
+    webauthn::{self, PublicKeyCredential, RelyingParty, WebauthnError},
+};
+
+const COOKIE_NAME: &str = "second-factor";
+
+/// A password check waiting for the second factor
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    username: String,
+    /// The session being reauthenticated, if any
+    session_id: Option<i64>,
+    /// The post auth action, URL-encoded as it can't go through the cookie
+    /// encoding directly
+    post_auth_action: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub(crate) enum SecondFactorForm {
+    Totp { code: String },
+    Webauthn { credential: String },
+    RecoveryCode { code: String },
+}
+
+#[derive(Debug, Error)]
+#[error("invalid code")]
+struct InvalidCode;
+
+impl HtmlError for InvalidCode {
+    fn html_display(&self) -> String {
+        "Invalid code".to_string()
+    }
+}
+
+/// Ask for a second factor before logging in the user, or before recording the
+/// reauthentication of the given session
+pub(crate) fn challenge(
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    user: &User<PostgresqlBackend>,
+    session: Option<&BrowserSession<PostgresqlBackend>>,
+    query: &OptionalPostAuthAction,
+) -> anyhow::Result<(PrivateCookieJar<Encrypter>, Redirect)> {
+    let pending = PendingChallenge {
+        username: user.username.clone(),
+        session_id: session.map(|s| s.data),
+        post_auth_action: serde_urlencoded::to_string(query)?,
+        expires_at: Utc::now() + Duration::minutes(5),
+    };
+
+    let mut cookie = Cookie::new(COOKIE_NAME, "");
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    let cookie_jar = cookie_jar.add(cookie.encode(&pending));
+
+    Ok((cookie_jar, mas_router::SecondFactor.go()))
+}
+
+/// Whether the user enrolled any second factor, and must go through the
+/// challenge after the password check
+pub(crate) async fn required(
+    conn: &mut PgConnection,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<bool> {
+    if lookup_totp_secret(&mut *conn, user).await?.is_some() {
+        return Ok(true);
+    }
+
+    let credentials = get_user_webauthn_credentials(&mut *conn, user).await?;
+    Ok(!credentials.is_empty())
+}
+
+fn load_pending(cookie_jar: &PrivateCookieJar<Encrypter>) -> Option<PendingChallenge> {
+    let pending: PendingChallenge = cookie_jar.get(COOKIE_NAME)?.decode().ok()?;
+    (pending.expires_at > Utc::now()).then(|| pending)
+}
+
+async fn render(
+    templates: &Templates,
+    url_builder: &UrlBuilder,
+    conn: &mut PgConnection,
+    pending: &PendingChallenge,
+    user: &User<PostgresqlBackend>,
+    form_error: Option<ErroredForm<TotpFormField>>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+
+    let query: OptionalPostAuthAction = serde_urlencoded::from_str(&pending.post_auth_action)
+        .map_err(fancy_error(templates.clone()))?;
+    let next = query
+        .load_context(&mut *conn)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let ctx = SecondFactorContext::new(pending.username.clone());
+
+    let has_totp = lookup_totp_secret(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?
+        .is_some();
+    let ctx = if has_totp { ctx.with_totp() } else { ctx };
+
+    let credentials = get_user_webauthn_credentials(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    let (ctx, cookie_jar) = if credentials.is_empty() {
+        (ctx, cookie_jar)
+    } else {
+        let (challenge, cookie_jar) = webauthn::start_challenge(cookie_jar);
+        let options = RelyingParty::new(url_builder).request_options(&challenge, &credentials);
+        (ctx.with_webauthn(options), cookie_jar)
+    };
+
+    let recovery_codes = count_unused_recovery_codes(&mut *conn, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+    let ctx = if recovery_codes > 0 {
+        ctx.with_recovery_codes()
+    } else {
+        ctx
+    };
+
+    let ctx = if let Some(next) = next {
+        ctx.with_post_action(next)
+    } else {
+        ctx
+    };
+    let ctx = if let Some(form) = form_error {
+        ctx.with_form_error(form)
+    } else {
+        ctx
+    };
+    let ctx = ctx.with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_second_factor(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((cookie_jar, Html(content)).into_response())
+}
+
+pub(crate) async fn get(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let pending = if let Some(pending) = load_pending(&cookie_jar) {
+        pending
+    } else {
+        return Ok((cookie_jar, mas_router::Login::default().go()).into_response());
+    };
+
+    let mut conn = pool
+        .acquire()
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let user = lookup_user_by_username(&mut conn, &pending.username)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    render(
+        &templates,
+        &url_builder,
+        &mut conn,
+        &pending,
+        &user,
+        None,
+        cookie_jar,
+    )
+    .await
+}
+
+/// Check a passkey assertion against the credentials of the user, returning
+/// the error to display if it is rejected
+async fn check_passkey(
+    conn: &mut PgConnection,
+    url_builder: &UrlBuilder,
+    challenge: Result<String, WebauthnError>,
+    user: &User<PostgresqlBackend>,
+    credential: &str,
+) -> anyhow::Result<Option<WebauthnError>> {
+    let challenge = match challenge {
+        Ok(challenge) => challenge,
+        Err(e) => return Ok(Some(e)),
+    };
+
+    let credential: PublicKeyCredential = match serde_json::from_str(credential) {
+        Ok(credential) => credential,
+        Err(_) => return Ok(Some(WebauthnError::Malformed)),
+    };
+
+    let credentials = get_user_webauthn_credentials(&mut *conn, user).await?;
+    let stored = if let Some(stored) = credentials
+        .iter()
+        .find(|c| c.credential_id == credential.id())
+    {
+        stored
+    } else {
+        return Ok(Some(WebauthnError::UnknownCredential));
+    };
+
+    match RelyingParty::new(url_builder).verify_assertion(
+        &challenge,
+        &credential,
+        stored,
+        user,
+        false,
+    ) {
+        Ok(sign_count) => {
+            update_webauthn_sign_count(&mut *conn, stored, sign_count.into()).await?;
+            Ok(None)
+        }
+        Err(e) => Ok(Some(e)),
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::too_many_lines)]
+pub(crate) async fn post(
+    Extension(templates): Extension<Templates>,
+    Extension(pool): Extension<PgPool>,
+    Extension(brute_force): Extension<BruteForceProtection>,
+    Extension(encrypter): Extension<Encrypter>,
+    Extension(mailer): Extension<Mailer>,
+    Extension(url_builder): Extension<UrlBuilder>,
+    ClientIp(ip): ClientIp,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+    Form(form): Form<ProtectedForm<SecondFactorForm>>,
+) -> Result<Response, FancyError> {
+    let mut txn = pool.begin().await.map_err(fancy_error(templates.clone()))?;
+
+    let form = cookie_jar
+        .verify_form(form)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let pending = if let Some(pending) = load_pending(&cookie_jar) {
+        pending
+    } else {
+        return Ok((cookie_jar, mas_router::Login::default().go()).into_response());
+    };
+
+    let (session_info, cookie_jar) = cookie_jar.session_info();
+
+    // When reauthenticating, the challenge is only valid for the session it was
+    // started from
+    let session = if let Some(session_id) = pending.session_id {
+        let session = session_info
+            .load_session(&mut txn)
+            .await
+            .map_err(fancy_error(templates.clone()))?
+            .filter(|session| session.data == session_id);
+
+        if let Some(session) = session {
+            Some(session)
+        } else {
+            let cookie_jar = cookie_jar.remove(Cookie::build(COOKIE_NAME, "").path("/").finish());
+            return Ok((cookie_jar, mas_router::Login::default().go()).into_response());
+        }
+    } else {
+        None
+    };
+
+    let user = if let Some(session) = &session {
+        session.user.clone()
+    } else {
+        lookup_user_by_username(&mut txn, &pending.username)
+            .await
+            .map_err(fancy_error(templates.clone()))?
+    };
+
+    // Codes are counted the same way as passwords, to prevent guessing them
+    let throttled = brute_force
+        .check(&mut txn, &pending.username, ip)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    if let Some(throttled) = throttled {
+        return render(
+            &templates,
+            &url_builder,
+            &mut txn,
+            &pending,
+            &user,
+            Some(throttled.on_form()),
+            cookie_jar,
+        )
+        .await;
+    }
+
+    let (method, form_error, cookie_jar) = match form {
+        SecondFactorForm::Totp { code } => {
+            let valid = check_user_code(&mut txn, &encrypter, &user, &code)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            let error = (!valid).then(|| InvalidCode.on_field(TotpFormField::Code));
+            (AuthenticationMethod::Otp, error, cookie_jar)
+        }
+        SecondFactorForm::Webauthn { credential } => {
+            let (challenge, cookie_jar) = webauthn::take_challenge(cookie_jar);
+            let error = check_passkey(&mut txn, &url_builder, challenge, &user, &credential)
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            (
+                AuthenticationMethod::Webauthn,
+                error.map(WrapFormError::on_form),
+                cookie_jar,
+            )
+        }
+        SecondFactorForm::RecoveryCode { code } => {
+            let valid = consume_recovery_code(&mut txn, &user, recovery_codes::normalize(&code))
+                .await
+                .map_err(fancy_error(templates.clone()))?;
+            let error = (!valid).then(|| InvalidCode.on_field(TotpFormField::RecoveryCode));
+            // Recovery codes are one-time passwords too
+            (AuthenticationMethod::Otp, error, cookie_jar)
+        }
+    };
+
+    if let Some(form_error) = form_error {
+        brute_force
+            .record_failure(&pool, &mailer, &url_builder, &pending.username, ip)
+            .await
+            .map_err(fancy_error(templates.clone()))?;
+
+        return render(
+            &templates,
+            &url_builder,
+            &mut txn,
+            &pending,
+            &user,
+            Some(form_error),
+            cookie_jar,
+        )
+        .await;
+    }
+
+    // Failed attempts are only forgotten once the user is fully authenticated
+    brute_force
+        .record_success(&mut txn, &pending.username)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let mut session = if let Some(session) = session {
+        session
+    } else {
+        start_session(&mut txn, user)
+            .await
+            .map_err(fancy_error(templates.clone()))?
+    };
+
+    record_session_authentication(
+        &mut txn,
+        &mut session,
+        &[AuthenticationMethod::Password, method],
+    )
+    .await
+    .map_err(fancy_error(templates.clone()))?;
+
+    txn.commit().await.map_err(fancy_error(templates.clone()))?;
+
+    let query: OptionalPostAuthAction = serde_urlencoded::from_str(&pending.post_auth_action)
+        .map_err(fancy_error(templates.clone()))?;
+
+    let cookie_jar = cookie_jar
+        .remove(Cookie::build(COOKIE_NAME, "").path("/").finish())
+        .set_session(&session);
+    Ok((cookie_jar, query.go_next()).into_response())
+}
@@ -38,9 +38,17 @@ impl OptionalPostAuthAction {
         match &self.post_auth_action {
             Some(PostAuthAction::ContinueAuthorizationGrant { data }) => {
                 let grant = get_grant_by_id(conn, *data).await?;
-                let grant = grant.into();
+                let grant = Box::new(grant.into());
                 Ok(Some(PostAuthContext::ContinueAuthorizationGrant { grant }))
             }
+            Some(PostAuthAction::ManageRecoveryCodes) => {
+                Ok(Some(PostAuthContext::ManageRecoveryCodes))
+            }
+            Some(PostAuthAction::DeleteAccount) => Ok(Some(PostAuthContext::DeleteAccount)),
+            Some(PostAuthAction::Administrate) => Ok(Some(PostAuthContext::Administrate)),
+            Some(PostAuthAction::AdministrateUser { .. }) => {
+                Ok(Some(PostAuthContext::AdministrateUser))
+            }
             None => Ok(None),
         }
     }
@@ -0,0 +1,89 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! What users who can't log in are shown: a page for suspended users, and an
+//! error for deactivated ones
+
+use axum::{
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use axum_extra::extract::PrivateCookieJar;
+use mas_axum_utils::{csrf::CsrfExt, fancy_error, FancyError};
+use mas_config::Encrypter;
+use mas_data_model::{errors::HtmlError, User};
+use mas_storage::{suspension::get_active_suspension, PostgresqlBackend};
+use mas_templates::{AccountSuspendedContext, ErrorContext, TemplateContext, Templates};
+use sqlx::PgExecutor;
+use thiserror::Error;
+
+/// A deactivated user proved who they are, for example through a directory, a
+/// passkey or an upstream provider
+#[derive(Debug, Error)]
+#[error("this account has been deactivated")]
+pub(crate) struct AccountDeactivated;
+
+impl HtmlError for AccountDeactivated {
+    fn html_display(&self) -> String {
+        "This account has been deactivated".to_string()
+    }
+}
+
+/// Explain why the user can't log in if they are suspended. Returns the cookie
+/// jar back if they are not.
+pub(crate) async fn check(
+    templates: &Templates,
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Result<PrivateCookieJar<Encrypter>, Response>, FancyError> {
+    let suspension = get_active_suspension(executor, user)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    let suspension = if let Some(suspension) = suspension {
+        suspension
+    } else {
+        return Ok(Ok(cookie_jar));
+    };
+
+    let (csrf_token, cookie_jar) = cookie_jar.csrf_token();
+    let ctx = AccountSuspendedContext::new(suspension.reason, suspension.expires_at)
+        .with_csrf(csrf_token.form_value());
+
+    let content = templates
+        .render_account_suspended(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok(Err((cookie_jar, Html(content)).into_response()))
+}
+
+/// Error page shown to deactivated users coming back from somewhere else than
+/// the login form, like an upstream provider
+pub(crate) async fn deactivated(
+    templates: &Templates,
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> Result<Response, FancyError> {
+    let ctx = ErrorContext::new()
+        .with_code("account_deactivated")
+        .with_description(AccountDeactivated.html_display());
+
+    let content = templates
+        .render_error(&ctx)
+        .await
+        .map_err(fancy_error(templates.clone()))?;
+
+    Ok((StatusCode::FORBIDDEN, cookie_jar, Html(content)).into_response())
+}
@@ -0,0 +1,711 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Minimal `WebAuthn` relying party.
+//!
+//! Attestation statements are not checked: credentials are trusted on first
+//! use, which is what passkeys need. Only ES256 and RS256 keys are supported.
+
+use std::collections::BTreeMap;
+
+use axum_extra::extract::{cookie::Cookie, PrivateCookieJar};
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use mas_axum_utils::CookieExt;
+use mas_config::Encrypter;
+use mas_data_model::{errors::HtmlError, User};
+use mas_router::UrlBuilder;
+use mas_storage::{webauthn::WebauthnCredential, PostgresqlBackend};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use rand::{thread_rng, RngCore};
+use rsa::{BigUint, PublicKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+const COOKIE_NAME: &str = "webauthn-challenge";
+
+/// COSE algorithm identifiers
+const ES256: i128 = -7;
+const RS256: i128 = -257;
+
+/// Authenticator data flags
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+#[derive(Debug, Error)]
+pub(crate) enum WebauthnError {
+    #[error("no WebAuthn challenge in progress")]
+    NoChallenge,
+
+    #[error("malformed WebAuthn response")]
+    Malformed,
+
+    #[error("WebAuthn response does not match the challenge")]
+    ClientData,
+
+    #[error("WebAuthn response is for another relying party")]
+    RelyingParty,
+
+    #[error("user presence or verification is missing")]
+    User,
+
+    #[error("unsupported credential key")]
+    UnsupportedKey,
+
+    #[error("invalid signature")]
+    Signature,
+
+    #[error("signature counter went backwards, the credential might be cloned")]
+    SignCount,
+
+    #[error("unknown credential")]
+    UnknownCredential,
+}
+
+impl HtmlError for WebauthnError {
+    fn html_display(&self) -> String {
+        let mut message = self.to_string();
+        // Capitalize the first letter
+        if let Some(first) = message.get_mut(..1) {
+            first.make_ascii_uppercase();
+        }
+        message
+    }
+}
+
+/// A credential, as sent back by the `webauthn.js` script
+#[derive(Deserialize, Debug)]
+pub(crate) struct PublicKeyCredential {
+    id: String,
+    response: AuthenticatorResponse,
+}
+
+impl PublicKeyCredential {
+    /// The base64url-encoded credential ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AuthenticatorResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    attestation_object: Option<String>,
+    authenticator_data: Option<String>,
+    signature: Option<String>,
+    user_handle: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+/// A credential which passed the registration checks
+pub(crate) struct NewCredential {
+    /// The base64url-encoded credential ID
+    pub id: String,
+    /// The COSE-encoded public key
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Value)>,
+}
+
+/// State of a `WebAuthn` ceremony, kept in an encrypted cookie
+#[derive(Serialize, Deserialize)]
+struct PendingChallenge {
+    challenge: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Start a new ceremony, returning its challenge
+pub(crate) fn start_challenge(
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> (String, PrivateCookieJar<Encrypter>) {
+    let mut bytes = [0; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    let pending = PendingChallenge {
+        challenge: BASE64URL_NOPAD.encode(&bytes),
+        expires_at: Utc::now() + Duration::minutes(5),
+    };
+
+    let mut cookie = Cookie::new(COOKIE_NAME, "");
+    cookie.set_path("/");
+    cookie.set_http_only(true);
+    let cookie_jar = cookie_jar.add(cookie.encode(&pending));
+    (pending.challenge, cookie_jar)
+}
+
+/// Get the challenge of the ceremony in progress, which can only be used once
+pub(crate) fn take_challenge(
+    cookie_jar: PrivateCookieJar<Encrypter>,
+) -> (Result<String, WebauthnError>, PrivateCookieJar<Encrypter>) {
+    let pending: Option<PendingChallenge> = cookie_jar
+        .get(COOKIE_NAME)
+        .and_then(|cookie| cookie.decode().ok())
+        .filter(|pending: &PendingChallenge| pending.expires_at > Utc::now());
+    let cookie_jar = cookie_jar.remove(Cookie::build(COOKIE_NAME, "").path("/").finish());
+    let challenge = pending
+        .map(|pending| pending.challenge)
+        .ok_or(WebauthnError::NoChallenge);
+    (challenge, cookie_jar)
+}
+
+/// The service, as a `WebAuthn` relying party
+pub(crate) struct RelyingParty {
+    id: String,
+    origin: String,
+}
+
+impl RelyingParty {
+    pub fn new(url_builder: &UrlBuilder) -> Self {
+        let issuer = url_builder.oidc_issuer();
+        Self {
+            id: issuer.host_str().unwrap_or_default().to_owned(),
+            origin: issuer.origin().ascii_serialization(),
+        }
+    }
+
+    /// Options for `navigator.credentials.create`, as JSON with binary fields
+    /// base64url-encoded
+    pub fn creation_options(
+        &self,
+        challenge: &str,
+        user: &User<PostgresqlBackend>,
+        existing: &[WebauthnCredential],
+    ) -> String {
+        let exclude: Vec<_> = existing
+            .iter()
+            .map(|c| json!({ "type": "public-key", "id": c.credential_id }))
+            .collect();
+
+        json!({
+            "challenge": challenge,
+            "rp": { "id": self.id, "name": self.id },
+            "user": {
+                "id": user_handle(user),
+                "name": user.username,
+                "displayName": user.username,
+            },
+            "pubKeyCredParams": [
+                { "type": "public-key", "alg": ES256 },
+                { "type": "public-key", "alg": RS256 },
+            ],
+            "excludeCredentials": exclude,
+            "authenticatorSelection": {
+                "residentKey": "preferred",
+                "userVerification": "preferred",
+            },
+            "attestation": "none",
+            "timeout": 300_000,
+        })
+        .to_string()
+    }
+
+    /// Options for `navigator.credentials.get`. An empty list of credentials
+    /// lets the authenticator pick a discoverable credential.
+    pub fn request_options(&self, challenge: &str, allowed: &[WebauthnCredential]) -> String {
+        let allow: Vec<_> = allowed
+            .iter()
+            .map(|c| json!({ "type": "public-key", "id": c.credential_id }))
+            .collect();
+
+        json!({
+            "challenge": challenge,
+            "rpId": self.id,
+            "allowCredentials": allow,
+            "userVerification": if allowed.is_empty() { "required" } else { "preferred" },
+            "timeout": 300_000,
+        })
+        .to_string()
+    }
+
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        kind: &str,
+        challenge: &str,
+    ) -> Result<(), WebauthnError> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data_json).map_err(|_| WebauthnError::Malformed)?;
+
+        // Some browsers pad the challenge
+        let received = client_data.challenge.trim_end_matches('=');
+        if client_data.kind != kind || received != challenge || client_data.origin != self.origin {
+            return Err(WebauthnError::ClientData);
+        }
+
+        Ok(())
+    }
+
+    fn check_authenticator_data(
+        &self,
+        data: &AuthenticatorData,
+        user_verification: bool,
+    ) -> Result<(), WebauthnError> {
+        if data.rp_id_hash != Sha256::digest(self.id.as_bytes()).as_slice() {
+            return Err(WebauthnError::RelyingParty);
+        }
+
+        let required = if user_verification {
+            USER_PRESENT | USER_VERIFIED
+        } else {
+            USER_PRESENT
+        };
+        if data.flags & required != required {
+            return Err(WebauthnError::User);
+        }
+
+        Ok(())
+    }
+
+    /// Check the response to `navigator.credentials.create`
+    pub fn verify_registration(
+        &self,
+        challenge: &str,
+        credential: &PublicKeyCredential,
+    ) -> Result<NewCredential, WebauthnError> {
+        let response = &credential.response;
+        let client_data_json = decode(&response.client_data_json)?;
+        self.check_client_data(&client_data_json, "webauthn.create", challenge)?;
+
+        let attestation_object = decode(
+            response
+                .attestation_object
+                .as_deref()
+                .ok_or(WebauthnError::Malformed)?,
+        )?;
+        let attestation_object: BTreeMap<String, Value> =
+            serde_cbor::from_slice(&attestation_object).map_err(|_| WebauthnError::Malformed)?;
+        let auth_data = match attestation_object.get("authData") {
+            Some(Value::Bytes(bytes)) => parse_authenticator_data(bytes)?,
+            _ => return Err(WebauthnError::Malformed),
+        };
+        self.check_authenticator_data(&auth_data, false)?;
+
+        let (id, public_key) = auth_data
+            .attested_credential
+            .ok_or(WebauthnError::Malformed)?;
+
+        // Make sure the key can be used before accepting it
+        CoseKey::parse(&public_key)?;
+        let public_key = serde_cbor::to_vec(&public_key).map_err(|_| WebauthnError::Malformed)?;
+
+        Ok(NewCredential {
+            id: BASE64URL_NOPAD.encode(&id),
+            public_key,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    /// Check the response to `navigator.credentials.get` against a stored
+    /// credential, returning the new signature counter
+    pub fn verify_assertion(
+        &self,
+        challenge: &str,
+        credential: &PublicKeyCredential,
+        stored: &WebauthnCredential,
+        owner: &User<PostgresqlBackend>,
+        user_verification: bool,
+    ) -> Result<u32, WebauthnError> {
+        if credential.id != stored.credential_id {
+            return Err(WebauthnError::UnknownCredential);
+        }
+
+        let response = &credential.response;
+        let client_data_json = decode(&response.client_data_json)?;
+        self.check_client_data(&client_data_json, "webauthn.get", challenge)?;
+
+        if let Some(handle) = &response.user_handle {
+            if handle.trim_end_matches('=') != user_handle(owner) {
+                return Err(WebauthnError::UnknownCredential);
+            }
+        }
+
+        let raw_auth_data = decode(
+            response
+                .authenticator_data
+                .as_deref()
+                .ok_or(WebauthnError::Malformed)?,
+        )?;
+        let auth_data = parse_authenticator_data(&raw_auth_data)?;
+        self.check_authenticator_data(&auth_data, user_verification)?;
+
+        let signature = decode(
+            response
+                .signature
+                .as_deref()
+                .ok_or(WebauthnError::Malformed)?,
+        )?;
+        let mut signed = raw_auth_data;
+        signed.extend_from_slice(&Sha256::digest(&client_data_json));
+
+        let key: Value =
+            serde_cbor::from_slice(&stored.public_key).map_err(|_| WebauthnError::Malformed)?;
+        CoseKey::parse(&key)?.verify(&signed, &signature)?;
+
+        // Authenticators which don't implement the counter always send 0
+        let stored_count = u32::try_from(stored.sign_count).unwrap_or_default();
+        if (auth_data.sign_count != 0 || stored_count != 0) && auth_data.sign_count <= stored_count
+        {
+            return Err(WebauthnError::SignCount);
+        }
+
+        Ok(auth_data.sign_count)
+    }
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, WebauthnError> {
+    BASE64URL_NOPAD
+        .decode(value.trim_end_matches('=').as_bytes())
+        .map_err(|_| WebauthnError::Malformed)
+}
+
+/// The opaque user handle given to authenticators
+fn user_handle(user: &User<PostgresqlBackend>) -> String {
+    BASE64URL_NOPAD.encode(user.sub.as_bytes())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> Result<AuthenticatorData, WebauthnError> {
+    if data.len() < 37 {
+        return Err(WebauthnError::Malformed);
+    }
+
+    let rp_id_hash = data[..32].to_vec();
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & ATTESTED_CREDENTIAL_DATA == 0 {
+        None
+    } else {
+        // AAGUID (16 bytes), then the length of the credential ID (2 bytes)
+        let rest = data.get(37 + 16..).ok_or(WebauthnError::Malformed)?;
+        if rest.len() < 2 {
+            return Err(WebauthnError::Malformed);
+        }
+        let len = usize::from(u16::from_be_bytes([rest[0], rest[1]]));
+        let id = rest
+            .get(2..2 + len)
+            .ok_or(WebauthnError::Malformed)?
+            .to_vec();
+
+        // The public key might be followed by extensions
+        let key = serde_cbor::Deserializer::from_slice(&rest[2 + len..])
+            .into_iter::<Value>()
+            .next()
+            .ok_or(WebauthnError::Malformed)?
+            .map_err(|_| WebauthnError::Malformed)?;
+
+        Some((id, key))
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash,
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+enum CoseKey {
+    Es256(VerifyingKey),
+    Rs256(RsaPublicKey),
+}
+
+impl CoseKey {
+    fn parse(key: &Value) -> Result<Self, WebauthnError> {
+        let map = match key {
+            Value::Map(map) => map,
+            _ => return Err(WebauthnError::Malformed),
+        };
+        let get = |label: i128| map.get(&Value::Integer(label));
+        let bytes = |label: i128| match get(label) {
+            Some(Value::Bytes(bytes)) => Ok(bytes.as_slice()),
+            _ => Err(WebauthnError::UnsupportedKey),
+        };
+
+        match (get(1), get(3)) {
+            // EC2 key on the P-256 curve
+            (Some(Value::Integer(2)), Some(Value::Integer(ES256))) => {
+                if get(-1) != Some(&Value::Integer(1)) {
+                    return Err(WebauthnError::UnsupportedKey);
+                }
+                let mut point = vec![0x04];
+                point.extend_from_slice(bytes(-2)?);
+                point.extend_from_slice(bytes(-3)?);
+                let key = VerifyingKey::from_sec1_bytes(&point)
+                    .map_err(|_| WebauthnError::UnsupportedKey)?;
+                Ok(Self::Es256(key))
+            }
+
+            // RSA key
+            (Some(Value::Integer(3)), Some(Value::Integer(RS256))) => {
+                let n = BigUint::from_bytes_be(bytes(-1)?);
+                let e = BigUint::from_bytes_be(bytes(-2)?);
+                let key = RsaPublicKey::new(n, e).map_err(|_| WebauthnError::UnsupportedKey)?;
+                Ok(Self::Rs256(key))
+            }
+
+            _ => Err(WebauthnError::UnsupportedKey),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), WebauthnError> {
+        match self {
+            Self::Es256(key) => {
+                let signature =
+                    Signature::from_der(signature).map_err(|_| WebauthnError::Signature)?;
+                key.verify(message, &signature)
+                    .map_err(|_| WebauthnError::Signature)
+            }
+            Self::Rs256(key) => {
+                let digest = Sha256::digest(message);
+                key.verify(
+                    rsa::PaddingScheme::new_pkcs1v15_sign(Some(rsa::Hash::SHA2_256)),
+                    &digest,
+                    signature,
+                )
+                .map_err(|_| WebauthnError::Signature)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use p256::ecdsa::{signature::Signer, SigningKey};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    const CHALLENGE: &str = "Y2hhbGxlbmdl";
+
+    fn rp() -> RelyingParty {
+        RelyingParty::new(&UrlBuilder::new(
+            "https://auth.example.com/".parse().unwrap(),
+        ))
+    }
+
+    fn user() -> User<PostgresqlBackend> {
+        User {
+            data: 1,
+            username: "alice".to_owned(),
+            sub: "sub-alice".to_owned(),
+            primary_email: None,
+            is_admin: false,
+        }
+    }
+
+    /// A software authenticator, good enough to exercise the checks
+    struct Authenticator {
+        key: SigningKey,
+        id: Vec<u8>,
+        rp_id: String,
+        origin: String,
+        sign_count: u32,
+    }
+
+    impl Authenticator {
+        fn new() -> Self {
+            Self {
+                key: SigningKey::random(&mut OsRng),
+                id: b"credential-id".to_vec(),
+                rp_id: "auth.example.com".to_owned(),
+                origin: "https://auth.example.com".to_owned(),
+                sign_count: 0,
+            }
+        }
+
+        fn client_data(&self, kind: &str, challenge: &str) -> Vec<u8> {
+            json!({ "type": kind, "challenge": challenge, "origin": self.origin })
+                .to_string()
+                .into_bytes()
+        }
+
+        fn auth_data(&self, flags: u8, attested: bool) -> Vec<u8> {
+            let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+            data.push(
+                flags
+                    | if attested {
+                        ATTESTED_CREDENTIAL_DATA
+                    } else {
+                        0
+                    },
+            );
+            data.extend_from_slice(&self.sign_count.to_be_bytes());
+            if attested {
+                data.extend_from_slice(&[0; 16]);
+                data.extend_from_slice(&u16::try_from(self.id.len()).unwrap().to_be_bytes());
+                data.extend_from_slice(&self.id);
+
+                let point = self.key.verifying_key().to_encoded_point(false);
+                let mut key = BTreeMap::new();
+                key.insert(Value::Integer(1), Value::Integer(2));
+                key.insert(Value::Integer(3), Value::Integer(ES256));
+                key.insert(Value::Integer(-1), Value::Integer(1));
+                key.insert(
+                    Value::Integer(-2),
+                    Value::Bytes(point.x().unwrap().to_vec()),
+                );
+                key.insert(
+                    Value::Integer(-3),
+                    Value::Bytes(point.y().unwrap().to_vec()),
+                );
+                data.extend_from_slice(&serde_cbor::to_vec(&Value::Map(key)).unwrap());
+            }
+            data
+        }
+
+        fn create(&self, challenge: &str) -> PublicKeyCredential {
+            let mut attestation = BTreeMap::new();
+            attestation.insert("fmt", Value::Text("none".to_owned()));
+            attestation.insert("attStmt", Value::Map(BTreeMap::new()));
+            attestation.insert("authData", Value::Bytes(self.auth_data(USER_PRESENT, true)));
+
+            PublicKeyCredential {
+                id: BASE64URL_NOPAD.encode(&self.id),
+                response: AuthenticatorResponse {
+                    client_data_json: BASE64URL_NOPAD
+                        .encode(&self.client_data("webauthn.create", challenge)),
+                    attestation_object: Some(
+                        BASE64URL_NOPAD.encode(&serde_cbor::to_vec(&attestation).unwrap()),
+                    ),
+                    authenticator_data: None,
+                    signature: None,
+                    user_handle: None,
+                },
+            }
+        }
+
+        fn get(&mut self, challenge: &str, flags: u8) -> PublicKeyCredential {
+            self.sign_count += 1;
+            let client_data = self.client_data("webauthn.get", challenge);
+            let auth_data = self.auth_data(flags, false);
+            let mut signed = auth_data.clone();
+            signed.extend_from_slice(&Sha256::digest(&client_data));
+            let signature: Signature = self.key.sign(&signed);
+
+            PublicKeyCredential {
+                id: BASE64URL_NOPAD.encode(&self.id),
+                response: AuthenticatorResponse {
+                    client_data_json: BASE64URL_NOPAD.encode(&client_data),
+                    attestation_object: None,
+                    authenticator_data: Some(BASE64URL_NOPAD.encode(&auth_data)),
+                    signature: Some(BASE64URL_NOPAD.encode(signature.to_der().as_bytes())),
+                    user_handle: Some(user_handle(&user())),
+                },
+            }
+        }
+    }
+
+    fn register(authenticator: &Authenticator) -> WebauthnCredential {
+        let new = rp()
+            .verify_registration(CHALLENGE, &authenticator.create(CHALLENGE))
+            .unwrap();
+        WebauthnCredential {
+            id: 1,
+            credential_id: new.id,
+            public_key: new.public_key,
+            sign_count: new.sign_count.into(),
+            name: "test".to_owned(),
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    #[test]
+    fn registration_and_assertion() {
+        let mut authenticator = Authenticator::new();
+        let stored = register(&authenticator);
+        assert_eq!(
+            stored.credential_id,
+            BASE64URL_NOPAD.encode(b"credential-id")
+        );
+
+        let assertion = authenticator.get(CHALLENGE, USER_PRESENT | USER_VERIFIED);
+        let count = rp()
+            .verify_assertion(CHALLENGE, &assertion, &stored, &user(), true)
+            .unwrap();
+        assert_eq!(count, 1);
+
+        // User verification is only required when asked for
+        let assertion = authenticator.get(CHALLENGE, USER_PRESENT);
+        assert!(matches!(
+            rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), true),
+            Err(WebauthnError::User)
+        ));
+        rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), false)
+            .unwrap();
+    }
+
+    #[test]
+    fn rejects_mismatches() {
+        let mut authenticator = Authenticator::new();
+        assert!(matches!(
+            rp().verify_registration("other", &authenticator.create(CHALLENGE)),
+            Err(WebauthnError::ClientData)
+        ));
+
+        let mut stored = register(&authenticator);
+
+        authenticator.origin = "https://evil.example.com".to_owned();
+        let assertion = authenticator.get(CHALLENGE, USER_PRESENT);
+        assert!(matches!(
+            rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), false),
+            Err(WebauthnError::ClientData)
+        ));
+
+        authenticator.origin = "https://auth.example.com".to_owned();
+        authenticator.rp_id = "example.com".to_owned();
+        let assertion = authenticator.get(CHALLENGE, USER_PRESENT);
+        assert!(matches!(
+            rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), false),
+            Err(WebauthnError::RelyingParty)
+        ));
+
+        // Tampered signature
+        authenticator.rp_id = "auth.example.com".to_owned();
+        let mut assertion = authenticator.get(CHALLENGE, USER_PRESENT);
+        assertion.response.authenticator_data = Some(
+            BASE64URL_NOPAD.encode(&authenticator.auth_data(USER_PRESENT | USER_VERIFIED, false)),
+        );
+        assert!(matches!(
+            rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), false),
+            Err(WebauthnError::Signature)
+        ));
+
+        // Replayed counter
+        stored.sign_count = 100;
+        let assertion = authenticator.get(CHALLENGE, USER_PRESENT);
+        assert!(matches!(
+            rp().verify_assertion(CHALLENGE, &assertion, &stored, &user(), false),
+            Err(WebauthnError::SignCount)
+        ));
+    }
+}
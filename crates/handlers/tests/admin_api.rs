@@ -0,0 +1,428 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the admin API: its authorization, and a few of its operations.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use axum::Router;
+use chrono::Duration;
+use hyper::{
+    header::{AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+    Body, Method, Request, StatusCode,
+};
+use mas_data_model::{AuthenticationMethod, BrowserSession, TokenType, User};
+use mas_iana::oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod};
+use mas_router::SimpleRoute;
+use mas_storage::{
+    oauth2::{
+        access_token::add_access_token,
+        authorization_grant::{derive_session, new_authorization_grant},
+        client::{insert_client, lookup_client_by_client_id},
+    },
+    user::{lookup_user_by_username, record_session_authentication, set_user_admin, start_session},
+    PostgresqlBackend,
+};
+use oauth2_types::{
+    oidc::ApplicationType,
+    requests::{GrantType, ResponseMode},
+    scope::{Scope, ScopeToken, ADMIN, OPENID},
+};
+use rand::thread_rng;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+use self::common::random_string;
+
+mod common;
+
+const REDIRECT_URI: &str = "https://example.com/callback";
+
+struct TestState {
+    pool: PgPool,
+    router: Router<Body>,
+}
+
+/// Set up the whole application against the test database, or return `None`
+/// if no database is configured
+async fn setup() -> Option<TestState> {
+    let state = common::state(&common::config()).await?;
+
+    Some(TestState {
+        pool: state.pool.clone(),
+        router: mas_handlers::router(&state),
+    })
+}
+
+impl TestState {
+    /// Create a user with an active browser session
+    async fn user(&self, is_admin: bool) -> BrowserSession<PostgresqlBackend> {
+        let mut txn = self.pool.begin().await.unwrap();
+        let username = random_string();
+        sqlx::query("INSERT INTO users (username) VALUES ($1)")
+            .bind(&username)
+            .execute(&mut txn)
+            .await
+            .unwrap();
+        let user: User<PostgresqlBackend> =
+            lookup_user_by_username(&mut txn, &username).await.unwrap();
+        set_user_admin(&mut txn, &user, is_admin).await.unwrap();
+        let user = lookup_user_by_username(&mut txn, &username).await.unwrap();
+
+        let mut browser_session = start_session(&mut txn, user).await.unwrap();
+        record_session_authentication(
+            &mut txn,
+            &mut browser_session,
+            &[AuthenticationMethod::Password],
+        )
+        .await
+        .unwrap();
+        txn.commit().await.unwrap();
+
+        browser_session
+    }
+
+    /// Get an access token for the browser session, with the given scope
+    async fn access_token(
+        &self,
+        browser_session: BrowserSession<PostgresqlBackend>,
+        scope: &[ScopeToken],
+    ) -> String {
+        let mut txn = self.pool.begin().await.unwrap();
+        let client_id = random_string();
+        insert_client(
+            &mut txn,
+            &client_id,
+            &[REDIRECT_URI.parse().unwrap()],
+            None,
+            &[OAuthAuthorizationEndpointResponseType::Code],
+            &[GrantType::AuthorizationCode],
+            ApplicationType::Web,
+            &[],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(OAuthClientAuthenticationMethod::None),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+
+        let scope: Scope = scope.iter().cloned().collect();
+        let grant = new_authorization_grant(
+            &mut txn,
+            client,
+            REDIRECT_URI.parse().unwrap(),
+            true,
+            scope,
+            None,
+            None,
+            None,
+            None,
+            None,
+            ResponseMode::Query,
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        let session = derive_session(&mut txn, &grant, browser_session)
+            .await
+            .unwrap();
+
+        let token = TokenType::AccessToken.generate(thread_rng());
+        add_access_token(&mut txn, &session, &token, Duration::minutes(5))
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        token
+    }
+
+    /// An access token of a new administrator, with the admin scope
+    async fn admin_token(&self) -> String {
+        let browser_session = self.user(true).await;
+        self.access_token(browser_session, &[OPENID, ADMIN]).await
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        token: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Option<String>, Value) {
+        let mut request = Request::builder().method(method).uri(format!(
+            "{}{}",
+            mas_router::AdminApi::PATH,
+            path
+        ));
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+
+        let response = self
+            .router
+            .clone()
+            .oneshot(request.body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let www_authenticate = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .map(|value| value.to_str().unwrap().to_owned());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, www_authenticate, body)
+    }
+}
+
+#[tokio::test]
+async fn requires_admin_scope_and_user() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let (status, _, _) = state.request(Method::GET, "/users", None, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // An administrator, without the admin scope
+    let browser_session = state.user(true).await;
+    let token = state.access_token(browser_session, &[OPENID]).await;
+    let (status, www_authenticate, _) = state
+        .request(Method::GET, "/users", Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(www_authenticate
+        .unwrap()
+        .contains(r#"error="insufficient_scope""#));
+
+    // A regular user, with the admin scope
+    let browser_session = state.user(false).await;
+    let token = state.access_token(browser_session, &[OPENID, ADMIN]).await;
+    let (status, _, body) = state
+        .request(Method::GET, "/users", Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "not_admin");
+
+    let token = state.admin_token().await;
+    let (status, _, body) = state
+        .request(Method::GET, "/users", Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn list_and_get_users() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let token = state.admin_token().await;
+    let target = state.user(false).await;
+
+    let path = format!("/users?search={}", target.user.username);
+    let (status, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert_eq!(body["data"][0]["id"], target.user.data);
+    assert_eq!(body["data"][0]["is_admin"], false);
+    assert_eq!(body.get("next_offset"), None);
+
+    let (status, _, body) = state
+        .request(Method::GET, "/users?limit=1", Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    assert_eq!(body["next_offset"], 1);
+
+    let path = format!("/users/{}", target.user.data);
+    let (status, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert_eq!(body["username"], target.user.username.as_str());
+
+    let (status, _, body) = state
+        .request(Method::GET, "/users/0", Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "not_found");
+}
+
+#[tokio::test]
+async fn lock_and_unlock_user() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let token = state.admin_token().await;
+    let target = state.user(false).await;
+    let path = format!("/users/{}", target.user.data);
+
+    let (status, _, body) = state
+        .request(
+            Method::POST,
+            &format!("{}/lock", path),
+            Some(&token),
+            Some(json!({ "reason": "Spam" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "body: {}", body);
+
+    let (_, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(body["locked"], true);
+
+    let (status, _, _) = state
+        .request(
+            Method::POST,
+            &format!("{}/unlock", path),
+            Some(&token),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (_, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(body["locked"], false);
+}
+
+#[tokio::test]
+async fn end_browser_session() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let token = state.admin_token().await;
+    let target = state.user(false).await;
+
+    let path = format!("/users/{}/browser-sessions", target.user.data);
+    let (status, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert_eq!(body["data"][0]["id"], target.data);
+    assert_eq!(body["data"][0]["active"], true);
+
+    let path = format!("{}/{}", path, target.data);
+    let (status, _, _) = state
+        .request(Method::DELETE, &path, Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // The session already ended
+    let (status, _, _) = state
+        .request(Method::DELETE, &path, Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn add_and_remove_email() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let token = state.admin_token().await;
+    let target = state.user(false).await;
+    let path = format!("/users/{}/emails", target.user.data);
+
+    let (status, _, body) = state
+        .request(
+            Method::POST,
+            &path,
+            Some(&token),
+            Some(json!({ "email": "not an email" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "bad_request");
+
+    let email = format!("{}@example.com", random_string());
+    let (status, _, body) = state
+        .request(
+            Method::POST,
+            &path,
+            Some(&token),
+            Some(json!({ "email": email, "verified": true, "primary": true })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert_eq!(body["primary"], true);
+    assert!(body["confirmed_at"].is_string());
+    let id = body["id"].as_i64().unwrap();
+
+    let (_, _, body) = state.request(Method::GET, &path, Some(&token), None).await;
+    assert_eq!(body["data"][0]["email"], email.as_str());
+
+    let item = format!("{}/{}", path, id);
+    let (status, _, _) = state
+        .request(Method::DELETE, &item, Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _, _) = state
+        .request(Method::DELETE, &item, Some(&token), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn openapi_document() {
+    let state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let (status, _, body) = state
+        .request(Method::GET, "/openapi.json", None, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["servers"][0]["url"]
+        .as_str()
+        .unwrap()
+        .ends_with("/api/admin/v1"));
+
+    let operation = &body["paths"]["/users/{user_id}"]["get"];
+    assert_eq!(operation["operationId"], "getUser");
+    assert_eq!(operation["parameters"][0]["name"], "user_id");
+    assert_eq!(operation["parameters"][0]["in"], "path");
+
+    // Schemas referenced by the operations are all defined
+    let schemas = body["components"]["schemas"].as_object().unwrap();
+    assert!(schemas.contains_key("User"));
+    assert!(schemas.contains_key("ApiError"));
+}
@@ -0,0 +1,249 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the admin console: its access control, and the CSRF-protected
+//! actions administrators take on users.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use argon2::Argon2;
+use hyper::StatusCode;
+use mas_data_model::User;
+use mas_router::Route;
+use mas_storage::{
+    suspension::get_active_suspension,
+    user::{add_user_email, register_user, set_user_admin, start_session},
+    PostgresqlBackend,
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use self::common::{random_string, Browser, Page};
+
+mod common;
+
+const PASSWORD: &str = "hunter2";
+
+struct TestState {
+    pool: PgPool,
+    browser: Browser,
+}
+
+/// Set up the whole application against the test database, or return `None`
+/// if no database is configured
+async fn setup() -> Option<TestState> {
+    let state = common::state(&common::config()).await?;
+
+    Some(TestState {
+        pool: state.pool.clone(),
+        browser: Browser::new(mas_handlers::router(&state)),
+    })
+}
+
+impl TestState {
+    /// Register a user who can log in with [`PASSWORD`]
+    async fn user(&self, is_admin: bool) -> User<PostgresqlBackend> {
+        let mut txn = self.pool.begin().await.unwrap();
+        let user = register_user(&mut txn, Argon2::default(), &random_string(), PASSWORD)
+            .await
+            .unwrap();
+        set_user_admin(&mut txn, &user, is_admin).await.unwrap();
+        txn.commit().await.unwrap();
+        user
+    }
+
+    /// Register an administrator and log them in with the browser
+    async fn admin(&mut self) -> User<PostgresqlBackend> {
+        let admin = self.user(true).await;
+        let page = self.browser.login(&admin.username, PASSWORD).await;
+        assert_eq!(page.status, StatusCode::SEE_OTHER);
+        admin
+    }
+}
+
+fn user_page(user: &User<PostgresqlBackend>) -> String {
+    mas_router::AdminUser(user.data).relative_url().into_owned()
+}
+
+#[tokio::test]
+async fn console_requires_login() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let page = state.browser.get("/admin/users/42").await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        page.location.as_deref(),
+        Some("/login?next=administrate_user&data=42")
+    );
+}
+
+#[tokio::test]
+async fn console_forbidden_to_users() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let user = state.user(false).await;
+    let page = state.browser.login(&user.username, PASSWORD).await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+
+    let page = state.browser.get(mas_router::Admin::route()).await;
+    assert_eq!(page.status, StatusCode::FORBIDDEN);
+
+    let page = state.browser.get(&user_page(&user)).await;
+    assert_eq!(page.status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn console_requires_recent_authentication() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    let admin = state.admin().await;
+
+    let page = state.browser.get(mas_router::Admin::route()).await;
+    assert_eq!(page.status, StatusCode::OK);
+
+    // Pretend the administrator logged in a while ago
+    sqlx::query(
+        r#"
+            UPDATE user_session_authentications
+            SET created_at = created_at - INTERVAL '1 hour'
+            WHERE session_id IN (SELECT id FROM user_sessions WHERE user_id = $1)
+        "#,
+    )
+    .bind(admin.data)
+    .execute(&state.pool)
+    .await
+    .unwrap();
+
+    let page = state.browser.get(mas_router::Admin::route()).await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+    assert_eq!(page.location.as_deref(), Some("/reauth?next=administrate"));
+
+    // Confirming the password gives access again
+    let reauth = state.browser.get(mas_router::Reauth::route()).await;
+    let page = state
+        .browser
+        .post_form(
+            "/reauth?next=administrate",
+            &reauth,
+            &[("password", PASSWORD)],
+        )
+        .await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+    assert_eq!(page.location.as_deref(), Some(mas_router::Admin::route()));
+
+    let page = state.browser.get(mas_router::Admin::route()).await;
+    assert_eq!(page.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn console_locks_user() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    state.admin().await;
+    let user = state.user(false).await;
+    let path = user_page(&user);
+    let form = json!({ "action": "lock", "reason": "Spam" });
+
+    // A form without the right CSRF token is refused
+    let forged = Page {
+        status: StatusCode::OK,
+        location: None,
+        body: r#"<input name="csrf" value="forged">"#.to_owned(),
+    };
+    let page = state.browser.post_form(&path, &forged, &form).await;
+    assert_ne!(page.status, StatusCode::OK);
+    let suspension = get_active_suspension(&state.pool, &user).await.unwrap();
+    assert!(suspension.is_none());
+
+    let page = state.browser.get(&path).await;
+    assert_eq!(page.status, StatusCode::OK);
+    let page = state.browser.post_form(&path, &page, &form).await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.body.contains("User suspended"));
+
+    let suspension = get_active_suspension(&state.pool, &user).await.unwrap();
+    assert_eq!(suspension.unwrap().reason, "Spam");
+
+    let page = state
+        .browser
+        .post_form(&path, &page, &json!({ "action": "unlock" }))
+        .await;
+    assert_eq!(page.status, StatusCode::OK);
+    let suspension = get_active_suspension(&state.pool, &user).await.unwrap();
+    assert!(suspension.is_none());
+}
+
+#[tokio::test]
+async fn console_ends_session() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    state.admin().await;
+    let user = state.user(false).await;
+    let session = start_session(&state.pool, user.clone()).await.unwrap();
+    let path = user_page(&user);
+
+    let page = state.browser.get(&path).await;
+    let form = json!({ "action": "end_session", "data": session.data.to_string() });
+    let page = state.browser.post_form(&path, &page, &form).await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.body.contains("Browser session ended"));
+
+    let active: bool = sqlx::query_scalar("SELECT active FROM user_sessions WHERE id = $1")
+        .bind(session.data)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert!(!active);
+}
+
+#[tokio::test]
+async fn console_resends_verification() {
+    let mut state = match setup().await {
+        Some(state) => state,
+        None => return,
+    };
+    state.admin().await;
+    let user = state.user(false).await;
+    let address = format!("{}@example.com", random_string().to_lowercase());
+    let email = add_user_email(&state.pool, &user, address).await.unwrap();
+    let path = user_page(&user);
+
+    let page = state.browser.get(&path).await;
+    let form = json!({ "action": "resend_verification", "data": email.data.to_string() });
+    let page = state.browser.post_form(&path, &page, &form).await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.body.contains("Verification email sent"));
+
+    // The code was committed along with the page
+    let codes: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM user_email_verifications WHERE user_email_id = $1",
+    )
+    .bind(email.data)
+    .fetch_one(&state.pool)
+    .await
+    .unwrap();
+    assert_eq!(codes, 1);
+}
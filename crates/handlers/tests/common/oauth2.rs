@@ -0,0 +1,292 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Clients, authorization grants and token requests, for the tests going
+//! through the OAuth 2.0 endpoints
+
+use std::sync::Arc;
+
+use axum::Router;
+use hyper::{header::CONTENT_TYPE, Body, Request, StatusCode};
+use mas_config::{Encrypter, OAuth2Config};
+use mas_data_model::{
+    AuthenticationMethod, AuthorizationCode, AuthorizationGrant, Client, Pkce, User,
+};
+use mas_iana::oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod};
+use mas_jose::{JsonWebKeySet, StaticKeystore};
+use mas_router::{SimpleRoute, UrlBuilder};
+use mas_storage::{
+    oauth2::{
+        authorization_grant::{derive_session, fulfill_grant, new_authorization_grant},
+        client::{insert_client, lookup_client_by_client_id},
+    },
+    user::{lookup_user, record_session_authentication, start_session},
+    PostgresqlBackend,
+};
+use oauth2_types::{
+    oidc::ApplicationType,
+    requests::{GrantType, ResponseMode},
+    scope::{Scope, OPENID},
+};
+use serde_json::Value;
+use sqlx::PgPool;
+use tower::ServiceExt;
+
+use super::random_string;
+
+pub const REDIRECT_URI: &str = "https://example.com/callback";
+pub const CLIENT_SECRET: &str = "client-secret";
+
+pub struct TestState {
+    pub pool: PgPool,
+    pub encrypter: Encrypter,
+    pub key_store: Arc<StaticKeystore>,
+    pub url_builder: UrlBuilder,
+    pub router: Router<Body>,
+}
+
+/// Set up the whole application against the test database, or return `None`
+/// if no database is configured
+pub async fn setup(oauth2: OAuth2Config) -> Option<TestState> {
+    let mut config = super::config();
+    config.oauth2 = oauth2;
+    let state = super::state(&config).await?;
+
+    Some(TestState {
+        pool: state.pool.clone(),
+        encrypter: state.encrypter.clone(),
+        key_store: state.key_store.clone(),
+        url_builder: state.url_builder.clone(),
+        router: mas_handlers::router(&state),
+    })
+}
+
+/// Most tests are not about PKCE, so let public clients go without it
+pub fn without_pkce() -> OAuth2Config {
+    let mut config = OAuth2Config::default();
+    config.pkce.required_for_public_clients = false;
+    config
+}
+
+impl TestState {
+    pub async fn client(
+        &self,
+        auth_method: OAuthClientAuthenticationMethod,
+        grant_types: &[GrantType],
+    ) -> Client<PostgresqlBackend> {
+        self.client_with_jwks(auth_method, grant_types, None).await
+    }
+
+    pub async fn client_with_jwks(
+        &self,
+        auth_method: OAuthClientAuthenticationMethod,
+        grant_types: &[GrantType],
+        jwks: Option<&JsonWebKeySet>,
+    ) -> Client<PostgresqlBackend> {
+        let client_id = random_string();
+        let encrypted_client_secret =
+            (auth_method != OAuthClientAuthenticationMethod::None).then(|| {
+                self.encrypter
+                    .encryt_to_string(CLIENT_SECRET.as_bytes())
+                    .unwrap()
+            });
+
+        let mut txn = self.pool.begin().await.unwrap();
+        insert_client(
+            &mut txn,
+            &client_id,
+            &[REDIRECT_URI.parse().unwrap()],
+            encrypted_client_secret.as_deref(),
+            &[OAuthAuthorizationEndpointResponseType::Code],
+            grant_types,
+            ApplicationType::Web,
+            &[],
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            jwks,
+            None,
+            None,
+            Some(auth_method),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let client = lookup_client_by_client_id(&mut txn, &client_id)
+            .await
+            .unwrap();
+        txn.commit().await.unwrap();
+
+        client
+    }
+
+    pub async fn public_client(&self) -> Client<PostgresqlBackend> {
+        self.client(
+            OAuthClientAuthenticationMethod::None,
+            &[GrantType::AuthorizationCode, GrantType::RefreshToken],
+        )
+        .await
+    }
+
+    /// Create an authorization grant for the client, without fulfilling it
+    pub async fn pending_grant(
+        &self,
+        client: &Client<PostgresqlBackend>,
+        redirect_uri_provided: bool,
+        pkce: Option<Pkce>,
+    ) -> AuthorizationGrant<PostgresqlBackend> {
+        let code = AuthorizationCode {
+            code: random_string(),
+            pkce,
+        };
+        let scope: Scope = [OPENID].into_iter().collect();
+
+        new_authorization_grant(
+            &self.pool,
+            client.clone(),
+            REDIRECT_URI.parse().unwrap(),
+            redirect_uri_provided,
+            scope,
+            Some(code),
+            None,
+            None,
+            None,
+            None,
+            ResponseMode::Query,
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Create an authorization grant for the client and fulfill it with a
+    /// new user session, returning the authorization code
+    pub async fn fulfilled_grant(
+        &self,
+        client: &Client<PostgresqlBackend>,
+        redirect_uri_provided: bool,
+        pkce: Option<Pkce>,
+    ) -> (i64, String) {
+        let grant = self
+            .pending_grant(client, redirect_uri_provided, pkce)
+            .await;
+        let code = grant.code.as_ref().unwrap().code.clone();
+        let grant_id = grant.data;
+
+        let mut txn = self.pool.begin().await.unwrap();
+        let username = random_string();
+        let user_id: i64 =
+            sqlx::query_scalar("INSERT INTO users (username) VALUES ($1) RETURNING id")
+                .bind(&username)
+                .fetch_one(&mut txn)
+                .await
+                .unwrap();
+        let user = User {
+            data: user_id,
+            username,
+            sub: format!("fake-sub-{}", user_id),
+            primary_email: None,
+            is_admin: false,
+        };
+        let mut browser_session = start_session(&mut txn, user).await.unwrap();
+        record_session_authentication(
+            &mut txn,
+            &mut browser_session,
+            &[AuthenticationMethod::Password, AuthenticationMethod::Otp],
+        )
+        .await
+        .unwrap();
+        let session = derive_session(&mut txn, &grant, browser_session)
+            .await
+            .unwrap();
+        fulfill_grant(&mut txn, grant, session).await.unwrap();
+        txn.commit().await.unwrap();
+
+        (grant_id, code)
+    }
+
+    pub async fn execute(&self, sql: &str, grant_id: i64) {
+        sqlx::query(sql)
+            .bind(grant_id)
+            .execute(&self.pool)
+            .await
+            .unwrap();
+    }
+
+    pub async fn token_request(&self, form: &[(&str, &str)]) -> (StatusCode, Value) {
+        let body = serde_urlencoded::to_string(form).unwrap();
+        let request = Request::post(mas_router::OAuth2TokenEndpoint::PATH)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = serde_json::from_slice(&body).unwrap();
+        (status, body)
+    }
+
+    pub async fn exchange_code(
+        &self,
+        client: &Client<PostgresqlBackend>,
+        code: &str,
+        extra: &[(&str, &str)],
+    ) -> (StatusCode, Value) {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("client_id", &client.client_id),
+            ("code", code),
+        ];
+        form.extend_from_slice(extra);
+        self.token_request(&form).await
+    }
+}
+
+/// The user who was granted access by the authorization grant
+pub async fn user_of_grant(pool: &PgPool, grant_id: i64) -> User<PostgresqlBackend> {
+    let user_id: i64 = sqlx::query_scalar(
+        r#"
+            SELECT us.user_id
+            FROM oauth2_authorization_grants og
+            INNER JOIN oauth2_sessions os ON os.id = og.oauth2_session_id
+            INNER JOIN user_sessions us ON us.id = os.user_session_id
+            WHERE og.id = $1
+        "#,
+    )
+    .bind(grant_id)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+
+    lookup_user(pool, user_id).await.unwrap()
+}
+
+#[track_caller]
+pub fn assert_error(response: &(StatusCode, Value), status: StatusCode, error: &str) {
+    let (got_status, body) = response;
+    assert_eq!(*got_status, status, "unexpected status, body: {}", body);
+    assert_eq!(body["error"], error, "unexpected error, body: {}", body);
+    assert!(
+        body["error_description"].is_string(),
+        "missing error_description, body: {}",
+        body
+    );
+}
@@ -0,0 +1,75 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of what deactivated users can still do with the tokens they were
+//! issued.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use hyper::StatusCode;
+use mas_storage::{
+    oauth2::access_token::lookup_active_access_token,
+    user::{lookup_user_by_username, username_exists},
+};
+
+use self::common::oauth2::{assert_error, setup, user_of_grant, without_pkce};
+
+mod common;
+
+#[tokio::test]
+async fn tokens_of_deactivated_user() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (grant_id, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    let access_token = body["access_token"].as_str().unwrap();
+    let refresh_token = body["refresh_token"].as_str().unwrap();
+
+    // Mark the user as deactivated without ending their sessions, to check
+    // that the lookups refuse the tokens on their own
+    let user = user_of_grant(&state.pool, grant_id).await;
+    sqlx::query("UPDATE users SET deactivated_at = NOW() WHERE id = $1")
+        .bind(user.data)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let err = lookup_active_access_token(&state.pool, access_token)
+        .await
+        .unwrap_err();
+    assert!(err.not_found());
+
+    let response = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client.client_id),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+
+    // The user can't be found anymore, but the username stays taken
+    let mut conn = state.pool.acquire().await.unwrap();
+    let err = lookup_user_by_username(&mut conn, &user.username)
+        .await
+        .unwrap_err();
+    assert!(err.not_found());
+    assert!(username_exists(&mut conn, &user.username).await.unwrap());
+}
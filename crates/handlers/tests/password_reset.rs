@@ -0,0 +1,290 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the password reset pages: asking for a link, and choosing a new
+//! password with it.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use argon2::Argon2;
+use hyper::StatusCode;
+use mas_data_model::{User, UserEmail};
+use mas_router::{Route, SimpleRoute};
+use mas_storage::{
+    user::{add_user_email, mark_user_email_as_verified, register_user},
+    PostgresqlBackend,
+};
+use serde_json::json;
+use sqlx::PgPool;
+
+use self::common::{
+    oauth2::{assert_error, setup, user_of_grant, without_pkce},
+    random_string, Browser,
+};
+
+mod common;
+
+const NEW_PASSWORD: &str = "Tr0ub4dor&3-staple-horse";
+
+fn random_email() -> String {
+    format!("{}@example.com", random_string().to_lowercase())
+}
+
+/// Give the user a new verified email address
+async fn verified_email(
+    pool: &PgPool,
+    user: &User<PostgresqlBackend>,
+) -> UserEmail<PostgresqlBackend> {
+    let email = add_user_email(pool, user, random_email()).await.unwrap();
+    mark_user_email_as_verified(pool, email).await.unwrap()
+}
+
+/// Register a user who can log in with `hunter2`, with a verified email
+async fn user(pool: &PgPool) -> (User<PostgresqlBackend>, UserEmail<PostgresqlBackend>) {
+    let mut txn = pool.begin().await.unwrap();
+    let user = register_user(&mut txn, Argon2::default(), &random_string(), "hunter2")
+        .await
+        .unwrap();
+    txn.commit().await.unwrap();
+    let email = verified_email(pool, &user).await;
+    (user, email)
+}
+
+/// The codes of the resets sent to the address, from the oldest one
+async fn reset_codes(pool: &PgPool, email: &UserEmail<PostgresqlBackend>) -> Vec<String> {
+    sqlx::query_scalar(
+        r#"
+            SELECT code
+            FROM user_password_resets
+            WHERE user_email_id = $1
+            ORDER BY created_at ASC
+        "#,
+    )
+    .bind(email.data)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+async fn ask_reset(browser: &mut Browser, address: &str) -> String {
+    let path = mas_router::PasswordForgot::PATH;
+    let page = browser.get(path).await;
+    let page = browser
+        .post_form(path, &page, &json!({ "email": address }))
+        .await;
+    assert_eq!(page.status, StatusCode::OK);
+    page.body
+}
+
+/// Choose a new password with the reset link, returning the page body if it
+/// didn't redirect to the login page
+async fn reset(browser: &mut Browser, code: &str) -> Result<(), String> {
+    let path = mas_router::PasswordReset(code.to_owned())
+        .relative_url()
+        .into_owned();
+    let page = browser.get(&path).await;
+    if page.status != StatusCode::OK {
+        return Err(page.body);
+    }
+
+    let form = json!({ "password": NEW_PASSWORD, "password_confirm": NEW_PASSWORD });
+    let page = browser.post_form(&path, &page, &form).await;
+    if page.status != StatusCode::SEE_OTHER {
+        return Err(page.body);
+    }
+    assert_eq!(page.location.as_deref(), Some(mas_router::Login::route()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn link_sent_to_verified_addresses_only() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (user, email) = user(&state.pool).await;
+    let unverified = add_user_email(&state.pool, &user, random_email())
+        .await
+        .unwrap();
+
+    let sent = ask_reset(&mut browser, &email.email).await;
+    assert!(sent.contains("Check your inbox"));
+    assert_eq!(reset_codes(&state.pool, &email).await.len(), 1);
+
+    // Unknown and unverified addresses get the same answer, without a link
+    let unknown = ask_reset(&mut browser, &random_email()).await;
+    assert!(unknown.contains("Check your inbox"));
+    let not_verified = ask_reset(&mut browser, &unverified.email).await;
+    assert!(not_verified.contains("Check your inbox"));
+    assert!(reset_codes(&state.pool, &unverified).await.is_empty());
+}
+
+#[tokio::test]
+async fn requests_are_throttled_per_address() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (_, email) = user(&state.pool).await;
+
+    for _ in 0..2 {
+        let body = ask_reset(&mut browser, &email.email).await;
+        assert!(body.contains("Check your inbox"));
+    }
+    let body = ask_reset(&mut browser, &email.email).await;
+    assert!(body.contains("Too many reset links"));
+    assert_eq!(reset_codes(&state.pool, &email).await.len(), 2);
+
+    // Unknown addresses are counted the same way
+    let address = random_email();
+    for _ in 0..2 {
+        ask_reset(&mut browser, &address).await;
+    }
+    let body = ask_reset(&mut browser, &address).await;
+    assert!(body.contains("Too many reset links"));
+}
+
+#[tokio::test]
+async fn reset_password() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (user, email) = user(&state.pool).await;
+
+    ask_reset(&mut browser, &email.email).await;
+    let code = reset_codes(&state.pool, &email).await.pop().unwrap();
+    reset(&mut browser, &code).await.unwrap();
+
+    let page = browser.login(&user.username, "hunter2").await;
+    assert_eq!(page.status, StatusCode::OK);
+    let page = browser.login(&user.username, NEW_PASSWORD).await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+
+    // The link can only be used once
+    let err = reset(&mut browser, &code).await.unwrap_err();
+    assert!(err.contains("invalid or has expired"));
+}
+
+#[tokio::test]
+async fn other_pending_resets_are_consumed() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (user, email) = user(&state.pool).await;
+    let other_email = verified_email(&state.pool, &user).await;
+
+    ask_reset(&mut browser, &email.email).await;
+    ask_reset(&mut browser, &other_email.email).await;
+    let first = reset_codes(&state.pool, &email).await.pop().unwrap();
+    let second = reset_codes(&state.pool, &other_email).await.pop().unwrap();
+
+    reset(&mut browser, &second).await.unwrap();
+    let err = reset(&mut browser, &first).await.unwrap_err();
+    assert!(err.contains("invalid or has expired"));
+}
+
+#[tokio::test]
+async fn expired_link() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (_, email) = user(&state.pool).await;
+
+    ask_reset(&mut browser, &email.email).await;
+    let code = reset_codes(&state.pool, &email).await.pop().unwrap();
+    sqlx::query(
+        r#"
+            UPDATE user_password_resets
+            SET created_at = created_at - INTERVAL '2 hours'
+            WHERE code = $1
+        "#,
+    )
+    .bind(&code)
+    .execute(&state.pool)
+    .await
+    .unwrap();
+
+    let err = reset(&mut browser, &code).await.unwrap_err();
+    assert!(err.contains("invalid or has expired"));
+}
+
+#[tokio::test]
+async fn link_of_deactivated_user() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let (user, email) = user(&state.pool).await;
+
+    ask_reset(&mut browser, &email.email).await;
+    let code = reset_codes(&state.pool, &email).await.pop().unwrap();
+    sqlx::query("UPDATE users SET deactivated_at = NOW() WHERE id = $1")
+        .bind(user.data)
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    let err = reset(&mut browser, &code).await.unwrap_err();
+    assert!(err.contains("invalid or has expired"));
+}
+
+#[tokio::test]
+async fn sessions_are_ended() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let mut browser = Browser::new(state.router.clone());
+    let client = state.public_client().await;
+    let (grant_id, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    let refresh_token = body["refresh_token"].as_str().unwrap();
+
+    let user = user_of_grant(&state.pool, grant_id).await;
+    let email = verified_email(&state.pool, &user).await;
+    ask_reset(&mut browser, &email.email).await;
+    let reset_code = reset_codes(&state.pool, &email).await.pop().unwrap();
+    reset(&mut browser, &reset_code).await.unwrap();
+
+    // Whoever knew the old password is logged out, from the browser and from
+    // the clients
+    let active: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM user_sessions WHERE user_id = $1 AND active")
+            .bind(user.data)
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+    assert_eq!(active, 0);
+
+    let response = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client.client_id),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
@@ -0,0 +1,79 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of the second factor prompt shown after the password check.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use argon2::Argon2;
+use hyper::StatusCode;
+use mas_config::AttemptLimitsConfig;
+use mas_router::SimpleRoute;
+use mas_storage::{totp::set_totp_secret, user::register_user};
+use serde_json::json;
+
+use self::common::{random_string, Browser};
+
+mod common;
+
+#[tokio::test]
+async fn wrong_codes_lock_out() {
+    let mut config = common::config();
+    config.brute_force.account = AttemptLimitsConfig {
+        free_attempts: 10,
+        lockout_threshold: 3,
+    };
+    let state = match common::state(&config).await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let username = random_string();
+    let mut txn = state.pool.begin().await.unwrap();
+    let user = register_user(&mut txn, Argon2::default(), &username, "hunter2")
+        .await
+        .unwrap();
+    let secret = state.encrypter.encryt_to_string(&[42; 20]).unwrap();
+    set_totp_secret(&mut txn, &user, &secret).await.unwrap();
+    txn.commit().await.unwrap();
+
+    let mut browser = Browser::new(mas_handlers::router(&state));
+    let page = browser.login(&username, "hunter2").await;
+    assert_eq!(page.status, StatusCode::SEE_OTHER);
+    assert_eq!(
+        page.location.as_deref(),
+        Some(mas_router::SecondFactor::PATH)
+    );
+
+    let path = mas_router::SecondFactor::PATH;
+    let form = json!({ "method": "totp", "code": "000000" });
+    for _ in 0..3 {
+        let page = browser.get(path).await;
+        let page = browser.post_form(path, &page, &form).await;
+        assert_eq!(page.status, StatusCode::OK);
+        assert!(page.body.contains("Invalid code"));
+    }
+
+    // The codes are not checked anymore once the account is locked
+    let page = browser.get(path).await;
+    let page = browser.post_form(path, &page, &form).await;
+    assert!(!page.body.contains("Invalid code"));
+    assert!(page.body.contains("Too many failed attempts"));
+
+    // Neither is the password
+    let page = browser.login(&username, "hunter2").await;
+    assert_eq!(page.status, StatusCode::OK);
+    assert!(page.body.contains("Too many failed attempts"));
+}
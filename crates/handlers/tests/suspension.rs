@@ -0,0 +1,71 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests of what suspended users can still do with the tokens they were
+//! issued.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use chrono::{Duration, Utc};
+use hyper::StatusCode;
+use mas_storage::{
+    oauth2::access_token::lookup_active_access_token,
+    suspension::{lift_user_suspensions, suspend_user},
+};
+
+use self::common::oauth2::{setup, user_of_grant, without_pkce};
+
+mod common;
+
+#[tokio::test]
+async fn tokens_of_suspended_user() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (grant_id, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    let access_token = body["access_token"].as_str().unwrap();
+
+    let user = user_of_grant(&state.pool, grant_id).await;
+
+    // Suspended users can't use their tokens...
+    suspend_user(&state.pool, &user, "Spam", None)
+        .await
+        .unwrap();
+    let err = lookup_active_access_token(&state.pool, access_token)
+        .await
+        .unwrap_err();
+    assert!(err.not_found());
+
+    // ...until the suspension is lifted
+    assert!(lift_user_suspensions(&state.pool, &user).await.unwrap());
+    lookup_active_access_token(&state.pool, access_token)
+        .await
+        .unwrap();
+    assert!(!lift_user_suspensions(&state.pool, &user).await.unwrap());
+
+    // Expired suspensions don't count
+    let expires_at = Utc::now() - Duration::minutes(1);
+    suspend_user(&state.pool, &user, "Spam", Some(expires_at))
+        .await
+        .unwrap();
+    lookup_active_access_token(&state.pool, access_token)
+        .await
+        .unwrap();
+}
@@ -0,0 +1,568 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Conformance tests for the token endpoint, covering the checks from RFC 6749
+//! section 4.1.3 and the error responses from section 5.2.
+//!
+//! Those tests need a PostgreSQL database, given through the `DATABASE_URL`
+//! environment variable. They are skipped if it is not set.
+
+use chrono::{Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use hyper::StatusCode;
+use mas_config::OAuth2Config;
+use mas_data_model::{Client, Pkce};
+use mas_iana::{
+    jose::JsonWebSignatureAlg,
+    oauth::{OAuthClientAuthenticationMethod, PkceCodeChallengeMethod},
+};
+use mas_jose::{DecodedJsonWebToken, SharedSecret, SigningKeystore, StaticKeystore};
+use mas_storage::PostgresqlBackend;
+use oauth2_types::requests::GrantType;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tower::{Service, ServiceExt};
+
+use self::common::{
+    oauth2::{assert_error, setup, without_pkce, TestState, CLIENT_SECRET, REDIRECT_URI},
+    random_string,
+};
+
+mod common;
+
+const JWT_BEARER_CLIENT_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+impl TestState {
+    /// Sign a `client_assertion` for the client with the given key store
+    async fn client_assertion<S: SigningKeystore>(
+        &self,
+        client: &Client<PostgresqlBackend>,
+        store: &S,
+        alg: JsonWebSignatureAlg,
+    ) -> String {
+        let now = Utc::now();
+        let claims = json!({
+            "iss": client.client_id,
+            "sub": client.client_id,
+            "aud": self.url_builder.oauth_token_endpoint(),
+            "iat": now.timestamp(),
+            "exp": (now + Duration::minutes(5)).timestamp(),
+            "jti": random_string(),
+        });
+        let header = store.prepare_header(alg).await.unwrap();
+        DecodedJsonWebToken::new(header, claims)
+            .sign(store)
+            .await
+            .unwrap()
+            .serialize()
+    }
+}
+
+fn s256(verifier: &str) -> Pkce {
+    Pkce {
+        challenge_method: PkceCodeChallengeMethod::S256,
+        challenge: BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes())),
+    }
+}
+
+#[tokio::test]
+async fn exchange_code() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, true, None).await;
+
+    let (status, body) = state
+        .exchange_code(&client, &code, &[("redirect_uri", REDIRECT_URI)])
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    assert!(body["access_token"].is_string());
+    assert!(body["refresh_token"].is_string());
+    assert_eq!(body["token_type"], "Bearer");
+
+    // The ID token tells how the user authenticated
+    let id_token = body["id_token"].as_str().unwrap();
+    let payload = id_token.split('.').nth(1).unwrap();
+    let claims: Value =
+        serde_json::from_slice(&BASE64URL_NOPAD.decode(payload.as_bytes()).unwrap()).unwrap();
+    assert_eq!(claims["amr"], serde_json::json!(["pwd", "otp", "mfa"]));
+}
+
+#[tokio::test]
+async fn exchange_code_without_redirect_uri() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+
+    // The redirect_uri was not in the authorization request, so it is not needed
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn missing_client_credentials() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let response = state
+        .token_request(&[("grant_type", "authorization_code"), ("code", "abc")])
+        .await;
+    assert_error(&response, StatusCode::UNAUTHORIZED, "invalid_client");
+}
+
+#[tokio::test]
+async fn unknown_client() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+
+    let response = state
+        .token_request(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", "does-not-exist"),
+            ("code", "abc"),
+        ])
+        .await;
+    assert_error(&response, StatusCode::UNAUTHORIZED, "invalid_client");
+}
+
+#[tokio::test]
+async fn wrong_client_secret() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state
+        .client(
+            OAuthClientAuthenticationMethod::ClientSecretPost,
+            &[GrantType::AuthorizationCode],
+        )
+        .await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let response = state
+        .exchange_code(&client, &code, &[("client_secret", "wrong")])
+        .await;
+    assert_error(&response, StatusCode::UNAUTHORIZED, "invalid_client");
+
+    let (status, body) = state
+        .exchange_code(&client, &code, &[("client_secret", CLIENT_SECRET)])
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn client_auth_method_not_allowed() {
+    let mut config = without_pkce();
+    config.client_auth_methods = vec![OAuthClientAuthenticationMethod::ClientSecretBasic];
+    let state = match setup(config).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::UNAUTHORIZED, "invalid_client");
+}
+
+#[tokio::test]
+async fn client_secret_jwt() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state
+        .client(
+            OAuthClientAuthenticationMethod::ClientSecretJwt,
+            &[GrantType::AuthorizationCode],
+        )
+        .await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let secret = SharedSecret::new(&CLIENT_SECRET);
+    let assertion = state
+        .client_assertion(&client, &secret, JsonWebSignatureAlg::Hs256)
+        .await;
+    let (status, body) = state
+        .exchange_code(
+            &client,
+            &code,
+            &[
+                ("client_assertion_type", JWT_BEARER_CLIENT_ASSERTION),
+                ("client_assertion", &assertion),
+            ],
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn client_assertion_alg_not_allowed() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+
+    // The client has both an RSA and an ECDSA key, but only RS256 is advertised
+    let mut key_store: &StaticKeystore = state.key_store.as_ref();
+    let jwks = key_store.ready().await.unwrap().call(()).await.unwrap();
+    let client = state
+        .client_with_jwks(
+            OAuthClientAuthenticationMethod::PrivateKeyJwt,
+            &[GrantType::AuthorizationCode],
+            Some(&jwks),
+        )
+        .await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let assertion = state
+        .client_assertion(&client, key_store, JsonWebSignatureAlg::Es256)
+        .await;
+    let response = state
+        .exchange_code(
+            &client,
+            &code,
+            &[
+                ("client_assertion_type", JWT_BEARER_CLIENT_ASSERTION),
+                ("client_assertion", &assertion),
+            ],
+        )
+        .await;
+    assert_error(&response, StatusCode::UNAUTHORIZED, "invalid_client");
+
+    let assertion = state
+        .client_assertion(&client, key_store, JsonWebSignatureAlg::Rs256)
+        .await;
+    let (status, body) = state
+        .exchange_code(
+            &client,
+            &code,
+            &[
+                ("client_assertion_type", JWT_BEARER_CLIENT_ASSERTION),
+                ("client_assertion", &assertion),
+            ],
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn malformed_request() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+
+    // The code is missing
+    let response = state
+        .token_request(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", &client.client_id),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_request");
+}
+
+#[tokio::test]
+async fn unsupported_grant_type() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+
+    for grant_type in ["password", "client_credentials"] {
+        let response = state
+            .token_request(&[("grant_type", grant_type), ("client_id", &client.client_id)])
+            .await;
+        assert_error(&response, StatusCode::BAD_REQUEST, "unsupported_grant_type");
+    }
+}
+
+#[tokio::test]
+async fn unauthorized_client() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state
+        .client(
+            OAuthClientAuthenticationMethod::None,
+            &[GrantType::RefreshToken],
+        )
+        .await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "unauthorized_client");
+}
+
+#[tokio::test]
+async fn unknown_code() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+
+    let response = state.exchange_code(&client, "unknown-code", &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn code_issued_to_another_client() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let other_client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let response = state.exchange_code(&other_client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn pending_grant() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let grant = state.pending_grant(&client, false, None).await;
+    let code = &grant.code.as_ref().unwrap().code;
+
+    let response = state.exchange_code(&client, code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn cancelled_grant() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let grant = state.pending_grant(&client, false, None).await;
+    state
+        .execute(
+            "UPDATE oauth2_authorization_grants SET cancelled_at = NOW() WHERE id = $1",
+            grant.data,
+        )
+        .await;
+    let code = &grant.code.as_ref().unwrap().code;
+
+    let response = state.exchange_code(&client, code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn expired_code() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (grant_id, code) = state.fulfilled_grant(&client, false, None).await;
+    state
+        .execute(
+            "UPDATE oauth2_authorization_grants \
+             SET fulfilled_at = fulfilled_at - INTERVAL '1 hour' WHERE id = $1",
+            grant_id,
+        )
+        .await;
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn code_reuse() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn redirect_uri_mismatch() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+
+    for provided in [true, false] {
+        let (_, code) = state.fulfilled_grant(&client, provided, None).await;
+        let response = state
+            .exchange_code(
+                &client,
+                &code,
+                &[("redirect_uri", "https://example.com/other")],
+            )
+            .await;
+        assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+    }
+}
+
+#[tokio::test]
+async fn redirect_uri_missing() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, true, None).await;
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_request");
+}
+
+#[tokio::test]
+async fn pkce_required() {
+    let state = match setup(OAuth2Config::default()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
+
+#[tokio::test]
+async fn pkce_verifier() {
+    let state = match setup(OAuth2Config::default()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let verifier = random_string() + &random_string() + &random_string();
+
+    // Missing verifier
+    let (_, code) = state
+        .fulfilled_grant(&client, false, Some(s256(&verifier)))
+        .await;
+    let response = state.exchange_code(&client, &code, &[]).await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_request");
+
+    // Wrong verifier
+    let (_, code) = state
+        .fulfilled_grant(&client, false, Some(s256(&verifier)))
+        .await;
+    let wrong = random_string() + &random_string() + &random_string();
+    let response = state
+        .exchange_code(&client, &code, &[("code_verifier", &wrong)])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+
+    // Right verifier
+    let (_, code) = state
+        .fulfilled_grant(&client, false, Some(s256(&verifier)))
+        .await;
+    let (status, body) = state
+        .exchange_code(&client, &code, &[("code_verifier", &verifier)])
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+}
+
+#[tokio::test]
+async fn unexpected_pkce_verifier() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+    let verifier = random_string() + &random_string() + &random_string();
+
+    let response = state
+        .exchange_code(&client, &code, &[("code_verifier", &verifier)])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_request");
+}
+
+#[tokio::test]
+async fn refresh_token() {
+    let state = match setup(without_pkce()).await {
+        Some(state) => state,
+        None => return,
+    };
+    let client = state.public_client().await;
+    let other_client = state.public_client().await;
+    let (_, code) = state.fulfilled_grant(&client, false, None).await;
+
+    let (status, body) = state.exchange_code(&client, &code, &[]).await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+    let refresh_token = body["refresh_token"].as_str().unwrap();
+
+    // Unknown refresh token
+    let response = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client.client_id),
+            ("refresh_token", "unknown"),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+
+    // Refresh token issued to another client
+    let response = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &other_client.client_id),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+
+    let (status, body) = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client.client_id),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_eq!(status, StatusCode::OK, "body: {}", body);
+
+    // Refresh tokens can only be used once
+    let response = state
+        .token_request(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", &client.client_id),
+            ("refresh_token", refresh_token),
+        ])
+        .await;
+    assert_error(&response, StatusCode::BAD_REQUEST, "invalid_grant");
+}
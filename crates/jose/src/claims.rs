@@ -311,6 +311,7 @@ mod oidc_core {
     use super::{Claim, Timestamp};
 
     pub const AUTH_TIME: Claim<Timestamp> = Claim::new("auth_time");
+    pub const AMR: Claim<Vec<String>> = Claim::new("amr");
     pub const NONCE: Claim<String> = Claim::new("nonce");
     pub const AT_HASH: Claim<String> = Claim::new("at_hash");
     pub const C_HASH: Claim<String> = Claim::new("c_hash");
@@ -0,0 +1,239 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use mas_config::{LdapAttributesConfig, LdapBindConfig, LdapConfig};
+use tracing::{info_span, Instrument};
+
+use crate::{Directory, DirectoryEntry, DirectoryError};
+
+/// Result code of a bind with a wrong password, as per RFC 4511 appendix A
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// Replace the `{username}` placeholder in a template with an escaped username
+fn render(template: &str, escaped_username: &str) -> String {
+    template.replace("{username}", escaped_username)
+}
+
+fn first_value(attrs: &mut HashMap<String, Vec<String>>, name: Option<&String>) -> Option<String> {
+    let values = attrs.remove(name?)?;
+    values.into_iter().next()
+}
+
+/// A [`Directory`] backed by an LDAP server
+#[derive(Debug, Clone)]
+pub struct LdapDirectory {
+    config: LdapConfig,
+}
+
+impl LdapDirectory {
+    /// Create a directory from the LDAP configuration
+    #[must_use]
+    pub fn new(config: &LdapConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    fn attributes(&self) -> Vec<&str> {
+        let LdapAttributesConfig {
+            email,
+            display_name,
+        } = &self.config.attributes;
+        email
+            .iter()
+            .chain(display_name.iter())
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn entry(&self, entry: SearchEntry) -> DirectoryEntry {
+        let mut attrs = entry.attrs;
+        let attributes = &self.config.attributes;
+        DirectoryEntry {
+            dn: entry.dn,
+            email: first_value(&mut attrs, attributes.email.as_ref()),
+            display_name: first_value(&mut attrs, attributes.display_name.as_ref()),
+        }
+    }
+
+    async fn connect(&self) -> Result<Ldap, DirectoryError> {
+        let settings = LdapConnSettings::new().set_starttls(self.config.starttls);
+        let (conn, ldap) = LdapConnAsync::from_url_with_settings(settings, &self.config.url)
+            .instrument(info_span!("Connect to LDAP server"))
+            .await?;
+        ldap3::drive!(conn);
+        Ok(ldap)
+    }
+
+    /// Bind as the user, returning false if the password is wrong
+    async fn bind(ldap: &mut Ldap, dn: &str, password: &str) -> Result<bool, DirectoryError> {
+        let res = ldap
+            .simple_bind(dn, password)
+            .instrument(info_span!("Bind as user"))
+            .await?;
+        if res.rc == INVALID_CREDENTIALS {
+            return Ok(false);
+        }
+        res.success()?;
+        Ok(true)
+    }
+
+    async fn authenticate_with(
+        &self,
+        ldap: &mut Ldap,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryEntry>, DirectoryError> {
+        let dn = match &self.config.bind {
+            LdapBindConfig::Direct { dn_template } => {
+                let dn = render(dn_template, &dn_escape(username));
+                if !Self::bind(ldap, &dn, password).await? {
+                    return Ok(None);
+                }
+                dn
+            }
+
+            LdapBindConfig::Search {
+                bind_dn,
+                bind_password,
+                base_dn,
+                filter,
+            } => {
+                if let Some(bind_dn) = bind_dn {
+                    let password = bind_password.as_deref().unwrap_or_default();
+                    ldap.simple_bind(bind_dn, password)
+                        .instrument(info_span!("Bind as search user"))
+                        .await?
+                        .success()?;
+                }
+
+                let filter = render(filter, &ldap_escape(username));
+                let (mut entries, _) = ldap
+                    .search(base_dn, Scope::Subtree, &filter, vec!["1.1"])
+                    .instrument(info_span!("Search user"))
+                    .await?
+                    .success()?;
+
+                let entry = match (entries.pop(), entries.is_empty()) {
+                    (None, _) => return Ok(None),
+                    (Some(entry), true) => SearchEntry::construct(entry),
+                    (Some(_), false) => {
+                        return Err(DirectoryError::Ambiguous {
+                            username: username.to_owned(),
+                        })
+                    }
+                };
+
+                if !Self::bind(ldap, &entry.dn, password).await? {
+                    return Ok(None);
+                }
+                entry.dn
+            }
+        };
+
+        // Read the attributes of the entry as the user
+        let (mut entries, _) = ldap
+            .search(&dn, Scope::Base, "(objectClass=*)", self.attributes())
+            .instrument(info_span!("Fetch user attributes"))
+            .await?
+            .success()?;
+
+        let entry = match entries.pop() {
+            Some(entry) => self.entry(SearchEntry::construct(entry)),
+            None => DirectoryEntry {
+                dn,
+                ..DirectoryEntry::default()
+            },
+        };
+
+        Ok(Some(entry))
+    }
+}
+
+#[async_trait]
+impl Directory for LdapDirectory {
+    #[tracing::instrument(skip(self, password))]
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryEntry>, DirectoryError> {
+        // An empty password would be an unauthenticated bind, which succeeds
+        // without checking anything (RFC 4513 section 5.1.2)
+        if password.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ldap = self.connect().await?;
+        let res = self.authenticate_with(&mut ldap, username, password).await;
+        // Errors on unbind are not interesting once we have the result
+        let _ = ldap.unbind().await;
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_templates() {
+        assert_eq!(
+            render(
+                "uid={username},ou=people,dc=example,dc=com",
+                &dn_escape("alice,ou=admins")
+            ),
+            "uid=alice\\2cou\\3dadmins,ou=people,dc=example,dc=com"
+        );
+        assert_eq!(
+            render("(uid={username})", &ldap_escape("*)(uid=*")),
+            "(uid=\\2a\\29\\28uid=\\2a)"
+        );
+    }
+
+    #[test]
+    fn map_attributes() {
+        let directory = LdapDirectory::new(&LdapConfig::default());
+        assert_eq!(directory.attributes(), vec!["mail", "displayName"]);
+
+        let mut attrs = HashMap::new();
+        attrs.insert("mail".to_owned(), vec!["alice@example.com".to_owned()]);
+        attrs.insert("cn".to_owned(), vec!["Alice".to_owned()]);
+        let entry = directory.entry(SearchEntry {
+            dn: "uid=alice,dc=example,dc=com".to_owned(),
+            attrs,
+            bin_attrs: HashMap::new(),
+        });
+
+        assert_eq!(
+            entry,
+            DirectoryEntry {
+                dn: "uid=alice,dc=example,dc=com".to_owned(),
+                email: Some("alice@example.com".to_owned()),
+                display_name: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_password_is_rejected() {
+        // No server is listening there, this would fail if it tried to connect
+        let directory = LdapDirectory::new(&LdapConfig::default());
+        assert_eq!(directory.authenticate("alice", "").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checks user passwords against an LDAP directory
+
+#![forbid(unsafe_code)]
+#![deny(clippy::all, missing_docs, rustdoc::broken_intra_doc_links)]
+#![warn(clippy::pedantic)]
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+mod ldap;
+
+pub use self::ldap::LdapDirectory;
+
+/// A user entry of the directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryEntry {
+    /// DN of the entry
+    pub dn: String,
+
+    /// Email address of the user, from the configured attribute
+    pub email: Option<String>,
+
+    /// Display name of the user, from the configured attribute
+    pub display_name: Option<String>,
+}
+
+/// Failed to check the credentials of a user against the directory
+#[derive(Debug, Error)]
+pub enum DirectoryError {
+    /// The directory could not be reached or returned an error
+    #[error("LDAP operation failed")]
+    Ldap(#[from] ldap3::LdapError),
+
+    /// Several entries matched the username
+    #[error("several directory entries match {username:?}")]
+    Ambiguous {
+        /// The username being looked up
+        username: String,
+    },
+}
+
+/// A directory of users able to check their passwords
+#[async_trait]
+pub trait Directory: std::fmt::Debug + Send + Sync {
+    /// Check the password of a user, returning their entry if it is valid.
+    ///
+    /// Returns `Ok(None)` if the user does not exist or if the password is
+    /// wrong, without telling them apart.
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryEntry>, DirectoryError>;
+}
+
+/// An in-memory directory, standing in for an LDAP server in tests and
+/// development setups
+#[derive(Debug, Default)]
+pub struct MemoryDirectory {
+    users: HashMap<String, (String, DirectoryEntry)>,
+}
+
+impl MemoryDirectory {
+    /// Create an empty directory
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a user to the directory
+    #[must_use]
+    pub fn with_user(mut self, username: &str, password: &str, entry: DirectoryEntry) -> Self {
+        self.users
+            .insert(username.to_owned(), (password.to_owned(), entry));
+        self
+    }
+}
+
+#[async_trait]
+impl Directory for MemoryDirectory {
+    async fn authenticate(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<DirectoryEntry>, DirectoryError> {
+        Ok(self
+            .users
+            .get(username)
+            .filter(|(expected, _)| !password.is_empty() && expected == password)
+            .map(|(_, entry)| entry.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_directory() {
+        let entry = DirectoryEntry {
+            dn: "uid=alice,ou=people,dc=example,dc=com".to_owned(),
+            email: Some("alice@example.com".to_owned()),
+            display_name: Some("Alice".to_owned()),
+        };
+        let directory = MemoryDirectory::new().with_user("alice", "hunter2", entry.clone());
+
+        assert_eq!(
+            directory.authenticate("alice", "hunter2").await.unwrap(),
+            Some(entry)
+        );
+        assert_eq!(
+            directory.authenticate("alice", "wrong").await.unwrap(),
+            None
+        );
+        assert_eq!(
+            directory.authenticate("bob", "hunter2").await.unwrap(),
+            None
+        );
+    }
+}
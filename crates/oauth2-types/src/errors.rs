@@ -12,10 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-#[derive(serde::Serialize)]
+use std::borrow::Cow;
+
+use url::Url;
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct ClientError {
     pub error: &'static str,
-    pub error_description: &'static str,
+    pub error_description: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_uri: Option<Url>,
 }
 
 impl ClientError {
@@ -23,9 +29,25 @@ impl ClientError {
     pub const fn new(error: &'static str, error_description: &'static str) -> Self {
         Self {
             error,
-            error_description,
+            error_description: Cow::Borrowed(error_description),
+            error_uri: None,
         }
     }
+
+    /// Replace the generic description of the error with one specific to the
+    /// failure
+    #[must_use]
+    pub fn with_description(mut self, error_description: impl Into<Cow<'static, str>>) -> Self {
+        self.error_description = error_description.into();
+        self
+    }
+
+    /// Point to a human-readable page with more information about the error
+    #[must_use]
+    pub fn with_uri(mut self, error_uri: Url) -> Self {
+        self.error_uri = Some(error_uri);
+        self
+    }
 }
 
 pub mod rfc6749 {
@@ -134,6 +156,16 @@ pub mod oidc_core {
     );
 }
 
+pub mod rfc7636 {
+    use super::ClientError;
+
+    pub const CODE_CHALLENGE_REQUIRED: ClientError =
+        ClientError::new("invalid_request", "Code challenge required.");
+
+    pub const TRANSFORM_ALGORITHM_NOT_SUPPORTED: ClientError =
+        ClientError::new("invalid_request", "Transform algorithm not supported.");
+}
+
 mod rfc7591 {
     use super::ClientError;
 
@@ -151,3 +183,33 @@ mod rfc7591 {
 pub use oidc_core::*;
 pub use rfc6749::*;
 pub use rfc7591::*;
+pub use rfc7636::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_client_error() {
+        assert_eq!(
+            serde_json::to_value(INVALID_GRANT).unwrap(),
+            serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": "The provided access grant is invalid, expired, or revoked.",
+            })
+        );
+
+        let error = INVALID_GRANT
+            .with_description(format!("code {} expired", "abc"))
+            .with_uri("https://example.com/errors/expired".parse().unwrap());
+
+        assert_eq!(
+            serde_json::to_value(error).unwrap(),
+            serde_json::json!({
+                "error": "invalid_grant",
+                "error_description": "code abc expired",
+                "error_uri": "https://example.com/errors/expired",
+            })
+        );
+    }
+}
@@ -21,17 +21,76 @@ use mas_iana::{
 };
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
-use url::Url;
+use url::{Host, Url};
 
 use crate::requests::{Display, GrantType, Prompt, ResponseMode};
 
-#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Debug,
+    parse_display::Display,
+    parse_display::FromStr,
+)]
 #[serde(rename_all = "lowercase")]
+#[display(style = "lowercase")]
 pub enum ApplicationType {
     Web,
     Native,
 }
 
+impl ApplicationType {
+    /// Check whether a client of this type can register the given redirect
+    /// URI.
+    ///
+    /// Native apps are restricted to the options listed in
+    /// [RFC 8252 section 7](https://www.rfc-editor.org/rfc/rfc8252#section-7):
+    /// private-use URI schemes based on a reverse domain name, claimed `https`
+    /// URIs, and `http` URIs on the loopback interface.
+    #[must_use]
+    pub fn is_valid_redirect_uri(self, uri: &Url) -> bool {
+        match self {
+            Self::Web => true,
+            Self::Native => match uri.scheme() {
+                "https" => true,
+                "http" => is_loopback_redirect_uri(uri),
+                scheme => is_reverse_domain_scheme(scheme),
+            },
+        }
+    }
+}
+
+/// Whether the URI is an `http` URI pointing to the loopback interface, as
+/// described in
+/// [RFC 8252 section 7.3](https://www.rfc-editor.org/rfc/rfc8252#section-7.3).
+///
+/// The `localhost` name is accepted alongside the loopback IP literals, even
+/// though its use is not recommended.
+#[must_use]
+pub fn is_loopback_redirect_uri(uri: &Url) -> bool {
+    if uri.scheme() != "http" {
+        return false;
+    }
+
+    match uri.host() {
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        Some(Host::Domain(domain)) => domain == "localhost",
+        None => false,
+    }
+}
+
+/// Private-use URI schemes must be based on a domain name, e.g.
+/// `com.example.app`
+fn is_reverse_domain_scheme(scheme: &str) -> bool {
+    scheme.contains('.') && scheme.split('.').all(|label| !label.is_empty())
+}
+
 #[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum SubjectType {
@@ -232,4 +291,66 @@ pub struct Metadata {
 
     /// Array containing the list of prompt values that this OP supports.
     pub prompt_values_supported: Option<Vec<Prompt>>,
+
+    /// JWT containing metadata values about the authorization server as
+    /// claims, as described in
+    /// [RFC 8414 section 2.1](https://www.rfc-editor.org/rfc/rfc8414#section-2.1).
+    pub signed_metadata: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn web_redirect_uris() {
+        let web = ApplicationType::Web;
+        assert!(web.is_valid_redirect_uri(&"https://example.com/cb".parse().unwrap()));
+        assert!(web.is_valid_redirect_uri(&"http://example.com/cb".parse().unwrap()));
+    }
+
+    #[test]
+    fn native_redirect_uris() {
+        let native = ApplicationType::Native;
+        let valid = [
+            "com.example.app:/callback",
+            "com.example.app://callback",
+            "https://app.example.com/callback",
+            "http://127.0.0.1/callback",
+            "http://127.0.0.1:51004/callback",
+            "http://[::1]:51004/callback",
+            "http://localhost:1234/callback",
+        ];
+        for uri in valid {
+            assert!(
+                native.is_valid_redirect_uri(&uri.parse().unwrap()),
+                "{}",
+                uri
+            );
+        }
+
+        let invalid = [
+            "http://example.com/callback",
+            "http://192.168.1.1/callback",
+            "myapp:/callback",
+            "com..app:/callback",
+            "com.example.:/callback",
+        ];
+        for uri in invalid {
+            assert!(
+                !native.is_valid_redirect_uri(&uri.parse().unwrap()),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[test]
+    fn application_type_string() {
+        assert_eq!(ApplicationType::Native.to_string(), "native");
+        assert_eq!(
+            "web".parse::<ApplicationType>().unwrap(),
+            ApplicationType::Web
+        );
+    }
 }
@@ -46,8 +46,14 @@ impl CodeChallengeMethodExt for PkceCodeChallengeMethod {
     }
 }
 
+/// As per RFC 7636 section 4.3, the method defaults to `plain` when omitted
+const fn default_code_challenge_method() -> PkceCodeChallengeMethod {
+    PkceCodeChallengeMethod::Plain
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct AuthorizationRequest {
+    #[serde(default = "default_code_challenge_method")]
     pub code_challenge_method: PkceCodeChallengeMethod,
     pub code_challenge: String,
 }
@@ -203,7 +203,7 @@ pub enum AccessTokenRequest {
     AuthorizationCode(AuthorizationCodeGrant),
     RefreshToken(RefreshTokenGrant),
     ClientCredentials(ClientCredentialsGrant),
-    #[serde(skip_deserializing, other)]
+    #[serde(other)]
     Unsupported,
 }
 
@@ -347,4 +347,16 @@ mod tests {
 
         assert_serde_json(&req, expected);
     }
+
+    #[test]
+    fn deserialize_unsupported_grant() {
+        let req: AccessTokenRequest = serde_json::from_value(json!({
+            "grant_type": "password",
+            "username": "john",
+            "password": "hunter2",
+        }))
+        .unwrap();
+
+        assert_eq!(req, AccessTokenRequest::Unsupported);
+    }
 }
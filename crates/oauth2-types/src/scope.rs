@@ -43,6 +43,9 @@ pub const ADDRESS: ScopeToken = ScopeToken::from_static("address");
 pub const PHONE: ScopeToken = ScopeToken::from_static("phone");
 pub const OFFLINE_ACCESS: ScopeToken = ScopeToken::from_static("offline_access");
 
+/// Gives access to the admin API. Only granted to administrators.
+pub const ADMIN: ScopeToken = ScopeToken::from_static("urn:mas:admin");
+
 // As per RFC6749 appendix A:
 // https://datatracker.ietf.org/doc/html/rfc6749#appendix-A
 //
@@ -23,6 +23,13 @@ pub enum PostAuthAction {
         #[serde(deserialize_with = "serde_with::rust::display_fromstr::deserialize")]
         data: i64,
     },
+    ManageRecoveryCodes,
+    DeleteAccount,
+    Administrate,
+    AdministrateUser {
+        #[serde(deserialize_with = "serde_with::rust::display_fromstr::deserialize")]
+        data: i64,
+    },
 }
 
 impl PostAuthAction {
@@ -35,6 +42,10 @@ impl PostAuthAction {
     pub fn go_next(&self) -> axum::response::Redirect {
         match self {
             Self::ContinueAuthorizationGrant { data } => ContinueAuthorizationGrant(*data).go(),
+            Self::ManageRecoveryCodes => AccountRecoveryCodes.go(),
+            Self::DeleteAccount => AccountDelete.go(),
+            Self::Administrate => Admin::default().go(),
+            Self::AdministrateUser { data } => AdminUser(*data).go(),
         }
     }
 }
@@ -47,6 +58,14 @@ impl SimpleRoute for OidcConfiguration {
     const PATH: &'static str = "/.well-known/openid-configuration";
 }
 
+/// `GET /.well-known/oauth-authorization-server`
+#[derive(Debug, Clone)]
+pub struct OAuth2AuthorizationServerMetadata;
+
+impl SimpleRoute for OAuth2AuthorizationServerMetadata {
+    const PATH: &'static str = "/.well-known/oauth-authorization-server";
+}
+
 /// `GET /.well-known/webfinger`
 #[derive(Debug, Clone)]
 pub struct Webfinger;
@@ -103,6 +122,14 @@ impl SimpleRoute for OAuth2AuthorizationEndpoint {
     const PATH: &'static str = "/authorize";
 }
 
+/// Base of the admin API, under `/api/admin/v1`
+#[derive(Debug, Clone)]
+pub struct AdminApi;
+
+impl SimpleRoute for AdminApi {
+    const PATH: &'static str = "/api/admin/v1";
+}
+
 /// `GET /`
 #[derive(Debug, Clone)]
 pub struct Index;
@@ -235,6 +262,38 @@ impl From<Option<PostAuthAction>> for Reauth {
     }
 }
 
+/// `POST /login/passkey`
+#[derive(Default, Debug, Clone)]
+pub struct PasskeyLogin {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl Route for PasskeyLogin {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/login/passkey"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for PasskeyLogin {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
+/// `GET|POST /login/second-factor`
+#[derive(Debug, Clone)]
+pub struct SecondFactor;
+
+impl SimpleRoute for SecondFactor {
+    const PATH: &'static str = "/login/second-factor";
+}
+
 /// `GET|POST /register`
 #[derive(Default, Debug, Clone)]
 pub struct Register {
@@ -304,6 +363,62 @@ impl Route for VerifyEmail {
     }
 }
 
+/// `GET|POST /verify-email`
+#[derive(Default, Debug, Clone)]
+pub struct EmailVerificationRequired {
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl EmailVerificationRequired {
+    #[must_use]
+    pub fn and_then(action: PostAuthAction) -> Self {
+        Self {
+            post_auth_action: Some(action),
+        }
+    }
+}
+
+impl Route for EmailVerificationRequired {
+    type Query = PostAuthAction;
+
+    fn route() -> &'static str {
+        "/verify-email"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+}
+
+impl From<Option<PostAuthAction>> for EmailVerificationRequired {
+    fn from(post_auth_action: Option<PostAuthAction>) -> Self {
+        Self { post_auth_action }
+    }
+}
+
+/// `GET|POST /password/forgot`
+#[derive(Debug, Clone)]
+pub struct PasswordForgot;
+
+impl SimpleRoute for PasswordForgot {
+    const PATH: &'static str = "/password/forgot";
+}
+
+/// `GET|POST /password/reset/:code`
+#[derive(Debug, Clone)]
+pub struct PasswordReset(pub String);
+
+impl Route for PasswordReset {
+    type Query = ();
+    fn route() -> &'static str {
+        "/password/reset/:code"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/password/reset/{}", self.0).into()
+    }
+}
+
 /// `GET /account`
 #[derive(Debug, Clone)]
 pub struct Account;
@@ -320,6 +435,30 @@ impl SimpleRoute for AccountPassword {
     const PATH: &'static str = "/account/password";
 }
 
+/// `GET|POST /account/totp`
+#[derive(Debug, Clone)]
+pub struct AccountTotp;
+
+impl SimpleRoute for AccountTotp {
+    const PATH: &'static str = "/account/totp";
+}
+
+/// `GET|POST /account/passkeys`
+#[derive(Debug, Clone)]
+pub struct AccountPasskeys;
+
+impl SimpleRoute for AccountPasskeys {
+    const PATH: &'static str = "/account/passkeys";
+}
+
+/// `GET|POST /account/recovery-codes`
+#[derive(Debug, Clone)]
+pub struct AccountRecoveryCodes;
+
+impl SimpleRoute for AccountRecoveryCodes {
+    const PATH: &'static str = "/account/recovery-codes";
+}
+
 /// `GET|POST /account/emails`
 #[derive(Debug, Clone)]
 pub struct AccountEmails;
@@ -328,6 +467,68 @@ impl SimpleRoute for AccountEmails {
     const PATH: &'static str = "/account/emails";
 }
 
+/// `GET|POST /account/delete`
+#[derive(Debug, Clone)]
+pub struct AccountDelete;
+
+impl SimpleRoute for AccountDelete {
+    const PATH: &'static str = "/account/delete";
+}
+
+/// Search parameters of the admin console
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct AdminSearch {
+    /// Text to search in usernames and primary emails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+
+    /// Number of users to skip
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+}
+
+/// `GET /admin`
+#[derive(Default, Debug, Clone)]
+pub struct Admin {
+    search: Option<AdminSearch>,
+}
+
+impl Admin {
+    #[must_use]
+    pub fn new(search: AdminSearch) -> Self {
+        // Avoid a dangling `?` when there is nothing to search
+        let search = (search.search.is_some() || search.offset.is_some()).then(|| search);
+        Self { search }
+    }
+}
+
+impl Route for Admin {
+    type Query = AdminSearch;
+
+    fn route() -> &'static str {
+        "/admin"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.search.as_ref()
+    }
+}
+
+/// `GET|POST /admin/users/:user_id`
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub i64);
+
+impl Route for AdminUser {
+    type Query = ();
+    fn route() -> &'static str {
+        "/admin/users/:user_id"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/admin/users/{}", self.0).into()
+    }
+}
+
 /// `GET /authorize/:grant_id`
 #[derive(Debug, Clone)]
 pub struct ContinueAuthorizationGrant(pub i64);
@@ -357,3 +558,50 @@ impl Route for Consent {
         format!("/consent/{}", self.0).into()
     }
 }
+
+/// `GET /upstream/authorize/:provider`
+#[derive(Debug, Clone)]
+pub struct UpstreamOAuth2Authorize {
+    provider: String,
+    post_auth_action: Option<PostAuthAction>,
+}
+
+impl UpstreamOAuth2Authorize {
+    #[must_use]
+    pub fn new(provider: String, post_auth_action: Option<PostAuthAction>) -> Self {
+        Self {
+            provider,
+            post_auth_action,
+        }
+    }
+}
+
+impl Route for UpstreamOAuth2Authorize {
+    type Query = PostAuthAction;
+    fn route() -> &'static str {
+        "/upstream/authorize/:provider"
+    }
+
+    fn query(&self) -> Option<&Self::Query> {
+        self.post_auth_action.as_ref()
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/authorize/{}", self.provider).into()
+    }
+}
+
+/// `GET /upstream/callback/:provider`
+#[derive(Debug, Clone)]
+pub struct UpstreamOAuth2Callback(pub String);
+
+impl Route for UpstreamOAuth2Callback {
+    type Query = ();
+    fn route() -> &'static str {
+        "/upstream/callback/:provider"
+    }
+
+    fn path(&self) -> std::borrow::Cow<'static, str> {
+        format!("/upstream/callback/{}", self.0).into()
+    }
+}
@@ -34,11 +34,35 @@ mod tests {
             OidcConfiguration.relative_url(),
             Cow::Borrowed("/.well-known/openid-configuration")
         );
+        assert_eq!(
+            OAuth2AuthorizationServerMetadata.relative_url(),
+            Cow::Borrowed("/.well-known/oauth-authorization-server")
+        );
         assert_eq!(Index.relative_url(), Cow::Borrowed("/"));
         assert_eq!(
             Login::and_continue_grant(42).relative_url(),
             Cow::Borrowed("/login?next=continue_authorization_grant&data=42")
         );
+        assert_eq!(
+            Reauth::and_then(PostAuthAction::ManageRecoveryCodes).relative_url(),
+            Cow::Borrowed("/reauth?next=manage_recovery_codes")
+        );
+        assert_eq!(
+            Reauth::and_then(PostAuthAction::AdministrateUser { data: 42 }).relative_url(),
+            Cow::Borrowed("/reauth?next=administrate_user&data=42")
+        );
+        assert_eq!(
+            AdminUser(42).relative_url(),
+            Cow::Borrowed("/admin/users/42")
+        );
+        assert_eq!(
+            Admin::new(AdminSearch {
+                search: Some("john doe".to_string()),
+                offset: Some(50),
+            })
+            .relative_url(),
+            Cow::Borrowed("/admin?search=john+doe&offset=50")
+        );
     }
 
     #[test]
@@ -49,6 +49,12 @@ impl UrlBuilder {
         self.url_for(&crate::endpoints::OidcConfiguration)
     }
 
+    /// OAuth 2.0 authorization server metadata URL
+    #[must_use]
+    pub fn oauth_authorization_server_metadata(&self) -> Url {
+        self.url_for(&crate::endpoints::OAuth2AuthorizationServerMetadata)
+    }
+
     /// OAuth 2.0 authorization endpoint
     #[must_use]
     pub fn oauth_authorization_endpoint(&self) -> Url {
@@ -90,6 +96,30 @@ impl UrlBuilder {
     pub fn email_verification(&self, code: String) -> Url {
         self.url_for(&crate::endpoints::VerifyEmail(code))
     }
+
+    /// Page to ask for a password reset link
+    #[must_use]
+    pub fn password_forgot(&self) -> Url {
+        self.url_for(&crate::endpoints::PasswordForgot)
+    }
+
+    /// Password reset URL
+    #[must_use]
+    pub fn password_reset(&self, code: String) -> Url {
+        self.url_for(&crate::endpoints::PasswordReset(code))
+    }
+
+    /// Redirect URI registered on an upstream OIDC provider
+    #[must_use]
+    pub fn upstream_oauth_callback(&self, provider: String) -> Url {
+        self.url_for(&crate::endpoints::UpstreamOAuth2Callback(provider))
+    }
+
+    /// Base URL of the admin API
+    #[must_use]
+    pub fn admin_api(&self) -> Url {
+        self.url_for(&crate::endpoints::AdminApi)
+    }
 }
 
 #[cfg(test)]
@@ -105,4 +135,24 @@ mod tests {
             "https://example.com/verify/123456abcdef"
         );
     }
+
+    #[test]
+    fn build_password_reset_url() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let builder = UrlBuilder::new(base);
+        assert_eq!(
+            builder.password_reset("123456abcdef".into()).as_str(),
+            "https://example.com/password/reset/123456abcdef"
+        );
+    }
+
+    #[test]
+    fn build_password_forgot_url() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let builder = UrlBuilder::new(base);
+        assert_eq!(
+            builder.password_forgot().as_str(),
+            "https://example.com/password/forgot"
+        );
+    }
 }
@@ -0,0 +1,72 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Solves the proof-of-work challenge of forms with a `data-pow-challenge`
+// input: finds a counter such that the SHA-256 hash of "<challenge>:<counter>"
+// starts with `data-pow-difficulty` zero bits, and puts it in the input. The
+// search starts when the page loads, and the form is only submitted once it is
+// done.
+
+(function () {
+  "use strict";
+
+  function leadingZeros(buffer) {
+    const bytes = new Uint8Array(buffer);
+    let zeros = 0;
+    for (const byte of bytes) {
+      if (byte === 0) {
+        zeros += 8;
+        continue;
+      }
+      zeros += Math.clz32(byte) - 24;
+      break;
+    }
+    return zeros;
+  }
+
+  async function solve(challenge, difficulty) {
+    const encoder = new TextEncoder();
+    for (let counter = 0; ; counter++) {
+      const data = encoder.encode(challenge + ":" + counter);
+      const hash = await crypto.subtle.digest("SHA-256", data);
+      if (leadingZeros(hash) >= difficulty) {
+        return counter.toString();
+      }
+    }
+  }
+
+  document.querySelectorAll("input[data-pow-challenge]").forEach((input) => {
+    const form = input.form;
+    const challenge = input.dataset.powChallenge;
+    const difficulty = parseInt(input.dataset.powDifficulty, 10);
+
+    const solution = solve(challenge, difficulty).then((value) => {
+      input.value = value;
+    });
+
+    form.addEventListener("submit", (event) => {
+      if (input.value) {
+        return;
+      }
+
+      event.preventDefault();
+      const buttons = form.querySelectorAll("button[type=submit], input[type=submit]");
+      buttons.forEach((button) => (button.disabled = true));
+      solution.then(() => {
+        buttons.forEach((button) => (button.disabled = false));
+        form.submit();
+      });
+    });
+  });
+})();
@@ -0,0 +1,98 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Runs the WebAuthn ceremonies for forms marked with a `data-webauthn`
+// attribute, either "create" or "get". The options given by the server in the
+// `data-webauthn-options` attribute have their binary fields base64url-encoded,
+// and so does the resulting credential, put in the `credential` field of the
+// form before submitting it.
+
+(function () {
+  "use strict";
+
+  function decode(value) {
+    const base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+    const padded = base64 + "===".slice((base64.length + 3) % 4);
+    return Uint8Array.from(atob(padded), (c) => c.charCodeAt(0)).buffer;
+  }
+
+  function encode(buffer) {
+    const bytes = String.fromCharCode(...new Uint8Array(buffer));
+    return btoa(bytes).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+  }
+
+  function decodeCredentials(list) {
+    return (list || []).map((c) => Object.assign({}, c, { id: decode(c.id) }));
+  }
+
+  async function create(options) {
+    const publicKey = Object.assign({}, options, {
+      challenge: decode(options.challenge),
+      user: Object.assign({}, options.user, { id: decode(options.user.id) }),
+      excludeCredentials: decodeCredentials(options.excludeCredentials),
+    });
+    const credential = await navigator.credentials.create({ publicKey });
+    return {
+      id: credential.id,
+      type: credential.type,
+      response: {
+        clientDataJSON: encode(credential.response.clientDataJSON),
+        attestationObject: encode(credential.response.attestationObject),
+      },
+    };
+  }
+
+  async function get(options) {
+    const publicKey = Object.assign({}, options, {
+      challenge: decode(options.challenge),
+      allowCredentials: decodeCredentials(options.allowCredentials),
+    });
+    const credential = await navigator.credentials.get({ publicKey });
+    const response = credential.response;
+    return {
+      id: credential.id,
+      type: credential.type,
+      response: {
+        clientDataJSON: encode(response.clientDataJSON),
+        authenticatorData: encode(response.authenticatorData),
+        signature: encode(response.signature),
+        userHandle: response.userHandle ? encode(response.userHandle) : null,
+      },
+    };
+  }
+
+  function setup(form) {
+    if (!window.PublicKeyCredential) {
+      form.hidden = true;
+      return;
+    }
+
+    form.addEventListener("submit", async (event) => {
+      event.preventDefault();
+      const options = JSON.parse(form.dataset.webauthnOptions);
+      const ceremony = form.dataset.webauthn === "create" ? create : get;
+      try {
+        const credential = await ceremony(options);
+        form.elements.credential.value = JSON.stringify(credential);
+        form.submit();
+      } catch (error) {
+        console.error("WebAuthn ceremony failed", error);
+      }
+    });
+  }
+
+  document.addEventListener("DOMContentLoaded", () => {
+    document.querySelectorAll("form[data-webauthn]").forEach(setup);
+  });
+})();
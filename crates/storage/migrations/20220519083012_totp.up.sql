@@ -0,0 +1,30 @@
+-- Copyright 2022 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Authentication method references (RFC 8176) of each authentication.
+-- Existing authentications were all done with a password
+ALTER TABLE user_session_authentications
+  ADD COLUMN amr TEXT[] NOT NULL DEFAULT '{}';
+
+UPDATE user_session_authentications SET amr = '{pwd}';
+
+CREATE TABLE user_totp_secrets (
+  "id" BIGSERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL UNIQUE REFERENCES users (id) ON DELETE CASCADE,
+  -- Encrypted with the server encryption key
+  "encrypted_secret" TEXT NOT NULL,
+  -- Last time step a code was accepted for, to prevent replays
+  "last_used_step" BIGINT,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,29 @@
+-- Copyright 2022 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+CREATE TABLE user_webauthn_credentials (
+  "id" BIGSERIAL PRIMARY KEY,
+  "user_id" BIGINT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+  -- Credential ID, base64url-encoded
+  "credential_id" TEXT NOT NULL UNIQUE,
+  -- COSE-encoded public key
+  "public_key" BYTEA NOT NULL,
+  "sign_count" BIGINT NOT NULL DEFAULT 0,
+  "name" TEXT NOT NULL,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+  "last_used_at" TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX user_webauthn_credentials_user_id_idx
+  ON user_webauthn_credentials (user_id);
@@ -0,0 +1,25 @@
+-- Copyright 2022 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Tokens handed out by admins to let people register when registration is
+-- restricted
+CREATE TABLE registration_tokens (
+  "id" BIGSERIAL PRIMARY KEY,
+  "token" TEXT NOT NULL UNIQUE,
+  "usage_limit" INTEGER CHECK ("usage_limit" > 0),
+  "uses" INTEGER NOT NULL DEFAULT 0,
+  "expires_at" TIMESTAMP WITH TIME ZONE,
+  "revoked_at" TIMESTAMP WITH TIME ZONE,
+  "created_at" TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,38 @@
+-- Copyright 2022 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Usernames are unique regardless of their case. Existing users whose usernames
+-- only differ by their case have to be renamed or removed first, so list them
+-- instead of failing on the index creation.
+DO $$
+DECLARE
+  conflicts TEXT;
+BEGIN
+  SELECT string_agg(usernames, '; ')
+    INTO conflicts
+    FROM (
+      SELECT string_agg(username, ', ' ORDER BY username) AS usernames
+        FROM users
+       GROUP BY LOWER(username)
+      HAVING COUNT(*) > 1
+    ) c;
+
+  IF conflicts IS NOT NULL THEN
+    RAISE EXCEPTION 'Some usernames only differ by their case: %', conflicts
+      USING HINT = 'Rename or remove the conflicting users, then run the migrations again.';
+  END IF;
+END
+$$;
+
+CREATE UNIQUE INDEX users_username_lower_idx ON users (LOWER("username"));
@@ -0,0 +1,24 @@
+-- Copyright 2022 The Matrix.org Foundation C.I.C.
+--
+-- Licensed under the Apache License, Version 2.0 (the "License");
+-- you may not use this file except in compliance with the License.
+-- You may obtain a copy of the License at
+--
+--     http://www.apache.org/licenses/LICENSE-2.0
+--
+-- Unless required by applicable law or agreed to in writing, software
+-- distributed under the License is distributed on an "AS IS" BASIS,
+-- WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+-- See the License for the specific language governing permissions and
+-- limitations under the License.
+
+-- Proof-of-work challenges of the registration form which were already solved,
+-- kept until they expire so that a solution can't be used twice
+CREATE TABLE consumed_captcha_challenges (
+  "id" BIGSERIAL PRIMARY KEY,
+  "challenge" TEXT NOT NULL UNIQUE,
+  "expires_at" TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE INDEX consumed_captcha_challenges_expires_at_idx
+  ON consumed_captcha_challenges (expires_at);
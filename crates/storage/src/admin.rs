@@ -0,0 +1,448 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Queries used to administrate users: listing them, with their sessions and
+//! consents
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use mas_data_model::User;
+use serde::Serialize;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tracing::{info_span, Instrument};
+
+use crate::PostgresqlBackend;
+
+/// A user, as shown in administration listings
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSummary {
+    pub id: i64,
+    pub username: String,
+    pub primary_email: Option<String>,
+    pub has_verified_email: bool,
+    /// Whether the user is currently suspended
+    pub locked: bool,
+    pub is_admin: bool,
+    pub created_at: DateTime<Utc>,
+    pub deactivated_at: Option<DateTime<Utc>>,
+}
+
+/// Filters of the user listing. Unset fields don't filter anything.
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    /// Only the user with this ID
+    pub id: Option<i64>,
+    /// Only users whose username or primary email contains this, regardless of
+    /// the case
+    pub search: Option<String>,
+    pub created_since: Option<DateTime<Utc>>,
+    pub has_verified_email: Option<bool>,
+    pub locked: Option<bool>,
+    pub deactivated: Option<bool>,
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn list_users(
+    executor: impl PgExecutor<'_>,
+    filter: &UserFilter,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<Vec<UserSummary>> {
+    let res = sqlx::query_as!(
+        UserSummary,
+        r#"
+            SELECT
+                s.id                 AS "id!",
+                s.username           AS "username!",
+                s.primary_email      AS "primary_email?",
+                s.has_verified_email AS "has_verified_email!",
+                s.locked             AS "locked!",
+                s.is_admin           AS "is_admin!",
+                s.created_at         AS "created_at!",
+                s.deactivated_at     AS "deactivated_at?"
+            FROM (
+                SELECT
+                    u.id,
+                    u.username,
+                    ue.email AS primary_email,
+                    EXISTS(
+                        SELECT 1 FROM user_emails v
+                        WHERE v.user_id = u.id AND v.confirmed_at IS NOT NULL
+                    ) AS has_verified_email,
+                    EXISTS(
+                        SELECT 1 FROM user_suspensions sus
+                        WHERE sus.user_id = u.id
+                          AND sus.lifted_at IS NULL
+                          AND (sus.expires_at IS NULL OR sus.expires_at > NOW())
+                    ) AS locked,
+                    u.is_admin,
+                    u.created_at,
+                    u.deactivated_at
+                FROM users u
+                LEFT JOIN user_emails ue
+                  ON ue.id = u.primary_email_id
+            ) s
+            WHERE ($1::TEXT IS NULL
+                   OR STRPOS(LOWER(s.username), LOWER($1)) > 0
+                   OR STRPOS(LOWER(COALESCE(s.primary_email, '')), LOWER($1)) > 0)
+              AND ($2::TIMESTAMPTZ IS NULL OR s.created_at >= $2)
+              AND ($3::BOOLEAN IS NULL OR s.has_verified_email = $3)
+              AND ($4::BOOLEAN IS NULL OR s.locked = $4)
+              AND ($5::BOOLEAN IS NULL OR (s.deactivated_at IS NOT NULL) = $5)
+              AND ($6::BIGINT IS NULL OR s.id = $6)
+            ORDER BY s.id ASC
+            LIMIT $7 OFFSET $8
+        "#,
+        filter.search,
+        filter.created_since,
+        filter.has_verified_email,
+        filter.locked,
+        filter.deactivated,
+        filter.id,
+        limit,
+        offset,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("List users"))
+    .await
+    .context("could not list users")?;
+
+    Ok(res)
+}
+
+/// A browser session of a user
+#[derive(Debug, Clone, Serialize)]
+pub struct UserSessionSummary {
+    pub id: i64,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_authenticated_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn list_user_sessions(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<UserSessionSummary>> {
+    let res = sqlx::query_as!(
+        UserSessionSummary,
+        r#"
+            SELECT
+                s.id,
+                s.active,
+                s.created_at,
+                MAX(a.created_at) AS last_authenticated_at
+            FROM user_sessions s
+            LEFT JOIN user_session_authentications a
+              ON a.session_id = s.id
+            WHERE s.user_id = $1
+            GROUP BY s.id
+            ORDER BY s.created_at ASC, s.id ASC
+        "#,
+        user.data,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("List user sessions"))
+    .await
+    .context("could not list user sessions")?;
+
+    Ok(res)
+}
+
+/// A session of an OAuth 2.0 client, started from a browser session of a user
+#[derive(Debug, Clone, Serialize)]
+pub struct OAuth2SessionSummary {
+    pub id: i64,
+    pub user_session_id: i64,
+    pub client_id: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub ended_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn list_user_oauth2_sessions(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<OAuth2SessionSummary>> {
+    let res = sqlx::query_as!(
+        OAuth2SessionSummary,
+        r#"
+            SELECT
+                os.id,
+                os.user_session_id,
+                c.client_id,
+                os.scope,
+                os.created_at,
+                os.ended_at
+            FROM oauth2_sessions os
+            INNER JOIN user_sessions us
+              ON us.id = os.user_session_id
+            INNER JOIN oauth2_clients c
+              ON c.id = os.oauth2_client_id
+            WHERE us.user_id = $1
+            ORDER BY os.created_at ASC, os.id ASC
+        "#,
+        user.data,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("List OAuth 2.0 sessions"))
+    .await
+    .context("could not list OAuth 2.0 sessions")?;
+
+    Ok(res)
+}
+
+/// End a browser session of a user, and the OAuth 2.0 sessions started from
+/// it. Returns `false` if the user has no such active session.
+#[tracing::instrument(skip(txn, user), fields(user.id = user.data))]
+pub async fn end_user_session(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+    id: i64,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE user_sessions
+            SET active = FALSE
+            WHERE id = $1 AND user_id = $2 AND active
+        "#,
+        id,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("End user session"))
+    .await
+    .context("could not end user session")?;
+
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+            UPDATE oauth2_sessions
+            SET ended_at = NOW()
+            WHERE user_session_id = $1 AND ended_at IS NULL
+        "#,
+        id,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("End OAuth 2.0 sessions"))
+    .await
+    .context("could not end OAuth 2.0 sessions")?;
+
+    Ok(true)
+}
+
+/// End an OAuth 2.0 session of a user. Returns `false` if the user has no such
+/// session, or if it already ended.
+#[tracing::instrument(skip(executor, user), fields(user.id = user.data))]
+pub async fn end_user_oauth2_session(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    id: i64,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE oauth2_sessions
+            SET ended_at = NOW()
+            WHERE id = $1
+              AND ended_at IS NULL
+              AND user_session_id IN (
+                SELECT id FROM user_sessions WHERE user_id = $2
+              )
+        "#,
+        id,
+        user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("End OAuth 2.0 session"))
+    .await
+    .context("could not end OAuth 2.0 session")?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// The scope a user consented to give to a client
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsentSummary {
+    pub client_id: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn list_user_consents(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<ConsentSummary>> {
+    let res = sqlx::query_as!(
+        ConsentSummary,
+        r#"
+            SELECT
+                c.client_id,
+                STRING_AGG(co.scope_token, ' ' ORDER BY co.scope_token) AS "scope!",
+                MIN(co.created_at) AS "created_at!",
+                MAX(co.updated_at) AS "updated_at!"
+            FROM oauth2_consents co
+            INNER JOIN oauth2_clients c
+              ON c.id = co.oauth2_client_id
+            WHERE co.user_id = $1
+            GROUP BY c.client_id
+            ORDER BY c.client_id ASC
+        "#,
+        user.data,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("List consents"))
+    .await
+    .context("could not list consents")?;
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use rand::{distributions::Alphanumeric, thread_rng, Rng};
+    use sqlx::PgPool;
+
+    use super::*;
+    use crate::{
+        suspension::suspend_user,
+        user::{
+            add_user_email, deactivate_user, mark_user_email_as_verified,
+            register_passwordless_user, set_user_email_as_primary,
+        },
+        MIGRATOR,
+    };
+
+    async fn pool() -> Option<PgPool> {
+        let database_url = std::env::var("DATABASE_URL").ok()?;
+        let pool = PgPool::connect(&database_url).await.unwrap();
+        MIGRATOR.run(&pool).await.unwrap();
+        Some(pool)
+    }
+
+    #[tokio::test]
+    async fn user_filters() {
+        let pool = if let Some(pool) = pool().await {
+            pool
+        } else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return;
+        };
+        let mut txn = pool.begin().await.unwrap();
+
+        // All the users share a prefix, so that searching for it only lists them
+        let prefix: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>()
+            .to_lowercase();
+
+        // A user with a verified email, an old one, a locked one and a
+        // deactivated one
+        let verified = register_passwordless_user(&mut txn, &format!("{}-verified", prefix))
+            .await
+            .unwrap();
+        let email = add_user_email(&mut txn, &verified, format!("{}@example.com", prefix))
+            .await
+            .unwrap();
+        let email = mark_user_email_as_verified(&mut txn, email).await.unwrap();
+        set_user_email_as_primary(&mut txn, &email).await.unwrap();
+
+        let old = register_passwordless_user(&mut txn, &format!("{}-old", prefix))
+            .await
+            .unwrap();
+        sqlx::query("UPDATE users SET created_at = NOW() - INTERVAL '1 day' WHERE id = $1")
+            .bind(old.data)
+            .execute(&mut txn)
+            .await
+            .unwrap();
+
+        let locked = register_passwordless_user(&mut txn, &format!("{}-locked", prefix))
+            .await
+            .unwrap();
+        suspend_user(&mut txn, &locked, "Spam", None).await.unwrap();
+
+        let deactivated = register_passwordless_user(&mut txn, &format!("{}-deactivated", prefix))
+            .await
+            .unwrap();
+        deactivate_user(&mut txn, &deactivated).await.unwrap();
+
+        let mut filter = UserFilter {
+            search: Some(prefix.to_uppercase()),
+            ..UserFilter::default()
+        };
+        let ids = |users: Vec<UserSummary>| -> Vec<i64> {
+            users.into_iter().map(|user| user.id).collect()
+        };
+
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(
+            ids(users),
+            vec![verified.data, old.data, locked.data, deactivated.data]
+        );
+
+        // The search also looks at the primary email
+        let search = UserFilter {
+            search: Some(format!("{}@EXAMPLE", prefix)),
+            ..UserFilter::default()
+        };
+        let users = list_users(&mut txn, &search, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![verified.data]);
+
+        // Pagination
+        let users = list_users(&mut txn, &filter, 2, 0).await.unwrap();
+        assert_eq!(ids(users), vec![verified.data, old.data]);
+        let users = list_users(&mut txn, &filter, 2, 2).await.unwrap();
+        assert_eq!(ids(users), vec![locked.data, deactivated.data]);
+        let users = list_users(&mut txn, &filter, 2, 4).await.unwrap();
+        assert!(users.is_empty());
+
+        filter.created_since = Some(Utc::now() - Duration::hours(1));
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(
+            ids(users),
+            vec![verified.data, locked.data, deactivated.data]
+        );
+        filter.created_since = None;
+
+        filter.has_verified_email = Some(true);
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![verified.data]);
+        filter.has_verified_email = Some(false);
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![old.data, locked.data, deactivated.data]);
+        filter.has_verified_email = None;
+
+        filter.locked = Some(true);
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![locked.data]);
+        filter.locked = None;
+
+        filter.deactivated = Some(true);
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![deactivated.data]);
+        filter.deactivated = Some(false);
+        let users = list_users(&mut txn, &filter, 50, 0).await.unwrap();
+        assert_eq!(ids(users), vec![verified.data, old.data, locked.data]);
+
+        txn.rollback().await.unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Proof-of-work challenges already used on the registration form
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use tracing::{info_span, Instrument};
+
+/// Mark a challenge as used, until it expires.
+///
+/// Returns `false` if it was already used, in which case the solution must be
+/// rejected to prevent replays.
+#[tracing::instrument(skip(conn))]
+pub async fn consume_captcha_challenge(
+    conn: &mut PgConnection,
+    challenge: &str,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<bool> {
+    // Expired challenges are refused anyway, no need to remember them
+    sqlx::query!(
+        r#"
+            DELETE FROM consumed_captcha_challenges
+            WHERE expires_at < NOW()
+        "#,
+    )
+    .execute(&mut *conn)
+    .instrument(info_span!("Forget expired captcha challenges"))
+    .await
+    .context("could not forget expired captcha challenges")?;
+
+    let res = sqlx::query!(
+        r#"
+            INSERT INTO consumed_captcha_challenges (challenge, expires_at)
+            VALUES ($1, $2)
+            ON CONFLICT (challenge) DO NOTHING
+        "#,
+        challenge,
+        expires_at,
+    )
+    .execute(&mut *conn)
+    .instrument(info_span!("Consume captcha challenge"))
+    .await
+    .context("could not consume captcha challenge")?;
+
+    Ok(res.rows_affected() == 1)
+}
@@ -56,8 +56,19 @@ struct IdAndCreationTime {
     created_at: DateTime<Utc>,
 }
 
+pub mod admin;
+pub mod captcha;
+pub mod login_throttle;
 pub mod oauth2;
+pub mod password_reset;
+pub mod passwords;
+pub mod recovery_codes;
+pub mod registration_token;
+pub mod suspension;
+pub mod totp;
+pub mod upstream_oauth2;
 pub mod user;
+pub mod webauthn;
 
 /// Embedded migrations, allowing them to run on startup
 pub static MIGRATOR: Migrator = sqlx::migrate!();
@@ -0,0 +1,168 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Counters of failed password attempts, used to slow down password guessing,
+//! and of password reset requests, used to avoid flooding inboxes
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{postgres::types::PgInterval, PgExecutor};
+use tracing::{info_span, Instrument};
+
+/// What failed attempts are counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleScope {
+    /// A username, whether the account exists or not
+    Account,
+
+    /// A client IP address
+    Ip,
+
+    /// An email address password reset links are sent to
+    ResetAddress,
+
+    /// A client IP address asking for password reset links
+    ResetIp,
+}
+
+impl ThrottleScope {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Ip => "ip",
+            Self::ResetAddress => "reset_address",
+            Self::ResetIp => "reset_ip",
+        }
+    }
+}
+
+/// Failed attempts of an account or IP address
+#[derive(Debug, Clone)]
+pub struct LoginThrottle {
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn lookup_login_throttle(
+    executor: impl PgExecutor<'_>,
+    scope: ThrottleScope,
+    key: &str,
+) -> anyhow::Result<Option<LoginThrottle>> {
+    let res = sqlx::query_as!(
+        LoginThrottle,
+        r#"
+            SELECT failures, last_failure_at, locked_until
+            FROM login_throttles
+            WHERE scope = $1 AND key = $2
+        "#,
+        scope.as_str(),
+        key,
+    )
+    .fetch_optional(executor)
+    .instrument(info_span!("Lookup login throttle"))
+    .await
+    .context("could not lookup login throttle")?;
+
+    Ok(res)
+}
+
+/// Count a failed attempt. Failures older than `forget_after` are forgotten
+/// first.
+#[tracing::instrument(skip(executor))]
+pub async fn record_login_failure(
+    executor: impl PgExecutor<'_>,
+    scope: ThrottleScope,
+    key: &str,
+    forget_after: Duration,
+) -> anyhow::Result<LoginThrottle> {
+    let forget_after = PgInterval::try_from(forget_after)
+        .map_err(|e| anyhow::anyhow!("failed to encode duration: {}", e))?;
+
+    let res = sqlx::query_as!(
+        LoginThrottle,
+        r#"
+            INSERT INTO login_throttles (scope, key, failures)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (scope, key) DO UPDATE
+            SET failures = CASE
+                  WHEN login_throttles.last_failure_at + $3 < NOW() THEN 1
+                  ELSE login_throttles.failures + 1
+                END,
+                last_failure_at = NOW()
+            RETURNING failures, last_failure_at, locked_until
+        "#,
+        scope.as_str(),
+        key,
+        forget_after,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Record login failure"))
+    .await
+    .context("could not record login failure")?;
+
+    Ok(res)
+}
+
+/// Refuse attempts until the given time, starting the count of failures over
+#[tracing::instrument(skip(executor))]
+pub async fn lock_login_throttle(
+    executor: impl PgExecutor<'_>,
+    scope: ThrottleScope,
+    key: &str,
+    until: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE login_throttles
+            SET locked_until = $3, failures = 0
+            WHERE scope = $1 AND key = $2
+        "#,
+        scope.as_str(),
+        key,
+        until,
+    )
+    .execute(executor)
+    .instrument(info_span!("Lock login throttle"))
+    .await
+    .context("could not lock login throttle")?;
+
+    Ok(())
+}
+
+/// Forget the failed attempts of an account or IP address, lifting any
+/// lockout. Returns whether there was anything to forget.
+#[tracing::instrument(skip(executor))]
+pub async fn clear_login_throttle(
+    executor: impl PgExecutor<'_>,
+    scope: ThrottleScope,
+    key: &str,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            DELETE FROM login_throttles
+            WHERE scope = $1 AND key = $2
+        "#,
+        scope.as_str(),
+        key,
+    )
+    .execute(executor)
+    .instrument(info_span!("Clear login throttle"))
+    .await
+    .context("could not clear login throttle")?;
+
+    Ok(res.rows_affected() > 0)
+}
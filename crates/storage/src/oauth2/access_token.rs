@@ -19,7 +19,9 @@ use sqlx::{Acquire, PgExecutor, Postgres};
 use thiserror::Error;
 
 use super::client::{lookup_client, ClientFetchError};
-use crate::{DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend};
+use crate::{
+    user::authentication_methods, DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend,
+};
 
 pub async fn add_access_token(
     executor: impl PgExecutor<'_>,
@@ -70,8 +72,10 @@ pub struct OAuth2AccessTokenLookup {
     user_session_created_at: DateTime<Utc>,
     user_id: i64,
     user_username: String,
+    user_is_admin: bool,
     user_session_last_authentication_id: Option<i64>,
     user_session_last_authentication_created_at: Option<DateTime<Utc>>,
+    user_session_last_authentication_amr: Option<Vec<String>>,
     user_email_id: Option<i64>,
     user_email: Option<String>,
     user_email_created_at: Option<DateTime<Utc>>,
@@ -125,8 +129,10 @@ where
                 us.created_at      AS "user_session_created_at!",
                  u.id              AS "user_id!",
                  u.username        AS "user_username!",
+                 u.is_admin        AS "user_is_admin!",
                 usa.id             AS "user_session_last_authentication_id?",
                 usa.created_at     AS "user_session_last_authentication_created_at?",
+                usa.amr            AS "user_session_last_authentication_amr?",
                 ue.id              AS "user_email_id?",
                 ue.email           AS "user_email?",
                 ue.created_at      AS "user_email_created_at?",
@@ -148,6 +154,13 @@ where
               AND at.created_at + (at.expires_after * INTERVAL '1 second') >= now()
               AND us.active
               AND os.ended_at IS NULL
+              AND u.deactivated_at IS NULL
+              AND NOT EXISTS (
+                SELECT 1 FROM user_suspensions sus
+                WHERE sus.user_id = u.id
+                  AND sus.lifted_at IS NULL
+                  AND (sus.expires_at IS NULL OR sus.expires_at > NOW())
+              )
 
             ORDER BY usa.created_at DESC
             LIMIT 1
@@ -188,17 +201,20 @@ where
             username: res.user_username,
             sub: format!("fake-sub-{}", res.user_id),
             primary_email,
+            is_admin: res.user_is_admin,
         };
 
         let last_authentication = match (
             res.user_session_last_authentication_id,
             res.user_session_last_authentication_created_at,
+            res.user_session_last_authentication_amr,
         ) {
-            (None, None) => None,
-            (Some(id), Some(created_at)) => Some(Authentication {
+            (Some(id), Some(created_at), Some(amr)) => Some(Authentication {
                 data: id,
                 created_at,
+                methods: authentication_methods(&amr)?,
             }),
+            (None, None, None) => None,
             _ => return Err(DatabaseInconsistencyError.into()),
         };
 
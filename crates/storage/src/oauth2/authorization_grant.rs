@@ -25,16 +25,20 @@ use mas_data_model::{
 use mas_iana::oauth::PkceCodeChallengeMethod;
 use oauth2_types::{requests::ResponseMode, scope::Scope};
 use sqlx::{PgConnection, PgExecutor};
+use thiserror::Error;
 use url::Url;
 
 use super::client::lookup_client;
-use crate::{DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend};
+use crate::{
+    user::authentication_methods, DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend,
+};
 
 #[allow(clippy::too_many_arguments)]
 pub async fn new_authorization_grant(
     executor: impl PgExecutor<'_>,
     client: Client<PostgresqlBackend>,
     redirect_uri: Url,
+    redirect_uri_provided: bool,
     scope: Scope,
     code: Option<AuthorizationCode>,
     state: Option<String>,
@@ -62,9 +66,9 @@ pub async fn new_authorization_grant(
                 (oauth2_client_id, redirect_uri, scope, state, nonce, max_age,
                  acr_values, response_mode, code_challenge, code_challenge_method,
                  response_type_code, response_type_token, response_type_id_token,
-                 code, requires_consent)
+                 code, requires_consent, redirect_uri_provided)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
             RETURNING id, created_at
         "#,
         &client.data,
@@ -83,6 +87,7 @@ pub async fn new_authorization_grant(
         response_type_id_token,
         code_str,
         requires_consent,
+        redirect_uri_provided,
     )
     .fetch_one(executor)
     .await
@@ -93,6 +98,7 @@ pub async fn new_authorization_grant(
         stage: AuthorizationGrantStage::Pending,
         code,
         redirect_uri,
+        redirect_uri_provided,
         client,
         scope,
         state,
@@ -117,6 +123,7 @@ struct GrantLookup {
     grant_scope: String,
     grant_state: Option<String>,
     grant_redirect_uri: String,
+    grant_redirect_uri_provided: bool,
     grant_response_mode: String,
     grant_nonce: Option<String>,
     grant_max_age: Option<i32>,
@@ -134,8 +141,10 @@ struct GrantLookup {
     user_session_created_at: Option<DateTime<Utc>>,
     user_id: Option<i64>,
     user_username: Option<String>,
+    user_is_admin: Option<bool>,
     user_session_last_authentication_id: Option<i64>,
     user_session_last_authentication_created_at: Option<DateTime<Utc>>,
+    user_session_last_authentication_amr: Option<Vec<String>>,
     user_email_id: Option<i64>,
     user_email: Option<String>,
     user_email_created_at: Option<DateTime<Utc>>,
@@ -161,12 +170,14 @@ impl GrantLookup {
         let last_authentication = match (
             self.user_session_last_authentication_id,
             self.user_session_last_authentication_created_at,
+            self.user_session_last_authentication_amr,
         ) {
-            (Some(id), Some(created_at)) => Some(Authentication {
+            (Some(id), Some(created_at), Some(amr)) => Some(Authentication {
                 data: id,
                 created_at,
+                methods: authentication_methods(&amr)?,
             }),
-            (None, None) => None,
+            (None, None, None) => None,
             _ => return Err(DatabaseInconsistencyError),
         };
 
@@ -209,6 +220,7 @@ impl GrantLookup {
                     username: user_username,
                     sub: format!("fake-sub-{}", user_id),
                     primary_email,
+                    is_admin: self.user_is_admin.unwrap_or_default(),
                 };
 
                 let browser_session = BrowserSession {
@@ -317,6 +329,7 @@ impl GrantLookup {
             max_age, // TODO
             response_mode,
             redirect_uri,
+            redirect_uri_provided: self.grant_redirect_uri_provided,
             created_at: self.grant_created_at,
             response_type_token: self.grant_response_type_token,
             response_type_id_token: self.grant_response_type_id_token,
@@ -342,6 +355,7 @@ pub async fn get_grant_by_id(
                 og.scope         AS grant_scope,
                 og.state         AS grant_state,
                 og.redirect_uri  AS grant_redirect_uri,
+                og.redirect_uri_provided  AS grant_redirect_uri_provided,
                 og.response_mode AS grant_response_mode,
                 og.nonce         AS grant_nonce,
                 og.max_age       AS grant_max_age,
@@ -359,8 +373,10 @@ pub async fn get_grant_by_id(
                 us.created_at      AS "user_session_created_at?",
                  u.id              AS "user_id?",
                  u.username        AS "user_username?",
+                 u.is_admin        AS "user_is_admin?",
                 usa.id             AS "user_session_last_authentication_id?",
                 usa.created_at     AS "user_session_last_authentication_created_at?",
+                usa.amr            AS "user_session_last_authentication_amr?",
                 ue.id              AS "user_email_id?",
                 ue.email           AS "user_email?",
                 ue.created_at      AS "user_email_created_at?",
@@ -394,11 +410,24 @@ pub async fn get_grant_by_id(
     Ok(grant)
 }
 
+#[derive(Debug, Error)]
+#[error("failed to lookup authorization grant")]
+pub enum GrantLookupError {
+    Fetch(#[from] sqlx::Error),
+    Conversion(#[from] DatabaseInconsistencyError),
+}
+
+impl GrantLookupError {
+    #[must_use]
+    pub fn not_found(&self) -> bool {
+        matches!(self, Self::Fetch(sqlx::Error::RowNotFound))
+    }
+}
+
 pub async fn lookup_grant_by_code(
     conn: &mut PgConnection,
     code: &str,
-) -> anyhow::Result<AuthorizationGrant<PostgresqlBackend>> {
-    // TODO: handle "not found" cases
+) -> Result<AuthorizationGrant<PostgresqlBackend>, GrantLookupError> {
     let res = sqlx::query_as!(
         GrantLookup,
         r#"
@@ -411,6 +440,7 @@ pub async fn lookup_grant_by_code(
                 og.scope         AS grant_scope,
                 og.state         AS grant_state,
                 og.redirect_uri  AS grant_redirect_uri,
+                og.redirect_uri_provided  AS grant_redirect_uri_provided,
                 og.response_mode AS grant_response_mode,
                 og.nonce         AS grant_nonce,
                 og.max_age       AS grant_max_age,
@@ -428,8 +458,10 @@ pub async fn lookup_grant_by_code(
                 us.created_at      AS "user_session_created_at?",
                  u.id              AS "user_id?",
                  u.username        AS "user_username?",
+                 u.is_admin        AS "user_is_admin?",
                 usa.id             AS "user_session_last_authentication_id?",
                 usa.created_at     AS "user_session_last_authentication_created_at?",
+                usa.amr            AS "user_session_last_authentication_amr?",
                 ue.id              AS "user_email_id?",
                 ue.email           AS "user_email?",
                 ue.created_at      AS "user_email_created_at?",
@@ -455,8 +487,7 @@ pub async fn lookup_grant_by_code(
         code,
     )
     .fetch_one(&mut *conn)
-    .await
-    .context("failed to lookup grant by code")?;
+    .await?;
 
     let grant = res.into_authorization_grant(&mut *conn).await?;
 
@@ -20,7 +20,7 @@ use mas_iana::{
     oauth::{OAuthAuthorizationEndpointResponseType, OAuthClientAuthenticationMethod},
 };
 use mas_jose::JsonWebKeySet;
-use oauth2_types::requests::GrantType;
+use oauth2_types::{oidc::ApplicationType, requests::GrantType};
 use sqlx::{PgConnection, PgExecutor};
 use thiserror::Error;
 use url::Url;
@@ -36,6 +36,7 @@ pub struct OAuth2ClientLookup {
     response_types: Vec<String>,
     grant_type_authorization_code: bool,
     grant_type_refresh_token: bool,
+    application_type: Option<String>,
     contacts: Vec<String>,
     client_name: Option<String>,
     logo_uri: Option<String>,
@@ -71,6 +72,9 @@ pub enum ClientFetchError {
         source: mas_iana::ParseError,
     },
 
+    #[error("invalid application type {0:?}")]
+    InvalidApplicationType(String),
+
     #[error(transparent)]
     Database(#[from] sqlx::Error),
 }
@@ -109,6 +113,14 @@ impl TryInto<Client<PostgresqlBackend>> for OAuth2ClientLookup {
             grant_types.push(GrantType::RefreshToken);
         }
 
+        let application_type = self
+            .application_type
+            .map(|s| {
+                s.parse::<ApplicationType>()
+                    .map_err(|_| ClientFetchError::InvalidApplicationType(s))
+            })
+            .transpose()?;
+
         let logo_uri = self
             .logo_uri
             .map(|s| s.parse())
@@ -216,6 +228,7 @@ impl TryInto<Client<PostgresqlBackend>> for OAuth2ClientLookup {
             redirect_uris,
             response_types,
             grant_types,
+            application_type,
             contacts: self.contacts,
             client_name: self.client_name,
             logo_uri,
@@ -247,6 +260,7 @@ pub async fn lookup_client(
                 c.response_types,
                 c.grant_type_authorization_code,
                 c.grant_type_refresh_token,
+                c.application_type,
                 c.contacts,
                 c.client_name,
                 c.logo_uri,
@@ -289,6 +303,7 @@ pub async fn lookup_client_by_client_id(
                 c.response_types,
                 c.grant_type_authorization_code,
                 c.grant_type_refresh_token,
+                c.application_type,
                 c.contacts,
                 c.client_name,
                 c.logo_uri,
@@ -324,6 +339,7 @@ pub async fn insert_client(
     encrypted_client_secret: Option<&str>,
     response_types: &[OAuthAuthorizationEndpointResponseType],
     grant_types: &[GrantType],
+    application_type: ApplicationType,
     contacts: &[String],
     client_name: Option<&str>,
     logo_uri: Option<&Url>,
@@ -341,6 +357,7 @@ pub async fn insert_client(
     let response_types: Vec<String> = response_types.iter().map(ToString::to_string).collect();
     let grant_type_authorization_code = grant_types.contains(&GrantType::AuthorizationCode);
     let grant_type_refresh_token = grant_types.contains(&GrantType::RefreshToken);
+    let application_type = application_type.to_string();
     let logo_uri = logo_uri.map(Url::as_str);
     let client_uri = client_uri.map(Url::as_str);
     let policy_uri = policy_uri.map(Url::as_str);
@@ -361,6 +378,7 @@ pub async fn insert_client(
                  response_types,
                  grant_type_authorization_code,
                  grant_type_refresh_token,
+                 application_type,
                  contacts,
                  client_name,
                  logo_uri,
@@ -375,7 +393,7 @@ pub async fn insert_client(
                  token_endpoint_auth_signing_alg,
                  initiate_login_uri)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18)
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19)
             RETURNING id
         "#,
         client_id,
@@ -383,6 +401,7 @@ pub async fn insert_client(
         &response_types,
         grant_type_authorization_code,
         grant_type_refresh_token,
+        application_type,
         contacts,
         client_name,
         logo_uri,
@@ -416,9 +435,11 @@ pub async fn insert_client(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn insert_client_from_config(
     conn: &mut PgConnection,
     client_id: &str,
+    application_type: Option<ApplicationType>,
     client_auth_method: OAuthClientAuthenticationMethod,
     encrypted_client_secret: Option<&str>,
     jwks: Option<&JsonWebKeySet>,
@@ -440,6 +461,7 @@ pub async fn insert_client_from_config(
     let jwks_uri = jwks_uri.map(Url::as_str);
 
     let client_auth_method = client_auth_method.to_string();
+    let application_type = application_type.map(|t| t.to_string());
 
     let id = sqlx::query_scalar!(
         r#"
@@ -452,9 +474,10 @@ pub async fn insert_client_from_config(
                  token_endpoint_auth_method,
                  jwks,
                  jwks_uri,
+                 application_type,
                  contacts)
             VALUES
-                ($1, $2, $3, $4, $5, $6, $7, $8, '{}')
+                ($1, $2, $3, $4, $5, $6, $7, $8, $9, '{}')
             RETURNING id
         "#,
         client_id,
@@ -465,6 +488,7 @@ pub async fn insert_client_from_config(
         client_auth_method,
         jwks,
         jwks_uri,
+        application_type,
     )
     .fetch_one(&mut *conn)
     .await?;
@@ -491,3 +515,125 @@ pub async fn truncate_clients(executor: impl PgExecutor<'_>) -> anyhow::Result<(
         .await?;
     Ok(())
 }
+
+pub async fn list_clients(
+    executor: impl PgExecutor<'_>,
+) -> Result<Vec<Client<PostgresqlBackend>>, ClientFetchError> {
+    let res = sqlx::query_as!(
+        OAuth2ClientLookup,
+        r#"
+            SELECT
+                c.id,
+                c.client_id,
+                c.encrypted_client_secret,
+                ARRAY(SELECT redirect_uri FROM oauth2_client_redirect_uris r WHERE r.oauth2_client_id = c.id) AS "redirect_uris!",
+                c.response_types,
+                c.grant_type_authorization_code,
+                c.grant_type_refresh_token,
+                c.application_type,
+                c.contacts,
+                c.client_name,
+                c.logo_uri,
+                c.client_uri,
+                c.policy_uri,
+                c.tos_uri,
+                c.jwks_uri,
+                c.jwks,
+                c.id_token_signed_response_alg,
+                c.userinfo_signed_response_alg,
+                c.token_endpoint_auth_method,
+                c.token_endpoint_auth_signing_alg,
+                c.initiate_login_uri
+            FROM oauth2_clients c
+
+            ORDER BY c.client_id
+        "#,
+    )
+    .fetch_all(executor)
+    .await?;
+
+    res.into_iter().map(TryInto::try_into).collect()
+}
+
+/// Update the fields of a client which can be set from the configuration,
+/// replacing its redirect URIs
+#[allow(clippy::too_many_arguments)]
+pub async fn update_client_from_config(
+    conn: &mut PgConnection,
+    client: &Client<PostgresqlBackend>,
+    application_type: Option<ApplicationType>,
+    client_auth_method: OAuthClientAuthenticationMethod,
+    encrypted_client_secret: Option<&str>,
+    jwks: Option<&JsonWebKeySet>,
+    jwks_uri: Option<&Url>,
+    redirect_uris: &[Url],
+) -> anyhow::Result<()> {
+    let jwks = jwks.map(serde_json::to_value).transpose()?;
+    let jwks_uri = jwks_uri.map(Url::as_str);
+
+    let client_auth_method = client_auth_method.to_string();
+    let application_type = application_type.map(|t| t.to_string());
+
+    sqlx::query!(
+        r#"
+            UPDATE oauth2_clients
+            SET encrypted_client_secret = $2,
+                token_endpoint_auth_method = $3,
+                jwks = $4,
+                jwks_uri = $5,
+                application_type = $6
+            WHERE id = $1
+        "#,
+        client.data,
+        encrypted_client_secret,
+        client_auth_method,
+        jwks,
+        jwks_uri,
+        application_type,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let redirect_uris: Vec<String> = redirect_uris.iter().map(ToString::to_string).collect();
+
+    sqlx::query!(
+        r#"
+            DELETE FROM oauth2_client_redirect_uris
+            WHERE oauth2_client_id = $1
+        "#,
+        client.data,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO oauth2_client_redirect_uris (oauth2_client_id, redirect_uri)
+            SELECT $1, uri FROM UNNEST($2::text[]) uri
+        "#,
+        client.data,
+        &redirect_uris,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Delete a client, along with its sessions, tokens and consents
+pub async fn delete_client(
+    executor: impl PgExecutor<'_>,
+    client: Client<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            DELETE FROM oauth2_clients
+            WHERE id = $1
+        "#,
+        client.data,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
@@ -68,3 +68,24 @@ pub async fn insert_client_consent(
 
     Ok(())
 }
+
+/// Forget the consent a user gave to a client, so that they are asked again
+/// the next time. Returns `false` if there was no consent.
+pub async fn delete_client_consent(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    client: &Client<PostgresqlBackend>,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            DELETE FROM oauth2_consents
+            WHERE user_id = $1 AND oauth2_client_id = $2
+        "#,
+        user.data,
+        client.data,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(res.rows_affected() > 0)
+}
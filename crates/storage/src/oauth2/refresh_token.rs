@@ -21,7 +21,9 @@ use sqlx::{PgConnection, PgExecutor};
 use thiserror::Error;
 
 use super::client::{lookup_client, ClientFetchError};
-use crate::{DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend};
+use crate::{
+    user::authentication_methods, DatabaseInconsistencyError, IdAndCreationTime, PostgresqlBackend,
+};
 
 pub async fn add_refresh_token(
     executor: impl PgExecutor<'_>,
@@ -70,8 +72,10 @@ struct OAuth2RefreshTokenLookup {
     user_session_created_at: DateTime<Utc>,
     user_id: i64,
     user_username: String,
+    user_is_admin: bool,
     user_session_last_authentication_id: Option<i64>,
     user_session_last_authentication_created_at: Option<DateTime<Utc>>,
+    user_session_last_authentication_amr: Option<Vec<String>>,
     user_email_id: Option<i64>,
     user_email: Option<String>,
     user_email_created_at: Option<DateTime<Utc>>,
@@ -117,8 +121,10 @@ pub async fn lookup_active_refresh_token(
                 us.created_at      AS "user_session_created_at!",
                  u.id              AS "user_id!",
                  u.username        AS "user_username!",
+                 u.is_admin        AS "user_is_admin!",
                 usa.id             AS "user_session_last_authentication_id?",
                 usa.created_at     AS "user_session_last_authentication_created_at?",
+                usa.amr            AS "user_session_last_authentication_amr?",
                 ue.id              AS "user_email_id?",
                 ue.email           AS "user_email?",
                 ue.created_at      AS "user_email_created_at?",
@@ -141,6 +147,13 @@ pub async fn lookup_active_refresh_token(
               AND rt.next_token_id IS NULL
               AND us.active
               AND os.ended_at IS NULL
+              AND u.deactivated_at IS NULL
+              AND NOT EXISTS (
+                SELECT 1 FROM user_suspensions sus
+                WHERE sus.user_id = u.id
+                  AND sus.lifted_at IS NULL
+                  AND (sus.expires_at IS NULL OR sus.expires_at > NOW())
+              )
 
             ORDER BY usa.created_at DESC
             LIMIT 1
@@ -197,17 +210,20 @@ pub async fn lookup_active_refresh_token(
         username: res.user_username,
         sub: format!("fake-sub-{}", res.user_id),
         primary_email,
+        is_admin: res.user_is_admin,
     };
 
     let last_authentication = match (
         res.user_session_last_authentication_id,
         res.user_session_last_authentication_created_at,
+        res.user_session_last_authentication_amr,
     ) {
-        (None, None) => None,
-        (Some(id), Some(created_at)) => Some(Authentication {
+        (Some(id), Some(created_at), Some(amr)) => Some(Authentication {
             data: id,
             created_at,
+            methods: authentication_methods(&amr)?,
         }),
+        (None, None, None) => None,
         _ => return Err(DatabaseInconsistencyError.into()),
     };
 
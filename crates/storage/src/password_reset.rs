@@ -0,0 +1,173 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Password reset links sent by email
+
+use anyhow::Context;
+use chrono::Duration;
+use mas_data_model::{User, UserEmail};
+use sqlx::{postgres::types::PgInterval, PgExecutor, Postgres, Transaction};
+use tracing::{info_span, Instrument};
+
+use crate::{user::lookup_user_by_username, PostgresqlBackend};
+
+/// A pending password reset, not expired nor used yet
+#[derive(Debug, Clone)]
+pub struct PasswordReset {
+    pub id: i64,
+    pub user: User<PostgresqlBackend>,
+}
+
+/// Find the active user owning a verified email address, along with that
+/// address. Deactivated users are never found, like unknown addresses.
+#[tracing::instrument(skip(txn))]
+pub async fn lookup_user_by_verified_email(
+    txn: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> anyhow::Result<Option<(User<PostgresqlBackend>, UserEmail<PostgresqlBackend>)>> {
+    let res = sqlx::query!(
+        r#"
+            SELECT
+                u.username,
+                ue.id,
+                ue.email,
+                ue.created_at,
+                ue.confirmed_at
+            FROM user_emails ue
+            INNER JOIN users u
+              ON u.id = ue.user_id
+            WHERE ue.email = $1
+              AND ue.confirmed_at IS NOT NULL
+              AND u.deactivated_at IS NULL
+            ORDER BY ue.confirmed_at ASC
+            LIMIT 1
+        "#,
+        email,
+    )
+    .fetch_optional(&mut *txn)
+    .instrument(info_span!("Lookup verified email"))
+    .await
+    .context("could not lookup verified email")?;
+
+    let res = if let Some(res) = res {
+        res
+    } else {
+        return Ok(None);
+    };
+
+    let user = lookup_user_by_username(&mut *txn, &res.username).await?;
+    let email = UserEmail {
+        data: res.id,
+        email: res.email,
+        created_at: res.created_at,
+        confirmed_at: res.confirmed_at,
+    };
+
+    Ok(Some((user, email)))
+}
+
+/// Save a new password reset code for the given address
+#[tracing::instrument(skip(executor, email, code), fields(email.id = email.data))]
+pub async fn add_password_reset(
+    executor: impl PgExecutor<'_>,
+    email: &UserEmail<PostgresqlBackend>,
+    code: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO user_password_resets (user_email_id, code)
+            VALUES ($1, $2)
+        "#,
+        email.data,
+        code,
+    )
+    .execute(executor)
+    .instrument(info_span!("Add password reset"))
+    .await
+    .context("could not insert password reset")?;
+
+    Ok(())
+}
+
+/// Find a password reset by its code, if it is still usable. Resets of
+/// deactivated users are never found.
+#[tracing::instrument(skip(txn, code))]
+pub async fn lookup_password_reset(
+    txn: &mut Transaction<'_, Postgres>,
+    code: &str,
+    max_age: Duration,
+) -> anyhow::Result<Option<PasswordReset>> {
+    let max_age = PgInterval::try_from(max_age)
+        .map_err(|e| anyhow::anyhow!("failed to encode duration: {}", e))?;
+
+    let res = sqlx::query!(
+        r#"
+            SELECT
+                pr.id,
+                u.username
+            FROM user_password_resets pr
+            INNER JOIN user_emails ue
+              ON ue.id = pr.user_email_id
+            INNER JOIN users u
+              ON u.id = ue.user_id
+            WHERE pr.code = $1
+              AND pr.consumed_at IS NULL
+              AND pr.created_at + $2 >= NOW()
+              AND ue.confirmed_at IS NOT NULL
+              AND u.deactivated_at IS NULL
+        "#,
+        code,
+        max_age,
+    )
+    .fetch_optional(&mut *txn)
+    .instrument(info_span!("Lookup password reset"))
+    .await
+    .context("could not lookup password reset")?;
+
+    let res = if let Some(res) = res {
+        res
+    } else {
+        return Ok(None);
+    };
+
+    let user = lookup_user_by_username(&mut *txn, &res.username).await?;
+
+    Ok(Some(PasswordReset { id: res.id, user }))
+}
+
+/// Mark a password reset as used, along with all the other pending resets of
+/// the same user
+#[tracing::instrument(skip_all, fields(reset.id = reset.id, user.id = reset.user.data))]
+pub async fn consume_password_reset(
+    executor: impl PgExecutor<'_>,
+    reset: &PasswordReset,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE user_password_resets
+            SET consumed_at = NOW()
+            WHERE consumed_at IS NULL
+              AND user_email_id IN (
+                SELECT id FROM user_emails WHERE user_id = $1
+              )
+        "#,
+        reset.user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("Consume password resets"))
+    .await
+    .context("could not consume password resets")?;
+
+    Ok(())
+}
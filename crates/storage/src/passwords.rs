@@ -0,0 +1,233 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of password hashes, whatever scheme they were made with
+
+use std::str::FromStr;
+
+use argon2::{Algorithm, Argon2, Params};
+use password_hash::{PasswordHash, PasswordVerifier};
+use pbkdf2::Pbkdf2;
+use scrypt::Scrypt;
+use thiserror::Error;
+
+/// Scheme a password hash was made with. New passwords are always hashed with
+/// Argon2, other schemes come from users imported from other systems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordScheme {
+    /// Argon2, as a PHC string
+    Argon2,
+
+    /// bcrypt, in the modular crypt format used by Synapse
+    Bcrypt,
+
+    /// PBKDF2 with SHA-256 or SHA-512, as a PHC string
+    Pbkdf2,
+
+    /// scrypt, as a PHC string
+    Scrypt,
+}
+
+impl PasswordScheme {
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Argon2 => "argon2",
+            Self::Bcrypt => "bcrypt",
+            Self::Pbkdf2 => "pbkdf2",
+            Self::Scrypt => "scrypt",
+        }
+    }
+
+    /// Check that a hash is well-formed for this scheme
+    pub fn validate(self, hashed_password: &str) -> Result<(), password_hash::Error> {
+        match self {
+            Self::Bcrypt => {
+                bcrypt::HashParts::from_str(hashed_password)
+                    .map_err(|_| password_hash::Error::PhcStringInvalid)?;
+            }
+            Self::Argon2 | Self::Pbkdf2 | Self::Scrypt => {
+                let hash = PasswordHash::new(hashed_password)?;
+                if !self.has_algorithm(hash.algorithm.as_str()) {
+                    return Err(password_hash::Error::Algorithm);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a PHC string algorithm identifier belongs to this scheme
+    fn has_algorithm(self, algorithm: &str) -> bool {
+        match self {
+            Self::Argon2 => matches!(algorithm, "argon2d" | "argon2i" | "argon2id"),
+            Self::Pbkdf2 => matches!(algorithm, "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512"),
+            Self::Scrypt => algorithm == "scrypt",
+            Self::Bcrypt => false,
+        }
+    }
+
+    /// Verify a password against a hash made with this scheme
+    pub fn verify(self, hashed_password: &str, password: &str) -> Result<(), password_hash::Error> {
+        match self {
+            Self::Bcrypt => {
+                let valid = bcrypt::verify(password, hashed_password)
+                    .map_err(|_| password_hash::Error::PhcStringInvalid)?;
+                if valid {
+                    Ok(())
+                } else {
+                    Err(password_hash::Error::Password)
+                }
+            }
+            Self::Argon2 | Self::Pbkdf2 | Self::Scrypt => {
+                let hash = PasswordHash::new(hashed_password)?;
+                // The hash parameters are taken from the PHC string, so the
+                // hashers do not need to be configured
+                let verifier: &dyn PasswordVerifier = match self {
+                    Self::Argon2 => &Argon2::default(),
+                    Self::Pbkdf2 => &Pbkdf2,
+                    _ => &Scrypt,
+                };
+                verifier.verify_password(password.as_bytes(), &hash)
+            }
+        }
+    }
+
+    /// Whether a hash should be replaced by one made with the given hasher,
+    /// either because it uses another scheme or weaker parameters
+    #[must_use]
+    pub fn needs_rehash(self, hashed_password: &str, hasher: &Argon2<'_>) -> bool {
+        if self != Self::Argon2 {
+            return true;
+        }
+
+        let hash = match PasswordHash::new(hashed_password) {
+            Ok(hash) => hash,
+            Err(_) => return true,
+        };
+
+        let params = match Params::try_from(&hash) {
+            Ok(params) => params,
+            Err(_) => return true,
+        };
+
+        let current = hasher.params();
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || params.m_cost() != current.m_cost()
+            || params.t_cost() != current.t_cost()
+            || params.p_cost() != current.p_cost()
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown password hashing scheme {0:?}")]
+pub struct UnknownPasswordScheme(String);
+
+impl FromStr for PasswordScheme {
+    type Err = UnknownPasswordScheme;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "argon2" => Ok(Self::Argon2),
+            "bcrypt" => Ok(Self::Bcrypt),
+            "pbkdf2" => Ok(Self::Pbkdf2),
+            "scrypt" => Ok(Self::Scrypt),
+            s => Err(UnknownPasswordScheme(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use password_hash::{PasswordHasher, SaltString};
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    #[test]
+    fn verify_schemes() {
+        let salt = SaltString::generate(&mut OsRng);
+
+        let bcrypt = bcrypt::hash("hunter2", 4).unwrap();
+        let pbkdf2 = Pbkdf2
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        let scrypt = Scrypt
+            .hash_password_customized(
+                "hunter2".as_bytes(),
+                None,
+                None,
+                scrypt::Params::new(4, 8, 1).unwrap(),
+                &salt,
+            )
+            .unwrap()
+            .to_string();
+        let argon2 = Argon2::default()
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+
+        for (scheme, hash) in [
+            (PasswordScheme::Bcrypt, bcrypt),
+            (PasswordScheme::Pbkdf2, pbkdf2),
+            (PasswordScheme::Scrypt, scrypt),
+            (PasswordScheme::Argon2, argon2),
+        ] {
+            scheme.validate(&hash).unwrap();
+            scheme.verify(&hash, "hunter2").unwrap();
+            assert!(scheme.verify(&hash, "hunter3").is_err());
+        }
+    }
+
+    #[test]
+    fn validate_rejects_other_schemes() {
+        let salt = SaltString::generate(&mut OsRng);
+        let argon2 = Argon2::default()
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+        let pbkdf2 = Pbkdf2
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+
+        PasswordScheme::Argon2.validate(&argon2).unwrap();
+        assert!(PasswordScheme::Pbkdf2.validate(&argon2).is_err());
+        assert!(PasswordScheme::Scrypt.validate(&argon2).is_err());
+        assert!(PasswordScheme::Bcrypt.validate(&argon2).is_err());
+        assert!(PasswordScheme::Argon2.validate(&pbkdf2).is_err());
+    }
+
+    #[test]
+    fn needs_rehash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let hasher = Argon2::default();
+        let hash = hasher
+            .hash_password("hunter2".as_bytes(), &salt)
+            .unwrap()
+            .to_string();
+
+        assert!(!PasswordScheme::Argon2.needs_rehash(&hash, &hasher));
+
+        let stronger = Argon2::new(
+            Algorithm::Argon2id,
+            argon2::Version::V0x13,
+            Params::new(8192, 3, 1, None).unwrap(),
+        );
+        assert!(PasswordScheme::Argon2.needs_rehash(&hash, &stronger));
+
+        let bcrypt = bcrypt::hash("hunter2", 4).unwrap();
+        assert!(PasswordScheme::Bcrypt.needs_rehash(&bcrypt, &hasher));
+    }
+}
@@ -0,0 +1,172 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! One-time recovery codes, usable instead of a second factor
+
+use anyhow::Context;
+use argon2::Argon2;
+use mas_data_model::User;
+use password_hash::{PasswordHash, PasswordHasher, SaltString};
+use rand::rngs::OsRng;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tokio::task;
+use tracing::{info_span, Instrument};
+
+use crate::PostgresqlBackend;
+
+/// Replace the recovery codes of a user, invalidating the previous ones
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn set_recovery_codes(
+    txn: &mut Transaction<'_, Postgres>,
+    phf: impl PasswordHasher,
+    user: &User<PostgresqlBackend>,
+    codes: &[String],
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            DELETE FROM user_recovery_codes
+            WHERE user_id = $1
+        "#,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("Remove previous recovery codes"))
+    .await
+    .context("could not remove previous recovery codes")?;
+
+    for code in codes {
+        let salt = SaltString::generate(&mut OsRng);
+        let hashed_code = phf.hash_password(code.as_bytes(), &salt)?;
+
+        sqlx::query!(
+            r#"
+                INSERT INTO user_recovery_codes (user_id, hashed_code)
+                VALUES ($1, $2)
+            "#,
+            user.data,
+            hashed_code.to_string(),
+        )
+        .execute(&mut *txn)
+        .instrument(info_span!("Save recovery code"))
+        .await
+        .context("could not insert recovery code")?;
+    }
+
+    Ok(())
+}
+
+/// Count the recovery codes of a user which were not used yet
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn count_unused_recovery_codes(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<i64> {
+    let res = sqlx::query_scalar!(
+        r#"
+            SELECT COUNT(*) as "count!"
+            FROM user_recovery_codes
+            WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user.data,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Count recovery codes"))
+    .await
+    .context("could not count recovery codes")?;
+
+    Ok(res)
+}
+
+/// Whether recovery codes were ever generated for the user, used or not
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn has_recovery_codes(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query_scalar!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM user_recovery_codes WHERE user_id = $1
+            ) as "exists!"
+        "#,
+        user.data,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Check recovery codes"))
+    .await
+    .context("could not check recovery codes")?;
+
+    Ok(res)
+}
+
+/// Check a recovery code against the unused codes of a user, and mark the
+/// matching one as used. Returns whether a code matched.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn consume_recovery_code(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+    code: String,
+) -> anyhow::Result<bool> {
+    let candidates = sqlx::query!(
+        r#"
+            SELECT id, hashed_code
+            FROM user_recovery_codes
+            WHERE user_id = $1 AND used_at IS NULL
+            FOR UPDATE
+        "#,
+        user.data,
+    )
+    .fetch_all(&mut *txn)
+    .instrument(info_span!("Fetch recovery codes"))
+    .await
+    .context("could not fetch recovery codes")?;
+
+    let candidates: Vec<(i64, String)> = candidates
+        .into_iter()
+        .map(|row| (row.id, row.hashed_code))
+        .collect();
+
+    // Verify the code in a blocking thread to avoid blocking the async executor
+    let matching = task::spawn_blocking(move || {
+        let context = Argon2::default();
+        candidates.into_iter().find_map(|(id, hashed_code)| {
+            let hash = PasswordHash::new(&hashed_code).ok()?;
+            hash.verify_password(&[&context], &code).ok()?;
+            Some(id)
+        })
+    })
+    .instrument(info_span!("Verify recovery code"))
+    .await?;
+
+    let id = if let Some(id) = matching {
+        id
+    } else {
+        return Ok(false);
+    };
+
+    sqlx::query!(
+        r#"
+            UPDATE user_recovery_codes
+            SET used_at = NOW()
+            WHERE id = $1
+        "#,
+        id,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("Mark recovery code as used"))
+    .await
+    .context("could not mark recovery code as used")?;
+
+    Ok(true)
+}
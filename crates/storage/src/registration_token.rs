@@ -0,0 +1,138 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Registration tokens, required to create an account when registration is
+//! restricted
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgExecutor;
+use tracing::{info_span, Instrument};
+
+/// A registration token, as issued by an admin
+#[derive(Debug, Clone)]
+pub struct RegistrationToken {
+    pub id: i64,
+    pub token: String,
+    pub usage_limit: Option<i32>,
+    pub uses: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl RegistrationToken {
+    /// Whether the token can still be used to register
+    #[must_use]
+    pub fn is_valid(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none()
+            && self.expires_at.map_or(true, |expires_at| expires_at > now)
+            && self.usage_limit.map_or(true, |limit| self.uses < limit)
+    }
+}
+
+#[tracing::instrument(skip(executor, token))]
+pub async fn add_registration_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+    usage_limit: Option<i32>,
+    expires_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<RegistrationToken> {
+    let res = sqlx::query_as!(
+        RegistrationToken,
+        r#"
+            INSERT INTO registration_tokens (token, usage_limit, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, token, usage_limit, uses, expires_at, revoked_at, created_at
+        "#,
+        token,
+        usage_limit,
+        expires_at,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Add registration token"))
+    .await
+    .context("could not insert registration token")?;
+
+    Ok(res)
+}
+
+#[tracing::instrument(skip(executor))]
+pub async fn get_registration_tokens(
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<Vec<RegistrationToken>> {
+    let res = sqlx::query_as!(
+        RegistrationToken,
+        r#"
+            SELECT id, token, usage_limit, uses, expires_at, revoked_at, created_at
+            FROM registration_tokens
+            ORDER BY created_at ASC, id ASC
+        "#,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("Fetch registration tokens"))
+    .await
+    .context("could not fetch registration tokens")?;
+
+    Ok(res)
+}
+
+/// Revoke a token, so it can't be used anymore. Returns whether there was a
+/// token to revoke.
+#[tracing::instrument(skip(executor, token))]
+pub async fn revoke_registration_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE registration_tokens
+            SET revoked_at = NOW()
+            WHERE token = $1 AND revoked_at IS NULL
+        "#,
+        token,
+    )
+    .execute(executor)
+    .instrument(info_span!("Revoke registration token"))
+    .await
+    .context("could not revoke registration token")?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// Count a use of a token, if it is still valid. Returns `false` if the token
+/// does not exist, was revoked, expired or reached its usage limit.
+#[tracing::instrument(skip(executor, token))]
+pub async fn consume_registration_token(
+    executor: impl PgExecutor<'_>,
+    token: &str,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE registration_tokens
+            SET uses = uses + 1
+            WHERE token = $1
+              AND revoked_at IS NULL
+              AND (expires_at IS NULL OR expires_at > NOW())
+              AND (usage_limit IS NULL OR uses < usage_limit)
+        "#,
+        token,
+    )
+    .execute(executor)
+    .instrument(info_span!("Consume registration token"))
+    .await
+    .context("could not consume registration token")?;
+
+    Ok(res.rows_affected() > 0)
+}
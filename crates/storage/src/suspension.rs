@@ -0,0 +1,143 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Suspensions of users by moderators, temporary or not
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use mas_data_model::User;
+use sqlx::PgExecutor;
+use tracing::{info_span, Instrument};
+
+use crate::PostgresqlBackend;
+
+/// A suspension of a user, as decided by a moderator
+#[derive(Debug, Clone)]
+pub struct UserSuspension {
+    pub id: i64,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub lifted_at: Option<DateTime<Utc>>,
+}
+
+impl UserSuspension {
+    /// Whether the suspension still prevents the user from logging in
+    #[must_use]
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.lifted_at.is_none() && self.expires_at.map_or(true, |expires_at| expires_at > now)
+    }
+}
+
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn suspend_user(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    reason: &str,
+    expires_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<UserSuspension> {
+    let res = sqlx::query_as!(
+        UserSuspension,
+        r#"
+            INSERT INTO user_suspensions (user_id, reason, expires_at)
+            VALUES ($1, $2, $3)
+            RETURNING id, reason, created_at, expires_at, lifted_at
+        "#,
+        user.data,
+        reason,
+        expires_at,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Suspend user"))
+    .await
+    .context("could not suspend user")?;
+
+    Ok(res)
+}
+
+/// Lift the active suspensions of a user. Returns `false` if there were none.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn lift_user_suspensions(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE user_suspensions
+            SET lifted_at = NOW()
+            WHERE user_id = $1
+              AND lifted_at IS NULL
+              AND (expires_at IS NULL OR expires_at > NOW())
+        "#,
+        user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("Lift user suspensions"))
+    .await
+    .context("could not lift user suspensions")?;
+
+    Ok(res.rows_affected() > 0)
+}
+
+/// Get the suspension currently preventing the user from logging in, if any.
+/// If there are several, the one lasting the longest is returned.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn get_active_suspension(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Option<UserSuspension>> {
+    let res = sqlx::query_as!(
+        UserSuspension,
+        r#"
+            SELECT id, reason, created_at, expires_at, lifted_at
+            FROM user_suspensions
+            WHERE user_id = $1
+              AND lifted_at IS NULL
+              AND (expires_at IS NULL OR expires_at > NOW())
+            ORDER BY expires_at DESC NULLS FIRST
+            LIMIT 1
+        "#,
+        user.data,
+    )
+    .fetch_optional(executor)
+    .instrument(info_span!("Get active suspension"))
+    .await
+    .context("could not fetch active suspension")?;
+
+    Ok(res)
+}
+
+/// All the suspensions of a user, including the expired and lifted ones
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn get_user_suspensions(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<UserSuspension>> {
+    let res = sqlx::query_as!(
+        UserSuspension,
+        r#"
+            SELECT id, reason, created_at, expires_at, lifted_at
+            FROM user_suspensions
+            WHERE user_id = $1
+            ORDER BY created_at ASC, id ASC
+        "#,
+        user.data,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("Get user suspensions"))
+    .await
+    .context("could not fetch user suspensions")?;
+
+    Ok(res)
+}
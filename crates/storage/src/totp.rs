@@ -0,0 +1,131 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TOTP secrets enrolled by users as a second factor
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use mas_data_model::User;
+use sqlx::PgExecutor;
+use tracing::{info_span, Instrument};
+
+use crate::PostgresqlBackend;
+
+/// A TOTP secret, still encrypted with the server encryption key
+#[derive(Debug, Clone)]
+pub struct TotpSecret {
+    pub id: i64,
+    pub encrypted_secret: String,
+    pub last_used_step: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Get the TOTP secret enrolled by a user, if any
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn lookup_totp_secret(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Option<TotpSecret>> {
+    let res = sqlx::query_as!(
+        TotpSecret,
+        r#"
+            SELECT id, encrypted_secret, last_used_step, created_at
+            FROM user_totp_secrets
+            WHERE user_id = $1
+        "#,
+        user.data,
+    )
+    .fetch_optional(executor)
+    .instrument(info_span!("Lookup TOTP secret"))
+    .await
+    .context("could not lookup TOTP secret")?;
+
+    Ok(res)
+}
+
+/// Enrol a TOTP secret for a user, replacing the existing one
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn set_totp_secret(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    encrypted_secret: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO user_totp_secrets (user_id, encrypted_secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET encrypted_secret = EXCLUDED.encrypted_secret,
+                last_used_step = NULL,
+                created_at = NOW()
+        "#,
+        user.data,
+        encrypted_secret,
+    )
+    .execute(executor)
+    .instrument(info_span!("Save TOTP secret"))
+    .await
+    .context("could not save TOTP secret")?;
+
+    Ok(())
+}
+
+/// Remove the TOTP secret of a user
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn remove_totp_secret(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            DELETE FROM user_totp_secrets
+            WHERE user_id = $1
+        "#,
+        user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("Remove TOTP secret"))
+    .await
+    .context("could not remove TOTP secret")?;
+
+    Ok(())
+}
+
+/// Mark a time step as used for the TOTP secret of a user.
+///
+/// Returns `false` if a code for this step or a later one was already
+/// accepted, in which case the code must be rejected to prevent replays.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn consume_totp_step(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    step: i64,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query!(
+        r#"
+            UPDATE user_totp_secrets
+            SET last_used_step = $2
+            WHERE user_id = $1
+              AND (last_used_step IS NULL OR last_used_step < $2)
+        "#,
+        user.data,
+        step,
+    )
+    .execute(executor)
+    .instrument(info_span!("Consume TOTP step"))
+    .await
+    .context("could not update TOTP secret")?;
+
+    Ok(res.rows_affected() == 1)
+}
@@ -0,0 +1,77 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Links between local users and accounts on upstream identity providers
+
+use anyhow::Context;
+use mas_data_model::User;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tracing::{info_span, Instrument};
+
+use crate::{
+    user::{lookup_user, UserLookupError},
+    PostgresqlBackend,
+};
+
+/// Find the local user linked to the given subject on an upstream provider,
+/// who might be deactivated
+#[tracing::instrument(skip(txn))]
+pub async fn lookup_user_by_upstream_subject(
+    txn: &mut Transaction<'_, Postgres>,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<User<PostgresqlBackend>>, UserLookupError> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+            SELECT user_id
+            FROM upstream_oauth_links
+            WHERE provider = $1 AND subject = $2
+        "#,
+        provider,
+        subject,
+    )
+    .fetch_optional(&mut *txn)
+    .instrument(info_span!("Lookup upstream link"))
+    .await?;
+
+    match user_id {
+        Some(user_id) => Ok(Some(lookup_user(&mut *txn, user_id).await?)),
+        None => Ok(None),
+    }
+}
+
+/// Link a local user to a subject on an upstream provider
+#[tracing::instrument(skip(executor, user), fields(user.id = user.data))]
+pub async fn add_upstream_link(
+    executor: impl PgExecutor<'_>,
+    provider: &str,
+    subject: &str,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO upstream_oauth_links (provider, subject, user_id)
+            VALUES ($1, $2, $3)
+        "#,
+        provider,
+        subject,
+        user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("Add upstream link"))
+    .await
+    .context("could not insert upstream link")?;
+
+    Ok(())
+}
@@ -18,23 +18,24 @@ use anyhow::{bail, Context};
 use argon2::Argon2;
 use chrono::{DateTime, Utc};
 use mas_data_model::{
-    errors::HtmlError, Authentication, BrowserSession, User, UserEmail, UserEmailVerification,
-    UserEmailVerificationState,
+    errors::HtmlError, Authentication, AuthenticationMethod, BrowserSession, User, UserEmail,
+    UserEmailVerification, UserEmailVerificationState,
 };
 use password_hash::{PasswordHash, PasswordHasher, SaltString};
 use rand::rngs::OsRng;
-use sqlx::{postgres::types::PgInterval, Acquire, PgExecutor, Postgres, Transaction};
+use sqlx::{postgres::types::PgInterval, Acquire, PgConnection, PgExecutor, Postgres, Transaction};
 use thiserror::Error;
 use tokio::task;
 use tracing::{info_span, Instrument};
 
 use super::{DatabaseInconsistencyError, PostgresqlBackend};
-use crate::IdAndCreationTime;
+use crate::{passwords::PasswordScheme, IdAndCreationTime};
 
 #[derive(Debug, Clone)]
 struct UserLookup {
     user_id: i64,
     user_username: String,
+    user_is_admin: bool,
     user_email_id: Option<i64>,
     user_email: Option<String>,
     user_email_created_at: Option<DateTime<Utc>>,
@@ -71,14 +72,35 @@ impl HtmlError for LoginError {
     }
 }
 
-#[tracing::instrument(skip(conn, password))]
+#[tracing::instrument(skip(conn, hasher, password))]
 pub async fn login(
     conn: impl Acquire<'_, Database = Postgres>,
+    hasher: &Argon2<'static>,
     username: &str,
     password: String,
 ) -> Result<BrowserSession<PostgresqlBackend>, LoginError> {
     let mut txn = conn.begin().await.context("could not start transaction")?;
-    let user = lookup_user_by_username(&mut txn, username)
+    let user = verify_user_password(&mut txn, hasher, username, password).await?;
+
+    let mut session = start_session(&mut txn, user).await?;
+    record_session_authentication(&mut txn, &mut session, &[AuthenticationMethod::Password])
+        .await
+        .context("could not save session auth")?;
+
+    txn.commit().await.context("could not commit transaction")?;
+    Ok(session)
+}
+
+/// Check the password of a user without starting a session, e.g. when other
+/// factors have to be checked before the user is logged in
+#[tracing::instrument(skip(txn, hasher, password))]
+pub async fn verify_user_password(
+    txn: &mut Transaction<'_, Postgres>,
+    hasher: &Argon2<'static>,
+    username: &str,
+    password: String,
+) -> Result<User<PostgresqlBackend>, LoginError> {
+    let user = lookup_user_by_username(txn.borrow_mut(), username)
         .await
         .map_err(|source| {
             if source.not_found() {
@@ -91,11 +113,16 @@ pub async fn login(
             }
         })?;
 
-    let mut session = start_session(&mut txn, user).await?;
-    authenticate_session(&mut txn, &mut session, password)
+    check_password(txn, hasher, &user, password)
         .await
         .map_err(|source| {
-            if matches!(source, AuthenticationError::Password { .. }) {
+            // Users without a local password, e.g. provisioned from a
+            // directory, can't log in with one
+            if matches!(
+                source,
+                AuthenticationError::Password { .. }
+                    | AuthenticationError::Fetch(sqlx::Error::RowNotFound)
+            ) {
                 LoginError::Authentication {
                     username: username.to_string(),
                     source,
@@ -105,8 +132,7 @@ pub async fn login(
             }
         })?;
 
-    txn.commit().await.context("could not commit transaction")?;
-    Ok(session)
+    Ok(user)
 }
 
 #[derive(Debug, Error)]
@@ -123,13 +149,24 @@ impl ActiveSessionLookupError {
     }
 }
 
+/// Parse the `amr` column of an authentication
+pub(crate) fn authentication_methods(
+    amr: &[String],
+) -> Result<Vec<AuthenticationMethod>, DatabaseInconsistencyError> {
+    amr.iter()
+        .map(|method| method.parse().map_err(|_| DatabaseInconsistencyError))
+        .collect()
+}
+
 struct SessionLookup {
     id: i64,
     user_id: i64,
     username: String,
+    is_admin: bool,
     created_at: DateTime<Utc>,
     last_authentication_id: Option<i64>,
     last_authd_at: Option<DateTime<Utc>>,
+    last_authentication_amr: Option<Vec<String>>,
     user_email_id: Option<i64>,
     user_email: Option<String>,
     user_email_created_at: Option<DateTime<Utc>>,
@@ -161,14 +198,20 @@ impl TryInto<BrowserSession<PostgresqlBackend>> for SessionLookup {
             username: self.username,
             sub: format!("fake-sub-{}", self.user_id),
             primary_email,
+            is_admin: self.is_admin,
         };
 
-        let last_authentication = match (self.last_authentication_id, self.last_authd_at) {
-            (Some(id), Some(created_at)) => Some(Authentication {
+        let last_authentication = match (
+            self.last_authentication_id,
+            self.last_authd_at,
+            self.last_authentication_amr,
+        ) {
+            (Some(id), Some(created_at), Some(amr)) => Some(Authentication {
                 data: id,
                 created_at,
+                methods: authentication_methods(&amr)?,
             }),
-            (None, None) => None,
+            (None, None, None) => None,
             _ => return Err(DatabaseInconsistencyError),
         };
 
@@ -193,9 +236,11 @@ pub async fn lookup_active_session(
                 s.id,
                 u.id AS user_id,
                 u.username,
+                u.is_admin,
                 s.created_at,
                 a.id               AS "last_authentication_id?",
                 a.created_at       AS "last_authd_at?",
+                a.amr              AS "last_authentication_amr?",
                 ue.id              AS "user_email_id?",
                 ue.email           AS "user_email?",
                 ue.created_at      AS "user_email_created_at?",
@@ -207,7 +252,13 @@ pub async fn lookup_active_session(
                 ON a.session_id = s.id
             LEFT JOIN user_emails ue
               ON ue.id = u.primary_email_id
-            WHERE s.id = $1 AND s.active
+            WHERE s.id = $1 AND s.active AND u.deactivated_at IS NULL
+              AND NOT EXISTS (
+                SELECT 1 FROM user_suspensions sus
+                WHERE sus.user_id = u.id
+                  AND sus.lifted_at IS NULL
+                  AND (sus.expires_at IS NULL OR sus.expires_at > NOW())
+              )
             ORDER BY a.created_at DESC
             LIMIT 1
         "#,
@@ -279,6 +330,9 @@ pub enum AuthenticationError {
     #[error("could not save session auth")]
     Save(sqlx::Error),
 
+    #[error("could not upgrade the password hash")]
+    Upgrade(#[source] anyhow::Error),
+
     #[error("runtime error")]
     Internal(#[from] tokio::task::JoinError),
 }
@@ -286,55 +340,102 @@ pub enum AuthenticationError {
 #[tracing::instrument(skip_all, fields(session.id = session.data, user.id = session.user.data))]
 pub async fn authenticate_session(
     txn: &mut Transaction<'_, Postgres>,
+    hasher: &Argon2<'static>,
     session: &mut BrowserSession<PostgresqlBackend>,
     password: String,
 ) -> Result<(), AuthenticationError> {
-    // First, fetch the hashed password from the user associated with that session
-    let hashed_password: String = sqlx::query_scalar!(
+    check_password(txn, hasher, &session.user, password).await?;
+
+    // That went well, let's insert the auth info
+    record_session_authentication(txn.borrow_mut(), session, &[AuthenticationMethod::Password])
+        .await
+        .map_err(AuthenticationError::Save)?;
+
+    Ok(())
+}
+
+/// Check the local password of a user, without recording anything.
+///
+/// Hashes made with another scheme or weaker parameters than the ones of
+/// `hasher` are replaced after a successful check.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn check_password(
+    conn: &mut PgConnection,
+    hasher: &Argon2<'static>,
+    user: &User<PostgresqlBackend>,
+    password: String,
+) -> Result<(), AuthenticationError> {
+    // First, fetch the hashed password of the user
+    let res = sqlx::query!(
         r#"
-            SELECT up.hashed_password
+            SELECT up.hashed_password, up.scheme
             FROM user_passwords up
             WHERE up.user_id = $1
-            ORDER BY up.created_at DESC
+            ORDER BY up.created_at DESC, up.id DESC
             LIMIT 1
         "#,
-        session.user.data,
+        user.data,
     )
-    .fetch_one(txn.borrow_mut())
+    .fetch_one(&mut *conn)
     .instrument(tracing::info_span!("Lookup hashed password"))
     .await
     .map_err(AuthenticationError::Fetch)?;
 
-    // TODO: pass verifiers list as parameter
+    let scheme: PasswordScheme = res
+        .scheme
+        .parse()
+        .map_err(|_| AuthenticationError::Password(password_hash::Error::Algorithm))?;
+    let hashed_password = res.hashed_password;
+    let needs_rehash = scheme.needs_rehash(&hashed_password, hasher);
+
     // Verify the password in a blocking thread to avoid blocking the async executor
-    task::spawn_blocking(move || {
-        let context = Argon2::default();
-        let hasher = PasswordHash::new(&hashed_password).map_err(AuthenticationError::Password)?;
-        hasher
-            .verify_password(&[&context], &password)
+    let password = task::spawn_blocking(move || {
+        scheme
+            .verify(&hashed_password, &password)
+            .map(|()| password)
             .map_err(AuthenticationError::Password)
     })
     .instrument(tracing::info_span!("Verify hashed password"))
     .await??;
 
-    // That went well, let's insert the auth info
+    if needs_rehash {
+        set_password(&mut *conn, hasher.clone(), user, &password)
+            .await
+            .map_err(AuthenticationError::Upgrade)?;
+    }
+
+    Ok(())
+}
+
+/// Record that the user of a session successfully authenticated with the
+/// given methods, without checking any credential. Callers are responsible for
+/// having verified the user identity beforehand, e.g. through an upstream
+/// identity provider.
+#[tracing::instrument(skip_all, fields(session.id = session.data, user.id = session.user.data))]
+pub async fn record_session_authentication(
+    executor: impl PgExecutor<'_>,
+    session: &mut BrowserSession<PostgresqlBackend>,
+    methods: &[AuthenticationMethod],
+) -> Result<(), sqlx::Error> {
+    let amr: Vec<String> = methods.iter().map(ToString::to_string).collect();
     let res = sqlx::query_as!(
         IdAndCreationTime,
         r#"
-            INSERT INTO user_session_authentications (session_id)
-            VALUES ($1)
+            INSERT INTO user_session_authentications (session_id, amr)
+            VALUES ($1, $2)
             RETURNING id, created_at
         "#,
         session.data,
+        &amr,
     )
-    .fetch_one(txn.borrow_mut())
+    .fetch_one(executor)
     .instrument(tracing::info_span!("Save authentication"))
-    .await
-    .map_err(AuthenticationError::Save)?;
+    .await?;
 
     session.last_authentication = Some(Authentication {
         data: res.id,
         created_at: res.created_at,
+        methods: methods.to_vec(),
     });
 
     Ok(())
@@ -346,6 +447,20 @@ pub async fn register_user(
     phf: impl PasswordHasher,
     username: &str,
     password: &str,
+) -> anyhow::Result<User<PostgresqlBackend>> {
+    let user = register_passwordless_user(txn.borrow_mut(), username).await?;
+
+    set_password(txn.borrow_mut(), phf, &user, password).await?;
+
+    Ok(user)
+}
+
+/// Create a user which has no local password, e.g. one provisioned from an
+/// upstream identity provider
+#[tracing::instrument(skip(executor))]
+pub async fn register_passwordless_user(
+    executor: impl PgExecutor<'_>,
+    username: &str,
 ) -> anyhow::Result<User<PostgresqlBackend>> {
     let id: i64 = sqlx::query_scalar!(
         r#"
@@ -355,21 +470,48 @@ pub async fn register_user(
         "#,
         username,
     )
-    .fetch_one(txn.borrow_mut())
+    .fetch_one(executor)
     .instrument(info_span!("Register user"))
     .await
     .context("could not insert user")?;
 
-    let user = User {
+    Ok(User {
         data: id,
         username: username.to_string(),
         sub: format!("fake-sub-{}", id),
         primary_email: None,
-    };
+        is_admin: false,
+    })
+}
 
-    set_password(txn.borrow_mut(), phf, &user, password).await?;
+/// Save a password hash made by another system, which will be replaced by an
+/// Argon2 hash the next time the user logs in
+#[tracing::instrument(skip(executor, user, hashed_password), fields(user.id = user.data))]
+pub async fn import_password_hash(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    scheme: PasswordScheme,
+    hashed_password: &str,
+) -> anyhow::Result<()> {
+    scheme
+        .validate(hashed_password)
+        .context("invalid password hash")?;
 
-    Ok(user)
+    sqlx::query!(
+        r#"
+            INSERT INTO user_passwords (user_id, hashed_password, scheme)
+            VALUES ($1, $2, $3)
+        "#,
+        user.data,
+        hashed_password,
+        scheme.as_str(),
+    )
+    .execute(executor)
+    .instrument(info_span!("Import user password hash"))
+    .await
+    .context("could not insert user password")?;
+
+    Ok(())
 }
 
 #[tracing::instrument(skip_all, fields(user.id = user.data))]
@@ -398,6 +540,29 @@ pub async fn set_password(
     Ok(())
 }
 
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn set_display_name(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    display_name: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET display_name = $2
+            WHERE id = $1
+        "#,
+        user.data,
+        display_name,
+    )
+    .execute(executor)
+    .instrument(info_span!("Set display name"))
+    .await
+    .context("could not set display name")?;
+
+    Ok(())
+}
+
 #[tracing::instrument(skip_all, fields(session.id = session.data))]
 pub async fn end_session(
     executor: impl PgExecutor<'_>,
@@ -419,6 +584,145 @@ pub async fn end_session(
     }
 }
 
+/// End all the browser sessions of a user, and the OAuth 2.0 sessions started
+/// from them
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn end_user_sessions(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE oauth2_sessions
+            SET ended_at = NOW()
+            WHERE ended_at IS NULL
+              AND user_session_id IN (
+                SELECT id FROM user_sessions WHERE user_id = $1
+              )
+        "#,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("End OAuth 2.0 sessions"))
+    .await
+    .context("could not end OAuth 2.0 sessions")?;
+
+    sqlx::query!(
+        r#"
+            UPDATE user_sessions
+            SET active = FALSE
+            WHERE user_id = $1 AND active
+        "#,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("End user sessions"))
+    .await
+    .context("could not end user sessions")?;
+
+    Ok(())
+}
+
+/// Deactivate a user, ending all their sessions. They can't log in anymore,
+/// but their username stays reserved.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn deactivate_user(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    end_user_sessions(txn, user).await?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET deactivated_at = NOW()
+            WHERE id = $1 AND deactivated_at IS NULL
+        "#,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("Deactivate user"))
+    .await
+    .context("could not deactivate user")?;
+
+    Ok(())
+}
+
+/// Deactivate a user and delete their personal data: email addresses,
+/// display name, credentials, upstream links and consents. Only the username
+/// is kept, so that it is never reused.
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn erase_user(
+    txn: &mut Transaction<'_, Postgres>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<()> {
+    deactivate_user(txn, user).await?;
+
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET display_name = NULL,
+                primary_email_id = NULL,
+                erased_at = NOW()
+            WHERE id = $1
+        "#,
+        user.data,
+    )
+    .execute(&mut *txn)
+    .instrument(info_span!("Erase user"))
+    .await
+    .context("could not erase user")?;
+
+    sqlx::query!("DELETE FROM user_emails WHERE user_id = $1", user.data)
+        .execute(&mut *txn)
+        .await
+        .context("could not delete user emails")?;
+
+    sqlx::query!("DELETE FROM user_passwords WHERE user_id = $1", user.data)
+        .execute(&mut *txn)
+        .await
+        .context("could not delete user passwords")?;
+
+    sqlx::query!(
+        "DELETE FROM user_totp_secrets WHERE user_id = $1",
+        user.data
+    )
+    .execute(&mut *txn)
+    .await
+    .context("could not delete TOTP secret")?;
+
+    sqlx::query!(
+        "DELETE FROM user_webauthn_credentials WHERE user_id = $1",
+        user.data
+    )
+    .execute(&mut *txn)
+    .await
+    .context("could not delete WebAuthn credentials")?;
+
+    sqlx::query!(
+        "DELETE FROM user_recovery_codes WHERE user_id = $1",
+        user.data
+    )
+    .execute(&mut *txn)
+    .await
+    .context("could not delete recovery codes")?;
+
+    sqlx::query!(
+        "DELETE FROM upstream_oauth_links WHERE user_id = $1",
+        user.data
+    )
+    .execute(&mut *txn)
+    .await
+    .context("could not delete upstream links")?;
+
+    sqlx::query!("DELETE FROM oauth2_consents WHERE user_id = $1", user.data)
+        .execute(&mut *txn)
+        .await
+        .context("could not delete consents")?;
+
+    Ok(())
+}
+
 #[derive(Debug, Error)]
 #[error("failed to lookup user")]
 pub enum UserLookupError {
@@ -433,6 +737,36 @@ impl UserLookupError {
     }
 }
 
+impl TryFrom<UserLookup> for User<PostgresqlBackend> {
+    type Error = DatabaseInconsistencyError;
+
+    fn try_from(res: UserLookup) -> Result<Self, Self::Error> {
+        let primary_email = match (
+            res.user_email_id,
+            res.user_email,
+            res.user_email_created_at,
+            res.user_email_confirmed_at,
+        ) {
+            (Some(id), Some(email), Some(created_at), confirmed_at) => Some(UserEmail {
+                data: id,
+                email,
+                created_at,
+                confirmed_at,
+            }),
+            (None, None, None, None) => None,
+            _ => return Err(DatabaseInconsistencyError),
+        };
+
+        Ok(User {
+            data: res.user_id,
+            username: res.user_username,
+            sub: format!("fake-sub-{}", res.user_id),
+            primary_email,
+            is_admin: res.user_is_admin,
+        })
+    }
+}
+
 #[tracing::instrument(skip(executor))]
 pub async fn lookup_user_by_username(
     executor: impl PgExecutor<'_>,
@@ -444,6 +778,75 @@ pub async fn lookup_user_by_username(
             SELECT 
                 u.id            AS user_id, 
                 u.username      AS user_username,
+                u.is_admin      AS user_is_admin,
+                ue.id           AS "user_email_id?",
+                ue.email        AS "user_email?",
+                ue.created_at   AS "user_email_created_at?",
+                ue.confirmed_at AS "user_email_confirmed_at?"
+            FROM users u
+
+            LEFT JOIN user_emails ue
+              ON ue.id = u.primary_email_id
+
+            WHERE u.username = $1 AND u.deactivated_at IS NULL
+        "#,
+        username,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Fetch user"))
+    .await?;
+
+    Ok(res.try_into()?)
+}
+
+/// Lookup a user by its ID. Unlike [`lookup_user_by_username`], this also
+/// finds deactivated users.
+#[tracing::instrument(skip(executor))]
+pub async fn lookup_user(
+    executor: impl PgExecutor<'_>,
+    id: i64,
+) -> Result<User<PostgresqlBackend>, UserLookupError> {
+    let res = sqlx::query_as!(
+        UserLookup,
+        r#"
+            SELECT
+                u.id            AS user_id,
+                u.username      AS user_username,
+                u.is_admin      AS user_is_admin,
+                ue.id           AS "user_email_id?",
+                ue.email        AS "user_email?",
+                ue.created_at   AS "user_email_created_at?",
+                ue.confirmed_at AS "user_email_confirmed_at?"
+            FROM users u
+
+            LEFT JOIN user_emails ue
+              ON ue.id = u.primary_email_id
+
+            WHERE u.id = $1
+        "#,
+        id,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Fetch user"))
+    .await?;
+
+    Ok(res.try_into()?)
+}
+
+/// Lookup a user by its username, including deactivated users, for the
+/// administration tools
+#[tracing::instrument(skip(executor))]
+pub async fn lookup_any_user_by_username(
+    executor: impl PgExecutor<'_>,
+    username: &str,
+) -> Result<User<PostgresqlBackend>, UserLookupError> {
+    let res = sqlx::query_as!(
+        UserLookup,
+        r#"
+            SELECT
+                u.id            AS user_id,
+                u.username      AS user_username,
+                u.is_admin      AS user_is_admin,
                 ue.id           AS "user_email_id?",
                 ue.email        AS "user_email?",
                 ue.created_at   AS "user_email_created_at?",
@@ -461,28 +864,75 @@ pub async fn lookup_user_by_username(
     .instrument(info_span!("Fetch user"))
     .await?;
 
-    let primary_email = match (
-        res.user_email_id,
-        res.user_email,
-        res.user_email_created_at,
-        res.user_email_confirmed_at,
-    ) {
-        (Some(id), Some(email), Some(created_at), confirmed_at) => Some(UserEmail {
-            data: id,
-            email,
-            created_at,
-            confirmed_at,
-        }),
-        (None, None, None, None) => None,
-        _ => return Err(DatabaseInconsistencyError.into()),
-    };
+    Ok(res.try_into()?)
+}
 
-    Ok(User {
-        data: res.user_id,
-        username: res.user_username,
-        sub: format!("fake-sub-{}", res.user_id),
-        primary_email,
-    })
+/// Grant or revoke the administration privileges of a user
+#[tracing::instrument(skip_all, fields(user.id = user.data, is_admin))]
+pub async fn set_user_admin(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    is_admin: bool,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE users
+            SET is_admin = $2
+            WHERE id = $1
+        "#,
+        user.data,
+        is_admin,
+    )
+    .execute(executor)
+    .instrument(info_span!("Set user admin"))
+    .await
+    .context("could not update user")?;
+
+    Ok(())
+}
+
+/// Check if a username belongs to a deactivated user
+#[tracing::instrument(skip(executor))]
+pub async fn username_deactivated(
+    executor: impl PgExecutor<'_>,
+    username: &str,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query_scalar!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM users WHERE username = $1 AND deactivated_at IS NOT NULL
+            ) AS "deactivated!"
+        "#,
+        username,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Check user deactivation"))
+    .await
+    .context("could not check user deactivation")?;
+
+    Ok(res)
+}
+
+/// Check if a username is already used, regardless of its case
+#[tracing::instrument(skip(executor))]
+pub async fn username_exists(
+    executor: impl PgExecutor<'_>,
+    username: &str,
+) -> anyhow::Result<bool> {
+    let res = sqlx::query_scalar!(
+        r#"
+            SELECT EXISTS(
+                SELECT 1 FROM users WHERE LOWER(username) = LOWER($1)
+            ) AS "exists!"
+        "#,
+        username,
+    )
+    .fetch_one(executor)
+    .instrument(info_span!("Check username existence"))
+    .await
+    .context("could not check username existence")?;
+
+    Ok(res)
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,190 @@
+// Copyright 2022 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `WebAuthn` credentials (passkeys) registered by users
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use mas_data_model::User;
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tracing::{info_span, Instrument};
+
+use crate::{user::lookup_user, PostgresqlBackend};
+
+/// A `WebAuthn` credential registered by a user
+#[derive(Debug, Clone)]
+pub struct WebauthnCredential {
+    pub id: i64,
+    /// The credential ID, base64url-encoded
+    pub credential_id: String,
+    /// The COSE-encoded public key of the credential
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Register a new credential for a user
+#[tracing::instrument(skip(executor, user, public_key), fields(user.id = user.data))]
+pub async fn add_webauthn_credential(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    credential_id: &str,
+    public_key: &[u8],
+    sign_count: i64,
+    name: &str,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            INSERT INTO user_webauthn_credentials
+                (user_id, credential_id, public_key, sign_count, name)
+            VALUES ($1, $2, $3, $4, $5)
+        "#,
+        user.data,
+        credential_id,
+        public_key,
+        sign_count,
+        name,
+    )
+    .execute(executor)
+    .instrument(info_span!("Add WebAuthn credential"))
+    .await
+    .context("could not insert WebAuthn credential")?;
+
+    Ok(())
+}
+
+/// Get all the credentials registered by a user
+#[tracing::instrument(skip_all, fields(user.id = user.data))]
+pub async fn get_user_webauthn_credentials(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+) -> anyhow::Result<Vec<WebauthnCredential>> {
+    let res = sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+            SELECT id, credential_id, public_key, sign_count, name, created_at, last_used_at
+            FROM user_webauthn_credentials
+            WHERE user_id = $1
+            ORDER BY created_at ASC
+        "#,
+        user.data,
+    )
+    .fetch_all(executor)
+    .instrument(info_span!("Fetch WebAuthn credentials"))
+    .await
+    .context("could not fetch WebAuthn credentials")?;
+
+    Ok(res)
+}
+
+/// Find a credential by its credential ID, along with the user owning it, who
+/// might be deactivated
+#[tracing::instrument(skip(txn))]
+pub async fn lookup_webauthn_credential(
+    txn: &mut Transaction<'_, Postgres>,
+    credential_id: &str,
+) -> anyhow::Result<Option<(WebauthnCredential, User<PostgresqlBackend>)>> {
+    let res = sqlx::query!(
+        r#"
+            SELECT
+                c.id,
+                c.credential_id,
+                c.public_key,
+                c.sign_count,
+                c.name,
+                c.created_at,
+                c.last_used_at,
+                c.user_id
+            FROM user_webauthn_credentials c
+            WHERE c.credential_id = $1
+        "#,
+        credential_id,
+    )
+    .fetch_optional(&mut *txn)
+    .instrument(info_span!("Lookup WebAuthn credential"))
+    .await
+    .context("could not lookup WebAuthn credential")?;
+
+    let res = if let Some(res) = res {
+        res
+    } else {
+        return Ok(None);
+    };
+
+    let user = lookup_user(&mut *txn, res.user_id).await?;
+    let credential = WebauthnCredential {
+        id: res.id,
+        credential_id: res.credential_id,
+        public_key: res.public_key,
+        sign_count: res.sign_count,
+        name: res.name,
+        created_at: res.created_at,
+        last_used_at: res.last_used_at,
+    };
+
+    Ok(Some((credential, user)))
+}
+
+/// Record a successful use of a credential
+#[tracing::instrument(skip(executor, credential), fields(credential.id = credential.id))]
+pub async fn update_webauthn_sign_count(
+    executor: impl PgExecutor<'_>,
+    credential: &WebauthnCredential,
+    sign_count: i64,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+            UPDATE user_webauthn_credentials
+            SET sign_count = $2, last_used_at = NOW()
+            WHERE id = $1
+        "#,
+        credential.id,
+        sign_count,
+    )
+    .execute(executor)
+    .instrument(info_span!("Update WebAuthn credential"))
+    .await
+    .context("could not update WebAuthn credential")?;
+
+    Ok(())
+}
+
+/// Remove a credential of a user
+#[tracing::instrument(skip(executor, user), fields(user.id = user.data))]
+pub async fn remove_webauthn_credential(
+    executor: impl PgExecutor<'_>,
+    user: &User<PostgresqlBackend>,
+    id: i64,
+) -> anyhow::Result<()> {
+    let res = sqlx::query!(
+        r#"
+            DELETE FROM user_webauthn_credentials
+            WHERE id = $1 AND user_id = $2
+        "#,
+        id,
+        user.data,
+    )
+    .execute(executor)
+    .instrument(info_span!("Remove WebAuthn credential"))
+    .await
+    .context("could not remove WebAuthn credential")?;
+
+    if res.rows_affected() != 1 {
+        anyhow::bail!("unknown WebAuthn credential");
+    }
+
+    Ok(())
+}
@@ -16,6 +16,7 @@
 
 #![allow(clippy::trait_duplication_in_bounds)]
 
+use chrono::{DateTime, Duration, Utc};
 use mas_data_model::{
     errors::ErroredForm, AuthorizationGrant, BrowserSession, StorageBackend, User, UserEmail,
 };
@@ -236,8 +237,35 @@ pub enum PostAuthContext {
     /// Continue an authorization grant
     ContinueAuthorizationGrant {
         /// The authorization grant that will be continued after authentication
-        grant: AuthorizationGrant<()>,
+        grant: Box<AuthorizationGrant<()>>,
     },
+
+    /// Go back to the recovery codes management page
+    ManageRecoveryCodes,
+
+    /// Go back to the account deletion page
+    DeleteAccount,
+
+    /// Go back to the admin console
+    Administrate,
+
+    /// Go back to a user in the admin console
+    AdministrateUser,
+}
+
+/// An upstream identity provider users can log in with
+#[derive(Serialize, Debug, Clone)]
+pub struct UpstreamProviderLink {
+    name: String,
+    href: String,
+}
+
+impl UpstreamProviderLink {
+    /// Constructs a link to log in with an upstream provider
+    #[must_use]
+    pub fn new(name: String, href: String) -> Self {
+        Self { name, href }
+    }
 }
 
 /// Context used by the `login.html` template
@@ -246,6 +274,9 @@ pub struct LoginContext {
     form: ErroredForm<LoginFormField>,
     next: Option<PostAuthContext>,
     register_link: String,
+    upstream_providers: Vec<UpstreamProviderLink>,
+    webauthn_options: Option<String>,
+    passkey_action: Option<String>,
 }
 
 impl TemplateContext for LoginContext {
@@ -254,11 +285,27 @@ impl TemplateContext for LoginContext {
         Self: Sized,
     {
         // TODO: samples with errors
-        vec![LoginContext {
-            form: ErroredForm::default(),
-            next: None,
-            register_link: "/register".to_string(),
-        }]
+        vec![
+            LoginContext {
+                form: ErroredForm::default(),
+                next: None,
+                register_link: "/register".to_string(),
+                upstream_providers: Vec::new(),
+                webauthn_options: None,
+                passkey_action: None,
+            },
+            LoginContext {
+                form: ErroredForm::default(),
+                next: None,
+                register_link: "/register".to_string(),
+                upstream_providers: vec![UpstreamProviderLink::new(
+                    "Example".to_string(),
+                    "/upstream/authorize/example".to_string(),
+                )],
+                webauthn_options: Some(r#"{"challenge":"Y2hhbGxlbmdl"}"#.to_string()),
+                passkey_action: Some("/login/passkey".to_string()),
+            },
+        ]
     }
 }
 
@@ -286,6 +333,26 @@ impl LoginContext {
             ..self
         }
     }
+
+    /// Add links to log in with upstream providers
+    #[must_use]
+    pub fn with_upstream_providers(self, upstream_providers: Vec<UpstreamProviderLink>) -> Self {
+        Self {
+            upstream_providers,
+            ..self
+        }
+    }
+
+    /// Offer to sign in with a passkey, with the JSON-encoded options for
+    /// `navigator.credentials.get` and the URL the credential is sent to
+    #[must_use]
+    pub fn with_webauthn(self, options: String, action: String) -> Self {
+        Self {
+            webauthn_options: Some(options),
+            passkey_action: Some(action),
+            ..self
+        }
+    }
 }
 
 /// Fields of the registration form
@@ -300,14 +367,52 @@ pub enum RegisterFormField {
 
     /// The password confirmation field
     PasswordConfirm,
+
+    /// The email address field
+    Email,
+
+    /// The registration token field
+    Token,
+}
+
+/// Challenge shown in the registration form
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "service", rename_all = "snake_case")]
+pub enum CaptchaContext {
+    /// A proof-of-work solved by the browser
+    ProofOfWork {
+        /// Random challenge to hash with the solution
+        challenge: String,
+
+        /// Number of leading zero bits the hash must have
+        difficulty: u8,
+    },
+
+    /// An hCaptcha widget
+    #[serde(rename = "hcaptcha")]
+    HCaptcha {
+        /// The hCaptcha site key
+        site_key: String,
+    },
+
+    /// A reCAPTCHA widget
+    #[serde(rename = "recaptcha")]
+    ReCaptcha {
+        /// The reCAPTCHA site key
+        site_key: String,
+    },
 }
 
 /// Context used by the `register.html` template
 #[derive(Serialize, Default)]
 pub struct RegisterContext {
-    form: ErroredForm<LoginFormField>,
+    form: ErroredForm<RegisterFormField>,
     next: Option<PostAuthContext>,
     login_link: String,
+    disabled: bool,
+    email_required: bool,
+    token_required: bool,
+    captcha: Option<CaptchaContext>,
 }
 
 impl TemplateContext for RegisterContext {
@@ -316,18 +421,29 @@ impl TemplateContext for RegisterContext {
         Self: Sized,
     {
         // TODO: samples with errors
-        vec![RegisterContext {
-            form: ErroredForm::default(),
-            next: None,
+        let sample = || RegisterContext {
             login_link: "/login".to_string(),
-        }]
+            ..Self::default()
+        };
+        vec![
+            sample(),
+            sample().with_email_required().with_token_required(),
+            sample().with_captcha(CaptchaContext::ProofOfWork {
+                challenge: "c2FsdA".to_string(),
+                difficulty: 16,
+            }),
+            sample().with_captcha(CaptchaContext::HCaptcha {
+                site_key: "10000000-ffff-ffff-ffff-000000000001".to_string(),
+            }),
+            sample().disabled(),
+        ]
     }
 }
 
 impl RegisterContext {
     /// Add an error on the registration form
     #[must_use]
-    pub fn with_form_error(self, form: ErroredForm<LoginFormField>) -> Self {
+    pub fn with_form_error(self, form: ErroredForm<RegisterFormField>) -> Self {
         Self { form, ..self }
     }
 
@@ -345,6 +461,79 @@ impl RegisterContext {
     pub fn with_login_link(self, login_link: String) -> Self {
         Self { login_link, ..self }
     }
+
+    /// Tell that registration is closed, instead of showing the form
+    #[must_use]
+    pub fn disabled(self) -> Self {
+        Self {
+            disabled: true,
+            ..self
+        }
+    }
+
+    /// Make the email address mandatory in the registration form
+    #[must_use]
+    pub fn with_email_required(self) -> Self {
+        Self {
+            email_required: true,
+            ..self
+        }
+    }
+
+    /// Ask for a registration token in the registration form
+    #[must_use]
+    pub fn with_token_required(self) -> Self {
+        Self {
+            token_required: true,
+            ..self
+        }
+    }
+
+    /// Add a challenge to solve to the registration form
+    #[must_use]
+    pub fn with_captcha(self, captcha: CaptchaContext) -> Self {
+        Self {
+            captcha: Some(captcha),
+            ..self
+        }
+    }
+}
+
+/// Fields of the password change form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountPasswordFormField {
+    /// The current password field
+    CurrentPassword,
+
+    /// The new password field
+    NewPassword,
+
+    /// The new password confirmation field
+    NewPasswordConfirm,
+}
+
+/// Context used by the `account/password.html` template
+#[derive(Serialize, Default)]
+pub struct AccountPasswordContext {
+    form: ErroredForm<AccountPasswordFormField>,
+}
+
+impl TemplateContext for AccountPasswordContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::default()]
+    }
+}
+
+impl AccountPasswordContext {
+    /// Add an error on the password change form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<AccountPasswordFormField>) -> Self {
+        Self { form }
+    }
 }
 
 /// Context used by the `consent.html` template
@@ -430,94 +619,989 @@ pub struct ReauthContext {
     next: Option<PostAuthContext>,
 }
 
-/// Context used by the `account/index.html` template
+/// Fields of the second factor form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TotpFormField {
+    /// The one-time code field
+    Code,
+
+    /// The recovery code field, used instead of a second factor
+    RecoveryCode,
+}
+
+/// Context used by the `account_suspended.html` template
 #[derive(Serialize)]
-pub struct AccountContext {
-    active_sessions: usize,
-    emails: Vec<UserEmail<()>>,
+pub struct AccountSuspendedContext {
+    reason: String,
+    expires_at: Option<DateTime<Utc>>,
 }
 
-impl AccountContext {
-    /// Constructs a context for the "my account" page
+impl TemplateContext for AccountSuspendedContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![
+            Self::new("Spam".to_string(), None),
+            Self::new(
+                "Harassment of other users".to_string(),
+                Some(Utc::now() + Duration::days(7)),
+            ),
+        ]
+    }
+}
+
+impl AccountSuspendedContext {
+    /// Constructs a context explaining why the user can't log in
     #[must_use]
-    pub fn new<T>(active_sessions: usize, emails: Vec<T>) -> Self
+    pub fn new(reason: String, expires_at: Option<DateTime<Utc>>) -> Self {
+        Self { reason, expires_at }
+    }
+}
+
+/// Context used by the `second_factor.html` template
+#[derive(Serialize)]
+pub struct SecondFactorContext {
+    form: ErroredForm<TotpFormField>,
+    username: String,
+    totp: bool,
+    webauthn_options: Option<String>,
+    recovery_codes: bool,
+    next: Option<PostAuthContext>,
+}
+
+impl TemplateContext for SecondFactorContext {
+    fn sample() -> Vec<Self>
     where
-        T: Into<UserEmail<()>>,
+        Self: Sized,
     {
+        vec![
+            SecondFactorContext::new("john".to_string()).with_totp(),
+            SecondFactorContext::new("john".to_string())
+                .with_totp()
+                .with_webauthn(r#"{"challenge":"Y2hhbGxlbmdl"}"#.to_string())
+                .with_recovery_codes(),
+        ]
+    }
+}
+
+impl SecondFactorContext {
+    /// Constructs a context for the second factor prompt
+    #[must_use]
+    pub fn new(username: String) -> Self {
         Self {
-            active_sessions,
-            emails: emails.into_iter().map(Into::into).collect(),
+            form: ErroredForm::new(),
+            username,
+            totp: false,
+            webauthn_options: None,
+            recovery_codes: false,
+            next: None,
+        }
+    }
+
+    /// Ask for a code from an authenticator app
+    #[must_use]
+    pub fn with_totp(self) -> Self {
+        Self { totp: true, ..self }
+    }
+
+    /// Offer to use a passkey, with the JSON-encoded options for
+    /// `navigator.credentials.get`
+    #[must_use]
+    pub fn with_webauthn(self, options: String) -> Self {
+        Self {
+            webauthn_options: Some(options),
+            ..self
+        }
+    }
+
+    /// Offer to use a recovery code instead
+    #[must_use]
+    pub fn with_recovery_codes(self) -> Self {
+        Self {
+            recovery_codes: true,
+            ..self
+        }
+    }
+
+    /// Add an error on the second factor form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<TotpFormField>) -> Self {
+        Self { form, ..self }
+    }
+
+    /// Add a post authentication action to the context
+    #[must_use]
+    pub fn with_post_action(self, next: PostAuthContext) -> Self {
+        Self {
+            next: Some(next),
+            ..self
         }
     }
 }
 
-impl TemplateContext for AccountContext {
+/// A TOTP secret being enrolled, not yet confirmed by the user
+#[derive(Serialize, Debug, Clone)]
+pub struct TotpEnrolment {
+    secret: String,
+    qr_code: String,
+}
+
+impl TotpEnrolment {
+    /// Constructs an enrolment from the encoded secret and the QR code, as an
+    /// SVG image, of its provisioning URI
+    #[must_use]
+    pub fn new(secret: String, qr_code: String) -> Self {
+        Self { secret, qr_code }
+    }
+}
+
+/// Context used by the `account/totp.html` template
+#[derive(Serialize)]
+pub struct AccountTotpContext {
+    enabled: bool,
+    enrolment: Option<TotpEnrolment>,
+    form: ErroredForm<TotpFormField>,
+}
+
+impl TemplateContext for AccountTotpContext {
     fn sample() -> Vec<Self>
     where
         Self: Sized,
     {
-        let emails: Vec<UserEmail<()>> = UserEmail::samples();
-        vec![Self::new(5, emails)]
+        vec![
+            Self::enabled(),
+            Self::enrolling(TotpEnrolment::new(
+                "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ".to_string(),
+                "<svg></svg>".to_string(),
+            )),
+        ]
     }
 }
 
-/// Context used by the `account/emails.html` template
-#[derive(Serialize)]
-#[serde(bound(serialize = "T: StorageBackend"))]
-pub struct AccountEmailsContext<T: StorageBackend> {
-    emails: Vec<UserEmail<T>>,
+impl AccountTotpContext {
+    /// Constructs a context for a user who already enrolled an authenticator
+    #[must_use]
+    pub fn enabled() -> Self {
+        Self {
+            enabled: true,
+            enrolment: None,
+            form: ErroredForm::new(),
+        }
+    }
+
+    /// Constructs a context for a user enrolling a new authenticator
+    #[must_use]
+    pub fn enrolling(enrolment: TotpEnrolment) -> Self {
+        Self {
+            enabled: false,
+            enrolment: Some(enrolment),
+            form: ErroredForm::new(),
+        }
+    }
+
+    /// Add an error on the confirmation form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<TotpFormField>) -> Self {
+        Self { form, ..self }
+    }
 }
 
-impl<T: StorageBackend> AccountEmailsContext<T> {
-    /// Constructs a context for the email management page
+/// A passkey registered by the user
+#[derive(Serialize, Debug, Clone)]
+pub struct Passkey {
+    id: i64,
+    name: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl Passkey {
+    /// Constructs a passkey entry
     #[must_use]
-    pub fn new(emails: Vec<UserEmail<T>>) -> Self {
-        Self { emails }
+    pub fn new(
+        id: i64,
+        name: String,
+        created_at: DateTime<Utc>,
+        last_used_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            created_at,
+            last_used_at,
+        }
     }
 }
 
-impl<T: StorageBackend> TemplateContext for AccountEmailsContext<T> {
+/// Context used by the `account/passkeys.html` template
+#[derive(Serialize)]
+pub struct AccountPasskeysContext {
+    passkeys: Vec<Passkey>,
+    webauthn_options: String,
+    form: ErroredForm<PasskeyFormField>,
+}
+
+/// Fields of the passkey registration form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PasskeyFormField {
+    /// The name of the passkey
+    Name,
+}
+
+impl TemplateContext for AccountPasskeysContext {
     fn sample() -> Vec<Self>
     where
         Self: Sized,
     {
-        let emails: Vec<UserEmail<T>> = UserEmail::samples();
-        vec![Self::new(emails)]
+        let options = r#"{"challenge":"Y2hhbGxlbmdl"}"#.to_string();
+        vec![
+            Self::new(Vec::new(), options.clone()),
+            Self::new(
+                vec![Passkey::new(
+                    1,
+                    "Laptop".to_string(),
+                    Utc::now(),
+                    Some(Utc::now()),
+                )],
+                options,
+            ),
+        ]
     }
 }
 
-/// Context used by the `emails/verification.{txt,html}` templates
+impl AccountPasskeysContext {
+    /// Constructs a context for the passkeys management page, with the
+    /// JSON-encoded options for `navigator.credentials.create`
+    #[must_use]
+    pub fn new(passkeys: Vec<Passkey>, webauthn_options: String) -> Self {
+        Self {
+            passkeys,
+            webauthn_options,
+            form: ErroredForm::new(),
+        }
+    }
+
+    /// Add an error on the registration form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<PasskeyFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+/// Context used by the `recovery_codes.html` template, showing freshly
+/// generated recovery codes
 #[derive(Serialize)]
-pub struct EmailVerificationContext {
-    user: User<()>,
-    verification_link: Url,
+pub struct RecoveryCodesContext {
+    codes: Vec<String>,
+    next: String,
 }
 
-impl EmailVerificationContext {
-    /// Constructs a context for the verification email
+impl TemplateContext for RecoveryCodesContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::new(
+            vec!["abcde-fghjk".to_string(), "mnpqr-stuvw".to_string()],
+            "/account".to_string(),
+        )]
+    }
+}
+
+impl RecoveryCodesContext {
+    /// Constructs a context showing the given codes, with a link to continue
+    /// to once the user saved them
     #[must_use]
-    pub fn new(user: User<()>, verification_link: Url) -> Self {
-        Self {
-            user,
-            verification_link,
-        }
+    pub fn new(codes: Vec<String>, next: String) -> Self {
+        Self { codes, next }
     }
 }
 
-impl TemplateContext for EmailVerificationContext {
+/// Context used by the `account/recovery_codes.html` template
+#[derive(Serialize)]
+pub struct AccountRecoveryCodesContext {
+    remaining: i64,
+}
+
+impl TemplateContext for AccountRecoveryCodesContext {
     fn sample() -> Vec<Self>
     where
         Self: Sized,
     {
-        User::samples()
-            .into_iter()
-            .map(|u| {
-                Self::new(
-                    u,
-                    Url::parse("https://example.com/emails/verify?code=2134").unwrap(),
-                )
-            })
-            .collect()
+        vec![Self::new(0), Self::new(8)]
+    }
+}
+
+impl AccountRecoveryCodesContext {
+    /// Constructs a context for the recovery codes management page
+    #[must_use]
+    pub fn new(remaining: i64) -> Self {
+        Self { remaining }
+    }
+}
+
+/// Fields of the account deletion form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountDeleteFormField {
+    /// The username confirmation field
+    Username,
+}
+
+/// Context used by the `account/delete.html` template
+#[derive(Serialize, Default)]
+pub struct AccountDeleteContext {
+    form: ErroredForm<AccountDeleteFormField>,
+}
+
+impl TemplateContext for AccountDeleteContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::default()]
+    }
+}
+
+impl AccountDeleteContext {
+    /// Add an error on the account deletion form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<AccountDeleteFormField>) -> Self {
+        Self { form }
+    }
+}
+
+/// Context used by the `account/index.html` template
+#[derive(Serialize)]
+pub struct AccountContext {
+    active_sessions: usize,
+    emails: Vec<UserEmail<()>>,
+}
+
+impl AccountContext {
+    /// Constructs a context for the "my account" page
+    #[must_use]
+    pub fn new<T>(active_sessions: usize, emails: Vec<T>) -> Self
+    where
+        T: Into<UserEmail<()>>,
+    {
+        Self {
+            active_sessions,
+            emails: emails.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TemplateContext for AccountContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let emails: Vec<UserEmail<()>> = UserEmail::samples();
+        vec![Self::new(5, emails)]
+    }
+}
+
+/// Context used by the `account/emails.html` template
+#[derive(Serialize)]
+#[serde(bound(serialize = "T: StorageBackend"))]
+pub struct AccountEmailsContext<T: StorageBackend> {
+    emails: Vec<UserEmail<T>>,
+}
+
+impl<T: StorageBackend> AccountEmailsContext<T> {
+    /// Constructs a context for the email management page
+    #[must_use]
+    pub fn new(emails: Vec<UserEmail<T>>) -> Self {
+        Self { emails }
+    }
+}
+
+impl<T: StorageBackend> TemplateContext for AccountEmailsContext<T> {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let emails: Vec<UserEmail<T>> = UserEmail::samples();
+        vec![Self::new(emails)]
+    }
+}
+
+/// A user, as shown in the admin console
+#[derive(Serialize, Debug, Clone)]
+pub struct AdminUser {
+    id: i64,
+    username: String,
+    primary_email: Option<String>,
+    has_verified_email: bool,
+    locked: bool,
+    is_admin: bool,
+    created_at: DateTime<Utc>,
+    deactivated_at: Option<DateTime<Utc>>,
+}
+
+impl AdminUser {
+    /// Constructs a user entry of the admin console
+    #[allow(clippy::too_many_arguments)]
+    #[must_use]
+    pub fn new(
+        id: i64,
+        username: String,
+        primary_email: Option<String>,
+        has_verified_email: bool,
+        locked: bool,
+        is_admin: bool,
+        created_at: DateTime<Utc>,
+        deactivated_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            id,
+            username,
+            primary_email,
+            has_verified_email,
+            locked,
+            is_admin,
+            created_at,
+            deactivated_at,
+        }
+    }
+
+    fn samples() -> Vec<Self> {
+        vec![
+            Self::new(
+                1,
+                "john".to_string(),
+                Some("john@example.com".to_string()),
+                true,
+                false,
+                true,
+                Utc::now(),
+                None,
+            ),
+            Self::new(
+                2,
+                "spammer".to_string(),
+                None,
+                false,
+                true,
+                false,
+                Utc::now(),
+                Some(Utc::now()),
+            ),
+        ]
+    }
+}
+
+/// Context used by the `admin/index.html` template
+#[derive(Serialize)]
+pub struct AdminIndexContext {
+    search: Option<String>,
+    users: Vec<AdminUser>,
+    previous_page: Option<String>,
+    next_page: Option<String>,
+}
+
+impl TemplateContext for AdminIndexContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![
+            Self::new(None, Vec::new()),
+            Self::new(Some("john".to_string()), AdminUser::samples()).with_pages(
+                Some("/admin?search=john".to_string()),
+                Some("/admin?search=john&offset=100".to_string()),
+            ),
+        ]
+    }
+}
+
+impl AdminIndexContext {
+    /// Constructs a context for the user search of the admin console
+    #[must_use]
+    pub fn new(search: Option<String>, users: Vec<AdminUser>) -> Self {
+        Self {
+            search,
+            users,
+            previous_page: None,
+            next_page: None,
+        }
+    }
+
+    /// Add links to the previous and next pages of results
+    #[must_use]
+    pub fn with_pages(self, previous_page: Option<String>, next_page: Option<String>) -> Self {
+        Self {
+            previous_page,
+            next_page,
+            ..self
+        }
+    }
+}
+
+/// A browser session of a user, as shown in the admin console
+#[derive(Serialize, Debug, Clone)]
+pub struct AdminBrowserSession {
+    id: i64,
+    active: bool,
+    created_at: DateTime<Utc>,
+    last_authenticated_at: Option<DateTime<Utc>>,
+}
+
+impl AdminBrowserSession {
+    /// Constructs a browser session entry of the admin console
+    #[must_use]
+    pub fn new(
+        id: i64,
+        active: bool,
+        created_at: DateTime<Utc>,
+        last_authenticated_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            id,
+            active,
+            created_at,
+            last_authenticated_at,
+        }
+    }
+}
+
+/// A session of an OAuth 2.0 client, as shown in the admin console
+#[derive(Serialize, Debug, Clone)]
+pub struct AdminOAuth2Session {
+    id: i64,
+    browser_session_id: i64,
+    client_id: String,
+    scope: String,
+    created_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+}
+
+impl AdminOAuth2Session {
+    /// Constructs an OAuth 2.0 session entry of the admin console
+    #[must_use]
+    pub fn new(
+        id: i64,
+        browser_session_id: i64,
+        client_id: String,
+        scope: String,
+        created_at: DateTime<Utc>,
+        ended_at: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            id,
+            browser_session_id,
+            client_id,
+            scope,
+            created_at,
+            ended_at,
+        }
+    }
+}
+
+/// A client a user consented to, as shown in the admin console
+#[derive(Serialize, Debug, Clone)]
+pub struct AdminConsent {
+    client_id: String,
+    scope: String,
+    updated_at: DateTime<Utc>,
+}
+
+impl AdminConsent {
+    /// Constructs a consent entry of the admin console
+    #[must_use]
+    pub fn new(client_id: String, scope: String, updated_at: DateTime<Utc>) -> Self {
+        Self {
+            client_id,
+            scope,
+            updated_at,
+        }
+    }
+}
+
+/// Fields of the user suspension form of the admin console
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminLockFormField {
+    /// The reason of the suspension
+    Reason,
+}
+
+/// Context used by the `admin/user.html` template
+#[derive(Serialize)]
+pub struct AdminUserContext {
+    user: AdminUser,
+    suspension: Option<AccountSuspendedContext>,
+    emails: Vec<UserEmail<()>>,
+    browser_sessions: Vec<AdminBrowserSession>,
+    oauth2_sessions: Vec<AdminOAuth2Session>,
+    consents: Vec<AdminConsent>,
+    notice: Option<String>,
+    form: ErroredForm<AdminLockFormField>,
+}
+
+impl TemplateContext for AdminUserContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        AdminUser::samples()
+            .into_iter()
+            .map(|user| {
+                let locked = user.locked;
+                let ctx = Self::new(user)
+                    .with_emails(UserEmail::<()>::samples())
+                    .with_sessions(
+                        vec![AdminBrowserSession::new(
+                            1,
+                            true,
+                            Utc::now(),
+                            Some(Utc::now()),
+                        )],
+                        vec![AdminOAuth2Session::new(
+                            1,
+                            1,
+                            "client".to_string(),
+                            "openid".to_string(),
+                            Utc::now(),
+                            None,
+                        )],
+                    )
+                    .with_consents(vec![AdminConsent::new(
+                        "client".to_string(),
+                        "openid email".to_string(),
+                        Utc::now(),
+                    )]);
+
+                if locked {
+                    ctx.with_suspension(AccountSuspendedContext::new("Spam".to_string(), None))
+                        .with_notice("User suspended".to_string())
+                } else {
+                    ctx
+                }
+            })
+            .collect()
+    }
+}
+
+impl AdminUserContext {
+    /// Constructs a context for the page of a user in the admin console
+    #[must_use]
+    pub fn new(user: AdminUser) -> Self {
+        Self {
+            user,
+            suspension: None,
+            emails: Vec::new(),
+            browser_sessions: Vec::new(),
+            oauth2_sessions: Vec::new(),
+            consents: Vec::new(),
+            notice: None,
+            form: ErroredForm::new(),
+        }
+    }
+
+    /// Add the suspension currently preventing the user from logging in
+    #[must_use]
+    pub fn with_suspension(self, suspension: AccountSuspendedContext) -> Self {
+        Self {
+            suspension: Some(suspension),
+            ..self
+        }
+    }
+
+    /// Add the email addresses of the user
+    #[must_use]
+    pub fn with_emails<T>(self, emails: Vec<T>) -> Self
+    where
+        T: Into<UserEmail<()>>,
+    {
+        Self {
+            emails: emails.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Add the browser and OAuth 2.0 sessions of the user
+    #[must_use]
+    pub fn with_sessions(
+        self,
+        browser_sessions: Vec<AdminBrowserSession>,
+        oauth2_sessions: Vec<AdminOAuth2Session>,
+    ) -> Self {
+        Self {
+            browser_sessions,
+            oauth2_sessions,
+            ..self
+        }
+    }
+
+    /// Add the clients the user consented to
+    #[must_use]
+    pub fn with_consents(self, consents: Vec<AdminConsent>) -> Self {
+        Self { consents, ..self }
+    }
+
+    /// Add a message about the outcome of the last action
+    #[must_use]
+    pub fn with_notice(self, notice: String) -> Self {
+        Self {
+            notice: Some(notice),
+            ..self
+        }
+    }
+
+    /// Add an error on the suspension form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<AdminLockFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+/// Context used by the `emails/verification.{txt,html}` templates
+#[derive(Serialize)]
+pub struct EmailVerificationContext {
+    user: User<()>,
+    verification_link: Url,
+}
+
+impl EmailVerificationContext {
+    /// Constructs a context for the verification email
+    #[must_use]
+    pub fn new(user: User<()>, verification_link: Url) -> Self {
+        Self {
+            user,
+            verification_link,
+        }
+    }
+}
+
+impl TemplateContext for EmailVerificationContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples()
+            .into_iter()
+            .map(|u| {
+                Self::new(
+                    u,
+                    Url::parse("https://example.com/emails/verify?code=2134").unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Context used by the `emails/password_reset.{txt,html}` templates
+#[derive(Serialize)]
+pub struct PasswordResetEmailContext {
+    user: User<()>,
+    reset_link: Url,
+}
+
+impl PasswordResetEmailContext {
+    /// Constructs a context for the password reset email
+    #[must_use]
+    pub fn new(user: User<()>, reset_link: Url) -> Self {
+        Self { user, reset_link }
+    }
+}
+
+impl TemplateContext for PasswordResetEmailContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples()
+            .into_iter()
+            .map(|u| {
+                Self::new(
+                    u,
+                    Url::parse("https://example.com/password/reset/2134").unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Fields of the password forgot form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordForgotFormField {
+    /// The email address field
+    Email,
+}
+
+/// Context used by the `password_forgot.html` template
+#[derive(Serialize, Default)]
+pub struct PasswordForgotContext {
+    form: ErroredForm<PasswordForgotFormField>,
+    sent: bool,
+}
+
+impl TemplateContext for PasswordForgotContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::default(), Self::sent()]
+    }
+}
+
+impl PasswordForgotContext {
+    /// Constructs a context telling the user that a link was sent, if the
+    /// address matched an account
+    #[must_use]
+    pub fn sent() -> Self {
+        Self {
+            sent: true,
+            ..Self::default()
+        }
+    }
+
+    /// Add an error on the password forgot form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<PasswordForgotFormField>) -> Self {
+        Self { form, ..self }
+    }
+}
+
+/// Context used by the `pages/email_verification_required.html` template
+#[derive(Serialize, Default)]
+pub struct EmailVerificationRequiredContext {
+    email: Option<String>,
+    continue_link: String,
+    sent: bool,
+    next: Option<PostAuthContext>,
+}
+
+impl TemplateContext for EmailVerificationRequiredContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        let sample = |email: Option<&str>| {
+            Self::new(email.map(ToOwned::to_owned), "/verify-email".to_string())
+        };
+        vec![
+            sample(Some("john@example.com")),
+            sample(Some("john@example.com")).sent(),
+            sample(None),
+        ]
+    }
+}
+
+impl EmailVerificationRequiredContext {
+    /// Constructs a context asking to verify the given address, if the user
+    /// has one, before following the continue link
+    #[must_use]
+    pub fn new(email: Option<String>, continue_link: String) -> Self {
+        Self {
+            email,
+            continue_link,
+            ..Self::default()
+        }
+    }
+
+    /// Tell that a new verification link was just sent
+    #[must_use]
+    pub fn sent(self) -> Self {
+        Self { sent: true, ..self }
+    }
+
+    /// Add a post authentication action to the context
+    #[must_use]
+    pub fn with_post_action(self, next: PostAuthContext) -> Self {
+        Self {
+            next: Some(next),
+            ..self
+        }
+    }
+}
+
+/// Context used by the `emails/account_locked.{txt,html}` templates
+#[derive(Serialize)]
+pub struct AccountLockedEmailContext {
+    user: User<()>,
+    locked_until: DateTime<Utc>,
+    password_forgot_link: Url,
+}
+
+impl AccountLockedEmailContext {
+    /// Constructs a context for the email sent when an account gets locked
+    /// after too many failed login attempts
+    #[must_use]
+    pub fn new(user: User<()>, locked_until: DateTime<Utc>, password_forgot_link: Url) -> Self {
+        Self {
+            user,
+            locked_until,
+            password_forgot_link,
+        }
+    }
+}
+
+impl TemplateContext for AccountLockedEmailContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        User::samples()
+            .into_iter()
+            .map(|u| {
+                Self::new(
+                    u,
+                    Utc::now() + Duration::minutes(15),
+                    Url::parse("https://example.com/password/forgot").unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Fields of the password reset form
+#[derive(Serialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PasswordResetFormField {
+    /// The new password field
+    Password,
+
+    /// The password confirmation field
+    PasswordConfirm,
+}
+
+/// Context used by the `password_reset.html` template
+#[derive(Serialize)]
+pub struct PasswordResetContext {
+    form: ErroredForm<PasswordResetFormField>,
+    username: String,
+}
+
+impl TemplateContext for PasswordResetContext {
+    fn sample() -> Vec<Self>
+    where
+        Self: Sized,
+    {
+        vec![Self::new("john".to_string())]
+    }
+}
+
+impl PasswordResetContext {
+    /// Constructs a context for the password reset form of the given user
+    #[must_use]
+    pub fn new(username: String) -> Self {
+        Self {
+            form: ErroredForm::new(),
+            username,
+        }
+    }
+
+    /// Add an error on the password reset form
+    #[must_use]
+    pub fn with_form_error(self, form: ErroredForm<PasswordResetFormField>) -> Self {
+        Self { form, ..self }
     }
 }
 
@@ -43,9 +43,17 @@ mod functions;
 mod macros;
 
 pub use self::context::{
-    AccountContext, AccountEmailsContext, ConsentContext, EmailVerificationContext, EmptyContext,
-    ErrorContext, FormPostContext, IndexContext, LoginContext, LoginFormField, PostAuthContext,
-    ReauthContext, ReauthFormField, RegisterContext, RegisterFormField, TemplateContext, WithCsrf,
+    AccountContext, AccountDeleteContext, AccountDeleteFormField, AccountEmailsContext,
+    AccountLockedEmailContext, AccountPasskeysContext, AccountPasswordContext,
+    AccountPasswordFormField, AccountRecoveryCodesContext, AccountSuspendedContext,
+    AccountTotpContext, AdminBrowserSession, AdminConsent, AdminIndexContext, AdminLockFormField,
+    AdminOAuth2Session, AdminUser, AdminUserContext, CaptchaContext, ConsentContext,
+    EmailVerificationContext, EmailVerificationRequiredContext, EmptyContext, ErrorContext,
+    FormPostContext, IndexContext, LoginContext, LoginFormField, Passkey, PasskeyFormField,
+    PasswordForgotContext, PasswordForgotFormField, PasswordResetContext,
+    PasswordResetEmailContext, PasswordResetFormField, PostAuthContext, ReauthContext,
+    ReauthFormField, RecoveryCodesContext, RegisterContext, RegisterFormField, SecondFactorContext,
+    TemplateContext, TotpEnrolment, TotpFormField, UpstreamProviderLink, WithCsrf,
     WithOptionalSession, WithSession,
 };
 
@@ -298,14 +306,53 @@ register_templates! {
     pub fn render_account_index(WithCsrf<WithSession<AccountContext>>) { "pages/account/index.html" }
 
     /// Render the password change page
-    pub fn render_account_password(WithCsrf<WithSession<EmptyContext>>) { "pages/account/password.html" }
+    pub fn render_account_password(WithCsrf<WithSession<AccountPasswordContext>>) { "pages/account/password.html" }
 
     /// Render the emails management
     pub fn render_account_emails<T: StorageBackend>(WithCsrf<WithSession<AccountEmailsContext<T>>>) { "pages/account/emails.html" }
 
+    /// Render the passkeys management page
+    pub fn render_account_passkeys(WithCsrf<WithSession<AccountPasskeysContext>>) { "pages/account/passkeys.html" }
+
+    /// Render the recovery codes management page
+    pub fn render_account_recovery_codes(WithCsrf<WithSession<AccountRecoveryCodesContext>>) { "pages/account/recovery_codes.html" }
+
+    /// Render newly generated recovery codes
+    pub fn render_recovery_codes(WithCsrf<WithSession<RecoveryCodesContext>>) { "pages/recovery_codes.html" }
+
+    /// Render the page explaining why a user can't log in
+    pub fn render_account_suspended(WithCsrf<AccountSuspendedContext>) { "pages/account_suspended.html" }
+
+    /// Render the account deletion page
+    pub fn render_account_delete(WithCsrf<WithSession<AccountDeleteContext>>) { "pages/account/delete.html" }
+
+    /// Render the page shown once an account was deleted
+    pub fn render_account_deleted(WithCsrf<WithOptionalSession<EmptyContext>>) { "pages/account_deleted.html" }
+
+    /// Render the TOTP management page
+    pub fn render_account_totp(WithCsrf<WithSession<AccountTotpContext>>) { "pages/account/totp.html" }
+
+    /// Render the user search of the admin console
+    pub fn render_admin_index(WithCsrf<WithSession<AdminIndexContext>>) { "pages/admin/index.html" }
+
+    /// Render the page of a user in the admin console
+    pub fn render_admin_user(WithCsrf<WithSession<AdminUserContext>>) { "pages/admin/user.html" }
+
     /// Render the re-authentication form
     pub fn render_reauth(WithCsrf<WithSession<ReauthContext>>) { "pages/reauth.html" }
 
+    /// Render the second factor prompt
+    pub fn render_second_factor(WithCsrf<SecondFactorContext>) { "pages/second_factor.html" }
+
+    /// Render the page asking to verify an email address before continuing
+    pub fn render_email_verification_required(WithCsrf<WithSession<EmailVerificationRequiredContext>>) { "pages/email_verification_required.html" }
+
+    /// Render the form asking for an email to send a password reset link to
+    pub fn render_password_forgot(WithCsrf<PasswordForgotContext>) { "pages/password_forgot.html" }
+
+    /// Render the password reset form
+    pub fn render_password_reset(WithCsrf<PasswordResetContext>) { "pages/password_reset.html" }
+
     /// Render the form used by the form_post response mode
     pub fn render_form_post<T: Serialize>(FormPostContext<T>) { "form_post.html" }
 
@@ -318,6 +365,18 @@ register_templates! {
     /// Render the email verification email (plain text variant)
     pub fn render_email_verification_html(EmailVerificationContext) { "emails/verification.html" }
 
+    /// Render the password reset email (plain text variant)
+    pub fn render_password_reset_txt(PasswordResetEmailContext) { "emails/password_reset.txt" }
+
+    /// Render the password reset email (HTML variant)
+    pub fn render_password_reset_html(PasswordResetEmailContext) { "emails/password_reset.html" }
+
+    /// Render the account lockout notification email (plain text variant)
+    pub fn render_account_locked_txt(AccountLockedEmailContext) { "emails/account_locked.txt" }
+
+    /// Render the account lockout notification email (HTML variant)
+    pub fn render_account_locked_html(AccountLockedEmailContext) { "emails/account_locked.html" }
+
     /// Render the email post-email verification page
     pub fn render_email_verification_done(WithCsrf<WithOptionalSession<EmptyContext>>) { "pages/verify.html" }
 }
@@ -333,11 +392,28 @@ impl Templates {
         check::render_account_index(self).await?;
         check::render_account_password(self).await?;
         check::render_account_emails::<()>(self).await?;
+        check::render_account_passkeys(self).await?;
+        check::render_account_recovery_codes(self).await?;
+        check::render_recovery_codes(self).await?;
+        check::render_account_suspended(self).await?;
+        check::render_account_delete(self).await?;
+        check::render_account_deleted(self).await?;
+        check::render_account_totp(self).await?;
+        check::render_admin_index(self).await?;
+        check::render_admin_user(self).await?;
         check::render_reauth(self).await?;
+        check::render_second_factor(self).await?;
+        check::render_email_verification_required(self).await?;
+        check::render_password_forgot(self).await?;
+        check::render_password_reset(self).await?;
         check::render_form_post::<EmptyContext>(self).await?;
         check::render_error(self).await?;
         check::render_email_verification_txt(self).await?;
         check::render_email_verification_html(self).await?;
+        check::render_password_reset_txt(self).await?;
+        check::render_password_reset_html(self).await?;
+        check::render_account_locked_txt(self).await?;
+        check::render_account_locked_html(self).await?;
         check::render_email_verification_done(self).await?;
         Ok(())
     }